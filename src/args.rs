@@ -1,43 +1,350 @@
 // imports
 use anyhow::anyhow;
 use anyhow::Context;
+use base64::Engine;
 use clap::Parser;
 use std::io::BufRead;
 
+/// Size of backups up to which the database is kept in memory, if not set explicitly
+const AUTO_IN_MEMORY_DB_MAX_SIZE: u64 = 1 << 30;
+
 #[derive(Parser)]
 #[command(name = clap::crate_name!())]
 #[command(version = clap::crate_version!())]
 #[command(about = clap::crate_description!())]
 #[command(author = clap::crate_authors!())]
 struct Args {
-	/// Sets the input file to use
+	/// Sets the input file to use, or an http(s) URL the backup is downloaded from while decoding (with --encode the directory of a decoded backup)
 	#[arg(value_name = "INPUT", required = true)]
 	input_file: std::path::PathBuf,
 
-	/// Directory to save output to. If not given, input file directory is used
+	/// Further parts of a backup split into several files, read after INPUT in the given order
+	#[arg(value_name = "PARTS", conflicts_with_all = ["follow", "encode"])]
+	input_parts: Vec<std::path::PathBuf>,
+
+	/// Directory to save output to. If not given, input file directory is used. Use - to write the database (output type RAW) or the frames (output type PROTOBUF-JSON) to stdout. The placeholders {date} (date of decoding as YYYY-MM-DD) and {version} (database version of the backup) are replaced, braces are written as {{ and }}
 	#[arg(short = 'o', long = "output-path", value_name = "FOLDER")]
 	output_path: Option<std::path::PathBuf>,
 
-	/// Output type, either RAW, CSV or NONE
+	/// Write output into an archive instead of a directory, its format is determined by the extension of FILE unless --archive-format is given (only considered with output type RAW or --only)
+	#[arg(long = "output-archive", alias = "output-tar", value_name = "FILE")]
+	output_archive: Option<std::path::PathBuf>,
+
+	/// Format of the archive, either TAR, TAR.GZ, TAR.ZST or ZIP
+	#[arg(long = "archive-format", value_name = "FORMAT", requires = "output_archive")]
+	archive_format: Option<String>,
+
+	/// Directory to save attachments to instead of the attachment directory in the output path (only considered with output type RAW or --only)
+	#[arg(long = "attachments-dir", value_name = "FOLDER")]
+	attachments_dir: Option<std::path::PathBuf>,
+
+	/// Directory to save avatars to instead of the avatar directory in the output path (only considered with output type RAW or --only)
+	#[arg(long = "avatars-dir", value_name = "FOLDER")]
+	avatars_dir: Option<std::path::PathBuf>,
+
+	/// Directory to save stickers to instead of the sticker directory in the output path (only considered with output type RAW or --only)
+	#[arg(long = "stickers-dir", value_name = "FOLDER")]
+	stickers_dir: Option<std::path::PathBuf>,
+
+	/// Name attachments with the extension of their content type in FILE, which contains lines of the form type=ext, falling back to the file type detected from their data and bin (only considered with output type RAW or --only)
+	#[arg(long = "mime-map", value_name = "FILE")]
+	mime_map: Option<std::path::PathBuf>,
+
+	/// Write the SHA-256 hash of every output file to manifest.sha256 (only considered with output type RAW or --only)
+	#[arg(long = "manifest", conflicts_with = "resume")]
+	manifest: bool,
+
+	/// Sign manifest.sha256 with the HMAC-SHA256 keyed by the content of FILE, written to manifest.sha256.hmac, implies --manifest
+	#[arg(long = "sign-key", value_name = "FILE", conflicts_with = "resume")]
+	sign_key: Option<std::path::PathBuf>,
+
+	/// Store attachments with their content type in attachments.sqlite instead of separate files (only considered with output type RAW)
+	#[arg(long = "attachments-in-db")]
+	attachments_in_db: bool,
+
+	/// Output type, either RAW, CSV, JSON, EML, SQL, DUMP, PARQUET (only with the feature parquet), DUCKDB (only with the feature duckdb), PROTOBUF-JSON or NONE
 	#[arg(short = 't', long = "output-type", value_name = "TYPE")]
 	output_type: Option<String>,
 
+	/// Sort rows by COLUMN instead of backup order. CSV supports address, body, date_sent and date_received, JSON any column of the database, --list-threads id, name, messages and last_message (only considered with output types CSV and JSON and --list-threads)
+	#[arg(long = "sort-by", value_name = "COLUMN")]
+	sort_by: Option<String>,
+
+	/// Also write all statements of the backup with their parameters and parameter types to the JSON file (only considered with output type JSON)
+	#[arg(long = "json-statements")]
+	json_statements: bool,
+
+	/// Also write the delivery, read and viewed receipts of every outgoing message to the JSON file, of each member for messages to groups (only considered with output type JSON)
+	#[arg(long = "json-receipts")]
+	json_receipts: bool,
+
+	/// Only export messages sent at or after DATE, e.g. 2024-01-31 or 2024-01-31T12:00:00+01:00. Output type RAW keeps all messages, but only writes their attachments (only supported with output types RAW, CSV, JSON and EML and --list-threads)
+	#[arg(long = "after", value_name = "DATE")]
+	after: Option<String>,
+
+	/// Only export messages sent before DATE, e.g. 2024-01-31 or 2024-01-31T12:00:00+01:00. Output type RAW keeps all messages, but only writes their attachments (only supported with output types RAW, CSV, JSON and EML and --list-threads)
+	#[arg(long = "before", value_name = "DATE")]
+	before: Option<String>,
+
 	/// Verbosity level, either DEBUG, INFO, WARN, or ERROR
 	#[arg(short = 'v', long = "verbosity", value_name = "LEVEL")]
 	log_level: Option<String>,
 
+	/// Also write log messages to FILE, an existing file is truncated
+	#[arg(long = "log-file", value_name = "FILE")]
+	log_file: Option<std::path::PathBuf>,
+
+	/// Write log messages only to the log file instead of also printing them
+	#[arg(long = "log-file-only", requires = "log_file")]
+	log_file_only: bool,
+
+	/// Keep an existing log file as FILE.1 instead of truncating it
+	#[arg(long = "rotate-log-file", requires = "log_file")]
+	rotate_log_file: bool,
+
+	/// Only print errors, overrides verbosity level and disables progress bars
+	#[arg(short = 'q', long = "quiet")]
+	quiet: bool,
+
+	/// Show the progress in a single line which is updated in place instead of progress bars, only on a terminal and not with --quiet
+	#[arg(long = "progress")]
+	progress: bool,
+
+	/// Count the frames of the backup in a first pass, which reads the whole backup, to show the progress of frames out of the total. The count is kept in checkpoints.
+	#[arg(long = "precount", conflicts_with_all = ["follow", "encode", "check_password"])]
+	precount: bool,
+
+	/// Read at most N frames per second, sleeping between frames, to decode with less load on a shared machine. Unlimited by default
+	#[arg(long = "max-frames-per-second", value_name = "N", value_parser = clap::value_parser!(u32).range(1..), conflicts_with = "encode")]
+	max_frames_per_second: Option<u32>,
+
+	/// Write every frame decrypted to FILE, which can be decoded again without password and decryption with --plaintext-frames
+	#[arg(long = "dump-frames", value_name = "FILE", conflicts_with_all = ["resume", "from_index", "encode", "check_password"])]
+	dump_frames: Option<std::path::PathBuf>,
+
+	/// Read a plaintext frames file written by --dump-frames instead of an encrypted backup, no password is needed. Such files are also recognized without this flag.
+	#[arg(long = "plaintext-frames", conflicts_with_all = ["password", "encode", "check_password"])]
+	plaintext_frames: bool,
+
+	/// Only check whether the password is correct by the decrypted length of the first frame and by reading it, no output is written
+	#[arg(long = "check-password")]
+	check_password: bool,
+
+	/// Write only the given comma separated kinds of files, any of ATTACHMENTS, AVATARS and STICKERS, while statements are discarded (only considered with output type NONE)
+	#[arg(long = "only", value_name = "KINDS", conflicts_with = "verify_only")]
+	only: Option<String>,
+
+	/// Write only attachments whose content type matches any of the comma separated GLOBS, e.g. image/* or image/*,video/mp4. Content types are taken from the database or, if unknown, detected from the data (only considered with output type RAW or --only attachments)
+	#[arg(long = "only-content-type", value_name = "GLOBS")]
+	only_content_type: Option<String>,
+
+	/// Write attachments smaller than BYTES to attachment/small and the others to attachment/large, BYTES is given as --attachment-size-threshold=BYTES and is 65536 if omitted (only considered with output type RAW or --only attachments)
+	#[arg(
+		long = "attachment-size-threshold",
+		value_name = "BYTES",
+		num_args = 0..=1,
+		require_equals = true,
+		default_missing_value = "65536",
+		conflicts_with = "attachments_in_db"
+	)]
+	attachment_size_threshold: Option<u64>,
+
+	/// Only verify the backup without writing output, like output type NONE
+	#[arg(long = "verify-only", conflicts_with_all = ["output_type", "encode", "check_password"])]
+	verify_only: bool,
+
+	/// Additionally check that every frame is of a known type and every statement has as many parameters as placeholders (only considered with --verify-only)
+	#[arg(long = "deep", requires = "verify_only")]
+	deep: bool,
+
+	/// List every conversation with its number of messages and the dates of its first and last message, instead of writing output. Threads are sorted by their last message, or with --sort-by by id, name or messages
+	#[arg(long = "list-threads", conflicts_with_all = ["output_type", "verify_only", "encode", "check_password"])]
+	list_threads: bool,
+
+	/// Print the number, type and size in bytes of every frame to stdout, one line per frame, instead of writing output. Attachments are still decrypted and verified
+	#[arg(long = "list-frames", conflicts_with_all = ["output_type", "verify_only", "list_threads", "info", "encode", "check_password", "color_frames_in_summary"])]
+	list_frames: bool,
+
+	/// Show information about the backup instead of decoding it: the version of its header, the lengths of salt and IV, its size, an estimate of its number of frames and whether it stores settings as key values, which need the password
+	#[arg(long = "info", conflicts_with_all = ["output_type", "verify_only", "list_threads", "encode", "check_password", "dump_frames"])]
+	info: bool,
+
+	/// Format of the information shown by --info, either TEXT (default) or JSON, a single object printed to stdout
+	#[arg(long = "format", value_name = "FORMAT", requires = "info")]
+	format: Option<String>,
+
+	/// Encode INPUT, a backup decoded with output type RAW, into the backup file given by --output-path
+	#[arg(long = "encode", requires = "output_path")]
+	encode: bool,
+
 	/// Overwrite existing output files
 	#[arg(short = 'f', long = "force")]
 	force_overwrite: bool,
 
+	/// Wait for more data at the end of a backup file which is still being written, like tail -f. Only finishes with the end of the backup.
+	#[arg(long = "follow")]
+	follow: bool,
+
+	/// Decode INPUT and PARTS as separate backups instead of parts of one backup, each into a directory named after its file in the output path
+	#[arg(long = "batch", conflicts_with_all = ["encode", "follow", "info", "list_frames", "output_archive", "attachments_dir", "avatars_dir", "stickers_dir", "dump_frames", "write_index", "from_index"])]
+	batch: bool,
+
+	/// Number of backups decoded at the same time with --batch
+	#[arg(short = 'j', long = "jobs", value_name = "N", requires = "batch", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+	jobs: u64,
+
+	/// Write a checkpoint to .checkpoint in the output directory every FRAMES frames, from which an interrupted decode continues with --resume (only considered with output type RAW)
+	#[arg(long = "checkpoint-interval", value_name = "FRAMES", value_parser = clap::value_parser!(u64).range(1..), conflicts_with_all = ["in_memory_db", "memory_limit", "attachments_in_db", "output_archive", "fast_db"])]
+	checkpoint_interval: Option<u64>,
+
+	/// Continue an interrupted decode from the checkpoint in the output directory, without --checkpoint-interval no further checkpoints are written (only considered with output type RAW)
+	#[arg(long = "resume", conflicts_with_all = ["in_memory_db", "memory_limit", "attachments_in_db", "output_archive", "encode", "fast_db"])]
+	resume: bool,
+
+	/// Write the position of every frame to FILE, from which decoding can start at any frame with --from-index
+	#[arg(long = "write-index", value_name = "FILE")]
+	write_index: Option<std::path::PathBuf>,
+
+	/// Start decoding at the frame given by --start-frame, whose position is read from FILE written by --write-index
+	#[arg(long = "from-index", value_name = "FILE", requires = "start_frame", conflicts_with_all = ["resume", "encode"])]
+	from_index: Option<std::path::PathBuf>,
+
+	/// Number of the frame decoding starts at with --from-index, as shown in log messages
+	#[arg(long = "start-frame", value_name = "N", requires = "from_index", value_parser = clap::value_parser!(u64).range(2..))]
+	start_frame: Option<u64>,
+
+	/// Cipher the backup is encrypted with, either AES-256-CTR (default, used by Signal) or AES-128-CTR
+	#[arg(long = "cipher", value_name = "CIPHER")]
+	cipher: Option<String>,
+
+	/// Parameters of deriving the keys from the password as HASH:ITERATIONS, e.g. SHA512:250000. By default selected by the version of the backup header
+	#[arg(long = "kdf-params", value_name = "HASH:ITERATIONS", conflicts_with = "encode")]
+	kdf_params: Option<String>,
+
+	/// Ignore the versions of the backup, e.g. of a salvaged backup whose version is malformed: keys are derived with the default parameters instead of those of the header version, and the version of version frames is replaced by 0 (unknown) without being checked
+	#[arg(long = "ignore-version-frame", conflicts_with = "encode")]
+	ignore_version_frame: bool,
+
+	/// Fail if any bytes follow the end frame of the backup, e.g. of a concatenated or corrupted file, instead of ignoring them
+	#[arg(long = "strict-end", conflicts_with_all = ["encode", "check_password", "info"])]
+	strict_end: bool,
+
+	/// Implementation of AES used to decrypt, either OPENSSL (default) or RUSTCRYPTO, which uses AES-NI if available and is only available with the feature rustcrypto
+	#[arg(long = "crypto-backend", value_name = "BACKEND")]
+	crypto_backend: Option<String>,
+
 	/// Do not verify the HMAC of each frame in the backup
 	#[arg(long = "no-verify-mac")]
 	no_verify_mac: bool,
 
+	/// Verify the HMAC of each frame in a separate thread while the next frames are read
+	#[arg(long = "async-verify", conflicts_with = "no_verify_mac")]
+	async_verify: bool,
+
+	/// Warn about attachments and statements which appear more than once, a sign of frames being decrypted wrongly
+	#[arg(long = "detect-duplicates")]
+	detect_duplicates: bool,
+
+	/// Show the proportion of each frame type by count and by bytes at the end, as colored bars scaled to the width of the terminal or as numbers if not on a terminal
+	#[arg(long = "color-frames-in-summary", conflicts_with_all = ["encode", "check_password", "info"])]
+	color_frames_in_summary: bool,
+
+	/// Log the byte count, declared lengths and file position of every frame, disables progress bars
+	#[arg(long = "debug-bytes")]
+	debug_bytes: bool,
+
+	/// Treat suspicious data in the backup as error instead of printing a warning
+	#[arg(long = "strict")]
+	strict: bool,
+
 	/// Do not use in memory sqlite database. Database is immediately created on disk (only considered with output type RAW).
 	#[arg(long = "no-in-memory-db")]
 	no_in_memory_db: bool,
 
+	/// Always use in memory sqlite database, even for backups larger than 1 GiB (only considered with output type RAW)
+	#[arg(long = "in-memory-db", conflicts_with = "no_in_memory_db")]
+	in_memory_db: bool,
+
+	/// Move the in memory sqlite database to disk once it grows larger than BYTES (only considered with output type RAW)
+	#[arg(long = "memory-limit", value_name = "BYTES", conflicts_with = "no_in_memory_db")]
+	memory_limit: Option<u64>,
+
+	/// Name attachments by their position in the backup (0001, 0002, ...) instead of their database ids (only considered with output type RAW)
+	#[arg(long = "sequential-attachments")]
+	sequential_attachments: bool,
+
+	/// Skip the table NAME with its rows and indexes, may be given multiple times (only considered with output type RAW, DUMP or PARQUET)
+	#[arg(long = "exclude-table", value_name = "NAME")]
+	exclude_table: Vec<String>,
+
+	/// Compare the SHA-256 hash of every attachment to the hash stored in the database and warn on mismatch, or fail with --strict (only considered with output type RAW)
+	#[arg(long = "verify-attachments")]
+	verify_attachments: bool,
+
+	/// Write all contacts to contacts.vcf (only considered with output type RAW)
+	#[arg(long = "export-vcard")]
+	export_vcard: bool,
+
+	/// Write the call history (date, peer, direction and type of calls) to calls.csv (only considered with output type RAW)
+	#[arg(long = "export-calls")]
+	export_calls: bool,
+
+	/// Write every extracted attachment with its file name, content type, size and the message and thread it belongs to to attachments_index.csv (only considered with output type RAW)
+	#[arg(long = "export-attachments-index", conflicts_with = "attachments_in_db")]
+	export_attachments_index: bool,
+
+	/// Write every sticker pack with its id, title and author and the emoji and file of each of its stickers to sticker/manifest.json (only considered with output type RAW)
+	#[arg(long = "export-sticker-manifest")]
+	export_sticker_manifest: bool,
+
+	/// Set the modification time of every extracted attachment to the date its message has been sent, attachments without a message are left as is (only considered with output type RAW written to a directory)
+	#[arg(long = "preserve-timestamps", conflicts_with_all = ["attachments_in_db", "output_archive"])]
+	preserve_timestamps: bool,
+
+	/// Draw all contacts with their avatars and names into contact_sheet.png (only considered with output type RAW)
+	#[arg(long = "avatar-contact-sheet")]
+	avatar_contact_sheet: bool,
+
+	/// Write every conversation with its recipients to threads/THREAD.sqlite (only considered with output type RAW)
+	#[arg(long = "split-db-by-thread")]
+	split_db_by_thread: bool,
+
+	/// Create the full text search table message_search over all message bodies in the database (only considered with output type RAW)
+	#[arg(long = "build-fts")]
+	build_fts: bool,
+
+	/// Only write rows which do not exist (by primary key) in DATABASE, the database of a previously decoded backup (only considered with output type RAW)
+	#[arg(long = "diff-against", value_name = "DATABASE")]
+	diff_against: Option<std::path::PathBuf>,
+
+	/// Rebuild indexes, update statistics and compact the database after loading the backup (only considered with output type RAW)
+	#[arg(long = "normalize-db")]
+	normalize_db: bool,
+
+	/// Record the version of this tool, the date of decoding, the input file with its size, the number of frames and whether MACs were verified in the table _decode_metadata (only considered with output type RAW)
+	#[arg(long = "decode-metadata")]
+	decode_metadata: bool,
+
+	/// Disable journal and synchronous writes of the database while loading the backup (only considered with output type RAW)
+	#[arg(long = "fast-db")]
+	fast_db: bool,
+
+	/// Number of database statements executed in one transaction (only considered with output type RAW)
+	#[arg(long = "sql-batch-size", value_name = "COUNT", default_value_t = 10000, value_parser = clap::value_parser!(u64).range(1..))]
+	sql_batch_size: u64,
+
+	/// Commit the database on disk every FRAMES frames, so that it can be opened with the rows written so far if decoding crashes. Forces the database on disk (only considered with output type RAW)
+	#[arg(long = "commit-interval", value_name = "FRAMES", value_parser = clap::value_parser!(u64).range(1..), conflicts_with_all = ["in_memory_db", "fast_db", "checkpoint_interval", "resume"])]
+	commit_interval: Option<u64>,
+
+	/// Write files larger than BYTES in parts of BYTES, e.g. to tune writing large attachments for a file system (only considered with output type RAW or --only)
+	#[arg(long = "write-chunk-size", value_name = "BYTES", default_value_t = 8 * 1024 * 1024, value_parser = clap::value_parser!(u64).range(1..))]
+	write_chunk_size: u64,
+
+	/// Read every written file back and compare it to the decrypted data, to detect corruption by the disk or file system. Doubles the disk I/O (only considered with output type RAW or --only)
+	#[arg(long = "verify-writes", conflicts_with = "output_archive")]
+	verify_writes: bool,
+
 	/// Backup password (30 digits, with or without spaces)
 	#[arg(short = 'p', long = "password", value_name = "PASSWORD", group = "password")]
 	password_string: Option<String>,
@@ -46,31 +353,407 @@ struct Args {
 	#[arg(long = "password-file", value_name = "FILE", group = "password")]
 	password_file: Option<std::path::PathBuf>,
 
+	/// Format of the backup password, either NUMERIC (default, 30 digits, other characters are removed), RAW (bytes as given) or BASE64 (decoded from base64), e.g. for test backups with other keys
+	#[arg(long = "password-format", value_name = "FORMAT")]
+	password_format: Option<String>,
+
+	/// Try every line of the password file as password until one is correct
+	#[arg(long = "password-generations", requires = "password_file", conflicts_with = "encode")]
+	password_generations: bool,
+
 	/// Read backup password from stdout from COMMAND
 	#[arg(long = "password-command", value_name = "COMMAND", group = "password")]
 	password_command: Option<String>,
+
+	/// Read backup password from the first line of stdin
+	#[arg(long = "password-stdin", group = "password")]
+	password_stdin: bool,
+
+	/// Maximum time to wait for the password from file, command or stdin
+	#[arg(
+		long = "password-timeout",
+		value_name = "SECONDS",
+		value_parser = clap::value_parser!(u64).range(1..)
+	)]
+	password_timeout: Option<u64>,
+}
+
+/// Placeholders of the output path, `{version}` is only known after reading the backup
+const OUTPUT_PATH_PLACEHOLDERS: [&str; 2] = ["date", "version"];
+
+/// Part of an output path
+#[derive(Debug, PartialEq)]
+enum OutputPathPart<'a> {
+	/// Text, in which the escaped braces `{{` and `}}` have been replaced by `{` and `}`
+	Text(String),
+	Placeholder(&'a str),
+}
+
+/// Splits the output path into text and placeholders `{name}`
+///
+/// Braces which are no part of a placeholder are written as `{{` and `}}`. Unknown placeholders
+/// are rejected, thus a typo does not silently create a directory of that name.
+fn parse_output_path(path: &str) -> Result<Vec<OutputPathPart<'_>>, anyhow::Error> {
+	let mut parts = Vec::new();
+	let mut text = String::new();
+	let mut rest = path;
+	while let Some(start) = rest.find(['{', '}']) {
+		text.push_str(&rest[..start]);
+		if rest[start..].starts_with("{{") || rest[start..].starts_with("}}") {
+			text.push_str(&rest[start..=start]);
+			rest = &rest[start + 2..];
+			continue;
+		}
+		if rest[start..].starts_with('}') {
+			return Err(anyhow!(
+				"Unmatched }} in output path, write }}}} for a brace: {}",
+				path
+			));
+		}
+		let end = rest[start..].find('}').with_context(|| {
+			format!(
+				"Unclosed placeholder in output path, write {{{{ for a brace: {}",
+				path
+			)
+		})?;
+		let name = &rest[start + 1..start + end];
+		if !OUTPUT_PATH_PLACEHOLDERS.contains(&name) {
+			return Err(anyhow!(
+				"Unknown placeholder {{{}}} in output path (expected {{date}} or {{version}}, write {{{{ and }}}} for braces)",
+				name
+			));
+		}
+		if !text.is_empty() {
+			parts.push(OutputPathPart::Text(std::mem::take(&mut text)));
+		}
+		parts.push(OutputPathPart::Placeholder(name));
+		rest = &rest[start + end + 1..];
+	}
+	text.push_str(rest);
+	if !text.is_empty() {
+		parts.push(OutputPathPart::Text(text));
+	}
+
+	Ok(parts)
+}
+
+/// Returns whether the output path contains the placeholder `{name}`
+fn has_output_path_placeholder(path: &std::path::Path, name: &str) -> Result<bool, anyhow::Error> {
+	let Some(path) = path.to_str() else {
+		return Ok(false);
+	};
+
+	Ok(parse_output_path(path)?.contains(&OutputPathPart::Placeholder(name)))
+}
+
+/// Replaces the placeholders `{name}` of the output path with their values of `values`
+///
+/// Placeholders without a value are kept. In this case, braces of the text stay escaped, thus
+/// the result is expanded again once the other values are known.
+fn expand_output_path(
+	path: &std::path::Path,
+	values: &[(&str, String)],
+) -> Result<std::path::PathBuf, anyhow::Error> {
+	let Some(path) = path.to_str() else {
+		return Ok(path.to_path_buf());
+	};
+	let parts = parse_output_path(path)?;
+	let pending = parts.iter().any(|x| match x {
+		OutputPathPart::Placeholder(name) => !values.iter().any(|(x, _)| x == name),
+		OutputPathPart::Text(_) => false,
+	});
+
+	let mut expanded = String::new();
+	for part in parts {
+		match part {
+			OutputPathPart::Text(x) if pending => {
+				expanded.push_str(&x.replace('{', "{{").replace('}', "}}"))
+			}
+			OutputPathPart::Text(x) => expanded.push_str(&x),
+			OutputPathPart::Placeholder(name) => match values.iter().find(|(x, _)| *x == name) {
+				Some((_, value)) => expanded.push_str(value),
+				None => expanded.push_str(&format!("{{{}}}", name)),
+			},
+		}
+	}
+
+	Ok(expanded.into())
+}
+
+/// Format of the backup password
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PasswordFormat {
+	/// 30 digits as shown by Signal, other characters are removed
+	Numeric,
+	/// Bytes of the password as given
+	Raw,
+	/// Bytes of the password encoded as base64
+	Base64,
+}
+
+/// Runs the password command with the current shell and returns the first line of its stdout
+///
+/// If the command has not finished after `timeout`, the shell is killed, thus it does not keep
+/// running in the background once we gave up waiting. Processes forked by the shell, e.g. for
+/// a list of commands, are not killed.
+fn run_password_command(
+	command: &str,
+	timeout: Option<std::time::Duration>,
+) -> Result<String, anyhow::Error> {
+	let shell = std::env::var("SHELL").context("Could not determine current shell")?;
+	let mut child = std::process::Command::new(shell)
+		.arg("-c")
+		.arg(command)
+		.stdout(std::process::Stdio::piped())
+		.spawn()
+		.context("Failed to execute password command")?;
+
+	// read stdout in a separate thread, otherwise a full pipe blocks the command
+	let mut stdout = child.stdout.take().unwrap();
+	let reader = std::thread::spawn(move || {
+		let mut output = Vec::new();
+		std::io::Read::read_to_end(&mut stdout, &mut output).map(|_| output)
+	});
+
+	let status = match timeout {
+		None => child
+			.wait()
+			.context("Failed to wait for password command")?,
+		Some(timeout) => {
+			let start = std::time::Instant::now();
+			loop {
+				if let Some(status) = child
+					.try_wait()
+					.context("Failed to wait for password command")?
+				{
+					break status;
+				}
+				if start.elapsed() >= timeout {
+					child.kill().ok();
+					child.wait().ok();
+					return Err(anyhow!(
+						"No password received within {} seconds",
+						timeout.as_secs()
+					));
+				}
+				std::thread::sleep(std::time::Duration::from_millis(10));
+			}
+		}
+	};
+	let output = reader
+		.join()
+		.unwrap()
+		.context("Unable to read from password command")?;
+
+	// check whether command returned an error code
+	if !status.success() {
+		return Err(anyhow!("Password command returned error code"));
+	}
+	Ok(String::from_utf8(output)
+		.context("Password command returned invalid characters")?
+		.lines()
+		.next()
+		.context("Password command returned empty line")?
+		.into())
+}
+
+/// Decides whether the database of a backup of `input_size` bytes is kept in memory, and why
+fn auto_in_memory_db(input_size: Option<u64>) -> (bool, String) {
+	match input_size {
+		Some(x) if x > AUTO_IN_MEMORY_DB_MAX_SIZE => {
+			(false, format!("backup size of {} bytes exceeds 1 GiB", x))
+		}
+		Some(x) => (true, format!("backup size of {} bytes is at most 1 GiB", x)),
+		// e.g. a download, which may be of any size
+		None => (false, String::from("backup size is unknown")),
+	}
+}
+
+/// Parses an ISO 8601 date to a timestamp in milliseconds
+///
+/// Dates without time are midnight, dates without timezone are in UTC.
+fn parse_date(value: &str) -> Result<i64, anyhow::Error> {
+	if let Ok(x) = chrono::DateTime::parse_from_rfc3339(value) {
+		return Ok(x.timestamp_millis());
+	}
+	if let Ok(x) = chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S") {
+		return Ok(x.and_utc().timestamp_millis());
+	}
+	match chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+		Ok(x) => Ok(x.and_time(chrono::NaiveTime::MIN).and_utc().timestamp_millis()),
+		Err(_) => Err(anyhow!(
+			"Invalid date: {} (expected ISO 8601, e.g. 2024-01-31 or 2024-01-31T12:00:00+01:00)",
+			value
+		)),
+	}
 }
 
 /// Config struct
 ///
 /// Stores all global variables
+#[derive(Clone)]
 pub struct Config {
 	/// Path to input file
 	pub path_input: std::path::PathBuf,
+	/// Paths to further parts of the input file, if split
+	pub path_input_parts: Vec<std::path::PathBuf>,
 	/// Path to output directory. If not given is automatically determined from input path.
 	pub path_output: std::path::PathBuf,
-	/// Password to open backup file
-	pub password: Vec<u8>,
+	/// Whether `path_output` contains the placeholder `{version}`, replaced by `set_output_version`
+	pub path_output_version: bool,
+	/// Candidate passwords to open backup file, usually only one
+	pub passwords: Vec<Vec<u8>>,
+	/// Is the input a plaintext frames file, which is read without password?
+	pub plaintext_frames: bool,
+	/// Path every frame is written to decrypted
+	pub path_dump_frames: Option<std::path::PathBuf>,
+	/// Number of unexpected characters removed from each password and its length before, for
+	/// logging
+	pub passwords_stripped: Vec<(usize, usize)>,
+	/// Cipher the backup is encrypted with
+	pub cipher: crate::decrypter::BackupCipher,
+	/// Parameters of the key derivation, if not selected by the backup header
+	pub kdf: Option<crate::decrypter::KdfParams>,
+	/// Ignore the versions of header and version frames?
+	pub ignore_version_frame: bool,
+	/// Fail on bytes after the end frame?
+	pub strict_end: bool,
+	/// Implementation of AES used to decrypt
+	pub crypto_backend: crate::decrypter::CryptoBackend,
 	/// Should HMAC be verified?
 	pub verify_mac: bool,
+	/// Verify HMACs in a separate thread?
+	pub async_verify: bool,
+	/// Warn about repeated frames?
+	pub detect_duplicates: bool,
+	/// Show the frame types at the end?
+	pub color_frames_in_summary: bool,
+	/// Log the byte accounting of every frame?
+	pub debug_bytes: bool,
+	/// Treat suspicious data as error?
+	pub strict: bool,
+	/// Wait for a backup file which is still being written?
+	pub follow: bool,
+	/// Are the input and its parts separate backups?
+	pub batch: bool,
+	/// Number of backups decoded at the same time
+	pub jobs: usize,
+	/// Number of frames after which a checkpoint is written
+	pub checkpoint_interval: Option<usize>,
+	/// Continue from the checkpoint?
+	pub resume: bool,
+	/// Path the frame index is written to
+	pub path_write_index: Option<std::path::PathBuf>,
+	/// Path of the frame index and number of the frame decoding starts at
+	pub start_frame: Option<(std::path::PathBuf, usize)>,
 	/// Log / verbosity level
 	pub log_level: log::LevelFilter,
+	/// File log messages are written to
+	pub log_file: Option<std::path::PathBuf>,
+	/// Write log messages only to `log_file`?
+	pub log_file_only: bool,
+	/// Keep the previous log file instead of truncating it?
+	pub log_file_rotate: bool,
+	/// Suppress all output except errors
+	pub quiet: bool,
+	/// Show a status line instead of progress bars?
+	pub progress: bool,
+	/// Count the frames before decoding?
+	pub precount: bool,
+	/// Maximum number of frames read per second
+	pub max_frames_per_second: Option<u32>,
+	/// Only check the password?
+	pub check_password: bool,
+	/// Print every frame with its type and size instead of decoding
+	pub list_frames: bool,
+	/// Format information about the backup is shown in, instead of decoding it
+	pub info: Option<crate::info::InfoFormat>,
+	/// Check frame types and statement parameters when verifying
+	pub verify_deep: bool,
+	/// Kinds of files written with output type NONE
+	pub only: Vec<crate::output::SignalOutputMedia>,
+	/// Content types of attachments which are written, all if not given
+	pub only_content_type: Option<crate::mime_map::ContentTypeFilter>,
+	/// Size in bytes from which attachments are written to a directory of large attachments
+	pub attachment_size_threshold: Option<u64>,
+	/// Encode a decoded backup instead of decoding?
+	pub encode: bool,
 	/// Overwrite existing output files?
 	pub force_overwrite: bool,
+	/// Path to attachment directory, if not in `path_output`
+	pub path_output_attachments: Option<std::path::PathBuf>,
+	/// Path to avatar directory, if not in `path_output`
+	pub path_output_avatars: Option<std::path::PathBuf>,
+	/// Path to sticker directory, if not in `path_output`
+	pub path_output_stickers: Option<std::path::PathBuf>,
+	/// Path to archive output is written to instead of `path_output`
+	pub path_output_archive: Option<std::path::PathBuf>,
+	/// Size of the parts large files are written in
+	pub write_chunk_size: usize,
+	/// Read files back after writing them
+	pub verify_writes: bool,
+	/// Path to the map of extensions by content type
+	pub path_mime_map: Option<std::path::PathBuf>,
+	/// Write a manifest of all output files
+	pub manifest: bool,
+	/// Path to the key the manifest is signed with
+	pub path_sign_key: Option<std::path::PathBuf>,
+	/// Format of the archive at `path_output_archive`
+	pub output_archive_format: crate::output_sink::ArchiveFormat,
 	/// Output type
 	pub output_type: crate::output::SignalOutputType,
+	/// Column rows of CSV and JSON output are sorted by
+	pub sort_by: Option<String>,
+	/// Write all statements to the JSON file
+	pub output_json_statements: bool,
+	/// Write receipts of outgoing messages to the JSON file
+	pub output_json_receipts: bool,
+	/// Range of dates messages are exported from
+	pub date_range: crate::database::DateRange,
 	/// Use in memory sqlite database
 	pub output_raw_db_in_memory: bool,
+	/// Why the database is (not) kept in memory, for logging
+	pub output_raw_db_in_memory_reason: String,
+	/// Is the in memory database chosen by the size of the backup instead of an option?
+	pub output_raw_db_in_memory_auto: bool,
+	/// Size of the in memory database at which it is moved to disk
+	pub output_raw_memory_limit: Option<u64>,
+	/// Number of statements per database transaction
+	pub output_raw_sql_batch_size: usize,
+	/// Number of frames after which the database is committed
+	pub output_raw_commit_interval: Option<usize>,
+	/// Trade crash safety of the database for loading speed
+	pub output_raw_fast_db: bool,
+	/// Tables which are not written to the database
+	pub output_raw_exclude_tables: Vec<String>,
+	/// Compare attachments to their stored hash
+	pub output_raw_verify_attachments: bool,
+	/// Write contacts as vCards
+	pub output_raw_export_vcard: bool,
+	/// Write the call history as CSV
+	pub output_raw_export_calls: bool,
+	/// Write an index of all attachment files as CSV
+	pub output_raw_export_attachments_index: bool,
+	/// Write a manifest of all sticker packs as JSON
+	pub output_raw_export_sticker_manifest: bool,
+	/// Set the modification time of attachment files to the date of their message
+	pub output_raw_preserve_timestamps: bool,
+	/// Draw contacts with their avatars into an image
+	pub output_raw_avatar_contact_sheet: bool,
+	/// Write a database of every thread
+	pub output_raw_split_db_by_thread: bool,
+	/// Name attachments sequentially
+	pub output_raw_sequential_attachments: bool,
+	/// Create a full text search table of messages
+	pub output_raw_build_fts: bool,
+	/// Database of a previous backup to write only the difference to
+	pub output_raw_diff_against: Option<std::path::PathBuf>,
+	/// Reindex, analyze and vacuum the database at the end
+	pub output_raw_normalize_db: bool,
+	/// Record how the database has been decoded in the database
+	pub output_raw_decode_metadata: bool,
+	/// Store attachments in a database instead of files
+	pub output_raw_attachments_in_db: bool,
 }
 
 impl Config {
@@ -83,7 +766,18 @@ impl Config {
 
 		// output path handling
 		let output_path = if let Some(path) = args.output_path {
-			path
+			if args.resume && has_output_path_placeholder(&path, "date")? {
+				return Err(anyhow!(
+					"Placeholder {{date}} in output path changes with the day, give the output path of the interrupted decode with --resume"
+				));
+			}
+			expand_output_path(
+				&path,
+				&[("date", chrono::Local::now().format("%Y-%m-%d").to_string())],
+			)?
+		} else if args.batch {
+			// every backup is written to the directory named after it
+			std::path::PathBuf::new()
 		} else {
 			std::path::PathBuf::from(
 				input_file
@@ -94,52 +788,148 @@ impl Config {
 			)
 		};
 
-		// password handling
-		let mut password = {
+		let path_output_version = has_output_path_placeholder(&output_path, "version")?;
+		if path_output_version && (args.encode || args.ignore_version_frame) {
+			return Err(anyhow!(
+				"Placeholder {{version}} in output path needs the version of a backup, not given with --encode or --ignore-version-frame"
+			));
+		}
+
+		// password handling, the header of a backup is shown without password
+		let password_format = match args.password_format.map(|x| x.to_lowercase()).as_deref() {
+			None | Some("numeric") => PasswordFormat::Numeric,
+			Some("raw") => PasswordFormat::Raw,
+			Some("base64") => PasswordFormat::Base64,
+			Some(x) => return Err(anyhow!("Unknown password format given: {}", x)),
+		};
+		let password_given = args.password_string.is_some()
+			|| args.password_file.is_some()
+			|| args.password_command.is_some()
+			|| args.password_stdin;
+		let password_generations = args.password_generations;
+		let password_timeout = args.password_timeout.map(std::time::Duration::from_secs);
+		let password_command = args.password_command.is_some();
+		let read_password = move || -> Result<Vec<String>, anyhow::Error> {
 			if let Some(pwd) = args.password_string {
-				pwd
+				Ok(vec![pwd])
 			} else if let Some(file_path) = args.password_file {
 				let password_file = std::io::BufReader::new(
 					std::fs::File::open(file_path).context("Unable to open password file")?,
 				);
-				password_file
+				let mut lines = password_file
 					.lines()
-					.next()
-					.context("Password file is empty")?
-					.context("Unable to read from password file")?
-			} else if let Some(command) = args.password_command {
-				let shell = std::env::var("SHELL").context("Could not determine current shell")?;
-				let output = std::process::Command::new(shell)
-					.arg("-c")
-					.arg(command)
-					.output()
-					.context("Failed to execute password command")?;
-
-				// check whether command returned an error code
-				if output.status.success() {
-					String::from_utf8(output.stdout)
-						.context("Password command returned invalid characters")?
-						.lines()
-						.next()
-						.context("Password command returned empty line")?
-						.into()
+					.collect::<Result<Vec<_>, _>>()
+					.context("Unable to read from password file")?;
+				if password_generations {
+					lines.retain(|x| !x.trim().is_empty());
 				} else {
-					return Err(anyhow!("Password command returned error code"));
+					lines.truncate(1);
 				}
+				if lines.is_empty() {
+					return Err(anyhow!("Password file is empty"));
+				}
+				Ok(lines)
+			} else if let Some(command) = args.password_command {
+				Ok(vec![run_password_command(&command, password_timeout)?])
+			} else if args.password_stdin {
+				Ok(vec![std::io::stdin()
+					.lock()
+					.lines()
+					.next()
+					.context("Standard input is empty")?
+					.context("Unable to read password from standard input")?])
 			} else {
-				return Err(anyhow!("No password provided"));
+				Err(anyhow!("No password provided"))
 			}
 		};
-		password.retain(|c| c >= '0' && c <= '9');
-		let password = password.as_bytes().to_vec();
-		if password.len() != 30 {
-			return Err(anyhow!(
-				"Wrong password length (30 numeric characters are expected)"
-			));
-		}
+		let passwords = if args.plaintext_frames || (args.info && !password_given) {
+			Vec::new()
+		} else if let Some(seconds) = args.password_timeout.filter(|_| !password_command) {
+			// reading from a FIFO or stdin may block forever, thus a separate thread reads the
+			// password while we wait for at most the given time (the command is killed instead)
+			let (tx, rx) = std::sync::mpsc::channel();
+			std::thread::spawn(move || tx.send(read_password()));
+			rx.recv_timeout(std::time::Duration::from_secs(seconds))
+				.map_err(|_| anyhow!("No password received within {} seconds", seconds))??
+		} else {
+			read_password()?
+		};
+		let mut passwords_stripped = Vec::new();
+		let passwords = passwords
+			.into_iter()
+			.map(|mut password| {
+				let length = password.chars().count();
+				match password_format {
+					PasswordFormat::Raw => {
+						passwords_stripped.push((0, length));
+						if password.is_empty() {
+							return Err(anyhow!("Password is empty"));
+						}
+						Ok(password.into_bytes())
+					}
+					PasswordFormat::Base64 => {
+						passwords_stripped.push((0, length));
+						let password = base64::engine::general_purpose::STANDARD
+							.decode(password.trim())
+							.context("Password is not valid base64")?;
+						if password.is_empty() {
+							return Err(anyhow!("Password is empty"));
+						}
+						Ok(password)
+					}
+					PasswordFormat::Numeric => {
+						// spaces and dashes separate the groups of digits when shown by Signal
+						let unexpected = password
+							.chars()
+							.filter(|c| !c.is_ascii_digit() && !c.is_whitespace() && *c != '-')
+							.count();
+						passwords_stripped.push((unexpected, length));
+						password.retain(|c| c.is_ascii_digit());
+						if password.len() != 30 {
+							// separators are expected, thus only other characters are reported
+							return Err(anyhow!(
+								"Wrong password length: found {} digits, expected 30{}",
+								password.len(),
+								if unexpected > 0 {
+									format!(
+										" ({} characters other than digits, spaces and dashes were removed)",
+										unexpected
+									)
+								} else {
+									String::new()
+								}
+							));
+						}
+						Ok(password.into_bytes())
+					}
+				}
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+
+		// large backups result in large databases, which may not fit into memory
+		let input_size = std::iter::once(&input_file)
+			.chain(&args.input_parts)
+			.map(|x| std::fs::metadata(x).ok().map(|x| x.len()))
+			.sum::<Option<u64>>();
+		let db_in_memory_forced = if args.in_memory_db {
+			Some((true, String::from("forced by --in-memory-db")))
+		} else if args.checkpoint_interval.is_some() || args.resume {
+			Some((false, String::from("checkpoints need a database on disk")))
+		} else if args.commit_interval.is_some() {
+			Some((false, String::from("forced by --commit-interval")))
+		} else if args.no_in_memory_db {
+			Some((false, String::from("forced by --no-in-memory-db")))
+		} else {
+			None
+		};
+		let db_in_memory_auto = db_in_memory_forced.is_none();
+		let (db_in_memory, db_in_memory_reason) =
+			db_in_memory_forced.unwrap_or_else(|| auto_in_memory_db(input_size));
 
 		// verbosity handling
-		let log_level = if let Some(x) = args.log_level {
+		let log_level = if args.quiet {
+			log::LevelFilter::Error
+		} else if let Some(x) = args.log_level {
 			match x.to_lowercase().as_str() {
 				"debug" => log::LevelFilter::Debug,
 				"info" => log::LevelFilter::Info,
@@ -152,26 +942,412 @@ impl Config {
 		};
 
 		// determine output type
-		let output_type = if let Some(x) = args.output_type {
+		let output_type = if args.verify_only {
+			crate::output::SignalOutputType::None
+		} else if args.list_threads {
+			crate::output::SignalOutputType::Threads
+		} else if let Some(x) = args.output_type {
 			match x.to_lowercase().as_str() {
 				"none" => crate::output::SignalOutputType::None,
 				"raw" => crate::output::SignalOutputType::Raw,
 				"csv" => crate::output::SignalOutputType::Csv,
+				"json" => crate::output::SignalOutputType::Json,
+				"eml" => crate::output::SignalOutputType::Eml,
+				"sql" => crate::output::SignalOutputType::Sql,
+				"dump" => crate::output::SignalOutputType::Dump,
+				"parquet" => crate::output::SignalOutputType::Parquet,
+				"duckdb" => crate::output::SignalOutputType::Duckdb,
+				"protobuf-json" => crate::output::SignalOutputType::ProtobufJson,
 				_ => return Err(anyhow!("Unknown output type given")),
 			}
 		} else {
 			crate::output::SignalOutputType::Raw
 		};
+		if !output_type.is_available() {
+			return Err(anyhow!(
+				"Output type is not available, it requires a build with its feature"
+			));
+		}
+
+		let info_format = match args.format.map(|x| x.to_lowercase()).as_deref() {
+			None | Some("text") => crate::info::InfoFormat::Text,
+			Some("json") => crate::info::InfoFormat::Json,
+			Some(x) => return Err(anyhow!("Unknown format given to --format: {}", x)),
+		};
+
+		let only = match args.only {
+			Some(x) => x
+				.split(',')
+				.map(|x| match x.trim().to_lowercase().as_str() {
+					"attachments" => Ok(crate::output::SignalOutputMedia::Attachments),
+					"avatars" => Ok(crate::output::SignalOutputMedia::Avatars),
+					"stickers" => Ok(crate::output::SignalOutputMedia::Stickers),
+					_ => Err(anyhow!("Unknown kind of files given to --only: {}", x)),
+				})
+				.collect::<Result<Vec<_>, _>>()?,
+			None => Vec::new(),
+		};
+		if !only.is_empty()
+			&& (!matches!(output_type, crate::output::SignalOutputType::None)
+				|| output_path == std::path::Path::new("-"))
+		{
+			return Err(anyhow!(
+				"--only is only supported with output type NONE written to a directory or archive"
+			));
+		}
+
+		let only_content_type = args
+			.only_content_type
+			.map(|x| {
+				crate::mime_map::ContentTypeFilter::parse(&x)
+					.context("Invalid content types given to --only-content-type")
+			})
+			.transpose()?;
+		if only_content_type.is_some()
+			&& !matches!(output_type, crate::output::SignalOutputType::Raw)
+			&& !only.contains(&crate::output::SignalOutputMedia::Attachments)
+		{
+			return Err(anyhow!(
+				"--only-content-type is only supported with output type RAW or --only attachments"
+			));
+		}
+		if args.attachment_size_threshold.is_some()
+			&& !matches!(output_type, crate::output::SignalOutputType::Raw)
+			&& !only.contains(&crate::output::SignalOutputMedia::Attachments)
+		{
+			return Err(anyhow!(
+				"--attachment-size-threshold is only supported with output type RAW or --only attachments"
+			));
+		}
+
+		if (args.after.is_some() || args.before.is_some())
+			&& !matches!(
+				output_type,
+				crate::output::SignalOutputType::Raw
+					| crate::output::SignalOutputType::Csv
+					| crate::output::SignalOutputType::Json
+					| crate::output::SignalOutputType::Eml
+					| crate::output::SignalOutputType::Threads
+			) {
+			return Err(anyhow!(
+				"--after and --before are only supported with output types RAW, CSV, JSON and EML and --list-threads"
+			));
+		}
+
+		let output_archive_format = match args.archive_format {
+			Some(x) => match x.to_lowercase().as_str() {
+				"tar" => crate::output_sink::ArchiveFormat::Tar,
+				"tar.gz" | "tgz" => crate::output_sink::ArchiveFormat::TarGz,
+				"tar.zst" | "tzst" => crate::output_sink::ArchiveFormat::TarZst,
+				"zip" => crate::output_sink::ArchiveFormat::Zip,
+				_ => return Err(anyhow!("Unknown archive format given")),
+			},
+			None => args
+				.output_archive
+				.as_deref()
+				.map(crate::output_sink::ArchiveFormat::from_path)
+				.unwrap_or(crate::output_sink::ArchiveFormat::Tar),
+		};
+
+		let cipher = match args.cipher {
+			Some(x) => match x.to_lowercase().as_str() {
+				"aes-256-ctr" => crate::decrypter::BackupCipher::Aes256Ctr,
+				"aes-128-ctr" => crate::decrypter::BackupCipher::Aes128Ctr,
+				_ => return Err(anyhow!("Unknown cipher given")),
+			},
+			None => crate::decrypter::BackupCipher::default(),
+		};
+
+		let kdf = match args.kdf_params {
+			Some(x) => {
+				let (hash, iterations) = x
+					.split_once(':')
+					.context("Key derivation parameters must be given as HASH:ITERATIONS")?;
+				Some(crate::decrypter::KdfParams {
+					hash: match hash.to_lowercase().as_str() {
+						"sha256" => crate::decrypter::KdfHash::Sha256,
+						"sha512" => crate::decrypter::KdfHash::Sha512,
+						_ => return Err(anyhow!("Unknown hash of key derivation given")),
+					},
+					iterations: iterations
+						.parse()
+						.context("Iterations of key derivation must be a number")?,
+				})
+			}
+			None if args.ignore_version_frame => Some(crate::decrypter::KdfParams::default()),
+			None => None,
+		};
+
+		let crypto_backend = match args.crypto_backend {
+			Some(x) => match x.to_lowercase().as_str() {
+				"openssl" => crate::decrypter::CryptoBackend::Openssl,
+				"rustcrypto" => crate::decrypter::CryptoBackend::RustCrypto,
+				_ => return Err(anyhow!("Unknown crypto backend given")),
+			},
+			None => crate::decrypter::CryptoBackend::default(),
+		};
+		if !crypto_backend.is_available() {
+			return Err(anyhow!(
+				"Crypto backend {:?} is not available, it requires a build with its feature",
+				crypto_backend
+			));
+		}
+
+		if (args.checkpoint_interval.is_some() || args.resume)
+			&& (!matches!(output_type, crate::output::SignalOutputType::Raw)
+				|| output_path == std::path::Path::new("-"))
+		{
+			return Err(anyhow!(
+				"Checkpoints are only supported with output type RAW written to a directory"
+			));
+		}
+		if args.batch && output_path == std::path::Path::new("-") {
+			return Err(anyhow!(
+				"--batch writes every backup to its own directory, not to stdout"
+			));
+		}
 
 		Ok(Self {
 			path_input: input_file,
+			path_input_parts: args.input_parts,
+			path_output_version,
 			path_output: output_path,
-			password,
+			path_output_attachments: args.attachments_dir,
+			path_output_avatars: args.avatars_dir,
+			path_output_stickers: args.stickers_dir,
+			path_output_archive: args.output_archive,
+			path_mime_map: args.mime_map,
+			verify_writes: args.verify_writes,
+			write_chunk_size: args
+				.write_chunk_size
+				.try_into()
+				.context("Write chunk size is too large")?,
+			manifest: args.manifest || args.sign_key.is_some(),
+			path_sign_key: args.sign_key,
+			output_archive_format,
+			passwords,
+			plaintext_frames: args.plaintext_frames,
+			path_dump_frames: args.dump_frames,
+			passwords_stripped,
+			cipher,
+			kdf,
+			ignore_version_frame: args.ignore_version_frame,
+			strict_end: args.strict_end,
+			crypto_backend,
 			verify_mac: !args.no_verify_mac,
+			async_verify: args.async_verify,
+			detect_duplicates: args.detect_duplicates,
+			color_frames_in_summary: args.color_frames_in_summary,
+			debug_bytes: args.debug_bytes,
+			strict: args.strict,
+			follow: args.follow,
+			batch: args.batch,
+			jobs: args
+				.jobs
+				.try_into()
+				.context("Number of jobs is too large")?,
+			checkpoint_interval: args
+				.checkpoint_interval
+				.map(usize::try_from)
+				.transpose()
+				.context("Checkpoint interval is too large")?,
+			resume: args.resume,
+			path_write_index: args.write_index,
+			start_frame: match (args.from_index, args.start_frame) {
+				(Some(path), Some(number)) => Some((
+					path,
+					usize::try_from(number).context("Start frame is too large")?,
+				)),
+				_ => None,
+			},
 			log_level,
+			log_file: args.log_file,
+			log_file_only: args.log_file_only,
+			log_file_rotate: args.rotate_log_file,
+			quiet: args.quiet,
+			progress: args.progress,
+			precount: args.precount,
+			max_frames_per_second: args.max_frames_per_second,
+			check_password: args.check_password,
+			list_frames: args.list_frames,
+			info: args.info.then_some(info_format),
+			verify_deep: args.deep,
+			only,
+			only_content_type,
+			attachment_size_threshold: args.attachment_size_threshold,
+			encode: args.encode,
 			force_overwrite: args.force_overwrite,
 			output_type,
-			output_raw_db_in_memory: !args.no_in_memory_db,
+			sort_by: args.sort_by,
+			output_json_statements: args.json_statements,
+			output_json_receipts: args.json_receipts,
+			date_range: crate::database::DateRange {
+				after: args.after.as_deref().map(parse_date).transpose()?,
+				before: args.before.as_deref().map(parse_date).transpose()?,
+			},
+			output_raw_db_in_memory: db_in_memory,
+			output_raw_db_in_memory_reason: db_in_memory_reason,
+			output_raw_db_in_memory_auto: db_in_memory_auto,
+			output_raw_memory_limit: args.memory_limit,
+			output_raw_sql_batch_size: args
+				.sql_batch_size
+				.try_into()
+				.context("SQL batch size is too large")?,
+			output_raw_commit_interval: args
+				.commit_interval
+				.map(usize::try_from)
+				.transpose()
+				.context("Commit interval is too large")?,
+			output_raw_fast_db: args.fast_db,
+			output_raw_exclude_tables: args.exclude_table,
+			output_raw_verify_attachments: args.verify_attachments,
+			output_raw_export_vcard: args.export_vcard,
+			output_raw_export_calls: args.export_calls,
+			output_raw_export_attachments_index: args.export_attachments_index,
+			output_raw_export_sticker_manifest: args.export_sticker_manifest,
+			output_raw_preserve_timestamps: args.preserve_timestamps,
+			output_raw_avatar_contact_sheet: args.avatar_contact_sheet,
+			output_raw_split_db_by_thread: args.split_db_by_thread,
+			output_raw_sequential_attachments: args.sequential_attachments,
+			output_raw_build_fts: args.build_fts,
+			output_raw_diff_against: args.diff_against,
+			output_raw_normalize_db: args.normalize_db,
+			output_raw_decode_metadata: args.decode_metadata,
+			output_raw_attachments_in_db: args.attachments_in_db,
 		})
 	}
+
+	/// Replaces the placeholder `{version}` of the output path with the database version
+	pub fn set_output_version(&mut self, version: u32) -> Result<(), anyhow::Error> {
+		self.path_output =
+			expand_output_path(&self.path_output, &[("version", version.to_string())])?;
+		self.path_output_version = false;
+		Ok(())
+	}
+
+	/// Returns the backups of a batch, the input and its parts
+	pub fn get_batch_inputs(&self) -> Vec<&std::path::Path> {
+		std::iter::once(&self.path_input)
+			.chain(&self.path_input_parts)
+			.map(std::path::PathBuf::as_path)
+			.collect()
+	}
+
+	/// Creates the config decoding `input` of a batch, written to the directory named after it
+	pub fn for_batch_input(&self, input: &std::path::Path) -> Result<Self, anyhow::Error> {
+		let mut config = self.clone();
+		config.path_output = self.path_output.join(
+			input
+				.file_stem()
+				.context("Could not determine output path from input file")?,
+		);
+		config.path_input = input.to_path_buf();
+		config.path_input_parts = Vec::new();
+		config.batch = false;
+		if self.output_raw_db_in_memory_auto {
+			let (db_in_memory, db_in_memory_reason) =
+				auto_in_memory_db(std::fs::metadata(input).ok().map(|x| x.len()));
+			config.output_raw_db_in_memory = db_in_memory;
+			config.output_raw_db_in_memory_reason = db_in_memory_reason;
+		}
+		Ok(config)
+	}
+
+	/// Returns whether the output is written to stdout instead of a directory
+	pub fn output_to_stdout(&self) -> bool {
+		self.path_output == std::path::Path::new("-")
+	}
+
+	/// Returns the directories of the output which are written to another location
+	pub fn get_output_directories(
+		&self,
+	) -> std::collections::HashMap<std::path::PathBuf, std::path::PathBuf> {
+		[
+			("attachment", &self.path_output_attachments),
+			("avatar", &self.path_output_avatars),
+			("sticker", &self.path_output_stickers),
+		]
+		.into_iter()
+		.filter_map(|(name, path)| Some((std::path::PathBuf::from(name), path.clone()?)))
+		.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn output_path_placeholders() {
+		let expand = |path: &str, values: &[(&str, String)]| {
+			expand_output_path(std::path::Path::new(path), values).unwrap()
+		};
+		let date = [("date", String::from("2024-01-31"))];
+		let version = [("version", String::from("42"))];
+
+		assert_eq!(
+			expand("exports/{date}/{version}", &date),
+			std::path::Path::new("exports/2024-01-31/{version}")
+		);
+		assert_eq!(
+			expand("exports/{version}", &version),
+			std::path::Path::new("exports/42")
+		);
+		assert_eq!(
+			expand("{date}_{date}", &date),
+			std::path::Path::new("2024-01-31_2024-01-31")
+		);
+
+		// braces are escaped by doubling them
+		assert_eq!(
+			expand("exports/{{backup}}/{date}", &date),
+			std::path::Path::new("exports/{backup}/2024-01-31")
+		);
+		assert_eq!(
+			expand("{{{date}}}", &date),
+			std::path::Path::new("{2024-01-31}")
+		);
+
+		// escaped braces are kept until all placeholders are replaced
+		let partial = expand("{{{date}}}/{{version}}/{version}", &date);
+		assert_eq!(
+			partial,
+			std::path::Path::new("{{2024-01-31}}/{{version}}/{version}")
+		);
+		assert_eq!(
+			expand(partial.to_str().unwrap(), &version),
+			std::path::Path::new("{2024-01-31}/{version}/42")
+		);
+		assert!(
+			!has_output_path_placeholder(std::path::Path::new("{{version}}"), "version").unwrap()
+		);
+
+		// unknown placeholders and unescaped braces are rejected
+		for path in ["exports/{verison}", "exports/{", "exports/}{date}", "exports/{}"] {
+			assert!(
+				expand_output_path(std::path::Path::new(path), &date).is_err(),
+				"{}",
+				path
+			);
+		}
+	}
+
+	#[test]
+	fn batch_args() {
+		let parse = |args: &[&str]| {
+			let inputs = ["signal-backup-decode", "a.backup", "b.backup"];
+			Args::try_parse_from(inputs.iter().chain(args))
+		};
+
+		let args = parse(&["--batch", "-j", "2"]).unwrap();
+		assert!(args.batch);
+		assert_eq!(args.jobs, 2);
+		assert_eq!(args.input_parts, [std::path::Path::new("b.backup")]);
+		assert_eq!(parse(&["--batch"]).unwrap().jobs, 1);
+
+		// jobs only apply to separate backups, which are written to their own directories
+		assert!(parse(&["-j", "2"]).is_err());
+		assert!(parse(&["--batch", "-j", "0"]).is_err());
+		assert!(parse(&["--batch", "--output-archive", "out.tar"]).is_err());
+	}
 }
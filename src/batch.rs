@@ -0,0 +1,78 @@
+use anyhow::Context;
+use log::{error, info, warn};
+
+/// Decodes the input and its parts as separate backups, up to `config.jobs` at the same time
+///
+/// Every backup is read by its own reader and decrypter and written to the directory named
+/// after it in the output path. A failed backup doesn't stop the others, the error of the first
+/// one is returned after all backups are done. After Ctrl-C, the backups being decoded are
+/// finished as usual, but no further ones are started.
+pub fn run(config: &crate::args::Config) -> Result<(), anyhow::Error> {
+	let inputs = config.get_batch_inputs();
+	let next = std::sync::atomic::AtomicUsize::new(0);
+	let done = std::sync::atomic::AtomicUsize::new(0);
+	let errors = std::sync::Mutex::new(Vec::new());
+
+	info!(
+		"Decoding {} backups, {} at the same time",
+		inputs.len(),
+		config.jobs.min(inputs.len())
+	);
+	std::thread::scope(|scope| {
+		for _ in 0..config.jobs.min(inputs.len()) {
+			scope.spawn(|| {
+				while !crate::INTERRUPTED.load(std::sync::atomic::Ordering::SeqCst) {
+					let i = next.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+					let Some(input) = inputs.get(i) else {
+						break;
+					};
+
+					let result = config
+						.for_batch_input(input)
+						.and_then(|mut config| crate::run(&mut config))
+						.with_context(|| format!("Failed to decode {}", input.display()));
+					let done = done.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+					match result {
+						Ok(()) => info!(
+							"Decoded {} ({} of {} backups done)",
+							input.display(),
+							done,
+							inputs.len()
+						),
+						Err(e) => {
+							error!("{:#}.", e);
+							errors.lock().unwrap().push((i, e));
+						}
+					}
+				}
+			});
+		}
+	});
+
+	let done = done.into_inner();
+	let mut errors = errors.into_inner().unwrap();
+	if done < inputs.len() {
+		warn!(
+			"{} of {} backups have not been decoded, as decoding has been interrupted",
+			inputs.len() - done,
+			inputs.len()
+		);
+	}
+
+	// the order of the inputs, not the order the decodes have failed in
+	errors.sort_by_key(|(i, _)| *i);
+	let failed = errors.len();
+	match errors.into_iter().next() {
+		None if done < inputs.len() => Err(crate::input::DecodeError::Interrupted.into()),
+		None => {
+			info!("Decoded {} backups", done);
+			Ok(())
+		}
+		// the exit code is determined by the first error
+		Some((_, e)) => Err(e.context(format!(
+			"{} of {} backups could not be decoded, the first one",
+			failed,
+			inputs.len()
+		))),
+	}
+}
@@ -0,0 +1,78 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// File name of the checkpoint in the output directory
+pub const FILENAME_CHECKPOINT: &str = ".checkpoint";
+
+/// Position in the backup file after a frame, from where reading can be continued
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Position {
+	/// Byte offset of the next frame
+	pub offset: u64,
+	/// Number of frames read, including the header
+	pub count_frame: usize,
+	/// Number of bytes read, as counted for the progress bar
+	pub count_byte: usize,
+	/// IV of the next frame
+	pub iv: Vec<u8>,
+}
+
+/// State of an interrupted decode
+///
+/// The checkpoint is only valid for the backup with the same header and the output it has been
+/// written for.
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+	/// Salt of the backup header
+	pub salt: Vec<u8>,
+	/// IV of the backup header
+	pub iv: Vec<u8>,
+	/// Position after the last written frame
+	pub position: Position,
+	/// State of the output after the last written frame
+	pub output: serde_json::Value,
+	/// Number of frames of the backup, if counted with `--precount`
+	#[serde(default)]
+	pub frames_total: Option<usize>,
+}
+
+impl Checkpoint {
+	/// Reads the checkpoint at `path`
+	pub fn read(path: &std::path::Path) -> Result<Self, anyhow::Error> {
+		let file = std::fs::File::open(path)
+			.with_context(|| format!("No checkpoint found: {}", path.to_string_lossy()))?;
+		serde_json::from_reader(std::io::BufReader::new(file))
+			.with_context(|| format!("Checkpoint is damaged: {}", path.to_string_lossy()))
+	}
+
+	/// Writes the checkpoint to `path`
+	///
+	/// The checkpoint is written to a temporary file first, which replaces the old checkpoint
+	/// afterwards. Thus, an interruption never leaves a partially written checkpoint.
+	pub fn write(&self, path: &std::path::Path) -> Result<(), anyhow::Error> {
+		let path_temporary = path.with_extension("tmp");
+		let data = serde_json::to_vec(self)?;
+		std::fs::write(&path_temporary, data).with_context(|| {
+			format!(
+				"Failed to write checkpoint: {}",
+				path_temporary.to_string_lossy()
+			)
+		})?;
+		std::fs::rename(&path_temporary, path)
+			.with_context(|| format!("Failed to write checkpoint: {}", path.to_string_lossy()))?;
+
+		Ok(())
+	}
+
+	/// Checks that the checkpoint has been written for a backup with `salt` and `iv`
+	pub fn check_header(&self, salt: &[u8], iv: &[u8]) -> Result<(), anyhow::Error> {
+		if self.salt != salt || self.iv != iv {
+			return Err(anyhow!(
+				"Checkpoint has been written for another backup file"
+			));
+		}
+
+		Ok(())
+	}
+}
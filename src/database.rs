@@ -0,0 +1,816 @@
+use anyhow::Context;
+
+/// Returns whether a statement of the backup must not be executed
+///
+/// In database version 9 signal added full text search and uses TRIGGERs to create the virtual
+/// tables. however this breaks when importing the data. Internal sqlite tables cannot be created
+/// either.
+pub fn is_statement_skipped(statement: &str) -> bool {
+	statement.starts_with("CREATE TRIGGER")
+		|| statement.contains("_fts")
+		|| statement.starts_with("CREATE TABLE sqlite_")
+}
+
+/// Splits `statement` at its placeholders `?`
+///
+/// Question marks in string literals, quoted identifiers and comments are kept. A statement with
+/// `n` placeholders results in `n + 1` parts. Numbered placeholders `?NNN` may refer to a
+/// parameter more than once or out of order, thus they are rejected.
+pub fn split_placeholders(statement: &str) -> Result<Vec<&str>, anyhow::Error> {
+	let mut parts = Vec::new();
+	let mut start = 0;
+	// end of the literal, identifier or comment we are in
+	let mut quote: Option<&str> = None;
+
+	let mut chars = statement.char_indices().peekable();
+	while let Some((i, c)) = chars.next() {
+		let rest = &statement[i..];
+		match quote {
+			Some(x) if rest.starts_with(x) => {
+				quote = None;
+				// skip the rest of the end of a block comment
+				for _ in 1..x.len() {
+					chars.next();
+				}
+			}
+			Some(_) => (),
+			None => match c {
+				'\'' => quote = Some("'"),
+				'"' => quote = Some("\""),
+				'`' => quote = Some("`"),
+				'[' => quote = Some("]"),
+				'-' if rest.starts_with("--") => quote = Some("\n"),
+				'/' if rest.starts_with("/*") => {
+					quote = Some("*/");
+					chars.next();
+				}
+				'?' => {
+					if chars.peek().is_some_and(|(_, x)| x.is_ascii_digit()) {
+						return Err(anyhow::anyhow!(
+							"Numbered placeholders are not supported: {}",
+							statement
+						));
+					}
+					parts.push(&statement[start..i]);
+					start = i + 1;
+				}
+				_ => (),
+			},
+		}
+	}
+	parts.push(&statement[start..]);
+
+	Ok(parts)
+}
+
+/// Executes a statement of the backup
+pub fn execute_statement(
+	connection: &rusqlite::Connection,
+	statement: &str,
+	parameters: &[rusqlite::types::Value],
+) -> Result<(), rusqlite::Error> {
+	connection
+		.prepare_cached(statement)?
+		.execute(rusqlite::params_from_iter(parameters.iter()))?;
+
+	Ok(())
+}
+
+/// Returns the column names of `table`, empty if the table does not exist
+pub fn get_columns(
+	connection: &rusqlite::Connection,
+	table: &str,
+) -> Result<Vec<String>, anyhow::Error> {
+	let mut stmt = connection
+		.prepare("SELECT name FROM pragma_table_info(?1)")
+		.context("failed to query table information")?;
+	let columns = stmt
+		.query_map([table], |row| row.get(0))?
+		.collect::<Result<_, _>>()
+		.with_context(|| format!("failed to read columns of table {}", table))?;
+
+	Ok(columns)
+}
+
+/// Returns whether `table` exists
+pub fn has_table(connection: &rusqlite::Connection, table: &str) -> Result<bool, anyhow::Error> {
+	Ok(!get_columns(connection, table)?.is_empty())
+}
+
+/// Returns `name` quoted as SQL identifier
+pub fn quote_identifier(name: &str) -> String {
+	format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Returns the names of all tables holding rows, in the order they have been created
+///
+/// Virtual tables, e.g. for full text search, are no tables of their own and left out.
+#[cfg(any(feature = "parquet", feature = "duckdb"))]
+pub fn get_data_tables(connection: &rusqlite::Connection) -> Result<Vec<String>, anyhow::Error> {
+	Ok(query_rows(
+		connection,
+		"SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND sql NOT LIKE 'CREATE VIRTUAL TABLE%' ORDER BY rowid",
+		[],
+	)?
+	.into_iter()
+	.filter_map(|x| x.get_string("name"))
+	.collect())
+}
+
+/// Type of the values of a column, as exported to formats with typed columns
+#[cfg(any(feature = "parquet", feature = "duckdb"))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+	Integer,
+	Real,
+	Text,
+	Blob,
+}
+
+/// Returns the type of `column` of `table` declared as `declared_type`
+///
+/// The type follows the affinity SQLite determines from the declared type. Columns of
+/// numeric affinity, e.g. BOOLEAN, are integers if they hold no other values.
+#[cfg(any(feature = "parquet", feature = "duckdb"))]
+pub fn get_column_type(
+	connection: &rusqlite::Connection,
+	table: &str,
+	column: &str,
+	declared_type: &str,
+) -> Result<ColumnType, anyhow::Error> {
+	let declared_type = declared_type.to_uppercase();
+	Ok(if declared_type.contains("INT") {
+		ColumnType::Integer
+	} else if ["CHAR", "CLOB", "TEXT"]
+		.iter()
+		.any(|x| declared_type.contains(x))
+	{
+		ColumnType::Text
+	} else if declared_type.contains("BLOB") || declared_type.is_empty() {
+		ColumnType::Blob
+	} else if ["REAL", "FLOA", "DOUB"]
+		.iter()
+		.any(|x| declared_type.contains(x))
+	{
+		ColumnType::Real
+	} else {
+		let non_integers: i64 = connection.query_row(
+			&format!(
+				"SELECT count(*) FROM {} WHERE typeof({}) NOT IN ('integer', 'null')",
+				quote_identifier(table),
+				quote_identifier(column)
+			),
+			[],
+			|row| row.get(0),
+		)?;
+		if non_integers == 0 {
+			ColumnType::Integer
+		} else {
+			ColumnType::Real
+		}
+	})
+}
+
+/// Returns the columns of `table` with their names and types, see `get_column_type`
+#[cfg(any(feature = "parquet", feature = "duckdb"))]
+pub fn get_typed_columns(
+	connection: &rusqlite::Connection,
+	table: &str,
+) -> Result<Vec<(String, ColumnType)>, anyhow::Error> {
+	let mut columns = Vec::new();
+	for column in query_rows(
+		connection,
+		"SELECT name, type FROM pragma_table_info(?1) ORDER BY cid",
+		[table],
+	)? {
+		let name = column.get_string("name").unwrap_or_default();
+		let column_type = get_column_type(
+			connection,
+			table,
+			&name,
+			&column.get_string("type").unwrap_or_default(),
+		)?;
+		columns.push((name, column_type));
+	}
+
+	Ok(columns)
+}
+
+/// Returns `value` converted to the type of a column of `column_type`
+///
+/// Numbers are parsed from text and reals without fraction are integers. Text columns take
+/// numbers as text and blobs of valid UTF-8, blob columns take any value as bytes. Null is
+/// kept, values which cannot be converted, e.g. text which is no number in an integer column,
+/// are returned as `None`.
+#[cfg(any(feature = "parquet", feature = "duckdb"))]
+pub fn convert_value(
+	value: rusqlite::types::ValueRef,
+	column_type: ColumnType,
+) -> Option<rusqlite::types::Value> {
+	use rusqlite::types::Value;
+	use rusqlite::types::ValueRef;
+
+	let text = |x: &[u8]| std::str::from_utf8(x).ok().map(|x| x.trim().to_string());
+	match (column_type, value) {
+		(_, ValueRef::Null) => Some(Value::Null),
+		(ColumnType::Integer, ValueRef::Integer(x)) => Some(Value::Integer(x)),
+		(ColumnType::Integer, ValueRef::Real(x)) if x.fract() == 0.0 => {
+			Some(Value::Integer(x as i64))
+		}
+		(ColumnType::Integer, ValueRef::Text(x)) => {
+			text(x).and_then(|x| x.parse().ok()).map(Value::Integer)
+		}
+		(ColumnType::Real, ValueRef::Integer(x)) => Some(Value::Real(x as f64)),
+		(ColumnType::Real, ValueRef::Real(x)) => Some(Value::Real(x)),
+		(ColumnType::Real, ValueRef::Text(x)) => {
+			text(x).and_then(|x| x.parse().ok()).map(Value::Real)
+		}
+		(ColumnType::Text, ValueRef::Integer(x)) => Some(Value::Text(x.to_string())),
+		(ColumnType::Text, ValueRef::Real(x)) => Some(Value::Text(x.to_string())),
+		(ColumnType::Text, ValueRef::Text(x) | ValueRef::Blob(x)) => std::str::from_utf8(x)
+			.ok()
+			.map(|x| Value::Text(x.to_string())),
+		(ColumnType::Blob, ValueRef::Text(x) | ValueRef::Blob(x)) => Some(Value::Blob(x.to_vec())),
+		(ColumnType::Blob, ValueRef::Integer(x)) => Some(Value::Blob(x.to_string().into_bytes())),
+		(ColumnType::Blob, ValueRef::Real(x)) => Some(Value::Blob(x.to_string().into_bytes())),
+		_ => None,
+	}
+}
+
+/// Returns an ORDER BY clause sorting `table` by `column`, if the table has this column
+///
+/// Rows are additionally sorted by rowid, which makes the order deterministic.
+pub fn get_order_by(
+	connection: &rusqlite::Connection,
+	table: &str,
+	column: Option<&str>,
+) -> Result<String, anyhow::Error> {
+	match column {
+		Some(x) if get_columns(connection, table)?.iter().any(|y| y == x) => {
+			Ok(format!("ORDER BY {}, rowid", quote_identifier(x)))
+		}
+		_ => Ok(String::from("ORDER BY rowid")),
+	}
+}
+
+/// Reads all rows of `query` as maps from column name to value
+///
+/// Signal changes its schema frequently, thus exports read rows by column name and treat
+/// missing columns as NULL.
+pub fn query_rows<P: rusqlite::Params>(
+	connection: &rusqlite::Connection,
+	query: &str,
+	params: P,
+) -> Result<Vec<Row>, anyhow::Error> {
+	let mut stmt = connection
+		.prepare(query)
+		.with_context(|| format!("failed to prepare query: {}", query))?;
+	let names: Vec<String> = stmt.column_names().iter().map(|x| x.to_string()).collect();
+	let rows = stmt
+		.query_map(params, |row| {
+			let mut values = std::collections::HashMap::new();
+			for (i, name) in names.iter().enumerate() {
+				values.insert(name.clone(), row.get::<_, rusqlite::types::Value>(i)?);
+			}
+			Ok(Row { values })
+		})?
+		.collect::<Result<_, _>>()
+		.with_context(|| format!("failed to execute query: {}", query))?;
+
+	Ok(rows)
+}
+
+/// Row of a query result with values accessible by column name
+pub struct Row {
+	values: std::collections::HashMap<String, rusqlite::types::Value>,
+}
+
+impl Row {
+	pub fn get_i64(&self, column: &str) -> Option<i64> {
+		match self.values.get(column) {
+			Some(rusqlite::types::Value::Integer(x)) => Some(*x),
+			_ => None,
+		}
+	}
+
+	pub fn get_string(&self, column: &str) -> Option<String> {
+		match self.values.get(column) {
+			Some(rusqlite::types::Value::Text(x)) if !x.is_empty() => Some(x.clone()),
+			_ => None,
+		}
+	}
+
+	/// Returns whether `column` is neither NULL nor empty
+	pub fn has_value(&self, column: &str) -> bool {
+		match self.values.get(column) {
+			None | Some(rusqlite::types::Value::Null) => false,
+			Some(rusqlite::types::Value::Text(x)) => !x.is_empty(),
+			Some(rusqlite::types::Value::Blob(x)) => !x.is_empty(),
+			Some(_) => true,
+		}
+	}
+
+	/// Returns the first non empty text of `columns`
+	pub fn get_first_string(&self, columns: &[&str]) -> Option<String> {
+		columns.iter().find_map(|x| self.get_string(x))
+	}
+}
+
+/// Name of the full text search table created by `build_fts`
+pub const TABLE_FTS: &str = "message_search";
+
+/// Name of the table recording how a database has been decoded, prefixed to never collide with
+/// a table of Signal
+pub const TABLE_DECODE_METADATA: &str = "_decode_metadata";
+
+/// Creates a full text search (FTS5) table over the bodies of all messages
+///
+/// Signal's own search tables are skipped while importing, thus a separate table is built. It
+/// contains the body, the table and id of the message and its thread. Older backups store
+/// messages in the tables `sms` and `mms` instead of `message`.
+pub fn build_fts(connection: &rusqlite::Connection) -> Result<(), anyhow::Error> {
+	connection
+		.execute_batch(&format!(
+			"DROP TABLE IF EXISTS {0};
+			CREATE VIRTUAL TABLE {0} USING fts5(body, source UNINDEXED, message_id UNINDEXED, thread_id UNINDEXED);",
+			TABLE_FTS
+		))
+		.context("failed to create full text search table")?;
+
+	for table in ["message", "sms", "mms"] {
+		let columns = get_columns(connection, table)?;
+		if !columns.iter().any(|x| x == "body") {
+			continue;
+		}
+
+		let thread_id = if columns.iter().any(|x| x == "thread_id") {
+			"thread_id"
+		} else {
+			"NULL"
+		};
+		connection
+			.execute(
+				&format!(
+					"INSERT INTO {0} (body, source, message_id, thread_id) SELECT body, ?1, _id, {1} FROM {2} WHERE body IS NOT NULL AND body != ''",
+					TABLE_FTS, thread_id, table
+				),
+				[table],
+			)
+			.with_context(|| format!("failed to index messages of table {}", table))?;
+	}
+
+	// merge the index into a single b-tree for fastest queries
+	connection
+		.execute_batch(&format!("INSERT INTO {0} ({0}) VALUES ('optimize');", TABLE_FTS))
+		.context("failed to optimize full text search table")?;
+
+	Ok(())
+}
+
+/// Database of a previous backup to decode only the difference to it
+///
+/// Rows of the backup are compared by the primary key of their table. Tables without primary
+/// key or missing in the reference database are never considered as already existing.
+pub struct ReferenceDatabase {
+	connection: rusqlite::Connection,
+	/// Primary key columns (index and name) by table name, `None` if rows cannot be compared
+	primary_keys: std::collections::HashMap<String, Option<Vec<(usize, String)>>>,
+}
+
+impl ReferenceDatabase {
+	/// Opens the database at `path` read only
+	pub fn open(path: &std::path::Path) -> Result<Self, anyhow::Error> {
+		let connection =
+			rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+				.with_context(|| {
+					format!(
+						"could not open reference database: {}",
+						path.to_string_lossy()
+					)
+				})?;
+
+		Ok(Self {
+			connection,
+			primary_keys: std::collections::HashMap::new(),
+		})
+	}
+
+	/// Returns whether the row inserted by `statement` exists in the reference database
+	///
+	/// Only statements of the form `INSERT INTO table VALUES (...)`, as written by Signal, are
+	/// compared. The columns of the table are read from `connection`, the database the backup is
+	/// written to.
+	pub fn contains(
+		&mut self,
+		connection: &rusqlite::Connection,
+		statement: &str,
+		parameters: &[rusqlite::types::Value],
+	) -> Result<bool, anyhow::Error> {
+		let Some(table) = get_insert_table(statement) else {
+			return Ok(false);
+		};
+
+		if !self.primary_keys.contains_key(table) {
+			let primary_key = self.get_primary_key(connection, table)?;
+			self.primary_keys.insert(table.to_string(), primary_key);
+		}
+		let Some(Some(primary_key)) = self.primary_keys.get(table) else {
+			return Ok(false);
+		};
+
+		let mut values = Vec::new();
+		for (index, _) in primary_key {
+			match parameters.get(*index) {
+				Some(x) => values.push(x),
+				None => return Ok(false),
+			}
+		}
+
+		let query = format!(
+			"SELECT 1 FROM {} WHERE {}",
+			quote_identifier(table),
+			primary_key
+				.iter()
+				.enumerate()
+				.map(|(i, (_, name))| format!("{} IS ?{}", quote_identifier(name), i + 1))
+				.collect::<Vec<_>>()
+				.join(" AND ")
+		);
+		let mut stmt = self
+			.connection
+			.prepare_cached(&query)
+			.with_context(|| format!("failed to query reference database: {}", query))?;
+
+		Ok(stmt.exists(rusqlite::params_from_iter(values))?)
+	}
+
+	fn get_primary_key(
+		&self,
+		connection: &rusqlite::Connection,
+		table: &str,
+	) -> Result<Option<Vec<(usize, String)>>, anyhow::Error> {
+		if !has_table(&self.connection, table)? {
+			return Ok(None);
+		}
+
+		let mut stmt = connection
+			.prepare("SELECT cid, name, pk FROM pragma_table_info(?1) WHERE pk > 0 ORDER BY pk")
+			.context("failed to query table information")?;
+		let primary_key = stmt
+			.query_map([table], |row| {
+				Ok((row.get::<_, u32>(0)? as usize, row.get::<_, String>(1)?))
+			})?
+			.collect::<Result<Vec<_>, _>>()
+			.with_context(|| format!("failed to read primary key of table {}", table))?;
+
+		// the reference database must have the same key, otherwise rows cannot be compared
+		let columns_reference = get_columns(&self.connection, table)?;
+		if primary_key.is_empty()
+			|| primary_key
+				.iter()
+				.any(|(_, name)| !columns_reference.contains(name))
+		{
+			return Ok(None);
+		}
+
+		Ok(Some(primary_key))
+	}
+}
+
+/// Returns the table of a statement of the form `INSERT INTO table VALUES (...)`
+pub fn get_insert_table(statement: &str) -> Option<&str> {
+	let rest = statement.strip_prefix("INSERT INTO ")?.trim_start();
+	let end = rest.find(|c: char| c.is_whitespace() || c == '(')?;
+	let (table, rest) = rest.split_at(end);
+
+	if rest.trim_start().starts_with("VALUES") {
+		Some(table.trim_matches(|c| c == '"' || c == '`' || c == '[' || c == ']'))
+	} else {
+		None
+	}
+}
+
+/// Returns the id of the message an attachment row belongs to
+pub fn get_attachment_message_id(row: &Row) -> Option<i64> {
+	// newer backups reference the message in column message_id, older ones in column mid
+	row.get_i64("message_id").or_else(|| row.get_i64("mid"))
+}
+
+/// Returns the date the message with id `message_id` has been sent at in milliseconds
+///
+/// Older backups store the date in column date of table mms, newer ones in column date_sent of
+/// table message.
+pub fn get_message_date(
+	connection: &rusqlite::Connection,
+	message_id: i64,
+) -> Result<Option<i64>, anyhow::Error> {
+	for table in ["message", "mms"] {
+		if !has_table(connection, table)? {
+			continue;
+		}
+		let rows = query_rows(
+			connection,
+			&format!("SELECT * FROM {} WHERE _id = ?1", table),
+			[message_id],
+		)?;
+		if let Some(row) = rows.into_iter().next() {
+			return Ok(row
+				.get_i64("date_sent")
+				.or_else(|| row.get_i64("date"))
+				.filter(|x| *x > 0));
+		}
+	}
+
+	Ok(None)
+}
+
+/// Returns the row of the attachment with id `row_id`
+///
+/// Newer backups store attachments in table `attachment`, older ones in table `part`. Their
+/// content type is stored in column `content_type` or `ct` respectively.
+pub fn get_attachment(
+	connection: &rusqlite::Connection,
+	row_id: i64,
+) -> Result<Option<Row>, anyhow::Error> {
+	for table in ["attachment", "part"] {
+		if !has_table(connection, table)? {
+			continue;
+		}
+		let rows = query_rows(
+			connection,
+			&format!("SELECT * FROM {} WHERE _id = ?1", table),
+			[row_id],
+		)?;
+		if let Some(row) = rows.into_iter().next() {
+			return Ok(Some(row));
+		}
+	}
+
+	Ok(None)
+}
+
+/// Returns the table a `CREATE` or `INSERT` statement writes to
+///
+/// For indexes and triggers, this is the table they are created on. Other statements, e.g.
+/// views, return `None`.
+pub fn get_statement_table(statement: &str) -> Option<&str> {
+	if let Some(table) = get_insert_table(statement) {
+		return Some(table);
+	}
+
+	let words: Vec<&str> = statement
+		.split(|c: char| c.is_whitespace() || c == '(')
+		.filter(|x| !x.is_empty())
+		.collect();
+	let name = match words.as_slice() {
+		["CREATE", "TABLE", "IF", "NOT", "EXISTS", name, ..]
+		| ["CREATE", "TABLE", name, ..]
+		| ["CREATE", "VIRTUAL", "TABLE", name, ..] => *name,
+		["CREATE", "INDEX" | "TRIGGER", ..] | ["CREATE", "UNIQUE", "INDEX", ..] => {
+			let i = words.iter().position(|x| x.eq_ignore_ascii_case("ON"))?;
+			words.get(i + 1)?
+		}
+		_ => return None,
+	};
+
+	Some(name.trim_matches(|c| c == '"' || c == '`' || c == '[' || c == ']'))
+}
+
+/// Returns the tables referenced by foreign keys in a `CREATE TABLE` statement
+pub fn get_referenced_tables(statement: &str) -> Vec<&str> {
+	let words: Vec<&str> = statement
+		.split(|c: char| c.is_whitespace() || c == '(' || c == ',')
+		.filter(|x| !x.is_empty())
+		.collect();
+
+	words
+		.windows(2)
+		.filter(|x| x[0].eq_ignore_ascii_case("REFERENCES"))
+		.map(|x| x[1].trim_matches(|c| c == '"' || c == '`' || c == '[' || c == ']'))
+		.collect()
+}
+
+/// Signal recipient (contact or group)
+pub struct Recipient {
+	pub id: i64,
+	/// Display name, the best available of system contact name, profile name and group title
+	pub name: Option<String>,
+	/// Phone number
+	pub phone: Option<String>,
+	/// Group id, only set for groups
+	pub group_id: Option<String>,
+	/// Timer of disappearing messages in seconds, 0 if they are off
+	pub message_expiration_time: i64,
+}
+
+impl Recipient {
+	/// Returns a human readable name of the recipient
+	pub fn display_name(&self) -> String {
+		self.name
+			.clone()
+			.or_else(|| self.phone.clone())
+			.or_else(|| self.group_id.clone())
+			.unwrap_or_else(|| format!("recipient {}", self.id))
+	}
+}
+
+/// Reads all recipients by their id
+pub fn get_recipients(
+	connection: &rusqlite::Connection,
+) -> Result<std::collections::HashMap<i64, Recipient>, anyhow::Error> {
+	let mut recipients = std::collections::HashMap::new();
+	if !has_table(connection, "recipient")? {
+		return Ok(recipients);
+	}
+
+	// group titles are not part of the recipient table
+	let mut group_titles = std::collections::HashMap::new();
+	if has_table(connection, "groups")? {
+		for row in query_rows(connection, "SELECT * FROM groups", [])? {
+			if let (Some(id), Some(title)) = (row.get_i64("recipient_id"), row.get_string("title"))
+			{
+				group_titles.insert(id, title);
+			}
+		}
+	}
+
+	for row in query_rows(connection, "SELECT * FROM recipient", [])? {
+		let id = row.get_i64("_id").unwrap_or_default();
+		let name = row
+			.get_first_string(&[
+				"system_joined_name",
+				"system_display_name",
+				"profile_joined_name",
+				"signal_profile_name",
+			])
+			.or_else(|| group_titles.get(&id).cloned());
+
+		recipients.insert(
+			id,
+			Recipient {
+				id,
+				name,
+				phone: row.get_first_string(&["e164", "phone"]),
+				group_id: row.get_string("group_id"),
+				message_expiration_time: row.get_i64("message_expiration_time").unwrap_or(0),
+			},
+		);
+	}
+
+	Ok(recipients)
+}
+
+/// Message quoted by a reply
+pub struct Quote {
+	/// Id of the quoted message, if it is part of the backup
+	pub id: Option<i64>,
+	/// Id of the recipient who wrote the quoted message
+	pub author_id: Option<i64>,
+	/// Text of the quoted message
+	pub body: Option<String>,
+}
+
+/// Reads the messages quoted by replies, by id of the reply
+///
+/// A reply stores the date the quoted message was sent in column `quote_id` and its author in
+/// `quote_author`, together with a copy of its text in `quote_body`. The quoted message is
+/// found by these, its own text is only used if the copy is missing. Quoted messages may have
+/// been deleted or have disappeared since.
+pub fn get_quotes(
+	connection: &rusqlite::Connection,
+) -> Result<std::collections::HashMap<i64, Quote>, anyhow::Error> {
+	let mut quotes = std::collections::HashMap::new();
+	if !has_table(connection, "message")?
+		|| !get_columns(connection, "message")?
+			.iter()
+			.any(|x| x == "quote_id")
+	{
+		return Ok(quotes);
+	}
+
+	let rows = query_rows(connection, "SELECT * FROM message ORDER BY _id", [])?;
+	let messages: std::collections::HashMap<(i64, Option<i64>), &Row> = rows
+		.iter()
+		.filter_map(|x| Some(((x.get_i64("date_sent")?, x.get_i64("from_recipient_id")), x)))
+		.collect();
+
+	for row in &rows {
+		let Some(date_sent) = row.get_i64("quote_id").filter(|x| *x > 0) else {
+			continue;
+		};
+		let author_id = row.get_i64("quote_author");
+		let quoted = messages.get(&(date_sent, author_id));
+
+		quotes.insert(
+			row.get_i64("_id").unwrap_or_default(),
+			Quote {
+				// edited messages are quoted by their original version, exported is the latest
+				id: quoted.and_then(|x| x.get_i64("latest_revision_id").or(x.get_i64("_id"))),
+				author_id,
+				body: row
+					.get_string("quote_body")
+					.or_else(|| quoted.and_then(|x| x.get_string("body"))),
+			},
+		);
+	}
+
+	Ok(quotes)
+}
+
+/// Previous version of an edited message
+pub struct Revision {
+	pub body: Option<String>,
+	/// Date this version was sent, as timestamp
+	pub date_sent: i64,
+}
+
+/// Returns whether a row of table `message` is a previous version of an edited message
+///
+/// Every version of an edited message is a row of its own, previous versions reference the
+/// latest version in column `latest_revision_id`.
+pub fn is_previous_revision(row: &Row) -> bool {
+	row.get_i64("latest_revision_id").is_some()
+}
+
+/// Reads the previous versions of edited messages, by id of their latest version
+///
+/// Versions are ordered from the original message to the last one before the latest.
+pub fn get_revisions(
+	connection: &rusqlite::Connection,
+) -> Result<std::collections::HashMap<i64, Vec<Revision>>, anyhow::Error> {
+	let mut revisions: std::collections::HashMap<i64, Vec<Revision>> =
+		std::collections::HashMap::new();
+	if !has_table(connection, "message")?
+		|| !get_columns(connection, "message")?
+			.iter()
+			.any(|x| x == "latest_revision_id")
+	{
+		return Ok(revisions);
+	}
+
+	for row in query_rows(
+		connection,
+		"SELECT * FROM message WHERE latest_revision_id IS NOT NULL ORDER BY _id",
+		[],
+	)? {
+		revisions
+			.entry(row.get_i64("latest_revision_id").unwrap_or_default())
+			.or_default()
+			.push(Revision {
+				body: row.get_string("body"),
+				date_sent: row.get_i64("date_sent").unwrap_or_default(),
+			});
+	}
+
+	Ok(revisions)
+}
+
+/// Formats the timer of disappearing messages, e.g. `1 week` or `off` for 0 seconds
+pub fn format_expiration(seconds: i64) -> String {
+	if seconds <= 0 {
+		return String::from("off");
+	}
+
+	// the largest unit which divides the timer exactly, as Signal offers 1 week but also 5 days
+	let (count, unit) = [
+		(7 * 24 * 60 * 60, "week"),
+		(24 * 60 * 60, "day"),
+		(60 * 60, "hour"),
+		(60, "minute"),
+		(1, "second"),
+	]
+	.into_iter()
+	.find(|(length, _)| seconds % length == 0)
+	.map(|(length, unit)| (seconds / length, unit))
+	.unwrap();
+
+	format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+/// Range of dates messages are exported from, as timestamps in milliseconds
+#[derive(Clone, Copy, Default)]
+pub struct DateRange {
+	/// Only messages sent at or after this time
+	pub after: Option<i64>,
+	/// Only messages sent before this time
+	pub before: Option<i64>,
+}
+
+impl DateRange {
+	/// Returns whether the range excludes any messages
+	pub fn is_limited(&self) -> bool {
+		self.after.is_some() || self.before.is_some()
+	}
+
+	/// Returns whether a message sent at `timestamp` is in the range
+	pub fn contains(&self, timestamp: i64) -> bool {
+		self.after.is_none_or(|x| timestamp >= x) && self.before.is_none_or(|x| timestamp < x)
+	}
+}
+
+/// Converts a timestamp in milliseconds as used by Signal to a date
+pub fn timestamp_to_date(timestamp: i64) -> chrono::NaiveDateTime {
+	chrono::DateTime::from_timestamp_millis(timestamp)
+		.unwrap_or_default()
+		.naive_utc()
+}
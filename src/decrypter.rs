@@ -1,40 +1,356 @@
 use hmac::Mac;
-use openssl;
 use sha2::Digest;
 use subtle::ConstantTimeEq;
 
 /// Used length of HMAC in bytes
 pub const LENGTH_HMAC: usize = 10;
 
+/// Number of messages which may wait for HMAC verification in the background
+const MAC_QUEUE_LENGTH: usize = 16;
+
+/// Maximum number of bytes in one message to the HMAC verification in the background
+///
+/// Large attachments are sent in parts, thus at most `MAC_QUEUE_LENGTH` parts are held in memory.
+const MAC_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Cipher the frames of a backup are encrypted with
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BackupCipher {
+	Aes128Ctr,
+	/// The backup header has no field selecting the cipher, all backups written by Signal use
+	/// AES-256 in CTR mode
+	#[default]
+	Aes256Ctr,
+}
+
+impl BackupCipher {
+	/// Returns the length of the key in bytes, taken from the start of the derived keys
+	pub fn key_length(self) -> usize {
+		match self {
+			Self::Aes128Ctr => 16,
+			Self::Aes256Ctr => 32,
+		}
+	}
+
+	fn openssl(self) -> openssl::symm::Cipher {
+		match self {
+			Self::Aes128Ctr => openssl::symm::Cipher::aes_128_ctr(),
+			Self::Aes256Ctr => openssl::symm::Cipher::aes_256_ctr(),
+		}
+	}
+}
+
+/// Hash function iterated to derive the keys of a backup
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KdfHash {
+	Sha256,
+	Sha512,
+}
+
+/// Parameters of deriving the keys of a backup from its password
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KdfParams {
+	pub hash: KdfHash,
+	pub iterations: u32,
+}
+
+/// Parameters of the key derivation of all backups written by Signal so far
+const KDF_SIGNAL: KdfParams = KdfParams {
+	hash: KdfHash::Sha512,
+	iterations: 250_000,
+};
+
+/// Parameters of the key derivation by version of the backup header
+///
+/// The database version is stored in the first encrypted frame and thus only known after the
+/// keys have been derived. The header is the only frame which is not encrypted, thus its
+/// version is the only one the parameters can be selected by. Headers without version are
+/// version 0.
+const KDF_BY_HEADER_VERSION: &[(std::ops::RangeInclusive<u32>, KdfParams)] =
+	&[(0..=u32::MAX, KDF_SIGNAL)];
+
+impl KdfParams {
+	/// Returns the parameters of backups with header version `version`
+	pub fn for_header_version(version: u32) -> Self {
+		KDF_BY_HEADER_VERSION
+			.iter()
+			.find(|(versions, _)| versions.contains(&version))
+			.map(|(_, params)| *params)
+			.unwrap_or_default()
+	}
+}
+
+impl Default for KdfParams {
+	fn default() -> Self {
+		KDF_SIGNAL
+	}
+}
+
+impl std::fmt::Display for KdfParams {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{:?} with {} iterations", self.hash, self.iterations)
+	}
+}
+
+/// Implementation of AES used to decrypt
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CryptoBackend {
+	#[default]
+	Openssl,
+	/// The crates `aes` and `ctr`, which use AES-NI if the CPU supports it, only available with
+	/// the feature `rustcrypto`
+	RustCrypto,
+}
+
+impl CryptoBackend {
+	/// Returns whether this tool has been built with the backend
+	pub fn is_available(self) -> bool {
+		match self {
+			Self::Openssl => true,
+			Self::RustCrypto => cfg!(feature = "rustcrypto"),
+		}
+	}
+
+	/// Creates a cipher in CTR mode starting at `iv`
+	fn create_ctr(
+		self,
+		cipher: BackupCipher,
+		key: &[u8],
+		iv: &[u8],
+	) -> Result<Box<dyn CtrCipher>, DecryptError> {
+		match self {
+			Self::Openssl => Ok(Box::new(OpensslCtr::new(cipher, key, iv)?)),
+			#[cfg(feature = "rustcrypto")]
+			Self::RustCrypto => Ok(rustcrypto_ctr::create(cipher, key, iv)),
+			#[cfg(not(feature = "rustcrypto"))]
+			Self::RustCrypto => Err(DecryptError::DecryptionFailed {
+				error: String::from("built without the crypto backend RustCrypto"),
+				data_length: 0,
+			}),
+		}
+	}
+}
+
+/// Cipher in CTR mode which continues its key stream between calls
+trait CtrCipher: Send {
+	/// Decrypts `data`, decryption and encryption are the same in CTR mode
+	fn apply(&mut self, data: &[u8]) -> Result<Vec<u8>, DecryptError>;
+}
+
+/// AES in CTR mode by openssl
+struct OpensslCtr {
+	crypter: openssl::symm::Crypter,
+	block_size: usize,
+}
+
+impl OpensslCtr {
+	fn new(cipher: BackupCipher, key: &[u8], iv: &[u8]) -> Result<Self, DecryptError> {
+		let cipher = cipher.openssl();
+		let crypter =
+			openssl::symm::Crypter::new(cipher, openssl::symm::Mode::Decrypt, key, Some(iv))
+				.map_err(|e| DecryptError::DecryptionFailed {
+					error: e.to_string(),
+					data_length: 0,
+				})?;
+
+		Ok(Self {
+			crypter,
+			block_size: cipher.block_size(),
+		})
+	}
+}
+
+impl CtrCipher for OpensslCtr {
+	fn apply(&mut self, data: &[u8]) -> Result<Vec<u8>, DecryptError> {
+		let mut output = vec![0u8; data.len() + self.block_size];
+		let count =
+			self.crypter
+				.update(data, &mut output)
+				.map_err(|e| DecryptError::DecryptionFailed {
+					error: e.to_string(),
+					data_length: data.len(),
+				})?;
+		output.truncate(count);
+
+		Ok(output)
+	}
+}
+
+/// AES in CTR mode by the crates of RustCrypto
+///
+/// Like openssl, the whole IV is a 128 bit big endian counter.
+#[cfg(feature = "rustcrypto")]
+mod rustcrypto_ctr {
+	use ctr::cipher::{KeyIvInit, StreamCipher};
+
+	struct RustCryptoCtr<T>(T);
+
+	impl<T: StreamCipher + Send> super::CtrCipher for RustCryptoCtr<T> {
+		fn apply(&mut self, data: &[u8]) -> Result<Vec<u8>, super::DecryptError> {
+			let mut output = data.to_vec();
+			self.0.apply_keystream(&mut output);
+			Ok(output)
+		}
+	}
+
+	/// Creates the cipher, key and IV have been checked to fit the cipher
+	pub(super) fn create(
+		cipher: super::BackupCipher,
+		key: &[u8],
+		iv: &[u8],
+	) -> Box<dyn super::CtrCipher> {
+		match cipher {
+			super::BackupCipher::Aes128Ctr => Box::new(RustCryptoCtr(
+				ctr::Ctr128BE::<aes::Aes128>::new_from_slices(key, iv).unwrap(),
+			)),
+			super::BackupCipher::Aes256Ctr => Box::new(RustCryptoCtr(
+				ctr::Ctr128BE::<aes::Aes256>::new_from_slices(key, iv).unwrap(),
+			)),
+		}
+	}
+}
+
 /// Decrypt bytes
 pub struct Decrypter {
 	mac: Option<hmac::Hmac<sha2::Sha256>>,
 	mac_key: Option<Vec<u8>>,
+	cipher: BackupCipher,
+	backend: CryptoBackend,
 	key: Vec<u8>,
 	iv: Vec<u8>,
+	/// Cipher of the current IV, keeps the CTR stream position between calls of `decrypt`
+	crypter: Option<Box<dyn CtrCipher>>,
+	/// Thread verifying HMACs, if they are verified in the background
+	mac_verifier: Option<MacVerifier>,
 }
 
-impl Decrypter {
-	pub fn new(key: &[u8], salt: &[u8], iv: &[u8], verify_mac: bool) -> Self {
-		// create hash
-		let mut hash = key.to_vec();
-		let mut hasher = sha2::Sha512::new();
-		hasher.update(&salt);
+/// Message to the thread verifying HMACs in the background
+enum MacMessage {
+	/// Part of the data of the current frame the HMAC is calculated of
+	Data(Vec<u8>),
+	/// HMAC of the current frame, which ends with it
+	Mac { frame: usize, mac: Vec<u8> },
+}
+
+/// Thread verifying HMACs while the next frames are read
+struct MacVerifier {
+	sender: std::sync::mpsc::SyncSender<MacMessage>,
+	thread: std::thread::JoinHandle<Result<(), DecryptError>>,
+}
+
+/// Compares the HMAC `hmac` calculated by us to the HMAC `hmac_control` of the backup
+///
+/// `frame` is the number of the frame, if the HMAC is verified in the background.
+fn check_mac(
+	hmac: hmac::Hmac<sha2::Sha256>,
+	hmac_control: &[u8],
+	frame: Option<usize>,
+) -> Result<(), DecryptError> {
+	let result = hmac.finalize();
+	let code_bytes = &result.into_bytes()[..LENGTH_HMAC];
+
+	// compare to given hmac
+	if code_bytes.ct_eq(hmac_control).unwrap_u8() == 0 {
+		return Err(DecryptError::MacVerificationFailed {
+			frame,
+			their_mac: hmac_control.to_vec(),
+			our_mac: code_bytes.to_vec(),
+		});
+	}
+
+	Ok(())
+}
+
+/// Iterates the hash `D` over the password `key`, starting with `salt`
+fn iterate_hash<D: Digest + sha2::digest::FixedOutputReset>(
+	key: &[u8],
+	salt: &[u8],
+	iterations: u32,
+) -> Vec<u8> {
+	let mut hash = key.to_vec();
+	let mut hasher = D::new();
+	Digest::update(&mut hasher, salt);
+
+	for _ in 0..iterations {
+		Digest::update(&mut hasher, &hash);
+		Digest::update(&mut hasher, key);
+		hash = Digest::finalize_reset(&mut hasher).to_vec();
+	}
+
+	hash
+}
+
+/// Derives the keys of a backup from its password and salt with `kdf`
+///
+/// The first 32 bytes are the cipher key, the last 32 bytes the HMAC key.
+pub fn derive_keys(key: &[u8], salt: &[u8], kdf: KdfParams) -> [u8; 64] {
+	// create hash
+	let hash = match kdf.hash {
+		KdfHash::Sha256 => iterate_hash::<sha2::Sha256>(key, salt, kdf.iterations),
+		KdfHash::Sha512 => iterate_hash::<sha2::Sha512>(key, salt, kdf.iterations),
+	};
+
+	// create secrets
+	let info = b"Backup Export";
+	let mut okm = [0u8; 64];
+	let hk = hkdf::Hkdf::<sha2::Sha256>::new(None, &hash[..32]);
+	hk.expand(info, &mut okm).unwrap();
+
+	okm
+}
 
-		for _ in 0..250000 {
-			hasher.update(&hash);
-			hasher.update(key);
-			hash = hasher.finalize_reset().to_vec();
+/// Increments the counter of an IV for the next frame
+///
+/// Signal uses the first 4 bytes of the IV as big endian counter, the carry runs from byte 3
+/// to byte 0 and the counter wraps around.
+pub fn increase_counter(iv: &mut [u8]) {
+	for v in iv.iter_mut().take(4).rev() {
+		if *v < u8::MAX {
+			*v += 1;
+			break;
+		} else {
+			*v = 0;
 		}
+	}
+}
 
-		// create secrets
-		let info = b"Backup Export";
-		let mut okm = [0u8; 64];
-		let hk = hkdf::Hkdf::<sha2::Sha256>::new(None, &hash[..32]);
-		hk.expand(info, &mut okm).unwrap();
+impl Decrypter {
+	/// Creates a decrypter of a backup encrypted with `cipher`
+	///
+	/// The key of the cipher is the start of the derived keys, its length is checked against
+	/// the key and IV length openssl expects for the cipher. Keys are derived with `kdf`.
+	pub fn new(
+		key: &[u8],
+		salt: &[u8],
+		iv: &[u8],
+		verify_mac: bool,
+		cipher: BackupCipher,
+		kdf: KdfParams,
+	) -> Result<Self, DecryptError> {
+		let okm = derive_keys(key, salt, kdf);
+		let cipher_key = &okm[..cipher.key_length()];
+
+		let expected = cipher.openssl().key_len();
+		if cipher_key.len() != expected {
+			return Err(DecryptError::InvalidKeyLength {
+				cipher,
+				name: "key",
+				length: cipher_key.len(),
+				expected,
+			});
+		}
+		let expected = cipher.openssl().iv_len().unwrap_or_default();
+		if iv.len() != expected {
+			return Err(DecryptError::InvalidKeyLength {
+				cipher,
+				name: "IV",
+				length: iv.len(),
+				expected,
+			});
+		}
 
 		// create hmac and cipher
-		Self {
+		Ok(Self {
 			mac: if verify_mac {
 				Some(hmac::Hmac::<sha2::Sha256>::new_from_slice(&okm[32..]).unwrap())
 			} else {
@@ -45,54 +361,176 @@ impl Decrypter {
 			} else {
 				None
 			},
-			key: okm[..32].to_vec(),
+			cipher,
+			backend: CryptoBackend::default(),
+			key: cipher_key.to_vec(),
 			iv: iv.to_vec(),
+			crypter: None,
+			mac_verifier: None,
+		})
+	}
+
+	/// Decrypts with `backend` from the next frame on
+	pub fn set_backend(&mut self, backend: CryptoBackend) {
+		self.backend = backend;
+		self.crypter = None;
+	}
+
+	/// Verifies HMACs in a separate thread from now on
+	///
+	/// `verify_mac` then only queues the HMAC and returns a failure of an earlier frame, if
+	/// any. All queued HMACs are verified by `finish_verify`. Without HMAC verification, this
+	/// does nothing.
+	pub fn set_async_verify(&mut self) {
+		let Some(mac_key) = self.mac_key.clone() else {
+			return;
+		};
+		if self.mac_verifier.is_some() {
+			return;
+		}
+
+		let (sender, receiver) = std::sync::mpsc::sync_channel::<MacMessage>(MAC_QUEUE_LENGTH);
+		let thread = std::thread::spawn(move || {
+			let new_hmac = || hmac::Hmac::<sha2::Sha256>::new_from_slice(&mac_key).unwrap();
+			let mut hmac = new_hmac();
+			// stops at the first failure, the next send then fails and reports it
+			for message in receiver {
+				match message {
+					MacMessage::Data(data) => hmac.update(&data),
+					MacMessage::Mac { frame, mac } => {
+						check_mac(std::mem::replace(&mut hmac, new_hmac()), &mac, Some(frame))?
+					}
+				}
+			}
+			Ok(())
+		});
+		self.mac_verifier = Some(MacVerifier { sender, thread });
+	}
+
+	/// Waits until all HMACs queued for verification in the background are verified
+	///
+	/// Verification in the background stops, `set_async_verify` starts it again.
+	pub fn finish_verify(&mut self) -> Result<(), DecryptError> {
+		match self.mac_verifier.take() {
+			Some(verifier) => {
+				drop(verifier.sender);
+				verifier.thread.join().expect("HMAC verification thread panicked")
+			}
+			None => Ok(()),
 		}
 	}
 
-	pub fn decrypt(&mut self, data_encrypted: &[u8]) -> Result<Vec<u8>, DecryptError> {
-		// check hmac?
-		if let Some(ref mut hmac) = self.mac {
-			// calculate hmac of frame data
-			hmac.update(&data_encrypted);
+	/// Sends `message` to the HMAC verification in the background
+	///
+	/// The thread only stops early after a failure, which is returned then.
+	fn send_mac(&mut self, message: MacMessage) -> Result<(), DecryptError> {
+		if let Some(verifier) = &self.mac_verifier
+			&& verifier.sender.send(message).is_err()
+		{
+			return self.finish_verify();
 		}
 
-		// decrypt
-		openssl::symm::decrypt(
-			openssl::symm::Cipher::aes_256_ctr(),
-			&self.key,
-			Some(&self.iv),
-			data_encrypted,
-		)
-		.map_err(|e| DecryptError::DecryptionFailed { 
-			error: e.to_string(),
-			data_length: data_encrypted.len()
-		})
+		Ok(())
 	}
 
-	pub fn mac_update_with_iv(&mut self) {
-		if let Some(ref mut hmac) = self.mac {
-			hmac.update(&self.iv);
+	/// Updates the HMAC of the current frame with `data`
+	fn mac_update(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+		if self.mac_verifier.is_some() {
+			for chunk in data.chunks(MAC_CHUNK_SIZE) {
+				self.send_mac(MacMessage::Data(chunk.to_vec()))?;
+			}
+		} else if let Some(ref mut hmac) = self.mac {
+			hmac.update(data);
 		}
+
+		Ok(())
+	}
+
+	/// Decrypts data and updates the HMAC with it
+	///
+	/// Consecutive calls continue the CTR stream of the current IV, i.e. decrypting data in parts
+	/// gives the same result as decrypting it at once. The stream restarts with `increase_iv`.
+	pub fn decrypt(&mut self, data_encrypted: &[u8]) -> Result<Vec<u8>, DecryptError> {
+		self.mac_update(data_encrypted)?;
+
+		// decrypt
+		if self.crypter.is_none() {
+			self.crypter = Some(self.backend.create_ctr(self.cipher, &self.key, &self.iv)?);
+		}
+		self.crypter.as_mut().unwrap().apply(data_encrypted)
+	}
+
+	/// Decrypts the start of the next frame, e.g. its length, to look at it before reading it
+	///
+	/// Neither the HMAC nor the CTR stream are changed, thus the same bytes are decrypted again
+	/// with the frame by `decrypt_frame`.
+	pub fn decrypt_preview(&self, data_encrypted: &[u8]) -> Result<Vec<u8>, DecryptError> {
+		self.backend
+			.create_ctr(self.cipher, &self.key, &self.iv)?
+			.apply(data_encrypted)
 	}
 
-	pub fn verify_mac(&mut self, hmac_control: &[u8]) -> Result<(), DecryptError> {
+	/// Decrypts the length of the next frame from its 4 encrypted bytes, see `decrypt_preview`
+	pub fn decrypt_frame_length(&self, encrypted_len_bytes: &[u8; 4]) -> Result<u32, DecryptError> {
+		let decrypted = self.decrypt_preview(encrypted_len_bytes)?;
+
+		Ok(u32::from_be_bytes([
+			decrypted[0],
+			decrypted[1],
+			decrypted[2],
+			decrypted[3],
+		]))
+	}
+
+	/// Decrypts a frame and verifies its HMAC
+	///
+	/// `encrypted_len_bytes` are the 4 encrypted bytes of the frame length, `encrypted_data` the
+	/// frame of this length, ending with its HMAC. Length and frame are one CTR stream and both
+	/// are covered by the HMAC. Afterwards, the IV is increased for the next frame. `frame` is the
+	/// number of the frame, see `verify_mac`.
+	pub fn decrypt_frame(
+		&mut self,
+		encrypted_len_bytes: &[u8; 4],
+		encrypted_data: &[u8],
+		frame: usize,
+	) -> Result<Vec<u8>, DecryptError> {
+		let data_len = encrypted_data
+			.len()
+			.checked_sub(LENGTH_HMAC)
+			.ok_or(DecryptError::FrameTooShort {
+				length: encrypted_data.len(),
+			})?;
+		let (data_encrypted, hmac) = encrypted_data.split_at(data_len);
+
+		// the decrypted length is already known, but it advances the stream and the HMAC
+		self.decrypt(encrypted_len_bytes)?;
+		let data = self.decrypt(data_encrypted)?;
+		self.verify_mac(hmac, frame)?;
+		self.increase_iv();
+
+		Ok(data)
+	}
+
+	pub fn mac_update_with_iv(&mut self) -> Result<(), DecryptError> {
+		let iv = self.iv.clone();
+		self.mac_update(&iv)
+	}
+
+	/// Verifies the HMAC of the data decrypted since the last verification
+	///
+	/// `frame` is the number of the frame, which is reported if the HMAC is verified in the
+	/// background and fails.
+	pub fn verify_mac(&mut self, hmac_control: &[u8], frame: usize) -> Result<(), DecryptError> {
+		if self.mac_verifier.is_some() {
+			return self.send_mac(MacMessage::Mac {
+				frame,
+				mac: hmac_control.to_vec(),
+			});
+		}
+
 		if let Some(ref mut hmac) = self.mac {
-			// Clone the HMAC, finalize it, and compare
-			let hmac_clone = hmac.clone();
-			let result = hmac_clone.finalize();
-			let code_bytes = &result.into_bytes()[..LENGTH_HMAC];
-
-			// compare to given hmac
-			let cmp_result = code_bytes.ct_eq(&hmac_control);
-
-			if cmp_result.unwrap_u8() == 0 {
-				return Err(DecryptError::MacVerificationFailed {
-					their_mac: hmac_control.to_vec(),
-					our_mac: code_bytes.to_vec(),
-				});
-			}
-			
+			check_mac(hmac.clone(), hmac_control, None)?;
+
 			// Reset the HMAC by creating a new one
 			if let Some(ref mac_key) = self.mac_key {
 				*hmac = hmac::Hmac::<sha2::Sha256>::new_from_slice(mac_key).unwrap();
@@ -102,30 +540,28 @@ impl Decrypter {
 		Ok(())
 	}
 
-	// TODO what is happening here?
+	/// Increments the counter of the IV for the next frame
 	pub fn increase_iv(&mut self) {
-		for v in self.iv.iter_mut().take(4).rev() {
-			if *v < std::u8::MAX {
-				*v += 1;
-				break;
-			} else {
-				*v = 0;
-			}
-		}
-	}
-	
-	pub fn get_key(&self) -> &[u8] {
-		&self.key
+		self.crypter = None;
+		increase_counter(&mut self.iv);
 	}
 	
 	pub fn get_iv(&self) -> &[u8] {
 		&self.iv
 	}
+
+	/// Sets the IV of the next frame, e.g. to continue reading at another frame
+	pub fn set_iv(&mut self, iv: &[u8]) {
+		self.crypter = None;
+		self.iv = iv.to_vec();
+	}
 }
 
 #[derive(Debug)]
 pub enum DecryptError {
 	MacVerificationFailed {
+		/// Number of the frame, if verified in the background
+		frame: Option<usize>,
 		their_mac: Vec<u8>,
 		our_mac: Vec<u8>,
 	},
@@ -133,6 +569,17 @@ pub enum DecryptError {
 		error: String,
 		data_length: usize,
 	},
+	/// The frame is shorter than its HMAC
+	FrameTooShort {
+		length: usize,
+	},
+	/// The key or IV does not fit the cipher
+	InvalidKeyLength {
+		cipher: BackupCipher,
+		name: &'static str,
+		length: usize,
+		expected: usize,
+	},
 }
 
 impl std::error::Error for DecryptError {}
@@ -140,16 +587,44 @@ impl std::error::Error for DecryptError {}
 impl std::fmt::Display for DecryptError {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match self {
-			Self::MacVerificationFailed { their_mac, our_mac } => write!(
+			Self::MacVerificationFailed {
+				frame: None,
+				their_mac,
+				our_mac,
+			} => write!(
 				f,
 				"HMAC verification failed (their mac: {:02X?}, our mac: {:02X?})",
 				their_mac, our_mac
 			),
+			Self::MacVerificationFailed {
+				frame: Some(frame),
+				their_mac,
+				our_mac,
+			} => write!(
+				f,
+				"HMAC verification of frame {} failed (their mac: {:02X?}, our mac: {:02X?})",
+				frame, their_mac, our_mac
+			),
 			Self::DecryptionFailed { error, data_length } => write!(
 				f,
 				"Decryption failed for data of length {} bytes: {}. This may indicate an incorrect password or corrupted backup file.",
 				data_length, error
 			),
+			Self::FrameTooShort { length } => write!(
+				f,
+				"Frame length {} is too small to contain HMAC",
+				length
+			),
+			Self::InvalidKeyLength {
+				cipher,
+				name,
+				length,
+				expected,
+			} => write!(
+				f,
+				"{} has a length of {} bytes, but {:?} needs {} bytes",
+				name, length, cipher, expected
+			),
 		}
 	}
 }
@@ -167,8 +642,12 @@ mod tests {
 		let mut dec = Decrypter {
 			mac: None,
 			mac_key: None,
+			cipher: BackupCipher::default(),
+			backend: CryptoBackend::default(),
 			key: key.to_vec(),
 			iv: iv.to_vec(),
+			crypter: None,
+			mac_verifier: None,
 		};
 		dec.increase_iv();
 
@@ -181,8 +660,12 @@ mod tests {
 		let mut dec = Decrypter {
 			mac: None,
 			mac_key: None,
+			cipher: BackupCipher::default(),
+			backend: CryptoBackend::default(),
 			key: key.to_vec(),
 			iv: iv.to_vec(),
+			crypter: None,
+			mac_verifier: None,
 		};
 		dec.increase_iv();
 
@@ -191,4 +674,278 @@ mod tests {
 		iv[1] = 1;
 		assert_eq!(dec.iv, iv);
 	}
+
+	#[test]
+	fn increase_iv_carry() {
+		let mut dec = Decrypter {
+			mac: None,
+			mac_key: None,
+			cipher: BackupCipher::default(),
+			backend: CryptoBackend::default(),
+			key: vec![0; 32],
+			iv: vec![0xAA; 16],
+			crypter: None,
+			mac_verifier: None,
+		};
+
+		// the counter is the big endian integer of the first 4 bytes
+		let mut counter = 0u32;
+		dec.iv[..4].copy_from_slice(&counter.to_be_bytes());
+		for _ in 0..70000 {
+			dec.increase_iv();
+			counter += 1;
+			assert_eq!(dec.iv[..4], counter.to_be_bytes());
+		}
+
+		// carry across 256 and 65536, the remaining bytes are never touched
+		for (before, after) in [
+			(0x000000FFu32, 0x00000100u32),
+			(0x0000FFFF, 0x00010000),
+			(0x00FFFFFF, 0x01000000),
+		] {
+			dec.iv[..4].copy_from_slice(&before.to_be_bytes());
+			dec.increase_iv();
+			assert_eq!(dec.iv[..4], after.to_be_bytes());
+			assert_eq!(dec.iv[4..], [0xAA; 12]);
+		}
+
+		// the counter wraps around
+		dec.iv[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+		dec.increase_iv();
+		assert_eq!(dec.iv[..4], [0; 4]);
+		assert_eq!(dec.iv[4..], [0xAA; 12]);
+	}
+
+	#[test]
+	fn decrypt_in_parts() {
+		let data = (0..100).collect::<Vec<u8>>();
+		let mut dec = Decrypter {
+			mac: None,
+			mac_key: None,
+			cipher: BackupCipher::default(),
+			backend: CryptoBackend::default(),
+			key: vec![1; 32],
+			iv: vec![2; 16],
+			crypter: None,
+			mac_verifier: None,
+		};
+		let all = dec.decrypt(&data).unwrap();
+
+		// length prefix and frame data are decrypted separately
+		dec.increase_iv();
+		dec.iv = vec![2; 16];
+		let mut parts = dec.decrypt(&data[..4]).unwrap();
+		parts.extend(dec.decrypt(&data[4..]).unwrap());
+		assert_eq!(all, parts);
+	}
+
+	#[test]
+	fn verify_mac_async() {
+		let password = b"123456789012345678901234567890";
+		let (salt, iv) = ([1; 32], [2; 16]);
+		let mut enc = crate::encrypter::Encrypter::new(password, &salt, &iv);
+		let mut dec = Decrypter::new(
+			password,
+			&salt,
+			&iv,
+			true,
+			BackupCipher::default(),
+			KdfParams::default(),
+		)
+		.unwrap();
+		dec.set_async_verify();
+
+		let mut results = Vec::new();
+		for frame in 1..=3 {
+			let data_encrypted = enc.encrypt(b"frame data").unwrap();
+			let mut mac = enc.finalize_mac();
+			enc.increase_iv();
+			// the HMAC of the second frame is damaged
+			if frame == 2 {
+				mac[0] ^= 1;
+			}
+
+			results.push(
+				dec.decrypt(&data_encrypted)
+					.and_then(|_| dec.verify_mac(&mac, frame)),
+			);
+			dec.increase_iv();
+		}
+		results.push(dec.finish_verify());
+
+		// the failure is reported once by a later call, with the number of the damaged frame
+		let errors: Vec<_> = results.into_iter().filter_map(Result::err).collect();
+		assert!(matches!(
+			errors[..],
+			[DecryptError::MacVerificationFailed { frame: Some(2), .. }]
+		));
+	}
+
+	/// Frames larger than `MAC_CHUNK_SIZE` are sent to the verification in parts
+	#[test]
+	fn verify_mac_async_chunks() {
+		let password = b"123456789012345678901234567890";
+		let (salt, iv) = ([1; 32], [2; 16]);
+		let mut enc = crate::encrypter::Encrypter::new(password, &salt, &iv);
+		let mut dec = Decrypter::new(
+			password,
+			&salt,
+			&iv,
+			true,
+			BackupCipher::default(),
+			KdfParams::default(),
+		)
+		.unwrap();
+		dec.set_async_verify();
+
+		let data = vec![7; 3 * MAC_CHUNK_SIZE + 5];
+		for frame in 1..=2 {
+			let data_encrypted = enc.encrypt(&data).unwrap();
+			let mac = enc.finalize_mac();
+			enc.increase_iv();
+
+			// the data is decrypted in parts of another size than the chunks
+			for part in data_encrypted.chunks(MAC_CHUNK_SIZE / 2 + 1) {
+				dec.decrypt(part).unwrap();
+			}
+			dec.verify_mac(&mac, frame).unwrap();
+			dec.increase_iv();
+		}
+		dec.finish_verify().unwrap();
+	}
+
+	#[test]
+	fn decrypt_frame() {
+		let password = b"123456789012345678901234567890";
+		let (salt, iv) = ([1; 32], [2; 16]);
+		let data = b"frame data".to_vec();
+
+		// length and frame are encrypted as one stream
+		let mut enc = crate::encrypter::Encrypter::new(password, &salt, &iv);
+		let length = (data.len() + LENGTH_HMAC) as u32;
+		let mut plain = length.to_be_bytes().to_vec();
+		plain.extend(&data);
+		let encrypted = enc.encrypt(&plain).unwrap();
+		let mut frame = encrypted[4..].to_vec();
+		frame.extend(enc.finalize_mac());
+		let len_bytes: [u8; 4] = encrypted[..4].try_into().unwrap();
+
+		let mut dec = Decrypter::new(
+			password,
+			&salt,
+			&iv,
+			true,
+			BackupCipher::default(),
+			KdfParams::default(),
+		)
+		.unwrap();
+		assert_eq!(dec.decrypt_frame_length(&len_bytes).unwrap(), length);
+		assert_eq!(dec.decrypt_frame(&len_bytes, &frame, 1).unwrap(), data);
+		let mut iv_next = iv;
+		increase_counter(&mut iv_next);
+		assert_eq!(dec.get_iv(), iv_next);
+	}
+
+	#[test]
+	fn kdf_params() {
+		assert_eq!(KdfParams::for_header_version(0), KdfParams::default());
+		assert_eq!(KdfParams::for_header_version(1), KdfParams::default());
+
+		let kdf = |hash, iterations| {
+			derive_keys(
+				b"123456789012345678901234567890",
+				&[1; 32],
+				KdfParams { hash, iterations },
+			)
+		};
+		assert_ne!(kdf(KdfHash::Sha512, 1), kdf(KdfHash::Sha512, 2));
+		assert_ne!(kdf(KdfHash::Sha512, 1), kdf(KdfHash::Sha256, 1));
+	}
+
+	#[test]
+	fn new_with_key_length() {
+		let password = b"123456789012345678901234567890";
+		let (salt, iv) = ([1; 32], [2; 16]);
+		let data = b"frame data".to_vec();
+
+		for (cipher, openssl_cipher) in [
+			(BackupCipher::Aes128Ctr, openssl::symm::Cipher::aes_128_ctr()),
+			(BackupCipher::Aes256Ctr, openssl::symm::Cipher::aes_256_ctr()),
+		] {
+			let mut dec =
+				Decrypter::new(password, &salt, &iv, false, cipher, KdfParams::default()).unwrap();
+			assert_eq!(dec.key.len(), cipher.key_length());
+
+			// the key is the start of the derived keys
+			let key = &derive_keys(password, &salt, KdfParams::default())[..cipher.key_length()];
+			let encrypted = openssl::symm::encrypt(openssl_cipher, key, Some(&iv), &data).unwrap();
+			assert_eq!(dec.decrypt(&encrypted).unwrap(), data);
+
+			// the IV must fit the cipher
+			assert!(matches!(
+				Decrypter::new(
+					password,
+					&salt,
+					&iv[..12],
+					false,
+					cipher,
+					KdfParams::default()
+				),
+				Err(DecryptError::InvalidKeyLength { name: "IV", .. })
+			));
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "rustcrypto")]
+	fn backends_identical() {
+		let password = b"123456789012345678901234567890";
+		let salt = [1; 32];
+		// the counter runs over the lower 64 bits within the data
+		let mut iv = [2; 16];
+		iv[8..].copy_from_slice(&[0xFF; 8]);
+		let data = (0..100).collect::<Vec<u8>>();
+
+		// frames encrypted by openssl are decrypted identically by all backends, also in parts
+		let mut enc = crate::encrypter::Encrypter::new(password, &salt, &iv);
+		let mut frames = Vec::new();
+		for _ in 0..3 {
+			let encrypted = enc.encrypt(&data).unwrap();
+			let mac = enc.finalize_mac();
+			enc.increase_iv();
+			frames.push((encrypted, mac));
+		}
+
+		for backend in [CryptoBackend::Openssl, CryptoBackend::RustCrypto] {
+			let mut dec = Decrypter::new(
+				password,
+				&salt,
+				&iv,
+				true,
+				BackupCipher::default(),
+				KdfParams::default(),
+			)
+			.unwrap();
+			dec.set_backend(backend);
+			for (frame, (encrypted, mac)) in frames.iter().enumerate() {
+				let mut decrypted = dec.decrypt(&encrypted[..4]).unwrap();
+				decrypted.extend(dec.decrypt(&encrypted[4..]).unwrap());
+				assert_eq!(decrypted, data, "{:?}", backend);
+				dec.verify_mac(mac, frame + 1).unwrap();
+				dec.increase_iv();
+			}
+		}
+
+		// both key lengths
+		for cipher in [BackupCipher::Aes128Ctr, BackupCipher::Aes256Ctr] {
+			let decrypted = [CryptoBackend::Openssl, CryptoBackend::RustCrypto].map(|backend| {
+				let mut dec =
+					Decrypter::new(password, &salt, &iv, false, cipher, KdfParams::default())
+						.unwrap();
+				dec.set_backend(backend);
+				dec.decrypt(&data).unwrap()
+			});
+			assert_eq!(decrypted[0], decrypted[1], "{:?}", cipher);
+		}
+	}
 }
@@ -1,13 +1,96 @@
+use std::io::Write;
+
+/// Minimum time between two updates of the status line
+const STATUS_LINE_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
 /// Dispaly a progress bar
 #[derive(Clone)]
 pub struct Progress {
+	// keeps the multi progress draw target alive as long as the bars exist
+	#[allow(dead_code)]
 	bar_multi: std::sync::Arc<indicatif::MultiProgress>,
 	bar_bytes: Option<indicatif::ProgressBar>,
 	bar_frames: Option<indicatif::ProgressBar>,
+	status_line: Option<std::sync::Arc<std::sync::Mutex<StatusLine>>>,
+	/// Is the length of the frame bar the total number of frames?
+	frames_total_known: bool,
+}
+
+/// Single line on stderr showing the progress, rewritten in place instead of the progress bars
+struct StatusLine {
+	start: std::time::Instant,
+	last_draw: Option<std::time::Instant>,
+	bytes_total: u64,
+	bytes_read: u64,
+	frames_written: u64,
+	frames_total: Option<u64>,
+	finished: bool,
+}
+
+impl StatusLine {
+	/// Rewrites the line, at most every `STATUS_LINE_INTERVAL` unless `force` is set
+	fn draw(&mut self, force: bool) {
+		if self.finished
+			|| (!force && self.last_draw.is_some_and(|x| x.elapsed() < STATUS_LINE_INTERVAL))
+		{
+			return;
+		}
+		self.last_draw = Some(std::time::Instant::now());
+
+		let elapsed = self.start.elapsed();
+		// the remaining time is estimated from the bytes read so far, if the size is known
+		let eta = if self.bytes_total > 0 && self.bytes_read > 0 {
+			let remaining = self.bytes_total.saturating_sub(self.bytes_read);
+			format_duration(elapsed.mul_f64(remaining as f64 / self.bytes_read as f64))
+		} else {
+			String::from("--:--:--")
+		};
+		let mib = |x: u64| x as f64 / (1024.0 * 1024.0);
+
+		let mut stderr = std::io::stderr().lock();
+		let _ = write!(
+			stderr,
+			"\rframes: {} | MiB: {:.1}/{:.1} | {} | ETA {}\x1b[K",
+			match self.frames_total {
+				Some(x) => format!("{}/{}", self.frames_written, x),
+				None => self.frames_written.to_string(),
+			},
+			mib(self.bytes_read),
+			mib(self.bytes_total),
+			format_duration(elapsed),
+			eta
+		);
+		let _ = stderr.flush();
+	}
+
+	/// Draws the line a last time and ends it
+	fn finish(&mut self) {
+		if !self.finished {
+			self.draw(true);
+			self.finished = true;
+			eprintln!();
+		}
+	}
+}
+
+/// Formats a duration as hours, minutes and seconds
+fn format_duration(duration: std::time::Duration) -> String {
+	let seconds = duration.as_secs();
+	format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
 }
 
 impl Progress {
-	pub fn new(bytes_to_read: u64, frames_to_read: u64, hidden: bool) -> Self {
+	/// Creates the progress bars, or a status line with `status_line`
+	///
+	/// If the total number of frames is known, written frames are shown out of `frames_total`
+	/// instead of the frames read so far.
+	pub fn new(
+		bytes_to_read: u64,
+		frames_to_read: u64,
+		hidden: bool,
+		status_line: bool,
+		frames_total: Option<u64>,
+	) -> Self {
 		let sty_bytes = indicatif::ProgressStyle::default_bar()
                     .template("             Bytes read: [{elapsed_precise}] [{bar:50.blue/blue}] {bytes}/{total_bytes}").unwrap()
                     .progress_chars("#>-");
@@ -19,13 +102,15 @@ impl Progress {
 		let bar_bytes;
 		let bar_frames;
 
-		if hidden {
+		if hidden || status_line {
 			bar_bytes = None;
 			bar_frames = None;
 		} else {
 			bar_bytes = Some(bar_multi.add(indicatif::ProgressBar::new(bytes_to_read)));
 			bar_bytes.as_ref().unwrap().set_style(sty_bytes);
-			bar_frames = Some(bar_multi.add(indicatif::ProgressBar::new(frames_to_read)));
+			bar_frames = Some(bar_multi.add(indicatif::ProgressBar::new(
+				frames_total.unwrap_or(frames_to_read),
+			)));
 			bar_frames.as_ref().unwrap().set_style(sty_frames);
 		}
 
@@ -33,10 +118,34 @@ impl Progress {
 			bar_multi: std::sync::Arc::new(bar_multi),
 			bar_bytes,
 			bar_frames,
+			frames_total_known: frames_total.is_some(),
+			status_line: (status_line && !hidden).then(|| {
+				std::sync::Arc::new(std::sync::Mutex::new(StatusLine {
+					start: std::time::Instant::now(),
+					last_draw: None,
+					bytes_total: bytes_to_read,
+					bytes_read: 0,
+					frames_written: 0,
+					frames_total,
+					finished: false,
+				}))
+			}),
+		}
+	}
+
+	/// Updates the status line, if shown
+	fn update_status_line(&self, update: impl FnOnce(&mut StatusLine)) {
+		if let Some(ref x) = self.status_line {
+			let mut status_line = x.lock().unwrap();
+			update(&mut status_line);
+			status_line.draw(false);
 		}
 	}
 
 	pub fn set_read_frames(&self, length: u64) {
+		if self.frames_total_known {
+			return;
+		}
 		if let Some(ref x) = self.bar_frames {
 			x.set_length(length)
 		};
@@ -46,18 +155,24 @@ impl Progress {
 		if let Some(ref x) = self.bar_frames {
 			x.set_position(length)
 		};
+		self.update_status_line(|x| x.frames_written = length);
 	}
 
 	pub fn set_read_bytes(&self, length: u64) {
 		if let Some(ref x) = self.bar_bytes {
 			x.set_position(length)
 		};
+		self.update_status_line(|x| x.bytes_read = length);
 	}
 
 	pub fn finish_frames(&self) {
 		if let Some(ref x) = self.bar_frames {
 			x.finish_with_message("done")
 		};
+		// frames are written after they are read, thus the line ends with them
+		if let Some(ref x) = self.status_line {
+			x.lock().unwrap().finish();
+		}
 	}
 
 	pub fn finish_bytes(&self) {
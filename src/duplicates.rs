@@ -0,0 +1,100 @@
+use log::{info, warn};
+use std::hash::{Hash, Hasher};
+
+/// Detects frames which appear more than once in a backup
+///
+/// A backup contains every attachment and every inserted row only once. Repeated frames are a
+/// strong sign that decoding went wrong, e.g. that the IV chain is out of sync, and the number
+/// of the first repeated frame shows where.
+pub struct DuplicateDetector {
+	/// Frame number of every attachment by row and attachment id
+	attachments: std::collections::HashMap<(u64, u64), usize>,
+	/// Frame number of every statement with parameters by hash of statement and parameters
+	statements: std::collections::HashMap<u64, usize>,
+	/// Frame numbers of all duplicates found
+	duplicates: Vec<usize>,
+}
+
+impl DuplicateDetector {
+	pub fn new() -> Self {
+		Self {
+			attachments: std::collections::HashMap::new(),
+			statements: std::collections::HashMap::new(),
+			duplicates: Vec::new(),
+		}
+	}
+
+	/// Checks whether `frame`, the frame with number `number`, has been seen before
+	pub fn check(&mut self, frame: &crate::frame::Frame, number: usize) {
+		let first = match frame {
+			crate::frame::Frame::Attachment { id, row, .. } => {
+				match self.attachments.insert((*row, *id), number) {
+					Some(first) => {
+						warn!(
+							"Frame {} repeats attachment {} (row {}) of frame {}",
+							number, id, row, first
+						);
+						first
+					}
+					None => return,
+				}
+			}
+			// statements without parameters, e.g. to create tables, carry no rows
+			crate::frame::Frame::Statement {
+				statement,
+				parameter,
+			} if !parameter.is_empty() => {
+				match self
+					.statements
+					.insert(hash_statement(statement, parameter), number)
+				{
+					Some(first) => {
+						warn!("Frame {} repeats the statement of frame {}", number, first);
+						first
+					}
+					None => return,
+				}
+			}
+			_ => return,
+		};
+
+		if self.duplicates.is_empty() {
+			warn!(
+				"Decoding may have gone wrong from frame {} on (first seen in frame {})",
+				number, first
+			);
+		}
+		self.duplicates.push(number);
+	}
+
+	/// Logs a summary of all duplicates found
+	pub fn finish(&self) {
+		match (self.duplicates.first(), self.duplicates.last()) {
+			(Some(first), Some(last)) => warn!(
+				"Found {} duplicate frames between frame {} and frame {}",
+				self.duplicates.len(),
+				first,
+				last
+			),
+			_ => info!("No duplicate frames found"),
+		}
+	}
+}
+
+/// Hashes a statement together with its parameters
+fn hash_statement(statement: &str, parameters: &[rusqlite::types::Value]) -> u64 {
+	let mut hasher = std::collections::hash_map::DefaultHasher::new();
+	statement.hash(&mut hasher);
+	for parameter in parameters {
+		// the discriminant distinguishes e.g. the integer 1 from the text "1"
+		std::mem::discriminant(parameter).hash(&mut hasher);
+		match parameter {
+			rusqlite::types::Value::Null => (),
+			rusqlite::types::Value::Integer(x) => x.hash(&mut hasher),
+			rusqlite::types::Value::Real(x) => x.to_bits().hash(&mut hasher),
+			rusqlite::types::Value::Text(x) => x.hash(&mut hasher),
+			rusqlite::types::Value::Blob(x) => x.hash(&mut hasher),
+		}
+	}
+	hasher.finish()
+}
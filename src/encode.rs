@@ -0,0 +1,491 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use log::{info, warn};
+use protobuf::Message;
+use std::io::Write;
+
+/// Writes frames of an encrypted backup file
+struct BackupWriter {
+	writer: std::io::BufWriter<std::fs::File>,
+	encrypter: crate::encrypter::Encrypter,
+}
+
+impl BackupWriter {
+	/// Creates the backup file and writes its header with random salt and IV
+	fn new(path: &std::path::Path, password: &[u8]) -> Result<Self, anyhow::Error> {
+		let mut salt = [0u8; 32];
+		let mut iv = [0u8; 16];
+		openssl::rand::rand_bytes(&mut salt).context("Failed to create salt")?;
+		openssl::rand::rand_bytes(&mut iv).context("Failed to create IV")?;
+
+		let mut writer = std::io::BufWriter::new(
+			std::fs::File::create(path)
+				.with_context(|| format!("Failed to open file: {}", path.to_string_lossy()))?,
+		);
+
+		// the header is the only frame which is not encrypted
+		let mut header = crate::Backups::Header::new();
+		header.salt = Some(salt.to_vec());
+		header.iv = Some(iv.to_vec());
+		let mut frame = crate::Backups::BackupFrame::new();
+		frame.header = protobuf::MessageField::some(header);
+		let data = frame.write_to_bytes()?;
+		writer.write_all(&u32::try_from(data.len())?.to_be_bytes())?;
+		writer.write_all(&data)?;
+
+		Ok(Self {
+			writer,
+			encrypter: crate::encrypter::Encrypter::new(password, &salt, &iv),
+		})
+	}
+
+	/// Writes an encrypted frame
+	///
+	/// The frame length is encrypted together with the frame and includes the HMAC.
+	fn write_frame(&mut self, frame: &crate::Backups::BackupFrame) -> Result<(), anyhow::Error> {
+		let data = frame.write_to_bytes()?;
+		let length = u32::try_from(data.len() + crate::decrypter::LENGTH_HMAC)?;
+
+		let mut plain = length.to_be_bytes().to_vec();
+		plain.extend_from_slice(&data);
+		let data_encrypted = self.encrypter.encrypt(&plain)?;
+
+		self.writer.write_all(&data_encrypted)?;
+		self.writer.write_all(&self.encrypter.finalize_mac())?;
+		self.encrypter.increase_iv();
+
+		Ok(())
+	}
+
+	/// Writes the data of the preceding attachment, avatar or sticker frame
+	fn write_data(&mut self, data: &[u8]) -> Result<(), anyhow::Error> {
+		self.encrypter.mac_update_with_iv();
+		let data_encrypted = self.encrypter.encrypt(data)?;
+
+		self.writer.write_all(&data_encrypted)?;
+		self.writer.write_all(&self.encrypter.finalize_mac())?;
+		self.encrypter.increase_iv();
+
+		Ok(())
+	}
+
+	fn write_statement(
+		&mut self,
+		statement: &str,
+		parameters: &[rusqlite::types::Value],
+	) -> Result<(), anyhow::Error> {
+		let mut sql = crate::Backups::SqlStatement::new();
+		sql.statement = Some(statement.to_string());
+		for value in parameters {
+			let mut parameter = crate::Backups::sql_statement::SqlParameter::new();
+			match value {
+				rusqlite::types::Value::Null => parameter.nullparameter = Some(true),
+				rusqlite::types::Value::Integer(x) => parameter.integerParameter = Some(*x as u64),
+				rusqlite::types::Value::Real(x) => parameter.doubleParameter = Some(*x),
+				rusqlite::types::Value::Text(x) => parameter.stringParamter = Some(x.clone()),
+				rusqlite::types::Value::Blob(x) => parameter.blobParameter = Some(x.clone()),
+			}
+			sql.parameters.push(parameter);
+		}
+
+		let mut frame = crate::Backups::BackupFrame::new();
+		frame.statement = protobuf::MessageField::some(sql);
+		self.write_frame(&frame)
+	}
+
+	/// Writes the end frame and flushes the file
+	fn finish(mut self) -> Result<(), anyhow::Error> {
+		let mut frame = crate::Backups::BackupFrame::new();
+		frame.end = Some(true);
+		self.write_frame(&frame)?;
+
+		self.writer.flush()?;
+		Ok(())
+	}
+}
+
+/// Returns all files of a directory of the decoded backup, sorted by name
+///
+/// Returns no files if the directory does not exist.
+fn read_files(path: &std::path::Path) -> Result<Vec<std::path::PathBuf>, anyhow::Error> {
+	if !path.is_dir() {
+		return Ok(Vec::new());
+	}
+
+	let mut files = Vec::new();
+	for entry in std::fs::read_dir(path)
+		.with_context(|| format!("Failed to read directory: {}", path.to_string_lossy()))?
+	{
+		let path = entry?.path();
+		if path.is_file() {
+			files.push(path);
+		}
+	}
+	files.sort();
+
+	Ok(files)
+}
+
+/// Returns a frame announcing the data of an attachment
+fn attachment_frame(attachment_id: u64, row_id: u64, length: u32) -> crate::Backups::BackupFrame {
+	let mut attachment = crate::Backups::Attachment::new();
+	attachment.attachmentId = Some(attachment_id);
+	attachment.rowId = Some(row_id);
+	attachment.length = Some(length);
+	let mut frame = crate::Backups::BackupFrame::new();
+	frame.attachment = protobuf::MessageField::some(attachment);
+	frame
+}
+
+/// Splits the name of a decoded file without extension at the last `_`
+///
+/// Attachments are named `{attachment id}_{row id}`, stickers `{row id}_{count}` and avatars
+/// `{name}_{count}`.
+fn split_file_name(path: &std::path::Path) -> Option<(String, String)> {
+	let stem = path.file_stem()?.to_str()?;
+	let (first, second) = stem.rsplit_once('_')?;
+	Some((first.to_string(), second.to_string()))
+}
+
+/// Encodes a backup decoded with output type RAW into a backup file
+///
+/// Tables, indexes, preferences, attachments, avatars and stickers are written. Attachments are
+/// read from `attachment` or, if decoded with `--attachments-in-db`, from the attachment database.
+/// Key values are only exported by the decoder without their type and thus missing in the backup.
+/// Files which cannot be assigned to their database rows, e.g. attachments named sequentially, are
+/// skipped with a warning. Media written to other directories with `--attachments-dir`,
+/// `--avatars-dir` or `--stickers-dir` has to be moved back first.
+pub fn encode(
+	path_input: &std::path::Path,
+	path_output: &std::path::Path,
+	password: &[u8],
+	force_write: bool,
+) -> Result<(), anyhow::Error> {
+	info!("Input path: {}", path_input.to_string_lossy());
+	info!("Output file: {}", path_output.to_string_lossy());
+
+	let path_database = path_input.join("signal_backup.db");
+	if !path_database.is_file() {
+		return Err(anyhow!(
+			"Database not found: {}. Is the input a backup decoded with output type RAW?",
+			path_database.to_string_lossy()
+		));
+	}
+	if path_output.exists() && !force_write {
+		return Err(anyhow!(
+			"Backup file does already exist: {}. Try -f",
+			path_output.to_string_lossy()
+		));
+	}
+
+	let connection = rusqlite::Connection::open_with_flags(
+		&path_database,
+		rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+	)
+	.with_context(|| {
+		format!(
+			"could not open connection to database file: {}",
+			path_database.to_string_lossy()
+		)
+	})?;
+
+	let mut writer = BackupWriter::new(path_output, password)?;
+
+	// version
+	let version: u32 = connection
+		.pragma_query_value(None, "user_version", |row| row.get(0))
+		.context("failed to read database version")?;
+	if version == 0 {
+		warn!("Database version is unknown, the database was probably decoded by an older version of this tool");
+	}
+	let mut frame = crate::Backups::BackupFrame::new();
+	let mut database_version = crate::Backups::DatabaseVersion::new();
+	database_version.version = Some(version);
+	frame.version = protobuf::MessageField::some(database_version);
+	writer.write_frame(&frame)?;
+
+	// tables and their rows, virtual tables (e.g. full text search) and tables added while
+	// decoding are not part of backups
+	let schema = crate::database::query_rows(
+		&connection,
+		"SELECT * FROM sqlite_master WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%' ORDER BY rowid",
+		[],
+	)?;
+	let mut count_row = 0;
+	for entry in &schema {
+		let (Some(name), Some(sql)) = (entry.get_string("name"), entry.get_string("sql")) else {
+			continue;
+		};
+		if entry.get_string("type").as_deref() != Some("table")
+			|| sql.starts_with("CREATE VIRTUAL TABLE")
+			|| name.starts_with(crate::database::TABLE_FTS)
+			|| name == crate::database::TABLE_DECODE_METADATA
+		{
+			continue;
+		}
+
+		writer.write_statement(&sql, &[])?;
+
+		let table = crate::database::quote_identifier(&name);
+		let mut stmt = connection.prepare(&format!("SELECT * FROM {}", table))?;
+		let columns = stmt.column_count();
+		let statement = format!(
+			"INSERT INTO {} VALUES ({})",
+			table,
+			vec!["?"; columns].join(",")
+		);
+		let mut rows = stmt.query([])?;
+		while let Some(row) = rows.next()? {
+			let parameters = (0..columns)
+				.map(|i| row.get::<_, rusqlite::types::Value>(i))
+				.collect::<Result<Vec<_>, _>>()?;
+			writer.write_statement(&statement, &parameters)?;
+			count_row += 1;
+		}
+	}
+
+	// indexes are created after the tables are filled
+	for entry in schema
+		.iter()
+		.filter(|x| x.get_string("type").as_deref() == Some("index"))
+	{
+		if let Some(sql) = entry.get_string("sql") {
+			writer.write_statement(&sql, &[])?;
+		}
+	}
+
+	// preferences
+	for path in read_files(&path_input.join("preference"))? {
+		let conf = ini::Ini::load_from_file(&path).with_context(|| {
+			format!("Could not read preference file: {}", path.to_string_lossy())
+		})?;
+		let file = path.file_name().unwrap_or_default().to_string_lossy();
+		for (key, value) in conf.general_section().iter() {
+			let mut preference = crate::Backups::SharedPreference::new();
+			preference.file = Some(file.to_string());
+			preference.key = Some(key.to_string());
+			preference.value = Some(value.to_string());
+			let mut frame = crate::Backups::BackupFrame::new();
+			frame.preference = protobuf::MessageField::some(preference);
+			writer.write_frame(&frame)?;
+		}
+	}
+
+	// attachments, avatars and stickers
+	let mut count_file = 0;
+	let mut count_attachment = 0;
+	let mut count_skipped = 0;
+	for directory in ["attachment", "avatar", "sticker"] {
+		let mut paths = read_files(&path_input.join(directory))?;
+		// attachments may be separated by their size, see --attachment-size-threshold
+		if directory == "attachment" {
+			for subdirectory in ["small", "large"] {
+				paths.extend(read_files(&path_input.join(directory).join(subdirectory))?);
+			}
+		}
+		for path in paths {
+			let names = split_file_name(&path);
+			let data = std::fs::read(&path)
+				.with_context(|| format!("Failed to read file: {}", path.to_string_lossy()))?;
+			let length = u32::try_from(data.len())
+				.with_context(|| format!("File is too large: {}", path.to_string_lossy()))?;
+
+			let mut frame = crate::Backups::BackupFrame::new();
+			match (directory, names) {
+				("attachment", Some((attachment_id, row_id))) => {
+					let (Ok(attachment_id), Ok(row_id)) = (attachment_id.parse(), row_id.parse())
+					else {
+						warn!("Skipping attachment with unknown name: {}", path.to_string_lossy());
+						count_skipped += 1;
+						continue;
+					};
+					frame = attachment_frame(attachment_id, row_id, length);
+					count_attachment += 1;
+				}
+				("avatar", Some((name, _))) => {
+					let mut avatar = crate::Backups::Avatar::new();
+					avatar.name = Some(name);
+					avatar.length = Some(length);
+					frame.avatar = protobuf::MessageField::some(avatar);
+				}
+				("sticker", Some((row_id, _))) => {
+					let Ok(row_id) = row_id.parse() else {
+						warn!("Skipping sticker with unknown name: {}", path.to_string_lossy());
+						count_skipped += 1;
+						continue;
+					};
+					let mut sticker = crate::Backups::Sticker::new();
+					sticker.rowId = Some(row_id);
+					sticker.length = Some(length);
+					frame.sticker = protobuf::MessageField::some(sticker);
+				}
+				_ => {
+					warn!("Skipping file with unknown name: {}", path.to_string_lossy());
+					count_skipped += 1;
+					continue;
+				}
+			}
+
+			writer.write_frame(&frame)?;
+			writer.write_data(&data)?;
+			count_file += 1;
+		}
+	}
+
+	// attachments stored in their own database, see --attachments-in-db
+	let path_attachments = path_input.join(crate::output_raw::FILENAME_DATABASE_ATTACHMENTS);
+	if path_attachments.is_file() {
+		let connection_attachments = rusqlite::Connection::open_with_flags(
+			&path_attachments,
+			rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+		)
+		.with_context(|| {
+			format!(
+				"could not open connection to database file: {}",
+				path_attachments.to_string_lossy()
+			)
+		})?;
+		let mut stmt = connection_attachments
+			.prepare("SELECT attachment_id, row_id, data FROM attachment ORDER BY row_id")
+			.context("failed to read attachment database")?;
+		let mut rows = stmt.query([])?;
+		while let Some(row) = rows.next()? {
+			let (attachment_id, row_id): (i64, i64) = (row.get(0)?, row.get(1)?);
+			let data: Vec<u8> = row.get(2)?;
+			let (Ok(attachment_id), Ok(row_id), Ok(length)) = (
+				u64::try_from(attachment_id),
+				u64::try_from(row_id),
+				u32::try_from(data.len()),
+			) else {
+				warn!("Skipping attachment {}_{} of attachment database", attachment_id, row_id);
+				count_skipped += 1;
+				continue;
+			};
+			writer.write_frame(&attachment_frame(attachment_id, row_id, length))?;
+			writer.write_data(&data)?;
+			count_file += 1;
+			count_attachment += 1;
+		}
+	}
+
+	// attachments written elsewhere, e.g. with --attachments-dir, are not found
+	if count_attachment == 0 {
+		let mut count_rows = 0;
+		for table in ["attachment", "part"] {
+			if crate::database::has_table(&connection, table)? {
+				count_rows += connection.query_row(
+					&format!(
+						"SELECT COUNT(*) FROM {}",
+						crate::database::quote_identifier(table)
+					),
+					[],
+					|row| row.get::<_, i64>(0),
+				)?;
+			}
+		}
+		if count_rows > 0 {
+			warn!(
+				"No attachment files found for {} attachment rows in the database. Attachments written \
+				to another directory have to be moved to {} first",
+				count_rows,
+				path_input.join("attachment").to_string_lossy()
+			);
+		}
+	}
+
+	writer.finish()?;
+	if count_skipped > 0 {
+		warn!(
+			"Skipped {} files which cannot be assigned to their database rows",
+			count_skipped
+		);
+	}
+	info!(
+		"Encoded {} database rows and {} files",
+		count_row, count_file
+	);
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Encodes a small decoded backup and compares the frames read back from it
+	#[test]
+	fn encode_decode() {
+		let directory = tempfile::tempdir().unwrap();
+		let path_input = directory.path().join("decoded");
+		std::fs::create_dir_all(path_input.join("attachment")).unwrap();
+		std::fs::create_dir_all(path_input.join("preference")).unwrap();
+
+		let connection = rusqlite::Connection::open(path_input.join("signal_backup.db")).unwrap();
+		connection
+			.execute_batch(
+				"PRAGMA user_version = 42;
+				CREATE TABLE message (_id INTEGER PRIMARY KEY, body TEXT, data BLOB, score REAL);
+				CREATE TABLE \"odd \"\"name\"\"\" (_id INTEGER PRIMARY KEY);
+				CREATE INDEX message_body ON message (body);
+				INSERT INTO message VALUES (1, 'it''s', x'00ff', 0.5), (2, NULL, NULL, NULL);
+				INSERT INTO \"odd \"\"name\"\"\" VALUES (3);",
+			)
+			.unwrap();
+		drop(connection);
+		std::fs::write(path_input.join("attachment/7_1.txt"), b"attachment").unwrap();
+		std::fs::write(path_input.join("preference/settings"), "theme=dark\n").unwrap();
+
+		let path_output = directory.path().join("encoded.backup");
+		encode(
+			&path_input,
+			&path_output,
+			b"123456789012345678901234567890",
+			false,
+		)
+		.unwrap();
+
+		let reader = crate::input::InputFile::new(
+			&path_output,
+			&[],
+			Some(b"123456789012345678901234567890"),
+			crate::decrypter::BackupCipher::default(),
+			None,
+			true,
+			true,
+		)
+		.unwrap();
+		let frames = reader
+			.map(|x| match x.unwrap() {
+				crate::frame::Frame::Statement {
+					statement,
+					parameter,
+				} => format!("{} {:?}", statement, parameter),
+				crate::frame::Frame::Preference { preference } => format!(
+					"Preference {} {} {}",
+					preference.file(),
+					preference.key(),
+					preference.value()
+				),
+				crate::frame::Frame::Attachment { id, row, data, .. } => {
+					format!("Attachment {} {} {:?}", id, row, data)
+				}
+				x => x.to_string(),
+			})
+			.collect::<Vec<_>>();
+
+		assert_eq!(
+			frames,
+			[
+				"Version (42)",
+				"CREATE TABLE message (_id INTEGER PRIMARY KEY, body TEXT, data BLOB, score REAL) []",
+				"INSERT INTO \"message\" VALUES (?,?,?,?) [Integer(1), Text(\"it's\"), Blob([0, 255]), Real(0.5)]",
+				"INSERT INTO \"message\" VALUES (?,?,?,?) [Integer(2), Null, Null, Null]",
+				"CREATE TABLE \"odd \"\"name\"\"\" (_id INTEGER PRIMARY KEY) []",
+				"INSERT INTO \"odd \"\"name\"\"\" VALUES (?) [Integer(3)]",
+				"CREATE INDEX message_body ON message (body) []",
+				"Preference settings theme dark",
+				"Attachment 7 1 Some([97, 116, 116, 97, 99, 104, 109, 101, 110, 116])",
+			]
+		);
+	}
+}
@@ -0,0 +1,85 @@
+use hmac::Mac;
+
+/// Encrypt bytes, the counterpart of `crate::decrypter::Decrypter`
+pub struct Encrypter {
+	mac: hmac::Hmac<sha2::Sha256>,
+	mac_key: Vec<u8>,
+	key: Vec<u8>,
+	iv: Vec<u8>,
+}
+
+impl Encrypter {
+	pub fn new(key: &[u8], salt: &[u8], iv: &[u8]) -> Self {
+		let okm = crate::decrypter::derive_keys(key, salt, crate::decrypter::KdfParams::default());
+
+		Self {
+			mac: hmac::Hmac::<sha2::Sha256>::new_from_slice(&okm[32..]).unwrap(),
+			mac_key: okm[32..].to_vec(),
+			key: okm[..32].to_vec(),
+			iv: iv.to_vec(),
+		}
+	}
+
+	/// Encrypts data with the current IV and updates the HMAC with the encrypted data
+	pub fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+		let data_encrypted = openssl::symm::encrypt(
+			openssl::symm::Cipher::aes_256_ctr(),
+			&self.key,
+			Some(&self.iv),
+			data,
+		)?;
+		self.mac.update(&data_encrypted);
+
+		Ok(data_encrypted)
+	}
+
+	pub fn mac_update_with_iv(&mut self) {
+		self.mac.update(&self.iv);
+	}
+
+	/// Returns the truncated HMAC of all data since the last call and resets it
+	pub fn finalize_mac(&mut self) -> Vec<u8> {
+		let mac = std::mem::replace(
+			&mut self.mac,
+			hmac::Hmac::<sha2::Sha256>::new_from_slice(&self.mac_key).unwrap(),
+		);
+		mac.finalize().into_bytes()[..crate::decrypter::LENGTH_HMAC].to_vec()
+	}
+
+	pub fn increase_iv(&mut self) {
+		crate::decrypter::increase_counter(&mut self.iv);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn encrypt_decrypt() {
+		let password = b"123456789012345678901234567890";
+		let salt = [1; 32];
+		let iv = [2; 16];
+		let data = b"frame data".to_vec();
+
+		let mut enc = Encrypter::new(password, &salt, &iv);
+		enc.increase_iv();
+		enc.mac_update_with_iv();
+		let data_encrypted = enc.encrypt(&data).unwrap();
+		let mac = enc.finalize_mac();
+
+		let mut dec = crate::decrypter::Decrypter::new(
+			password,
+			&salt,
+			&iv,
+			true,
+			crate::decrypter::BackupCipher::default(),
+			crate::decrypter::KdfParams::default(),
+		)
+		.unwrap();
+		dec.increase_iv();
+		dec.mac_update_with_iv().unwrap();
+		assert_eq!(dec.decrypt(&data_encrypted).unwrap(), data);
+		assert!(dec.verify_mac(&mac, 1).is_ok());
+	}
+}
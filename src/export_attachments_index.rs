@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+
+/// An attachment written to a file, as recorded while writing the backup
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WrittenAttachment {
+	/// Path of the file relative to the output directory
+	pub path: std::path::PathBuf,
+	pub attachment_id: u64,
+	pub row_id: u64,
+	/// Size of the file in bytes
+	pub size: usize,
+}
+
+/// A line of the attachments index
+#[derive(Serialize)]
+struct IndexEntry {
+	file: String,
+	attachment_id: u64,
+	row_id: u64,
+	content_type: Option<String>,
+	size: usize,
+	message_id: Option<i64>,
+	thread_id: Option<i64>,
+	/// Name of the contact or group of the thread
+	thread: Option<String>,
+	/// Whether the attachment belongs to no message of the backup
+	orphan: bool,
+}
+
+/// Returns the thread of every message by message id
+///
+/// Older backups store messages with attachments in table mms, newer ones in table message.
+fn get_message_threads(
+	connection: &rusqlite::Connection,
+) -> Result<std::collections::HashMap<i64, Option<i64>>, anyhow::Error> {
+	let mut threads = std::collections::HashMap::new();
+	for table in ["mms", "message"] {
+		if !crate::database::has_table(connection, table)? {
+			continue;
+		}
+		for row in crate::database::query_rows(connection, &format!("SELECT * FROM {}", table), [])?
+		{
+			if let Some(id) = row.get_i64("_id") {
+				threads.insert(id, row.get_i64("thread_id"));
+			}
+		}
+	}
+
+	Ok(threads)
+}
+
+/// Returns the date a message has been sent at in milliseconds by message id
+///
+/// Older backups store the date in column date of table mms, newer ones in column date_sent of
+/// table message. Messages without a date are left out.
+fn get_message_dates(
+	connection: &rusqlite::Connection,
+) -> Result<std::collections::HashMap<i64, i64>, anyhow::Error> {
+	let mut dates = std::collections::HashMap::new();
+	for table in ["mms", "message"] {
+		if !crate::database::has_table(connection, table)? {
+			continue;
+		}
+		for row in crate::database::query_rows(connection, &format!("SELECT * FROM {}", table), [])?
+		{
+			let date = row.get_i64("date_sent").or_else(|| row.get_i64("date"));
+			if let (Some(id), Some(date)) = (row.get_i64("_id"), date.filter(|x| *x > 0)) {
+				dates.insert(id, date);
+			}
+		}
+	}
+
+	Ok(dates)
+}
+
+/// Returns the name of every thread by thread id
+fn get_thread_names(
+	connection: &rusqlite::Connection,
+) -> Result<std::collections::HashMap<i64, String>, anyhow::Error> {
+	let mut names = std::collections::HashMap::new();
+	if !crate::database::has_table(connection, "thread")? {
+		return Ok(names);
+	}
+
+	let recipients = crate::database::get_recipients(connection)?;
+	for row in crate::database::query_rows(connection, "SELECT * FROM thread", [])? {
+		// older backups store the recipient in column thread_recipient_id
+		let (Some(id), Some(recipient_id)) = (
+			row.get_i64("_id"),
+			row.get_i64("recipient_id")
+				.or_else(|| row.get_i64("thread_recipient_id")),
+		) else {
+			continue;
+		};
+		names.insert(
+			id,
+			recipients
+				.get(&recipient_id)
+				.map(|x| x.display_name())
+				.unwrap_or_else(|| format!("recipient {}", recipient_id)),
+		);
+	}
+
+	Ok(names)
+}
+
+/// Creates a CSV file listing every written attachment with the message and thread it belongs to
+///
+/// The rows of attachments are looked up in table attachment or, in older backups, part.
+/// Attachments without a row or whose message is not part of the backup are marked as orphans.
+pub fn create_attachments_index(
+	connection: &rusqlite::Connection,
+	attachments: &[WrittenAttachment],
+) -> Result<Vec<u8>, anyhow::Error> {
+	let message_threads = get_message_threads(connection)?;
+	let thread_names = get_thread_names(connection)?;
+
+	let mut writer = csv::Writer::from_writer(Vec::new());
+	for attachment in attachments {
+		let row = crate::database::get_attachment(connection, attachment.row_id as i64)?;
+		let message_id = row.as_ref().and_then(crate::database::get_attachment_message_id);
+		let thread_id = message_id
+			.and_then(|x| message_threads.get(&x).copied())
+			.flatten();
+
+		writer.serialize(IndexEntry {
+			file: attachment.path.to_string_lossy().into_owned(),
+			attachment_id: attachment.attachment_id,
+			row_id: attachment.row_id,
+			content_type: row
+				.as_ref()
+				.and_then(|x| x.get_first_string(&["content_type", "ct"])),
+			size: attachment.size,
+			message_id,
+			thread_id,
+			thread: thread_id.and_then(|x| thread_names.get(&x).cloned()),
+			orphan: !message_id.is_some_and(|x| message_threads.contains_key(&x)),
+		})?;
+	}
+
+	Ok(writer.into_inner()?)
+}
+
+/// Returns the date of the message every written attachment belongs to, in the same order
+///
+/// The date is `None` for attachments without a row or whose message is not part of the
+/// backup.
+pub fn get_attachment_dates(
+	connection: &rusqlite::Connection,
+	attachments: &[WrittenAttachment],
+) -> Result<Vec<Option<std::time::SystemTime>>, anyhow::Error> {
+	let message_dates = get_message_dates(connection)?;
+
+	let mut dates = Vec::new();
+	for attachment in attachments {
+		let row = crate::database::get_attachment(connection, attachment.row_id as i64)?;
+		dates.push(
+			row.as_ref()
+				.and_then(crate::database::get_attachment_message_id)
+				.and_then(|x| message_dates.get(&x))
+				.map(|x| std::time::UNIX_EPOCH + std::time::Duration::from_millis(*x as u64)),
+		);
+	}
+
+	Ok(dates)
+}
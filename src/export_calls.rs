@@ -0,0 +1,155 @@
+use serde::Serialize;
+
+/// A voice or video call
+#[derive(Serialize)]
+struct Call {
+	/// Start of the call
+	date: chrono::NaiveDateTime,
+	/// Contact or group called
+	peer: String,
+	/// Either incoming, outgoing or missed
+	direction: &'static str,
+	/// Either audio, video or group
+	call_type: &'static str,
+}
+
+/// Reads calls from the call table, used since database version 180
+///
+/// Signal stores neither the duration nor the end of calls, neither here nor in messages.
+fn get_calls_from_call_table(
+	connection: &rusqlite::Connection,
+	recipients: &std::collections::HashMap<i64, crate::database::Recipient>,
+) -> Result<Vec<Call>, anyhow::Error> {
+	let mut calls = Vec::new();
+	for row in crate::database::query_rows(connection, "SELECT * FROM call ORDER BY timestamp", [])?
+	{
+		// see CallTable.Event of the Signal app, 4 marks deleted calls
+		let event = row.get_i64("event").unwrap_or_default();
+		if event == 4 {
+			continue;
+		}
+
+		let direction = match (row.get_i64("direction"), event) {
+			(_, 3) | (_, 10) => "missed",
+			(Some(1), _) => "outgoing",
+			_ => "incoming",
+		};
+		let call_type = match row.get_i64("type") {
+			Some(1) => "video",
+			Some(3) | Some(4) => "group",
+			_ => "audio",
+		};
+		let peer = row.get_i64("peer").unwrap_or_default();
+
+		calls.push(Call {
+			date: crate::database::timestamp_to_date(row.get_i64("timestamp").unwrap_or_default()),
+			peer: recipients
+				.get(&peer)
+				.map(|x| x.display_name())
+				.unwrap_or_else(|| format!("recipient {}", peer)),
+			direction,
+			call_type,
+		});
+	}
+
+	Ok(calls)
+}
+
+/// Reads calls from the message tables, where older backups store them as messages of a
+/// call type
+fn get_calls_from_messages(
+	connection: &rusqlite::Connection,
+	recipients: &std::collections::HashMap<i64, crate::database::Recipient>,
+) -> Result<Vec<Call>, anyhow::Error> {
+	let mut calls = Vec::new();
+	for table in ["sms", "mms", "message"] {
+		if !crate::database::has_table(connection, table)? {
+			continue;
+		}
+
+		let columns = crate::database::get_columns(connection, table)?;
+		let (column_type, column_date) = match (
+			["type", "msg_box"]
+				.into_iter()
+				.find(|x| columns.iter().any(|c| c == x)),
+			["date_sent", "date"]
+				.into_iter()
+				.find(|x| columns.iter().any(|c| c == x)),
+		) {
+			(Some(x), Some(y)) => (x, y),
+			_ => continue,
+		};
+
+		for row in crate::database::query_rows(
+			connection,
+			&format!("SELECT * FROM {} ORDER BY {}", table, column_date),
+			[],
+		)? {
+			// the lowest five bits are the base type, see MessageTypes of the Signal app
+			let (direction, call_type) = match row.get_i64(column_type).unwrap_or_default() & 0x1f {
+				1 => ("incoming", "audio"),
+				2 => ("outgoing", "audio"),
+				3 => ("missed", "audio"),
+				8 => ("missed", "video"),
+				10 => ("incoming", "video"),
+				11 => ("outgoing", "video"),
+				_ => continue,
+			};
+
+			// older tables reference the peer by address, newer ones by sender and receiver
+			let columns_peer: &[&str] = if direction == "outgoing" {
+				&["to_recipient_id", "recipient_id", "address"]
+			} else {
+				&["from_recipient_id", "recipient_id", "address"]
+			};
+			let peer = columns_peer
+				.iter()
+				.find_map(|x| {
+					row.get_i64(x)
+						.map(|id| {
+							recipients
+								.get(&id)
+								.map(|x| x.display_name())
+								.unwrap_or_else(|| format!("recipient {}", id))
+						})
+						.or_else(|| row.get_string(x))
+				})
+				.unwrap_or_default();
+
+			calls.push(Call {
+				date: crate::database::timestamp_to_date(
+					row.get_i64(column_date).unwrap_or_default(),
+				),
+				peer,
+				direction,
+				call_type,
+			});
+		}
+	}
+	calls.sort_by_key(|x| x.date);
+
+	Ok(calls)
+}
+
+/// Creates a CSV file of all calls
+///
+/// Calls are read from the call table if it exists and from call messages otherwise, as newer
+/// backups contain both.
+pub fn create_calls(connection: &rusqlite::Connection) -> Result<Vec<u8>, anyhow::Error> {
+	let recipients = crate::database::get_recipients(connection)?;
+	let mut calls = if crate::database::has_table(connection, "call")? {
+		get_calls_from_call_table(connection, &recipients)?
+	} else {
+		Vec::new()
+	};
+	if calls.is_empty() {
+		calls = get_calls_from_messages(connection, &recipients)?;
+	}
+
+	let mut writer = csv::Writer::from_writer(Vec::new());
+	for call in calls {
+		writer.serialize(call)?;
+	}
+
+	Ok(writer.into_inner()?)
+}
@@ -0,0 +1,145 @@
+use anyhow::Context;
+use font8x8::UnicodeFonts;
+
+/// Size of the avatar in a tile in pixels
+const SIZE_AVATAR: u32 = 96;
+
+/// Width of a tile in pixels, fits 16 characters per line of the name
+const WIDTH_TILE: u32 = 128;
+
+/// Height of a tile in pixels, fits the avatar and two lines of the name
+const HEIGHT_TILE: u32 = 8 + SIZE_AVATAR + 8 + 2 * LINE_HEIGHT + 4;
+
+/// Height of a line of text in pixels, glyphs are 8 pixels high
+const LINE_HEIGHT: u32 = 10;
+
+const COLOR_BACKGROUND: image::Rgba<u8> = image::Rgba([255, 255, 255, 255]);
+const COLOR_PLACEHOLDER: image::Rgba<u8> = image::Rgba([200, 200, 200, 255]);
+const COLOR_TEXT: image::Rgba<u8> = image::Rgba([0, 0, 0, 255]);
+
+/// Scales an avatar to the size of a tile, `None` if it is no supported image
+pub fn create_thumbnail(data: &[u8]) -> Option<image::RgbaImage> {
+	let avatar = image::load_from_memory(data).ok()?;
+	Some(
+		avatar
+			.resize_to_fill(
+				SIZE_AVATAR,
+				SIZE_AVATAR,
+				image::imageops::FilterType::Triangle,
+			)
+			.to_rgba8(),
+	)
+}
+
+/// Draws `c` with its top left corner at `x`, `y`, each pixel of the glyph `scale` pixels wide
+///
+/// Characters without glyph are drawn as `?`.
+fn draw_char(canvas: &mut image::RgbaImage, c: char, x: u32, y: u32, scale: u32) {
+	let glyph = font8x8::BASIC_FONTS
+		.get(c)
+		.or_else(|| font8x8::LATIN_FONTS.get(c))
+		.or_else(|| font8x8::BASIC_FONTS.get('?'))
+		.unwrap_or_default();
+
+	// every byte is a row, the lowest bit is the leftmost pixel
+	for (row, bits) in glyph.iter().enumerate() {
+		for column in 0..8 {
+			if bits & (1 << column) == 0 {
+				continue;
+			}
+			for dy in 0..scale {
+				for dx in 0..scale {
+					let (px, py) = (x + column * scale + dx, y + row as u32 * scale + dy);
+					if px < canvas.width() && py < canvas.height() {
+						canvas.put_pixel(px, py, COLOR_TEXT);
+					}
+				}
+			}
+		}
+	}
+}
+
+/// Splits a name into at most two lines fitting into a tile, cut off names end with `..`
+fn split_name(name: &str) -> Vec<String> {
+	let width = (WIDTH_TILE / 8) as usize;
+	let chars: Vec<char> = name.chars().collect();
+	let mut lines: Vec<String> = chars.chunks(width).map(|x| x.iter().collect()).collect();
+	if lines.len() > 2 {
+		lines.truncate(2);
+		lines[1] = format!("{}..", lines[1].chars().take(width - 2).collect::<String>());
+	}
+
+	lines
+}
+
+/// Creates a PNG image of all contacts with their avatar and name in a grid
+///
+/// `thumbnails` maps avatar names (recipient id or phone number, depending on the backup
+/// version) to avatars created with `create_thumbnail`. Contacts without avatar get a
+/// placeholder with their initial. Groups are skipped like in vCards.
+pub fn create_contact_sheet(
+	connection: &rusqlite::Connection,
+	thumbnails: &std::collections::HashMap<String, image::RgbaImage>,
+) -> Result<Vec<u8>, anyhow::Error> {
+	let mut contacts = Vec::new();
+	for recipient in crate::database::get_recipients(connection)?.into_values() {
+		if recipient.group_id.is_some() || (recipient.name.is_none() && recipient.phone.is_none()) {
+			continue;
+		}
+		let thumbnail = thumbnails
+			.get(&recipient.id.to_string())
+			.or_else(|| recipient.phone.as_ref().and_then(|x| thumbnails.get(x)));
+		contacts.push((recipient.id, recipient.display_name(), thumbnail));
+	}
+	contacts.sort_by_key(|x| x.0);
+
+	// as square as possible
+	let columns = ((contacts.len() as f64).sqrt().ceil() as u32).max(1);
+	let rows = (contacts.len() as u32).div_ceil(columns).max(1);
+	let mut sheet =
+		image::RgbaImage::from_pixel(columns * WIDTH_TILE, rows * HEIGHT_TILE, COLOR_BACKGROUND);
+
+	for (i, (_, name, thumbnail)) in contacts.iter().enumerate() {
+		let x = (i as u32 % columns) * WIDTH_TILE;
+		let y = (i as u32 / columns) * HEIGHT_TILE;
+		let x_avatar = x + (WIDTH_TILE - SIZE_AVATAR) / 2;
+		let y_avatar = y + 8;
+
+		match thumbnail {
+			Some(thumbnail) => {
+				image::imageops::overlay(&mut sheet, *thumbnail, x_avatar.into(), y_avatar.into())
+			}
+			None => {
+				let placeholder =
+					image::RgbaImage::from_pixel(SIZE_AVATAR, SIZE_AVATAR, COLOR_PLACEHOLDER);
+				image::imageops::overlay(
+					&mut sheet,
+					&placeholder,
+					x_avatar.into(),
+					y_avatar.into(),
+				);
+
+				// initial, 4 times the size of the name
+				let initial = name.chars().next().unwrap_or('?').to_ascii_uppercase();
+				let offset = (SIZE_AVATAR - 8 * 4) / 2;
+				draw_char(&mut sheet, initial, x_avatar + offset, y_avatar + offset, 4);
+			}
+		}
+
+		for (line, text) in split_name(name).iter().enumerate() {
+			let width = text.chars().count() as u32 * 8;
+			let x_text = x + (WIDTH_TILE - width) / 2;
+			let y_text = y_avatar + SIZE_AVATAR + 8 + line as u32 * LINE_HEIGHT;
+			for (j, c) in text.chars().enumerate() {
+				draw_char(&mut sheet, c, x_text + j as u32 * 8, y_text, 1);
+			}
+		}
+	}
+
+	let mut data = std::io::Cursor::new(Vec::new());
+	sheet
+		.write_to(&mut data, image::ImageFormat::Png)
+		.context("failed to encode contact sheet")?;
+
+	Ok(data.into_inner())
+}
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+/// A sticker written to a file, as recorded while writing the backup
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WrittenSticker {
+	/// Path of the file relative to the output directory
+	pub path: std::path::PathBuf,
+	pub row_id: u64,
+}
+
+/// The manifest of all written stickers
+#[derive(Serialize)]
+struct Manifest {
+	packs: Vec<Pack>,
+}
+
+/// A sticker pack of the manifest, fields unknown to the database are `null`
+#[derive(Serialize)]
+struct Pack {
+	id: Option<String>,
+	/// Key needed to download the pack again
+	key: Option<String>,
+	title: Option<String>,
+	author: Option<String>,
+	stickers: Vec<Sticker>,
+}
+
+/// A sticker of a pack in the manifest
+#[derive(Serialize)]
+struct Sticker {
+	/// Id of the sticker within its pack
+	id: Option<i64>,
+	emoji: Option<String>,
+	/// Path of the file relative to the manifest
+	file: String,
+	/// Whether the sticker is the cover of its pack
+	cover: bool,
+}
+
+/// Creates a JSON file listing every written sticker grouped by its pack
+///
+/// Packs and stickers are looked up in table sticker. Stickers without a row are listed in a
+/// pack without id, packs are listed in the order of their first sticker in the backup.
+pub fn create_sticker_manifest(
+	connection: &rusqlite::Connection,
+	stickers: &[WrittenSticker],
+) -> Result<Vec<u8>, anyhow::Error> {
+	let has_table = crate::database::has_table(connection, "sticker")?;
+
+	let mut packs: Vec<Pack> = Vec::new();
+	for sticker in stickers {
+		let row = if has_table {
+			crate::database::query_rows(
+				connection,
+				"SELECT * FROM sticker WHERE _id = ?1",
+				[sticker.row_id as i64],
+			)?
+			.into_iter()
+			.next()
+		} else {
+			None
+		};
+		let pack_id = row.as_ref().and_then(|x| x.get_string("pack_id"));
+		let index = match packs.iter().position(|x| x.id == pack_id) {
+			Some(x) => x,
+			None => {
+				packs.push(Pack {
+					id: pack_id,
+					key: row.as_ref().and_then(|x| x.get_string("pack_key")),
+					title: row.as_ref().and_then(|x| x.get_string("pack_title")),
+					author: row.as_ref().and_then(|x| x.get_string("pack_author")),
+					stickers: Vec::new(),
+				});
+				packs.len() - 1
+			}
+		};
+		// the metadata of a pack is stored with each of its stickers, some may lack it
+		let pack = &mut packs[index];
+		if let Some(row) = &row {
+			pack.key = pack.key.take().or_else(|| row.get_string("pack_key"));
+			pack.title = pack.title.take().or_else(|| row.get_string("pack_title"));
+			pack.author = pack.author.take().or_else(|| row.get_string("pack_author"));
+		}
+
+		pack.stickers.push(Sticker {
+			id: row.as_ref().and_then(|x| x.get_i64("sticker_id")),
+			emoji: row.as_ref().and_then(|x| x.get_string("emoji")),
+			file: sticker
+				.path
+				.file_name()
+				.unwrap_or(sticker.path.as_os_str())
+				.to_string_lossy()
+				.into_owned(),
+			cover: row
+				.as_ref()
+				.and_then(|x| x.get_i64("cover"))
+				.is_some_and(|x| x != 0),
+		});
+	}
+	for pack in &mut packs {
+		pack.stickers.sort_by_key(|x| (x.id, x.cover));
+	}
+
+	let mut data = serde_json::to_vec_pretty(&Manifest { packs })?;
+	data.push(b'\n');
+	Ok(data)
+}
@@ -0,0 +1,258 @@
+use anyhow::Context;
+
+/// Tables referencing messages without a thread id, with the referencing column
+///
+/// Older backups reference messages of the table `mms` instead of `message`.
+const TABLES_MESSAGE_REFERENCE: [(&str, &str); 5] = [
+	("part", "mid"),
+	("attachment", "message_id"),
+	("reaction", "message_id"),
+	("group_receipts", "mms_id"),
+	("call", "message_id"),
+];
+
+/// Columns referencing recipients in tables of a thread
+const COLUMNS_RECIPIENT: [&str; 7] = [
+	"recipient_id",
+	"recipient_ids",
+	"from_recipient_id",
+	"to_recipient_id",
+	"address",
+	"author_id",
+	"peer",
+];
+
+/// Name of the table attachments are copied to from the attachment database
+pub const TABLE_ATTACHMENT_DATA: &str = "attachment_data";
+
+/// Returns the ids of all threads
+pub fn get_thread_ids(connection: &rusqlite::Connection) -> Result<Vec<i64>, anyhow::Error> {
+	if !crate::database::has_table(connection, "thread")? {
+		return Ok(Vec::new());
+	}
+
+	Ok(
+		crate::database::query_rows(connection, "SELECT _id FROM thread ORDER BY _id", [])?
+			.iter()
+			.filter_map(|x| x.get_i64("_id"))
+			.collect(),
+	)
+}
+
+/// Returns the distinct integer values of `column` in `table` of the thread database
+fn get_ids(
+	connection: &rusqlite::Connection,
+	table: &str,
+	column: &str,
+) -> Result<Vec<i64>, anyhow::Error> {
+	let query = format!(
+		"SELECT DISTINCT {0} FROM split.{1} WHERE typeof({0}) = 'integer'",
+		crate::database::quote_identifier(column),
+		crate::database::quote_identifier(table)
+	);
+	Ok(crate::database::query_rows(connection, &query, [])?
+		.iter()
+		.filter_map(|x| x.get_i64(column))
+		.collect())
+}
+
+/// Copies the rows of `table` matching `condition` into the thread database
+fn copy_rows(
+	connection: &rusqlite::Connection,
+	table: &str,
+	condition: &str,
+	thread_id: i64,
+) -> Result<(), anyhow::Error> {
+	connection
+		.execute(
+			&format!(
+				"INSERT INTO split.{0} SELECT * FROM main.{0} WHERE {1}",
+				crate::database::quote_identifier(table),
+				condition
+			),
+			[],
+		)
+		.with_context(|| format!("failed to copy rows of table {} for thread {}", table, thread_id))?;
+
+	Ok(())
+}
+
+/// Fills the database attached as `split` with the rows of thread `thread_id`
+///
+/// These are the thread itself, its messages and all rows referencing them (e.g. attachments,
+/// reactions and mentions), the recipients involved and for group threads the group with its
+/// members.
+fn copy_thread(
+	connection: &rusqlite::Connection,
+	thread_id: i64,
+	has_attachments: bool,
+) -> Result<(), anyhow::Error> {
+	let tables: Vec<String> = crate::database::query_rows(
+		connection,
+		"SELECT name FROM split.sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+		[],
+	)?
+	.iter()
+	.filter_map(|x| x.get_string("name"))
+	.collect();
+	let has_table = |x: &str| tables.iter().any(|y| y == x);
+
+	let condition_thread = format!("thread_id = {}", thread_id);
+	copy_rows(connection, "thread", &format!("_id = {}", thread_id), thread_id)?;
+
+	// messages, mentions, drafts and everything else belonging to the thread directly
+	let mut tables_copied = vec![String::from("thread")];
+	for table in &tables {
+		let columns = crate::database::get_columns(connection, table)?;
+		if table != "thread" && columns.iter().any(|x| x == "thread_id") {
+			copy_rows(connection, table, &condition_thread, thread_id)?;
+			tables_copied.push(table.clone());
+		}
+	}
+
+	let table_message = if has_table("message") { "message" } else { "mms" };
+	for (table, column) in TABLES_MESSAGE_REFERENCE {
+		if !has_table(table) || tables_copied.iter().any(|x| x == table) {
+			continue;
+		}
+
+		// reactions of older backups reference either sms or mms
+		let columns = crate::database::get_columns(connection, table)?;
+		let condition = if columns.iter().any(|x| x == "is_mms") {
+			format!(
+				"(is_mms = 0 AND {0} IN (SELECT _id FROM split.sms)) OR (is_mms = 1 AND {0} IN (SELECT _id FROM split.mms))",
+				column
+			)
+		} else {
+			format!("{} IN (SELECT _id FROM split.{})", column, table_message)
+		};
+		copy_rows(connection, table, &condition, thread_id)?;
+		tables_copied.push(table.to_string());
+	}
+
+	// recipients of the thread and its messages
+	let mut recipients = std::collections::BTreeSet::new();
+	for table in &tables_copied {
+		for column in crate::database::get_columns(connection, table)? {
+			if COLUMNS_RECIPIENT.contains(&column.as_str()) {
+				recipients.extend(get_ids(connection, table, &column)?);
+			}
+		}
+	}
+
+	// groups with their members, stored as comma separated list in older backups
+	if has_table("groups") {
+		let ids: Vec<String> = recipients.iter().map(|x| x.to_string()).collect();
+		copy_rows(
+			connection,
+			"groups",
+			&format!("recipient_id IN ({})", ids.join(",")),
+			thread_id,
+		)?;
+		for row in crate::database::query_rows(connection, "SELECT * FROM split.groups", [])? {
+			let members = row.get_string("members").unwrap_or_default();
+			recipients.extend(members.split(',').filter_map(|x| x.trim().parse::<i64>().ok()));
+		}
+		if has_table("group_membership") {
+			copy_rows(
+				connection,
+				"group_membership",
+				"group_id IN (SELECT group_id FROM split.groups)",
+				thread_id,
+			)?;
+			recipients.extend(get_ids(connection, "group_membership", "recipient_id")?);
+		}
+	}
+
+	if has_table("recipient") {
+		let ids: Vec<String> = recipients.iter().map(|x| x.to_string()).collect();
+		copy_rows(
+			connection,
+			"recipient",
+			&format!("_id IN ({})", ids.join(",")),
+			thread_id,
+		)?;
+	}
+
+	if has_attachments {
+		let table_part = if has_table("attachment") { "attachment" } else { "part" };
+		connection
+			.execute_batch(&format!(
+				"CREATE TABLE split.{0} (attachment_id INTEGER NOT NULL, row_id INTEGER NOT NULL, content_type TEXT, data BLOB NOT NULL, PRIMARY KEY (attachment_id, row_id));
+				INSERT INTO split.{0} SELECT * FROM attachments.attachment WHERE row_id IN (SELECT _id FROM split.{1});",
+				TABLE_ATTACHMENT_DATA, table_part
+			))
+			.with_context(|| format!("failed to copy attachments of thread {}", thread_id))?;
+	}
+
+	Ok(())
+}
+
+/// Creates the database at `path` containing only the conversation of thread `thread_id`
+///
+/// The database has the schema of the backup database. If attachments are stored in a database,
+/// given by `path_attachments`, the attachments of the conversation are copied to the table
+/// `TABLE_ATTACHMENT_DATA`.
+pub fn create_thread_database(
+	connection: &rusqlite::Connection,
+	thread_id: i64,
+	path: &std::path::Path,
+	path_attachments: Option<&std::path::Path>,
+) -> Result<(), anyhow::Error> {
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)
+			.with_context(|| format!("Failed to create path: {}", parent.to_string_lossy()))?;
+	}
+	if path.exists() {
+		std::fs::remove_file(path).with_context(|| {
+			format!("Could not delete old database: {}", path.to_string_lossy())
+		})?;
+	}
+
+	// full text search tables are rebuilt with --build-fts and would only be partially filled
+	let schema: Vec<String> = crate::database::query_rows(
+		connection,
+		"SELECT sql FROM sqlite_master WHERE type IN ('table', 'index') AND sql IS NOT NULL AND name NOT LIKE 'sqlite_%' AND name NOT LIKE ?1 ORDER BY type DESC, rowid",
+		[format!("{}%", crate::database::TABLE_FTS)],
+	)?
+	.iter()
+	.filter_map(|x| x.get_string("sql"))
+	.filter(|x| !crate::database::is_statement_skipped(x))
+	.collect();
+
+	let thread_connection = rusqlite::Connection::open(path).with_context(|| {
+		format!(
+			"could not open connection to database file: {}",
+			path.to_string_lossy()
+		)
+	})?;
+	for statement in &schema {
+		thread_connection
+			.execute_batch(statement)
+			.with_context(|| format!("failed to create thread database: {}", statement))?;
+	}
+	drop(thread_connection);
+
+	connection
+		.execute("ATTACH DATABASE ?1 AS split", [path.to_string_lossy()])
+		.context("failed to attach thread database")?;
+	if let Some(x) = path_attachments {
+		connection
+			.execute("ATTACH DATABASE ?1 AS attachments", [x.to_string_lossy()])
+			.context("failed to attach attachment database")?;
+	}
+
+	let result = copy_thread(connection, thread_id, path_attachments.is_some());
+
+	// detach in any case, the connection is used for further exports
+	connection
+		.execute_batch("DETACH DATABASE split")
+		.context("failed to detach thread database")?;
+	if path_attachments.is_some() {
+		connection
+			.execute_batch("DETACH DATABASE attachments")
+			.context("failed to detach attachment database")?;
+	}
+
+	result
+}
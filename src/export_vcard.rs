@@ -0,0 +1,72 @@
+/// Escapes text for use in a vCard property value
+fn escape(value: &str) -> String {
+	value
+		.replace('\\', "\\\\")
+		.replace(',', "\\,")
+		.replace(';', "\\;")
+		.replace('\n', "\\n")
+}
+
+/// Creates vCards (version 3.0) of all contacts in the recipient table
+///
+/// Groups are not contacts and therefore skipped. `avatars` maps avatar names (recipient id or
+/// phone number, depending on the backup version) to the path of the extracted avatar file,
+/// which is referenced as photo of the contact.
+pub fn create_vcards(
+	connection: &rusqlite::Connection,
+	avatars: &std::collections::HashMap<String, std::path::PathBuf>,
+) -> Result<String, anyhow::Error> {
+	let mut vcards = String::new();
+	if !crate::database::has_table(connection, "recipient")? {
+		return Ok(vcards);
+	}
+
+	for row in crate::database::query_rows(connection, "SELECT * FROM recipient", [])? {
+		if row.get_string("group_id").is_some() {
+			continue;
+		}
+
+		let id = row.get_i64("_id").unwrap_or_default();
+		let phone = row.get_first_string(&["e164", "phone"]);
+		let given_name = row.get_first_string(&["system_given_name", "profile_given_name"]);
+		let family_name = row.get_first_string(&["system_family_name", "profile_family_name"]);
+		let name = row.get_first_string(&[
+			"system_joined_name",
+			"system_display_name",
+			"profile_joined_name",
+			"signal_profile_name",
+		]);
+
+		// contacts without name are only known by their phone number
+		let formatted_name = match (&name, &phone) {
+			(Some(x), _) => x.clone(),
+			(None, Some(x)) => x.clone(),
+			(None, None) => continue,
+		};
+
+		vcards.push_str("BEGIN:VCARD\r\nVERSION:3.0\r\n");
+		vcards.push_str(&format!("FN:{}\r\n", escape(&formatted_name)));
+		vcards.push_str(&format!(
+			"N:{};{};;;\r\n",
+			escape(family_name.as_deref().unwrap_or_default()),
+			escape(given_name.as_deref().unwrap_or_default())
+		));
+		if let Some(x) = &phone {
+			vcards.push_str(&format!("TEL;TYPE=CELL:{}\r\n", escape(x)));
+		}
+
+		let avatar = avatars
+			.get(&id.to_string())
+			.or_else(|| phone.as_ref().and_then(|x| avatars.get(x)));
+		if let Some(x) = avatar {
+			vcards.push_str(&format!(
+				"PHOTO;VALUE=uri:{}\r\n",
+				x.to_string_lossy().replace('\\', "/")
+			));
+		}
+
+		vcards.push_str("END:VCARD\r\n");
+	}
+
+	Ok(vcards)
+}
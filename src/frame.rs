@@ -1,4 +1,7 @@
+use anyhow::anyhow;
 use anyhow::Context;
+use base64::Engine;
+use log::warn;
 use std::convert::TryInto;
 
 /// Frame
@@ -43,11 +46,25 @@ pub enum Frame {
     // optional int32  integerValue = 5;
     // optional int64  longValue    = 6;
     // optional string stringValue  = 7;
-	}
+	},
+	/// Frame without any field known to this tool, probably written by a newer version of Signal
+	Unknown {
+		/// Numbers of the protobuf fields set in this frame
+		fields: Vec<u32>,
+	},
 }
 
 impl Frame {
-	pub fn new(frame: &mut crate::Backups::BackupFrame) -> Self {
+	/// Parses the protobuf of a frame from its decrypted `data`
+	pub fn parse(data: &[u8]) -> Result<crate::Backups::BackupFrame, anyhow::Error> {
+		protobuf::Message::parse_from_bytes(data)
+			.with_context(|| format!("Could not parse frame from {:02X?}", data))
+	}
+
+	/// Converts the protobuf of a frame, fails if it has more than one known field set
+	///
+	/// Unknown fields set alongside a known field are skipped with a warning.
+	pub fn new(frame: &mut crate::Backups::BackupFrame) -> Result<Self, anyhow::Error> {
 		let mut fields_count = 0;
 		let mut ret: Option<Self> = None;
 
@@ -63,27 +80,39 @@ impl Frame {
 		if frame.statement.is_some() {
 			fields_count += 1;
 			let statement = frame.statement.take().unwrap();
+			let mut params: Vec<rusqlite::types::Value> = Vec::new();
+			for (i, param) in statement.parameters.iter().enumerate() {
+				if param.has_stringParamter() {
+					params.push(param.stringParamter().to_string().into());
+				} else if param.has_integerParameter() {
+					params.push((param.integerParameter() as i64).into());
+				} else if param.has_doubleParameter() {
+					params.push(param.doubleParameter().into());
+				} else if param.has_blobParameter() {
+					params.push(param.blobParameter().to_vec().into());
+				} else if param.has_nullparameter() {
+					params.push(rusqlite::types::Null.into());
+				} else {
+					// probably a type of parameter added by a newer version of Signal
+					let mut fields: Vec<u32> = param
+						.special_fields
+						.unknown_fields()
+						.iter()
+						.map(|(number, _)| number)
+						.collect();
+					fields.sort_unstable();
+					fields.dedup();
+					return Err(anyhow!(
+						"Parameter {} of statement has an unknown type (fields: {:?}): {}",
+						i + 1,
+						fields,
+						statement.statement()
+					));
+				}
+			}
 			ret = Some(Self::Statement {
 				statement: statement.statement.clone().unwrap_or_default(),
-				parameter: {
-					let mut params: Vec<rusqlite::types::Value> = Vec::new();
-					for param in statement.parameters.iter() {
-						if param.has_stringParamter() {
-							params.push(param.stringParamter().to_string().into());
-						} else if param.has_integerParameter() {
-							params.push((param.integerParameter() as i64).into());
-						} else if param.has_doubleParameter() {
-							params.push(param.doubleParameter().into());
-						} else if param.has_blobParameter() {
-							params.push(param.blobParameter().to_vec().into());
-						} else if param.has_nullparameter() {
-							params.push(rusqlite::types::Null.into());
-						} else {
-							panic!("Parameter type not known {:?}", param);
-						}
-					}
-					params
-				},
+				parameter: params,
 			});
 		};
 
@@ -146,14 +175,31 @@ impl Frame {
 			});
 		};
 
+		let mut fields: Vec<u32> = frame
+			.special_fields
+			.unknown_fields()
+			.iter()
+			.map(|(number, _)| number)
+			.collect();
+		fields.sort_unstable();
+		fields.dedup();
+
+		if fields_count == 0 {
+			return Ok(Self::Unknown { fields });
+		};
+
 		if fields_count != 1 {
-			panic!(
+			return Err(anyhow!(
 				"Frame with an unsupported number of fields found, please report to author: {:?}",
 				frame
-			);
+			));
 		};
 
-		ret.unwrap()
+		let ret = ret.unwrap();
+		if !fields.is_empty() {
+			warn!("Skipped unknown fields {:?} of frame: {}", fields, ret);
+		}
+		Ok(ret)
 	}
 
 	pub fn set_data(&mut self, data_add: Vec<u8>) {
@@ -164,6 +210,22 @@ impl Frame {
 			_ => panic!("Cannot set data on variant without data field."),
 		}
 	}
+
+	/// Returns the name of the type of the frame
+	pub fn type_name(&self) -> &'static str {
+		match self {
+			Self::Header { .. } => "Header",
+			Self::Statement { .. } => "Statement",
+			Self::Preference { .. } => "Preference",
+			Self::Attachment { .. } => "Attachment",
+			Self::Version { .. } => "Version",
+			Self::End => "End",
+			Self::Avatar { .. } => "Avatar",
+			Self::Sticker { .. } => "Sticker",
+			Self::KeyValue { .. } => "KeyValue",
+			Self::Unknown { .. } => "Unknown",
+		}
+	}
 }
 
 impl std::fmt::Display for Frame {
@@ -185,16 +247,34 @@ impl std::fmt::Display for Frame {
 			Self::Version { version } => write!(f, "Version ({})", version),
 			Self::End => write!(f, "End"),
 			Self::KeyValue { .. } => write!(f, "KeyValue"),
+			Self::Unknown { fields } => write!(f, "Unknown (fields: {:?})", fields),
 		}
 	}
 }
 
+/// Returns the value of a key value as string, blobs encoded as base64
+pub fn format_key_value(key_value: &crate::Backups::KeyValue) -> String {
+	if let Some(x) = &key_value.stringValue {
+		x.clone()
+	} else if let Some(x) = key_value.booleanValue {
+		x.to_string()
+	} else if let Some(x) = key_value.integerValue {
+		x.to_string()
+	} else if let Some(x) = key_value.longValue {
+		x.to_string()
+	} else if let Some(x) = key_value.floatValue {
+		x.to_string()
+	} else if let Some(x) = &key_value.blobValue {
+		base64::engine::general_purpose::STANDARD.encode(x)
+	} else {
+		String::new()
+	}
+}
+
 impl std::convert::TryFrom<Vec<u8>> for Frame {
 	type Error = anyhow::Error;
 
 	fn try_from(data: Vec<u8>) -> Result<Self, Self::Error> {
-		let mut frame = protobuf::Message::parse_from_bytes(&data)
-			.with_context(|| format!("Could not parse frame from {:02X?}", &data))?;
-		Ok(Self::new(&mut frame))
+		Self::new(&mut Self::parse(&data)?)
 	}
 }
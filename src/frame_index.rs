@@ -0,0 +1,68 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// Position of every frame of a backup, from which decoding can start at any frame
+///
+/// The index is only valid for the backup with the same header.
+#[derive(Serialize, Deserialize)]
+pub struct FrameIndex {
+	/// Salt of the backup header
+	pub salt: Vec<u8>,
+	/// IV of the backup header
+	pub iv: Vec<u8>,
+	/// Position before every frame read, in order of the backup
+	pub frames: Vec<crate::checkpoint::Position>,
+}
+
+impl FrameIndex {
+	/// Creates an empty index of the backup with `salt` and `iv`
+	pub fn new(salt: &[u8], iv: &[u8]) -> Self {
+		Self {
+			salt: salt.to_vec(),
+			iv: iv.to_vec(),
+			frames: Vec::new(),
+		}
+	}
+
+	/// Reads the index at `path`
+	pub fn read(path: &std::path::Path) -> Result<Self, anyhow::Error> {
+		let file = std::fs::File::open(path)
+			.with_context(|| format!("Could not open index: {}", path.to_string_lossy()))?;
+		serde_json::from_reader(std::io::BufReader::new(file))
+			.with_context(|| format!("Index is damaged: {}", path.to_string_lossy()))
+	}
+
+	/// Writes the index to `path`
+	pub fn write(&self, path: &std::path::Path) -> Result<(), anyhow::Error> {
+		let file = std::fs::File::create(path)
+			.with_context(|| format!("Failed to write index: {}", path.to_string_lossy()))?;
+		serde_json::to_writer(std::io::BufWriter::new(file), self)
+			.with_context(|| format!("Failed to write index: {}", path.to_string_lossy()))
+	}
+
+	/// Checks that the index has been written for a backup with `salt` and `iv`
+	pub fn check_header(&self, salt: &[u8], iv: &[u8]) -> Result<(), anyhow::Error> {
+		if self.salt != salt || self.iv != iv {
+			return Err(anyhow!("Index has been written for another backup file"));
+		}
+
+		Ok(())
+	}
+
+	/// Returns the position before frame `number`, counted like in log messages
+	pub fn get(&self, number: usize) -> Result<&crate::checkpoint::Position, anyhow::Error> {
+		self.frames
+			.iter()
+			.find(|x| x.count_frame + 1 == number)
+			.ok_or_else(|| match (self.frames.first(), self.frames.last()) {
+				(Some(first), Some(last)) => anyhow!(
+					"Frame {} is not in the index, which covers frames {} to {}",
+					number,
+					first.count_frame + 1,
+					last.count_frame + 1
+				),
+				_ => anyhow!("Index is empty"),
+			})
+	}
+}
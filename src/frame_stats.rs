@@ -0,0 +1,105 @@
+use log::info;
+use std::io::Write;
+
+/// Width of the bars if the terminal is too narrow
+const MIN_BAR_WIDTH: usize = 10;
+
+/// Colors of the bars, one per frame type in order of first appearance
+const COLORS: [console::Color; 6] = [
+	console::Color::Cyan,
+	console::Color::Magenta,
+	console::Color::Yellow,
+	console::Color::Green,
+	console::Color::Blue,
+	console::Color::Red,
+];
+
+/// Number and bytes of the frames of each type
+///
+/// The bytes of a frame include its HMAC and the data of attachments, avatars and stickers
+/// following it.
+pub struct FrameStats {
+	/// Frame type with number and bytes of its frames, in order of first appearance
+	types: Vec<(&'static str, usize, usize)>,
+}
+
+impl FrameStats {
+	pub fn new() -> Self {
+		Self { types: Vec::new() }
+	}
+
+	/// Counts `frame`, which has been read from `bytes` bytes of the backup
+	pub fn add(&mut self, frame: &crate::frame::Frame, bytes: usize) {
+		let name = frame.type_name();
+		match self.types.iter_mut().find(|(x, ..)| *x == name) {
+			Some((_, count, sum)) => {
+				*count += 1;
+				*sum += bytes;
+			}
+			None => self.types.push((name, 1, bytes)),
+		}
+	}
+
+	/// Prints the proportion of each frame type by count and by bytes
+	///
+	/// On a terminal, colored bars are scaled to its width and written to stderr, unless
+	/// `quiet`. Otherwise, the numbers are logged.
+	pub fn finish(&self, quiet: bool) {
+		let count_total: usize = self.types.iter().map(|(_, count, _)| count).sum();
+		let bytes_total: usize = self.types.iter().map(|(.., bytes)| bytes).sum();
+		if count_total == 0 {
+			return;
+		}
+		let percent = |value: usize, total: usize| 100.0 * value as f64 / total.max(1) as f64;
+
+		let width = match console::Term::stderr().size_checked() {
+			Some((_, width)) if !quiet => width as usize,
+			_ => {
+				for (name, count, bytes) in &self.types {
+					info!(
+						"{} frames: {} ({:.1}% of frames), {} ({:.1}% of bytes)",
+						name,
+						count,
+						percent(*count, count_total),
+						indicatif::HumanBytes(*bytes as u64),
+						percent(*bytes, bytes_total)
+					);
+				}
+				return;
+			}
+		};
+
+		let name_width = self.types.iter().map(|(x, ..)| x.len()).max().unwrap_or(0);
+		// name, value with 10 characters, percentage and the spaces between them
+		let bar_width = width.saturating_sub(name_width + 22).max(MIN_BAR_WIDTH);
+		let mut lines = Vec::new();
+		for (title, total, by_bytes) in [
+			("by count", count_total, false),
+			("by bytes", bytes_total, true),
+		] {
+			lines.push(format!("Frames {}:", title));
+			for (i, (name, count, bytes)) in self.types.iter().enumerate() {
+				let (value, formatted) = if by_bytes {
+					(*bytes, indicatif::HumanBytes(*bytes as u64).to_string())
+				} else {
+					(*count, count.to_string())
+				};
+				let share = percent(value, total);
+				let bar = "#".repeat((share / 100.0 * bar_width as f64).round() as usize);
+				lines.push(format!(
+					"  {:<name_width$} {:>10} {:>5.1}% {}",
+					name,
+					formatted,
+					share,
+					console::style(bar).fg(COLORS[i % COLORS.len()])
+				));
+			}
+		}
+
+		let mut stderr = std::io::stderr().lock();
+		for line in lines {
+			// the summary is not worth failing for
+			let _ = writeln!(stderr, "{}", line);
+		}
+	}
+}
@@ -0,0 +1,77 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use log::info;
+use std::io::Write;
+
+/// Magic bytes at the start of a plaintext frames file
+///
+/// Read as length of a header frame, the first four bytes are far larger than any header of an
+/// encrypted backup. Thus, both formats can be told apart by their first bytes.
+pub const MAGIC: &[u8] = b"signal-backup-decode frames 1\n";
+
+/// Write every frame of a backup decrypted to a plaintext frames file
+///
+/// The file starts with `MAGIC`, followed by every frame as its protobuf prefixed with its
+/// length as 32 bit big endian integer, the header first and the end frame last. The data of
+/// attachments, avatars and stickers follows their frame without prefix, as its length is part
+/// of the frame. Such a file is read again without password by `InputFile`.
+pub struct FramesWriter {
+	writer: std::io::BufWriter<std::fs::File>,
+	written_frames: usize,
+}
+
+impl FramesWriter {
+	/// Creates new writer to file `path`
+	///
+	/// `force_overwrite` determines whether an existing file will be overwritten.
+	pub fn new(path: &std::path::Path, force_overwrite: bool) -> Result<Self, anyhow::Error> {
+		info!("Plaintext frames file: {}", &path.to_string_lossy());
+
+		if path.exists() && !force_overwrite {
+			return Err(anyhow!(
+				"Plaintext frames file already exists and may not be overwritten. Try -f"
+			));
+		}
+
+		let file = std::fs::File::create(path)
+			.with_context(|| format!("Failed to open file: {}", path.to_string_lossy()))?;
+		let mut writer = std::io::BufWriter::new(file);
+		writer
+			.write_all(MAGIC)
+			.context("Failed to write plaintext frames file")?;
+
+		Ok(Self {
+			writer,
+			written_frames: 0,
+		})
+	}
+
+	/// Writes the decrypted protobuf of a frame with its length
+	pub fn write_frame(&mut self, data: &[u8]) -> Result<(), anyhow::Error> {
+		let len = u32::try_from(data.len()).context("Frame is too large")?;
+		self.writer
+			.write_all(&len.to_be_bytes())
+			.and_then(|_| self.writer.write_all(data))
+			.context("Failed to write plaintext frames file")?;
+		self.written_frames += 1;
+
+		Ok(())
+	}
+
+	/// Writes the decrypted data of the last frame, e.g. an attachment
+	pub fn write_data(&mut self, data: &[u8]) -> Result<(), anyhow::Error> {
+		self.writer
+			.write_all(data)
+			.context("Failed to write plaintext frames file")
+	}
+
+	/// Writes all buffered frames
+	pub fn finish(&mut self) -> Result<(), anyhow::Error> {
+		self.writer
+			.flush()
+			.context("Failed to write plaintext frames file")?;
+		info!("Wrote {} plaintext frames", self.written_frames);
+
+		Ok(())
+	}
+}
@@ -0,0 +1,146 @@
+use log::info;
+use serde::Serialize;
+use std::io::Write;
+
+/// Number of frames read to estimate the number of frames of the whole backup
+const ESTIMATE_FRAMES: usize = 1000;
+
+/// Format the information about a backup is printed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InfoFormat {
+	/// Human readable text, one property per line
+	Text,
+	/// A single JSON object
+	Json,
+}
+
+/// Information about a backup, as printed by `print_info`
+#[derive(Serialize)]
+struct Info {
+	version: u32,
+	salt_len: usize,
+	iv_len: usize,
+	file_bytes: Option<u64>,
+	estimated_frames: Option<usize>,
+	/// Whether the backup stores settings as key values, like newer ones, `None` if not known
+	key_value_store: Option<bool>,
+}
+
+/// Number of frames and key value store of a backup, as estimated by `estimate_frames`
+struct Estimate {
+	frames: Option<usize>,
+	key_value_store: Option<bool>,
+}
+
+/// Estimates the number of frames of the backup, including the header
+///
+/// The first frames are read and their number is extrapolated to the size of the backup. Backups
+/// with at most `ESTIMATE_FRAMES` frames are read completely and counted exactly. Key values
+/// are written near the end of a backup, thus their absence is only known for those.
+fn estimate_frames(
+	config: &crate::args::Config,
+	password: Option<&[u8]>,
+) -> Result<Estimate, anyhow::Error> {
+	let mut reader = crate::open_reader(config, password)?;
+	let mut key_value_store = false;
+	while reader.get_count_frame() < ESTIMATE_FRAMES {
+		match reader.next_frame()? {
+			Some(crate::frame::Frame::KeyValue { .. }) => key_value_store = true,
+			Some(_) => (),
+			None => {
+				// the end frame is never written
+				return Ok(Estimate {
+					frames: Some(reader.get_count_frame() - 1),
+					key_value_store: Some(key_value_store),
+				});
+			}
+		}
+	}
+
+	let key_value_store = key_value_store.then_some(true);
+	let file_bytes = match reader.get_file_size() {
+		0 => {
+			return Ok(Estimate {
+				frames: None,
+				key_value_store,
+			});
+		}
+		x => x,
+	};
+	Ok(Estimate {
+		frames: Some(
+			(reader.get_count_frame() as f64 * file_bytes as f64 / reader.get_count_byte() as f64)
+				as usize,
+		),
+		key_value_store,
+	})
+}
+
+/// Prints information about the backup to stdout instead of decoding it
+///
+/// Version, salt and IV are read from the header of the backup. The number of frames is only
+/// estimated if the backup can be read, i.e. with `password` or for a plaintext frames file,
+/// as well as whether it has a key value store.
+/// Log messages are never written to stdout, thus JSON output can be parsed as it is.
+pub fn print_info(
+	config: &crate::args::Config,
+	backup: crate::input::BackupInfo,
+	password: Option<&[u8]>,
+	format: InfoFormat,
+) -> Result<(), anyhow::Error> {
+	let estimate = if backup.plaintext || password.is_some() {
+		estimate_frames(config, password)?
+	} else {
+		info!("Number of frames is not estimated without password");
+		Estimate {
+			frames: None,
+			key_value_store: None,
+		}
+	};
+	let info = Info {
+		version: backup.version,
+		salt_len: backup.salt_len,
+		iv_len: backup.iv_len,
+		file_bytes: backup.file_bytes,
+		estimated_frames: estimate.frames,
+		key_value_store: estimate.key_value_store,
+	};
+
+	let mut stdout = std::io::stdout().lock();
+	match format {
+		InfoFormat::Text => {
+			let unknown = String::from("unknown");
+			writeln!(stdout, "Header version:   {}", info.version)?;
+			writeln!(stdout, "Salt length:      {} bytes", info.salt_len)?;
+			writeln!(stdout, "IV length:        {} bytes", info.iv_len)?;
+			writeln!(
+				stdout,
+				"File size:        {}",
+				info.file_bytes
+					.map_or(unknown.clone(), |x| format!("{} bytes", x))
+			)?;
+			writeln!(
+				stdout,
+				"Estimated frames: {}",
+				info.estimated_frames
+					.map_or(unknown.clone(), |x| x.to_string())
+			)?;
+			writeln!(
+				stdout,
+				"Key-value store:  {}",
+				match info.key_value_store {
+					Some(true) => "yes",
+					Some(false) => "no",
+					None => &unknown,
+				}
+			)?;
+		}
+		InfoFormat::Json => {
+			serde_json::to_writer(&mut stdout, &info)?;
+			writeln!(stdout)?;
+		}
+	}
+	stdout.flush()?;
+
+	Ok(())
+}
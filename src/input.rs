@@ -1,64 +1,381 @@
 use anyhow::anyhow;
 use anyhow::Context;
 use byteorder::ReadBytesExt;
-use log::{debug, info};
+use log::{debug, info, warn};
 use std::convert::TryInto;
+use std::io::BufRead;
 use std::io::Read;
+use std::io::Seek;
+
+/// Length of the salt in the header
+const LENGTH_SALT: usize = 32;
+
+/// Length of the IV in the header
+const LENGTH_IV: usize = 16;
+
+/// Largest plausible length of the header frame in bytes, which only contains salt and IV
+const MAX_HEADER_SIZE: usize = 1024 * 1024;
+
+/// Newest database version of Signal this tool has been tested with
+const LAST_TESTED_VERSION: u32 = 250;
+
+/// Maximum size of a frame in bytes, larger frames are considered corrupted
+const MAX_FRAME_SIZE: usize = 100 * 1024 * 1024;
+
+/// Number of frames whose lengths are looked at when checking the password
+const PREVIEW_FRAMES: usize = 16;
+
+/// Source a backup is read from
+pub trait Source: Read + Seek + Send {
+	/// Returns the current size of the backup, if known
+	fn size(&self) -> Result<Option<u64>, std::io::Error>;
+}
+
+impl Source for std::fs::File {
+	fn size(&self) -> Result<Option<u64>, std::io::Error> {
+		Ok(Some(self.metadata()?.len()))
+	}
+}
+
+/// Buffered reader of a backup
+type Reader = std::io::BufReader<Box<dyn Source>>;
 
 /// Read input file
 pub struct InputFile {
-	reader: std::io::BufReader<std::fs::File>,
-	decrypter: crate::decrypter::Decrypter,
+	reader: Reader,
+	/// Decrypter of the backup, `None` if reading a plaintext frames file
+	decrypter: Option<crate::decrypter::Decrypter>,
 	count_frame: usize,
 	count_byte: usize,
-	file_bytes: u64,
+	file_bytes: Option<u64>,
+	strict: bool,
+	finished: bool,
+	follow: bool,
+	interrupt: Option<std::sync::Arc<std::sync::atomic::AtomicBool>>,
+	async_verify: bool,
+	debug_bytes: bool,
+	header_salt: Vec<u8>,
+	header_iv: Vec<u8>,
+	/// Decrypted data of the header frame
+	header_data: Vec<u8>,
+	/// Writer of every frame as protobuf in JSON
+	protobuf_json: Option<crate::output_protobuf_json::ProtobufJsonWriter>,
+	/// Writer of every frame decrypted to a plaintext frames file
+	frames_dump: Option<crate::frames_file::FramesWriter>,
+	/// Minimum time between reading two frames
+	frame_interval: Option<std::time::Duration>,
+	/// Time the last frame has been read at, if throttled
+	last_frame_read: Option<std::time::Instant>,
+	/// Replace the version of version frames by 0 instead of checking it?
+	ignore_version_frame: bool,
+	/// Fail if bytes follow the end frame?
+	strict_end: bool,
+}
+
+/// Information about a backup which is known without decrypting it
+pub struct BackupInfo {
+	/// Version of the backup header, 0 if it has none
+	pub version: u32,
+	pub salt_len: usize,
+	pub iv_len: usize,
+	/// Size of the backup in bytes, if known
+	pub file_bytes: Option<u64>,
+	/// Is the backup a plaintext frames file?
+	pub plaintext: bool,
 }
 
 impl InputFile {
+	/// Reads the header of the backup at `path`, no password is needed
+	///
+	/// Unlike `new`, salt and IV are not checked, thus the information of malformed headers is
+	/// returned as well.
+	pub fn read_info(
+		path: &std::path::Path,
+		parts: &[std::path::PathBuf],
+	) -> Result<BackupInfo, anyhow::Error> {
+		let (mut reader, file_bytes, plaintext) = open(path, parts)?;
+		let data = read_header_frame(&mut reader, file_bytes)?;
+		let version = header_version(&data)?;
+		match data.try_into()? {
+			crate::frame::Frame::Header { salt, iv } => Ok(BackupInfo {
+				version,
+				salt_len: salt.len(),
+				iv_len: iv.len(),
+				file_bytes,
+				plaintext,
+			}),
+			_ => Err(anyhow!("first frame is not a header")),
+		}
+	}
+
+	/// Opens the backup at `path`
+	///
+	/// A plaintext frames file, written by `set_frames_dump` before, is recognized by its magic
+	/// bytes and read without decrypting, the password is not needed then. Without `password`,
+	/// only plaintext frames files can be read.
+	///
+	/// Keys are derived with `kdf` or, if not given, with the parameters of the version of the
+	/// backup header. A backup split into several files is read from `path` followed by `parts`.
 	pub fn new(
 		path: &std::path::Path,
-		password: &[u8],
+		parts: &[std::path::PathBuf],
+		password: Option<&[u8]>,
+		cipher: crate::decrypter::BackupCipher,
+		kdf: Option<crate::decrypter::KdfParams>,
 		verify_mac: bool,
+		strict: bool,
 	) -> Result<Self, anyhow::Error> {
-		// open file
-		info!("Input file: {}", &path.to_string_lossy());
-		let file = std::fs::File::open(path)
-			.with_context(|| format!("Could not open backup file: {}", path.to_string_lossy()))?;
-		let file_bytes = file.metadata().unwrap().len();
-		let mut reader = std::io::BufReader::new(file);
+		let (mut reader, file_bytes, plaintext) = open(path, parts)?;
+		if plaintext {
+			info!("Input is a plaintext frames file, frames are not decrypted");
+		} else if password.is_none() {
+			return Err(anyhow!(
+				"Backup file is not a plaintext frames file, its password is needed"
+			));
+		}
 
 		// create decrypter
 		// - read first frame
-		let len: usize = reader
-			.read_u32::<byteorder::BigEndian>()
-			.context("Failed to read frame length from backup file")?
-			.try_into()
-			.context("Frame length too large to fit in memory")?;
-		let mut frame = vec![0u8; len];
-		reader.read_exact(&mut frame)?;
+		let frame = read_header_frame(&mut reader, file_bytes)?;
+		let len = frame.len();
+		let header_data = frame.clone();
 		let frame: crate::frame::Frame = frame.try_into()?;
 		debug!("Frame type: {}", &frame);
 
 		// check that frame is a header and return
 		match &frame {
-			crate::frame::Frame::Header { salt, iv } => Ok(Self {
-				reader,
-				decrypter: crate::decrypter::Decrypter::new(&password, &salt, &iv, verify_mac),
-				count_frame: 1,
-				// We already read `len` and 4 bytes with read_u32
-				// There are 16 bytes missing somewhere independent of the input
-				// file. However, I don't know why.
-				count_byte: len + std::mem::size_of::<u32>() + 16,
-				file_bytes,
-			}),
+			crate::frame::Frame::Header { salt, iv } => {
+				check_header(salt, iv)?;
+				let kdf = match kdf {
+					Some(x) => x,
+					None => {
+						let version = header_version(&header_data)?;
+						debug!("Header version: {}", version);
+						crate::decrypter::KdfParams::for_header_version(version)
+					}
+				};
+				if kdf != crate::decrypter::KdfParams::default() && !plaintext {
+					info!("Key derivation: {}", kdf);
+				}
+				Ok(Self {
+					reader,
+					decrypter: match password {
+						Some(password) if !plaintext => Some(crate::decrypter::Decrypter::new(
+							password, salt, iv, verify_mac, cipher, kdf,
+						)?),
+						_ => None,
+					},
+					count_frame: 1,
+					count_byte: if plaintext {
+						crate::frames_file::MAGIC.len() + std::mem::size_of::<u32>() + len
+					} else {
+						// We already read `len` and 4 bytes with read_u32
+						len + std::mem::size_of::<u32>()
+					},
+					file_bytes,
+					strict,
+					finished: false,
+					follow: false,
+					interrupt: None,
+					async_verify: false,
+					debug_bytes: false,
+					header_salt: salt.clone(),
+					header_iv: iv.clone(),
+					header_data,
+					protobuf_json: None,
+					frames_dump: None,
+					frame_interval: None,
+					last_frame_read: None,
+					ignore_version_frame: false,
+					strict_end: false,
+				})
+			}
 			_ => Err(anyhow!("first frame is not a header")),
 		}
 	}
 
+	/// Sets whether to wait for a backup file which is still being written
+	///
+	/// If set, reading waits at the end of the file until more data is written instead of
+	/// failing. Reading thus only finishes with the end frame of the backup.
+	pub fn set_follow(&mut self, follow: bool) {
+		self.follow = follow;
+	}
+
+	/// Sets a flag which stops reading before the next frame once it is set
+	///
+	/// Waiting for more data when following is stopped as well. Reading then fails with
+	/// `DecodeError::Interrupted`.
+	pub fn set_interrupt(&mut self, interrupt: std::sync::Arc<std::sync::atomic::AtomicBool>) {
+		self.interrupt = Some(interrupt);
+	}
+
+	/// Sets whether HMACs are verified in a separate thread while the next frames are read
+	///
+	/// The first frame read is always verified immediately, as its HMAC tells whether the
+	/// password is correct. Failures of later frames are reported when a subsequent frame is
+	/// read, at the latest with the end frame.
+	pub fn set_async_verify(&mut self, async_verify: bool) {
+		self.async_verify = async_verify;
+	}
+
+	/// Waits until the HMACs of all frames read so far are verified
+	///
+	/// With `set_async_verify`, a failure is otherwise only reported while reading a later frame.
+	/// Verification continues in the background with the next frame.
+	pub fn finish_verify(&mut self) -> Result<(), anyhow::Error> {
+		if let Some(decrypter) = &mut self.decrypter {
+			decrypter.finish_verify()?;
+		}
+
+		Ok(())
+	}
+
+	/// Sets the implementation of AES frames are decrypted with from the next frame on
+	pub fn set_crypto_backend(&mut self, backend: crate::decrypter::CryptoBackend) {
+		if let Some(decrypter) = &mut self.decrypter {
+			decrypter.set_backend(backend);
+		}
+	}
+
+	/// Limits reading to at most `frames_per_second` frames per second
+	///
+	/// Before a frame is read, reading sleeps until enough time has passed since the last one.
+	/// The time of writing frames counts towards this interval.
+	pub fn set_max_frames_per_second(&mut self, frames_per_second: Option<u32>) {
+		self.frame_interval = frames_per_second.map(|x| std::time::Duration::from_secs(1) / x);
+	}
+
+	/// Sets whether the version of version frames is ignored
+	///
+	/// The version of ignored frames is replaced by 0, the version of unknown databases, and
+	/// is not checked against the last tested version.
+	pub fn set_ignore_version_frame(&mut self, ignore_version_frame: bool) {
+		self.ignore_version_frame = ignore_version_frame;
+	}
+
+	/// Sets whether bytes following the end frame are an error
+	///
+	/// By default, reading stops at the end frame and ignores anything after it, e.g. padding.
+	pub fn set_strict_end(&mut self, strict_end: bool) {
+		self.strict_end = strict_end;
+	}
+
+	/// Sets whether the byte accounting of every frame is logged
+	///
+	/// For each frame, `count_byte` before and after, the declared lengths and the actual
+	/// position in the file are logged, to find where the count diverges from the position.
+	pub fn set_debug_bytes(&mut self, debug_bytes: bool) {
+		self.debug_bytes = debug_bytes;
+	}
+
+	/// Sets a writer every frame is written to as protobuf in JSON, starting with the header
+	///
+	/// Frames are written as soon as their protobuf is parsed, before it is converted to
+	/// `Frame`, which discards the fields this tool does not use.
+	pub fn set_protobuf_json(
+		&mut self,
+		mut writer: crate::output_protobuf_json::ProtobufJsonWriter,
+	) -> Result<(), anyhow::Error> {
+		writer.write_frame(&crate::frame::Frame::parse(&self.header_data)?)?;
+		self.protobuf_json = Some(writer);
+
+		Ok(())
+	}
+
+	/// Sets a writer every frame is written to decrypted, starting with the header
+	///
+	/// The data of attachments, avatars and stickers is written right after their frame.
+	pub fn set_frames_dump(
+		&mut self,
+		mut writer: crate::frames_file::FramesWriter,
+	) -> Result<(), anyhow::Error> {
+		writer.write_frame(&self.header_data)?;
+		self.frames_dump = Some(writer);
+
+		Ok(())
+	}
+
+	/// Returns whether a plaintext frames file is read, which is not decrypted
+	pub fn is_plaintext(&self) -> bool {
+		self.decrypter.is_none()
+	}
+
+	/// Returns the decrypter of an encrypted backup
+	fn decrypter(&mut self) -> &mut crate::decrypter::Decrypter {
+		self.decrypter
+			.as_mut()
+			.expect("plaintext frames file is not decrypted")
+	}
+
+	/// Waits until `length` bytes after the current position are in the file, if following
+	fn wait_for_data(&mut self, length: usize) -> Result<(), anyhow::Error> {
+		if !self.follow {
+			return Ok(());
+		}
+
+		let mut waiting = false;
+		loop {
+			let position = self.reader.stream_position()?;
+			// reading a source of unknown size simply blocks until data arrives
+			let Some(file_bytes) = self.reader.get_ref().size()? else {
+				return Ok(());
+			};
+			if file_bytes.saturating_sub(position) >= length as u64 {
+				self.file_bytes = Some(file_bytes);
+				return Ok(());
+			}
+
+			if self
+				.interrupt
+				.as_ref()
+				.is_some_and(|x| x.load(std::sync::atomic::Ordering::SeqCst))
+			{
+				return Err(DecodeError::Interrupted.into());
+			}
+
+			if !waiting {
+				info!("Waiting for more data in backup file");
+				waiting = true;
+			}
+			std::thread::sleep(std::time::Duration::from_secs(1));
+		}
+	}
+
 	fn read_data(
 		&mut self,
 		length: usize,
 		read_attachment: bool,
+	) -> Result<Vec<u8>, anyhow::Error> {
+		let data = if self.is_plaintext() {
+			self.read_plaintext_data(length)?
+		} else {
+			self.read_encrypted_data(length, read_attachment)?
+		};
+
+		if let Some(writer) = &mut self.frames_dump
+			&& read_attachment
+		{
+			writer.write_data(&data)?;
+		}
+
+		Ok(data)
+	}
+
+	/// Reads data following a frame of a plaintext frames file, which is stored as is
+	fn read_plaintext_data(&mut self, length: usize) -> Result<Vec<u8>, anyhow::Error> {
+		self.wait_for_data(length)?;
+		let mut data = vec![0u8; length];
+		self.reader.read_exact(&mut data)?;
+		self.count_byte += length;
+
+		Ok(data)
+	}
+
+	fn read_encrypted_data(
+		&mut self,
+		length: usize,
+		read_attachment: bool,
 	) -> Result<Vec<u8>, anyhow::Error> {
 		let mut hmac = [0u8; crate::decrypter::LENGTH_HMAC];
 		let mut data;
@@ -67,7 +384,9 @@ impl InputFile {
 		// And their given length corresponds to file length but frame length corresponds
 		// to data length + hmac data.
 		if read_attachment {
-			self.decrypter.mac_update_with_iv();
+			self.wait_for_data(length + crate::decrypter::LENGTH_HMAC)?;
+			self.check_data_length(length)?;
+			self.decrypter().mac_update_with_iv()?;
 			data = vec![0u8; length];
 		} else {
 			data = vec![0u8; length - crate::decrypter::LENGTH_HMAC];
@@ -75,14 +394,15 @@ impl InputFile {
 
 		// read data and decrypt
 		self.reader.read_exact(&mut data)?;
-		let data = self.decrypter.decrypt(&mut data)?;
+		let data = self.decrypter().decrypt(&data)?;
 
 		// read hmac
 		self.reader.read_exact(&mut hmac)?;
 
 		// verify mac
-		self.decrypter.verify_mac(&hmac)?;
-		self.decrypter.increase_iv();
+		let count_frame = self.count_frame;
+		self.decrypter().verify_mac(&hmac, count_frame + 1)?;
+		self.decrypter().increase_iv();
 
 		if read_attachment {
 			// we got file length, so we have to add 10 bytes for hmac data
@@ -96,8 +416,127 @@ impl InputFile {
 		Ok(data)
 	}
 
+	/// Marks an error of the first frame after the header as wrong password
+	///
+	/// The first frame is the first data decrypted with the password. Thus, if it cannot be
+	/// read, the password is almost always wrong.
+	fn annotate_first_frame(&self, e: impl Into<anyhow::Error>) -> anyhow::Error {
+		let e = e.into();
+		if self.count_frame == 1 && !self.is_plaintext() {
+			e.context(DecodeError::WrongPassword)
+		} else {
+			e
+		}
+	}
+
+	/// Checks whether the database version of the backup has been tested with this tool
+	///
+	/// Newer versions may change the database in ways this tool does not handle correctly. This
+	/// is reported as warning or, in strict mode, as error.
+	fn check_version(&self, version: u32) -> Result<(), anyhow::Error> {
+		if version <= LAST_TESTED_VERSION {
+			return Ok(());
+		}
+
+		let message = format!(
+			"Backup version {} in frame {} is newer than the last tested version {}, results may be incorrect",
+			version,
+			self.count_frame + 1,
+			LAST_TESTED_VERSION
+		);
+		if self.strict {
+			Err(anyhow!(message))
+		} else {
+			warn!("{}", message);
+			Ok(())
+		}
+	}
+
+	/// Checks whether the declared length of attached data is plausible
+	///
+	/// Implausible lengths are a sign of a corrupted backup or a wrong password. They are
+	/// reported as warning or, in strict mode, as error.
+	fn check_data_length(&mut self, length: usize) -> Result<(), anyhow::Error> {
+		let offset = self.reader.stream_position()?;
+		let bytes_remaining = match self.file_bytes {
+			Some(x) => usize::try_from(x.saturating_sub(offset)).unwrap_or(usize::MAX),
+			None => usize::MAX,
+		};
+
+		let problem = if length == 0 {
+			String::from("declares a length of 0 bytes")
+		} else if length + crate::decrypter::LENGTH_HMAC > bytes_remaining {
+			format!(
+				"declares a length of {} bytes but only {} bytes remain in the file",
+				length, bytes_remaining
+			)
+		} else {
+			return Ok(());
+		};
+
+		let message = format!(
+			"Data of frame {} at byte offset {} {}",
+			self.count_frame + 1,
+			offset,
+			problem
+		);
+		if self.strict {
+			Err(anyhow!(message))
+		} else {
+			warn!("{}", message);
+			Ok(())
+		}
+	}
+
+	/// Reads the next frame including its data
+	///
+	/// Errors contain the number and byte offset of the frame to examine damaged backups.
 	pub fn read_frame(&mut self) -> Result<crate::frame::Frame, anyhow::Error> {
+		let number = self.count_frame + 1;
+		let offset = self.reader.stream_position()?;
+
+		self.read_frame_at_offset().with_context(|| {
+			format!("Failed to read frame {} at byte offset {}", number, offset)
+		})
+	}
+
+	/// Reads the length and the protobuf of a frame from a plaintext frames file
+	///
+	/// Returns the length of the frame and its data.
+	fn read_plaintext_frame(&mut self) -> Result<(usize, Vec<u8>), anyhow::Error> {
+		self.wait_for_data(4)?;
+		let len = self
+			.reader
+			.read_u32::<byteorder::BigEndian>()
+			.context("Failed to read frame length from plaintext frames file")? as usize;
+		if len > MAX_FRAME_SIZE {
+			return Err(anyhow!(
+				"Frame {} has unreasonably large length of {} bytes (max {} bytes). This likely indicates a corrupted frames file.",
+				self.count_frame + 1,
+				len,
+				MAX_FRAME_SIZE
+			));
+		}
+		debug!(
+			"Reading frame {} with length of {} bytes",
+			self.count_frame + 1,
+			len
+		);
+
+		self.wait_for_data(len)?;
+		let mut data = vec![0u8; len];
+		self.reader.read_exact(&mut data)?;
+		self.count_byte += std::mem::size_of::<u32>() + len;
+
+		Ok((len, data))
+	}
+
+	/// Reads the length and the protobuf of a frame from an encrypted backup and decrypts them
+	///
+	/// Returns the length of the frame including its HMAC and the decrypted data.
+	fn read_encrypted_frame(&mut self) -> Result<(usize, Vec<u8>), anyhow::Error> {
 		// Read frame length (4 encrypted bytes)
+		self.wait_for_data(4)?;
 		let mut frame_len_bytes = [0u8; 4];
 		self.reader.read_exact(&mut frame_len_bytes)
 			.context("Failed to read frame length from backup file")?;
@@ -108,22 +547,8 @@ impl InputFile {
 			frame_len_bytes
 		);
 		
-		// Preview decrypt the length WITHOUT updating HMAC
-		// We use openssl directly to avoid HMAC side effects
-		let decrypted_len_bytes = openssl::symm::decrypt(
-			openssl::symm::Cipher::aes_256_ctr(),
-			self.decrypter.get_key(),
-			Some(self.decrypter.get_iv()),
-			&frame_len_bytes,
-		).map_err(|e| anyhow!("Failed to decrypt frame length: {}", e))?;
-		
-		let frame_len_raw = u32::from_be_bytes([
-			decrypted_len_bytes[0],
-			decrypted_len_bytes[1],
-			decrypted_len_bytes[2],
-			decrypted_len_bytes[3],
-		]);
-		
+		let frame_len_raw = self.decrypter().decrypt_frame_length(&frame_len_bytes)?;
+
 		debug!(
 			"Decrypted frame length for frame {}: {} bytes (0x{:08X})",
 			self.count_frame + 1,
@@ -136,14 +561,13 @@ impl InputFile {
 			.context(format!("Frame length {} is too large to fit in memory", frame_len_raw))?;
 		
 		// Validate frame length is reasonable (max 100MB per frame)
-		const MAX_FRAME_SIZE: usize = 100 * 1024 * 1024;
 		if len > MAX_FRAME_SIZE {
-			return Err(anyhow!(
+			return Err(self.annotate_first_frame(anyhow!(
 				"Frame {} has unreasonably large length of {} bytes (max {} bytes). This likely indicates a corrupted backup file or incorrect password.",
 				self.count_frame + 1,
 				len,
 				MAX_FRAME_SIZE
-			));
+			)));
 		}
 		
 		debug!(
@@ -151,41 +575,65 @@ impl InputFile {
 			self.count_frame + 1, len
 		);
 
-		// len includes the 10-byte HMAC, so actual encrypted data is len - 10
-		let data_len = len.checked_sub(crate::decrypter::LENGTH_HMAC)
-			.ok_or_else(|| anyhow!("Frame length {} is too small to contain HMAC", len))?;
-		
-		// Read the encrypted frame data
-		let mut encrypted_data = vec![0u8; data_len];
+		// len includes the 10-byte HMAC
+		if len < crate::decrypter::LENGTH_HMAC {
+			return Err(self.annotate_first_frame(anyhow!(
+				"Frame length {} is too small to contain HMAC",
+				len
+			)));
+		}
+
+		// Read the encrypted frame data together with its HMAC
+		self.wait_for_data(len)?;
+		let mut encrypted_data = vec![0u8; len];
 		self.reader.read_exact(&mut encrypted_data)?;
-		
-		// Concatenate length + data and decrypt as ONE continuous stream
-		// This is crucial for CTR mode to work correctly
-		let mut all_encrypted = Vec::with_capacity(4 + data_len);
-		all_encrypted.extend_from_slice(&frame_len_bytes);
-		all_encrypted.extend_from_slice(&encrypted_data);
-		
-		// Decrypt everything together (length + data) - this also updates HMAC
-		let all_decrypted = self.decrypter.decrypt(&all_encrypted)?;
-		
-		// Extract just the frame data part (skip the 4-byte length prefix)
-		let data = all_decrypted[4..].to_vec();
-		
-		// Read and verify HMAC
-		let mut hmac = [0u8; crate::decrypter::LENGTH_HMAC];
-		self.reader.read_exact(&mut hmac)?;
-		self.decrypter.verify_mac(&hmac)?;
-		
-		// Increment IV for next frame
-		self.decrypter.increase_iv();
-		
+
+		let count_frame = self.count_frame;
+		let data = self
+			.decrypter()
+			.decrypt_frame(&frame_len_bytes, &encrypted_data, count_frame + 1)
+			.map_err(|e| self.annotate_first_frame(e))?;
+
 		// Update byte counter (4 bytes length + len bytes for data+hmac)
 		self.count_byte += 4 + len;
 
+		Ok((len, data))
+	}
+
+	fn read_frame_at_offset(&mut self) -> Result<crate::frame::Frame, anyhow::Error> {
+		let count_byte_before = self.count_byte;
+		let position_before = if self.debug_bytes {
+			Some(self.reader.stream_position()?)
+		} else {
+			None
+		};
+
+		let (len, data) = if self.is_plaintext() {
+			self.read_plaintext_frame()?
+		} else {
+			self.read_encrypted_frame()?
+		};
+		if let Some(writer) = &mut self.frames_dump {
+			writer.write_frame(&data)?;
+		}
+
+
 		// Parse frame from decrypted data
-		let mut frame: crate::frame::Frame = data.try_into()?;
+		let mut backup_frame =
+			crate::frame::Frame::parse(&data).map_err(|e| self.annotate_first_frame(e))?;
+		if let Some(writer) = &mut self.protobuf_json {
+			writer.write_frame(&backup_frame)?;
+		}
+		let mut frame = crate::frame::Frame::new(&mut backup_frame)?;
 		debug!("Frame type: {}", &frame);
 
+		let data_length = match frame {
+			crate::frame::Frame::Attachment { data_length, .. }
+			| crate::frame::Frame::Avatar { data_length, .. }
+			| crate::frame::Frame::Sticker { data_length, .. } => Some(data_length),
+			_ => None,
+		};
+
 		match frame {
 			crate::frame::Frame::Attachment { data_length, .. } => {
 				frame.set_data(self.read_data(data_length, true)?);
@@ -197,14 +645,254 @@ impl InputFile {
 				frame.set_data(self.read_data(data_length, true)?);
 			}
 			crate::frame::Frame::Header { .. } => return Err(anyhow!("unexpected header found")),
+			crate::frame::Frame::Version { version } if self.ignore_version_frame => {
+				warn!(
+					"Ignored version {} of frame {}, the database version is unknown",
+					version,
+					self.count_frame + 1
+				);
+				frame = crate::frame::Frame::Version { version: 0 };
+			}
+			crate::frame::Frame::Version { version } => self.check_version(version)?,
 			_ => (),
 		};
 
+		if let Some(position_before) = position_before {
+			let position = self.reader.stream_position()?;
+			info!(
+				"Bytes of frame {}: declared length {}{}, count {} -> {}, position {} -> {}, count - position {}",
+				self.count_frame + 1,
+				len,
+				match data_length {
+					Some(x) => format!(" + data length {}", x),
+					None => String::new(),
+				},
+				count_byte_before,
+				self.count_byte,
+				position_before,
+				position,
+				self.count_byte as i128 - position as i128
+			);
+		}
+
 		// clean up and return
 		self.count_frame += 1;
+		if let Some(decrypter) = &mut self.decrypter
+			&& self.async_verify
+		{
+			decrypter.set_async_verify();
+		}
 		Ok(frame)
 	}
 
+	/// Checks that the end frame is at the end of the backup
+	fn check_end(&mut self) -> Result<(), anyhow::Error> {
+		let position = self.reader.stream_position()?;
+		let mut trailing = [0u8; 1];
+		if self.reader.read(&mut trailing)? == 0 {
+			return Ok(());
+		}
+
+		Err(anyhow!(
+			"Backup has {} after the end frame at byte offset {}, it may be concatenated with another file or corrupted",
+			match self.file_bytes {
+				Some(x) => format!("{} unexpected bytes", x.saturating_sub(position)),
+				None => "unexpected bytes".to_string(),
+			},
+			position
+		))
+	}
+
+	/// Reads the next frame
+	///
+	/// Returns `Ok(None)` only after the end frame, which marks a completely written backup. A
+	/// backup ending before its end frame results in an error.
+	pub fn next_frame(&mut self) -> Result<Option<crate::frame::Frame>, anyhow::Error> {
+		if self.finished {
+			return Ok(None);
+		}
+		if self
+			.interrupt
+			.as_ref()
+			.is_some_and(|x| x.load(std::sync::atomic::Ordering::SeqCst))
+		{
+			return Err(DecodeError::Interrupted.into());
+		}
+
+		if let Some(interval) = self.frame_interval {
+			if let Some(elapsed) = self.last_frame_read.map(|x| x.elapsed())
+				&& elapsed < interval
+			{
+				std::thread::sleep(interval - elapsed);
+			}
+			self.last_frame_read = Some(std::time::Instant::now());
+		}
+
+		match self.read_frame()? {
+			crate::frame::Frame::End => {
+				// the backup is only complete if all frames have been verified
+				self.finish_verify()?;
+				if self.strict_end {
+					self.check_end()?;
+				}
+				if let Some(writer) = &mut self.protobuf_json {
+					writer.finish()?;
+				}
+				if let Some(writer) = &mut self.frames_dump {
+					writer.finish()?;
+				}
+				self.finished = true;
+				Ok(None)
+			}
+			x => Ok(Some(x)),
+		}
+	}
+
+	/// Decrypts the lengths of up to `frames` frames at the current position without reading them
+	///
+	/// A length is plausible if it contains at least an HMAC, is not larger than
+	/// `MAX_FRAME_SIZE` and fits into the backup. As the HMACs are not verified, the frames are
+	/// parsed only to skip the data following attachments, avatars and stickers. Frame
+	/// boundaries are only known through the previous length, thus the preview cannot resync
+	/// and stops at the first implausible length or the end of the backup.
+	///
+	/// Returns the number of plausible lengths and of all lengths checked, the latter being at
+	/// most one more. The position and the IV are restored afterwards.
+	fn preview_frame_lengths(&mut self, frames: usize) -> Result<(usize, usize), anyhow::Error> {
+		let position = self.reader.stream_position()?;
+		let iv = self.decrypter().get_iv().to_vec();
+		let file_bytes = self.file_bytes.unwrap_or(u64::MAX);
+
+		let mut plausible = 0;
+		let mut checked = 0;
+		while checked < frames {
+			let mut encrypted = vec![0u8; 4];
+			if self.reader.read_exact(&mut encrypted).is_err() {
+				break;
+			}
+			checked += 1;
+
+			let len_bytes = self.decrypter().decrypt_preview(&encrypted)?;
+			let len = u32::from_be_bytes(len_bytes[..4].try_into()?) as usize;
+			let end = self.reader.stream_position()?.saturating_add(len as u64);
+			if !(crate::decrypter::LENGTH_HMAC..=MAX_FRAME_SIZE).contains(&len) || end > file_bytes
+			{
+				break;
+			}
+
+			// the length and the frame are decrypted as one stream
+			encrypted.resize(4 + len - crate::decrypter::LENGTH_HMAC, 0);
+			self.reader.read_exact(&mut encrypted[4..])?;
+			self.reader
+				.seek_relative(crate::decrypter::LENGTH_HMAC as i64)?;
+			let data = self.decrypter().decrypt_preview(&encrypted)?;
+			self.decrypter().increase_iv();
+			let frame = match crate::frame::Frame::parse(&data[4..])
+				.and_then(|mut x| crate::frame::Frame::new(&mut x))
+			{
+				Ok(x) => x,
+				Err(_) => break,
+			};
+			plausible += 1;
+
+			if let crate::frame::Frame::Attachment { data_length, .. }
+			| crate::frame::Frame::Avatar { data_length, .. }
+			| crate::frame::Frame::Sticker { data_length, .. } = frame
+			{
+				self.reader
+					.seek_relative((data_length + crate::decrypter::LENGTH_HMAC) as i64)?;
+				self.decrypter().increase_iv();
+			}
+		}
+
+		self.reader.seek(std::io::SeekFrom::Start(position))?;
+		self.decrypter().set_iv(&iv);
+		Ok((plausible, checked))
+	}
+
+	/// Checks the password by reading the first frame after the header
+	///
+	/// Before, the lengths of up to `PREVIEW_FRAMES` frames are decrypted without reading the
+	/// frames. With a wrong password, the length of the first frame already looks random and is
+	/// mostly too large, which is reported without reading that many bytes. The number of
+	/// plausible lengths only shows how far the backup can be followed. The first frame of a
+	/// backup is the database version or a statement. If it cannot be read or is of another
+	/// type, the password is considered wrong as well.
+	pub fn check_password(&mut self) -> Result<(), anyhow::Error> {
+		if !self.is_plaintext() {
+			let (plausible, checked) = self.preview_frame_lengths(PREVIEW_FRAMES)?;
+			info!(
+				"Lengths of {} of {} frames checked are plausible",
+				plausible, checked
+			);
+			if checked > 0 && plausible == 0 {
+				return Err(anyhow!(
+					"Password likely incorrect: length of the first frame is implausible"
+				)
+				.context(DecodeError::WrongPassword));
+			}
+		}
+
+		match self.read_frame()? {
+			crate::frame::Frame::Version { .. } | crate::frame::Frame::Statement { .. } => Ok(()),
+			frame => Err(anyhow!("First frame is of unexpected type: {}", frame)
+				.context(DecodeError::WrongPassword)),
+		}
+	}
+
+	/// Returns salt and IV of the backup header
+	pub fn get_header(&self) -> (&[u8], &[u8]) {
+		(&self.header_salt, &self.header_iv)
+	}
+
+	/// Returns the current position, i.e. the position after the last frame read
+	///
+	/// The IV is empty for a plaintext frames file.
+	pub fn get_position(&mut self) -> Result<crate::checkpoint::Position, anyhow::Error> {
+		Ok(crate::checkpoint::Position {
+			offset: self.reader.stream_position()?,
+			count_frame: self.count_frame,
+			count_byte: self.count_byte,
+			iv: self
+				.decrypter
+				.as_ref()
+				.map(|x| x.get_iv().to_vec())
+				.unwrap_or_default(),
+		})
+	}
+
+	/// Continues reading at `position`, previously returned by `get_position`
+	pub fn resume(&mut self, position: &crate::checkpoint::Position) -> Result<(), anyhow::Error> {
+		// a backup and the plaintext frames file written from it share their header, but not
+		// the offsets of their frames
+		if position.iv.is_empty() != self.is_plaintext() {
+			return Err(anyhow!(
+				"Position has been written for {}",
+				if self.is_plaintext() {
+					"the encrypted backup, not a plaintext frames file"
+				} else {
+					"a plaintext frames file, not the encrypted backup"
+				}
+			));
+		}
+
+		info!(
+			"Resume at frame {} (byte offset {})",
+			position.count_frame + 1,
+			position.offset
+		);
+		self.reader
+			.seek(std::io::SeekFrom::Start(position.offset))
+			.context("Failed to seek to position of checkpoint")?;
+		self.count_frame = position.count_frame;
+		self.count_byte = position.count_byte;
+		if let Some(decrypter) = &mut self.decrypter {
+			decrypter.set_iv(&position.iv);
+		}
+
+		Ok(())
+	}
+
 	pub fn get_count_frame(&self) -> usize {
 		self.count_frame
 	}
@@ -213,8 +901,9 @@ impl InputFile {
 		self.count_byte
 	}
 
+	/// Returns the size of the backup, 0 if it is unknown
 	pub fn get_file_size(&self) -> u64 {
-		self.file_bytes
+		self.file_bytes.unwrap_or(0)
 	}
 }
 
@@ -222,15 +911,554 @@ impl Iterator for InputFile {
 	type Item = Result<crate::frame::Frame, anyhow::Error>;
 
 	fn next(&mut self) -> Option<Self::Item> {
-		let ret = self.read_frame();
+		self.next_frame().transpose()
+	}
+}
 
-		if let Ok(x) = ret {
-			match x {
-				crate::frame::Frame::End => None,
-				_ => Some(Ok(x)),
-			}
-		} else {
-			Some(ret)
+/// Opens the backup at `path`, followed by `parts` if split, and returns its reader, size and
+/// whether it is a plaintext frames file
+///
+/// The magic bytes of a plaintext frames file are consumed, the header frame is read next.
+fn open(
+	path: &std::path::Path,
+	parts: &[std::path::PathBuf],
+) -> Result<(Reader, Option<u64>, bool), anyhow::Error> {
+	// open file, URLs are downloaded while reading
+	info!("Input file: {}", &path.to_string_lossy());
+	let source: Box<dyn Source> = if !parts.is_empty() {
+		Box::new(crate::input_parts::PartsSource::open(path, parts)?)
+	} else if crate::input_http::is_url(path) {
+		Box::new(crate::input_http::HttpSource::open(&path.to_string_lossy())?)
+	} else {
+		Box::new(std::fs::File::open(path).with_context(|| {
+			format!("Could not open backup file: {}", path.to_string_lossy())
+		})?)
+	};
+	let file_bytes = source.size()?;
+	let mut reader = std::io::BufReader::new(source);
+
+	let plaintext = reader.fill_buf()?.starts_with(crate::frames_file::MAGIC);
+	if plaintext {
+		reader.consume(crate::frames_file::MAGIC.len());
+	}
+
+	Ok((reader, file_bytes, plaintext))
+}
+
+/// Reads the unencrypted header frame at the start of a backup
+///
+/// Files which are empty, too short or start with an implausible length are reported as not
+/// being a Signal backup, before a frame of that length is allocated. `file_bytes` is the size
+/// of the file, if known.
+fn read_header_frame(
+	reader: &mut impl Read,
+	file_bytes: Option<u64>,
+) -> Result<Vec<u8>, anyhow::Error> {
+	if file_bytes == Some(0) {
+		return Err(anyhow!("Backup file is empty or not a Signal backup"));
+	}
+
+	let len = match reader.read_u32::<byteorder::BigEndian>() {
+		Ok(x) => x as usize,
+		Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+			return Err(anyhow!(
+				"Backup file is too short for the length of its header, it is empty or not a Signal backup"
+			));
+		}
+		Err(e) => return Err(e).context("Failed to read frame length from backup file"),
+	};
+	if len > MAX_HEADER_SIZE {
+		return Err(anyhow!(
+			"Header has an implausible length of {} bytes, this does not look like a Signal backup",
+			len
+		));
+	}
+
+	let mut frame = vec![0u8; len];
+	reader.read_exact(&mut frame).with_context(|| {
+		format!(
+			"Backup file ends within its header of {} bytes, it is truncated or not a Signal backup",
+			len
+		)
+	})?;
+
+	Ok(frame)
+}
+
+/// Returns the version of the backup header, 0 if it has none
+///
+/// The version is not part of `Backups.proto`, thus it is read from the unknown fields.
+fn header_version(data: &[u8]) -> Result<u32, anyhow::Error> {
+	let frame = crate::frame::Frame::parse(data)?;
+	match frame.header.special_fields.unknown_fields().get(3) {
+		Some(protobuf::UnknownValueRef::Varint(x)) => {
+			u32::try_from(x).context("Header version is too large")
+		}
+		_ => Ok(0),
+	}
+}
+
+/// Checks that salt and IV of the header are present and of the expected length
+///
+/// Otherwise, the key and cipher would be derived from bad parameters and every frame would
+/// silently decrypt to garbage.
+fn check_header(salt: &[u8], iv: &[u8]) -> Result<(), anyhow::Error> {
+	for (name, value, length) in [("salt", salt, LENGTH_SALT), ("iv", iv, LENGTH_IV)] {
+		if value.is_empty() {
+			return Err(anyhow!("malformed header: missing {}", name));
 		}
+		if value.len() != length {
+			return Err(anyhow!(
+				"malformed header: {} has a length of {} bytes instead of {} bytes",
+				name,
+				value.len(),
+				length
+			));
+		}
+	}
+
+	Ok(())
+}
+
+/// Errors of decoding a backup which need special handling by the caller
+#[derive(Debug)]
+pub enum DecodeError {
+	/// The first frame could not be decrypted
+	WrongPassword,
+	/// Reading has been stopped by Ctrl-C
+	Interrupted,
+}
+
+impl std::error::Error for DecodeError {}
+
+impl std::fmt::Display for DecodeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::WrongPassword => write!(f, "Backup password is probably wrong"),
+			Self::Interrupted => write!(f, "Decoding has been interrupted"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use protobuf::Message;
+
+	fn header(salt: Option<Vec<u8>>, iv: Option<Vec<u8>>) -> crate::frame::Frame {
+		let mut header = crate::Backups::Header::new();
+		header.salt = salt;
+		header.iv = iv;
+		let mut frame = crate::Backups::BackupFrame::new();
+		frame.header = protobuf::MessageField::some(header);
+
+		frame.write_to_bytes().unwrap().try_into().unwrap()
+	}
+
+	fn check(frame: crate::frame::Frame) -> Result<(), anyhow::Error> {
+		match frame {
+			crate::frame::Frame::Header { salt, iv } => check_header(&salt, &iv),
+			_ => panic!("frame is not a header"),
+		}
+	}
+
+	#[test]
+	fn header_complete() {
+		assert!(check(header(Some(vec![1; 32]), Some(vec![2; 16]))).is_ok());
+	}
+
+	#[test]
+	fn header_missing_salt_and_iv() {
+		let e = check(header(None, None)).unwrap_err();
+		assert_eq!(e.to_string(), "malformed header: missing salt");
+
+		let e = check(header(Some(vec![1; 32]), None)).unwrap_err();
+		assert_eq!(e.to_string(), "malformed header: missing iv");
+	}
+
+	#[test]
+	fn header_frame_not_a_backup() {
+		let read = |data: &[u8]| {
+			read_header_frame(&mut std::io::Cursor::new(data), Some(data.len() as u64))
+				.unwrap_err()
+				.to_string()
+		};
+
+		assert_eq!(read(b""), "Backup file is empty or not a Signal backup");
+		assert!(read(b"PK").contains("too short"));
+		// "SQLi" of an SQLite database read as length
+		assert!(read(b"SQLite format 3\0").contains("implausible length of 1397836905 bytes"));
+		assert!(read(&[0, 0, 0, 100, 1, 2]).contains("truncated"));
+
+		let frame = read_header_frame(&mut std::io::Cursor::new([0, 0, 0, 2, 1, 2]), None);
+		assert_eq!(frame.unwrap(), [1, 2]);
+	}
+
+	#[test]
+	fn plaintext_frames() {
+		let path = std::env::temp_dir().join(format!(
+			"signal-backup-decode-{}.frames",
+			std::process::id()
+		));
+		let mut writer = crate::frames_file::FramesWriter::new(&path, true).unwrap();
+		let mut header = crate::Backups::Header::new();
+		header.salt = Some(vec![1; 32]);
+		header.iv = Some(vec![2; 16]);
+		let mut frame = crate::Backups::BackupFrame::new();
+		frame.header = protobuf::MessageField::some(header);
+		writer.write_frame(&frame.write_to_bytes().unwrap()).unwrap();
+		let mut version = crate::Backups::DatabaseVersion::new();
+		version.version = Some(1);
+		let mut frame = crate::Backups::BackupFrame::new();
+		frame.version = protobuf::MessageField::some(version);
+		writer.write_frame(&frame.write_to_bytes().unwrap()).unwrap();
+		let mut frame = crate::Backups::BackupFrame::new();
+		frame.end = Some(true);
+		writer.write_frame(&frame.write_to_bytes().unwrap()).unwrap();
+		writer.finish().unwrap();
+		drop(writer);
+
+		let reader = InputFile::new(
+			&path,
+			&[],
+			None,
+			crate::decrypter::BackupCipher::default(),
+			None,
+			true,
+			false,
+		);
+		let frames = reader
+			.and_then(|reader| reader.collect::<Result<Vec<_>, _>>())
+			.map(|frames| frames.iter().map(|x| x.to_string()).collect::<Vec<_>>());
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(
+			frames.unwrap(),
+			[crate::frame::Frame::Version { version: 1 }.to_string()]
+		);
+	}
+
+	#[test]
+	fn plaintext_frames_corrupt_length() {
+		let path = std::env::temp_dir().join(format!(
+			"signal-backup-decode-{}-corrupt.frames",
+			std::process::id()
+		));
+		let mut writer = crate::frames_file::FramesWriter::new(&path, true).unwrap();
+		let mut header = crate::Backups::Header::new();
+		header.salt = Some(vec![1; 32]);
+		header.iv = Some(vec![2; 16]);
+		let mut frame = crate::Backups::BackupFrame::new();
+		frame.header = protobuf::MessageField::some(header);
+		writer
+			.write_frame(&frame.write_to_bytes().unwrap())
+			.unwrap();
+		writer.finish().unwrap();
+		drop(writer);
+		let mut file = std::fs::OpenOptions::new()
+			.append(true)
+			.open(&path)
+			.unwrap();
+		std::io::Write::write_all(&mut file, &[0xff; 8]).unwrap();
+		drop(file);
+
+		let reader = InputFile::new(
+			&path,
+			&[],
+			None,
+			crate::decrypter::BackupCipher::default(),
+			None,
+			true,
+			false,
+		);
+		let e = reader
+			.and_then(|mut reader| reader.next_frame())
+			.err()
+			.unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert!(
+			format!("{:#}", e).contains("unreasonably large length"),
+			"{:#}",
+			e
+		);
+	}
+
+	#[test]
+	fn frame_fields() {
+		// version frame with the unknown field 20 set as well
+		let mut data = vec![42, 2, 8, 1];
+		data.extend_from_slice(&[160, 1, 5]);
+		let frame: crate::frame::Frame = data.try_into().unwrap();
+		assert_eq!(
+			frame.to_string(),
+			crate::frame::Frame::Version { version: 1 }.to_string()
+		);
+
+		let frame: crate::frame::Frame = vec![160, 1, 5].try_into().unwrap();
+		assert_eq!(frame.to_string(), "Unknown (fields: [20])");
+
+		// version and end frame at once
+		let frame: Result<crate::frame::Frame, _> = vec![42, 2, 8, 1, 48, 1].try_into();
+		assert!(frame.is_err());
+
+		// statement with a parameter of the unknown type 20
+		let frame: Result<crate::frame::Frame, _> =
+			vec![18, 8, 10, 1, b'X', 18, 3, 160, 1, 5].try_into();
+		let e = frame.err().unwrap().to_string();
+		assert!(
+			e.contains("Parameter 1 of statement has an unknown type (fields: [20]): X"),
+			"{}",
+			e
+		);
+	}
+
+	/// Checks the keys, the keystream and the HMAC of the first frame of
+	/// `tests/fixtures/tiny.backup` against answers of an implementation independent of this tool,
+	/// see `tests/fixtures/known_answers.py`
+	#[test]
+	fn test_vector_known_answers() {
+		let hex = |x: &str| -> Vec<u8> {
+			(0..x.len())
+				.step_by(2)
+				.map(|i| u8::from_str_radix(&x[i..i + 2], 16).unwrap())
+				.collect()
+		};
+		let keys = hex(
+			"8be0a62118e80d9158a7b4a1bab265c520f938d2d479bf738c1a1aeb7ca31c73\
+			a5a03a0f7e2cf3cf780ae3858e94c4f8f23e1c322d6db940156729c2c115a538",
+		);
+		let keystream = hex("87f4c1b66da4276c06fd15e08c364bf416941356c208ceb0322cf85a43198be3");
+		let mac = hex("10f5b529cef1fbc05394");
+		let password = b"123456789012345678901234567890";
+
+		let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tiny.backup");
+		let data = std::fs::read(&path).unwrap();
+		let reader = InputFile::new(
+			&path,
+			&[],
+			Some(password),
+			crate::decrypter::BackupCipher::default(),
+			None,
+			true,
+			true,
+		)
+		.unwrap();
+		let (salt, iv) = reader.get_header();
+
+		// key derivation
+		let okm =
+			crate::decrypter::derive_keys(password, salt, crate::decrypter::KdfParams::default());
+		assert_eq!(okm.to_vec(), keys);
+
+		// keystream of the first frame, which starts with its length
+		let mut decrypter = crate::decrypter::Decrypter::new(
+			password,
+			salt,
+			iv,
+			true,
+			crate::decrypter::BackupCipher::default(),
+			crate::decrypter::KdfParams::default(),
+		)
+		.unwrap();
+		assert_eq!(decrypter.decrypt_preview(&[0; 32]).unwrap(), keystream);
+
+		// the first frame follows the header, its HMAC is truncated to 10 bytes
+		let offset = 4 + u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+		let length_encrypted: [u8; 4] = data[offset..offset + 4].try_into().unwrap();
+		assert_eq!(
+			decrypter.decrypt_frame_length(&length_encrypted).unwrap(),
+			14
+		);
+		let frame = &data[offset + 4..offset + 4 + 14];
+		assert_eq!(&frame[4..], mac);
+		assert_eq!(
+			decrypter
+				.decrypt_frame(&length_encrypted, frame, 2)
+				.unwrap(),
+			// BackupFrame { version: { version: 42 } }
+			[0x2a, 0x02, 0x08, 0x2a]
+		);
+	}
+
+	/// Decodes the backup `tests/fixtures/tiny.backup` and compares its frames to
+	/// `tests/fixtures/tiny.expected`, statements with their parameters and media with their hash
+	#[test]
+	fn test_vector() {
+		let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+		let mut reader = InputFile::new(
+			&path.join("tiny.backup"),
+			&[],
+			Some(b"123456789012345678901234567890"),
+			crate::decrypter::BackupCipher::default(),
+			None,
+			true,
+			true,
+		)
+		.unwrap();
+
+		let mut frames = Vec::new();
+		while let Some(frame) = reader.next_frame().unwrap() {
+			frames.push(match &frame {
+				crate::frame::Frame::Statement {
+					statement,
+					parameter,
+				} => format!("Statement: {} {:?}", statement, parameter),
+				crate::frame::Frame::Attachment { data: Some(data), .. }
+				| crate::frame::Frame::Avatar { data: Some(data), .. }
+				| crate::frame::Frame::Sticker { data: Some(data), .. } => {
+					format!("{}: {}", frame, crate::output_sink::hash_hex(data))
+				}
+				_ => frame.to_string(),
+			});
+		}
+		let expected = std::fs::read_to_string(path.join("tiny.expected")).unwrap();
+		assert_eq!(frames, expected.lines().collect::<Vec<_>>());
+
+		// header and end frame are counted, but not returned
+		assert_eq!(reader.get_count_frame(), frames.len() + 2);
+		// the whole file is read and every byte of it is counted
+		assert_eq!(reader.get_position().unwrap().offset, reader.get_file_size());
+		assert_eq!(reader.get_count_byte() as u64, reader.get_file_size());
+	}
+
+	/// Checks the password of `tests/fixtures/tiny.backup` by the lengths of its frames, which
+	/// are all plausible with the right password and are read unchanged afterwards
+	#[test]
+	fn preview_frame_lengths() {
+		let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+		let open = |password: &[u8]| {
+			InputFile::new(
+				&path.join("tiny.backup"),
+				&[],
+				Some(password),
+				crate::decrypter::BackupCipher::default(),
+				None,
+				true,
+				true,
+			)
+			.unwrap()
+		};
+
+		let mut reader = open(b"123456789012345678901234567890");
+		let frames = std::fs::read_to_string(path.join("tiny.expected"))
+			.unwrap()
+			.lines()
+			.count();
+		// all frames including the end frame, attachments are skipped with their data
+		assert_eq!(
+			reader.preview_frame_lengths(100).unwrap(),
+			(frames + 1, frames + 1)
+		);
+		assert_eq!(reader.preview_frame_lengths(3).unwrap(), (3, 3));
+		reader.check_password().unwrap();
+		assert_eq!(
+			reader.collect::<Result<Vec<_>, _>>().unwrap().len(),
+			frames - 1
+		);
+
+		let mut reader = open(b"123456789012345678901234567891");
+		let e = reader.check_password().unwrap_err();
+		assert!(matches!(
+			e.downcast_ref::<DecodeError>(),
+			Some(DecodeError::WrongPassword)
+		));
+		assert_eq!(reader.get_count_frame(), 1);
+	}
+
+	/// Decodes `tests/fixtures/tiny.backup` split into three parts, with parts given in the wrong
+	/// order being rejected
+	#[test]
+	fn split_parts() {
+		let data = std::fs::read(
+			std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tiny.backup"),
+		)
+		.unwrap();
+		let dir =
+			std::env::temp_dir().join(format!("signal-backup-decode-parts-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let paths: Vec<std::path::PathBuf> = data
+			.chunks(data.len().div_ceil(3))
+			.enumerate()
+			.map(|(i, chunk)| {
+				let path = dir.join(format!("tiny.backup.part{}", i + 1));
+				std::fs::write(&path, chunk).unwrap();
+				path
+			})
+			.collect();
+
+		let read = |parts: &[std::path::PathBuf]| {
+			InputFile::new(
+				&paths[0],
+				parts,
+				Some(b"123456789012345678901234567890"),
+				crate::decrypter::BackupCipher::default(),
+				None,
+				true,
+				true,
+			)
+			.and_then(|reader| reader.collect::<Result<Vec<_>, _>>())
+			.map(|frames| frames.len())
+		};
+		let frames = read(&paths[1..]);
+		let reversed = read(&[paths[2].clone(), paths[1].clone()]);
+		std::fs::remove_dir_all(&dir).unwrap();
+
+		let expected = std::fs::read_to_string(
+			std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tiny.expected"),
+		)
+		.unwrap();
+		assert_eq!(frames.unwrap(), expected.lines().count());
+		assert!(reversed.unwrap_err().to_string().contains("not in order"));
+	}
+
+	/// Reads `tests/fixtures/tiny.backup` with bytes appended after its end frame, which are only
+	/// an error with `set_strict_end`
+	#[test]
+	fn strict_end() {
+		let mut data = std::fs::read(
+			std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tiny.backup"),
+		)
+		.unwrap();
+		let size = data.len();
+		data.extend_from_slice(&[0; 5]);
+		let path =
+			std::env::temp_dir().join(format!("signal-backup-decode-end-{}", std::process::id()));
+		std::fs::write(&path, &data).unwrap();
+
+		let read = |strict_end: bool| {
+			let mut reader = InputFile::new(
+				&path,
+				&[],
+				Some(b"123456789012345678901234567890"),
+				crate::decrypter::BackupCipher::default(),
+				None,
+				true,
+				true,
+			)?;
+			reader.set_strict_end(strict_end);
+			reader
+				.collect::<Result<Vec<_>, _>>()
+				.map(|frames| frames.len())
+		};
+		let lenient = read(false);
+		let strict = read(true);
+		std::fs::remove_file(&path).unwrap();
+
+		assert!(lenient.is_ok());
+		assert_eq!(
+			strict.unwrap_err().to_string(),
+			format!(
+				"Backup has 5 unexpected bytes after the end frame at byte offset {}, it may be concatenated with another file or corrupted",
+				size
+			)
+		);
+	}
+
+	#[test]
+	fn header_wrong_length() {
+		let e = check(header(Some(vec![1; 32]), Some(vec![2; 12]))).unwrap_err();
+		assert_eq!(
+			e.to_string(),
+			"malformed header: iv has a length of 12 bytes instead of 16 bytes"
+		);
 	}
 }
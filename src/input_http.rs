@@ -0,0 +1,578 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use log::{info, warn};
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+
+/// Maximum number of redirects followed for a request
+const MAX_REDIRECTS: usize = 5;
+
+/// Number of times a dropped connection is resumed at the same position
+const MAX_RETRIES: usize = 3;
+
+/// Maximum length in bytes of the status line and of each header line of a response
+const MAX_HEADER_LINE: usize = 8192;
+
+/// Maximum number of header lines of a response
+const MAX_HEADERS: usize = 100;
+
+/// Time to wait for a connection to the server
+const TIMEOUT_CONNECT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Time to wait for data from or sending data to the server, after which the connection is
+/// considered dropped
+const TIMEOUT_READ_WRITE: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Returns whether `path` is an http(s) URL instead of a file path
+pub fn is_url(path: &std::path::Path) -> bool {
+	path.to_str()
+		.is_some_and(|x| x.starts_with("http://") || x.starts_with("https://"))
+}
+
+/// URL of a backup
+#[derive(Clone)]
+struct Url {
+	https: bool,
+	/// Host name or IP address, IPv6 addresses without brackets
+	host: String,
+	port: u16,
+	/// Path including the query
+	path: String,
+}
+
+impl Url {
+	fn parse(url: &str) -> Result<Self, anyhow::Error> {
+		let (https, rest) = if let Some(x) = url.strip_prefix("https://") {
+			(true, x)
+		} else if let Some(x) = url.strip_prefix("http://") {
+			(false, x)
+		} else {
+			return Err(anyhow!("Unsupported URL: {}", url));
+		};
+
+		let (authority, path) = match rest.find('/') {
+			Some(i) => (&rest[..i], &rest[i..]),
+			None => (rest, "/"),
+		};
+		// IPv6 addresses are enclosed in brackets, as they contain colons themselves
+		let (host, port) = if let Some(x) = authority.strip_prefix('[') {
+			let (host, rest) = x
+				.split_once(']')
+				.with_context(|| format!("Invalid IPv6 address in URL: {}", url))?;
+			let port = match rest {
+				"" => None,
+				x => Some(
+					x.strip_prefix(':')
+						.with_context(|| format!("Invalid port in URL: {}", url))?,
+				),
+			};
+			(host, port)
+		} else {
+			match authority.rsplit_once(':') {
+				Some((host, port)) => (host, Some(port)),
+				None => (authority, None),
+			}
+		};
+		let port = match port {
+			Some(x) => x
+				.parse()
+				.with_context(|| format!("Invalid port in URL: {}", url))?,
+			None => default_port(https),
+		};
+		if host.is_empty() {
+			return Err(anyhow!("URL has no host: {}", url));
+		}
+		if host.contains(':') && !authority.starts_with('[') {
+			return Err(anyhow!("IPv6 address in URL needs brackets: {}", url));
+		}
+
+		Ok(Self {
+			https,
+			host: host.to_string(),
+			port,
+			path: path.to_string(),
+		})
+	}
+
+	/// Returns host and port as written in a URL, the port only if it is not the default one
+	fn authority(&self) -> String {
+		let host = if self.host.contains(':') {
+			format!("[{}]", self.host)
+		} else {
+			self.host.clone()
+		};
+		if self.port == default_port(self.https) {
+			host
+		} else {
+			format!("{}:{}", host, self.port)
+		}
+	}
+
+	/// Resolves the `Location` of a redirect relative to this URL as in RFC 3986
+	///
+	/// Redirects from HTTPS to plain HTTP are refused, as they would expose the download.
+	fn join(&self, location: &str) -> Result<Self, anyhow::Error> {
+		// the fragment is never sent to the server
+		let location = location.split('#').next().unwrap_or_default();
+		let url = if location.starts_with("http://") || location.starts_with("https://") {
+			Self::parse(location)?
+		} else if location.starts_with("//") {
+			let scheme = if self.https { "https:" } else { "http:" };
+			Self::parse(&format!("{}{}", scheme, location))?
+		} else if location.split_once(':').is_some_and(|(scheme, _)| {
+			scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+				&& scheme
+					.chars()
+					.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+		}) {
+			return Err(anyhow!("Unsupported redirect location: {}", location));
+		} else {
+			let base = match self.path.find('?') {
+				Some(i) => &self.path[..i],
+				None => &self.path,
+			};
+			let path = if location.is_empty() {
+				self.path.clone()
+			} else if location.starts_with('/') {
+				remove_dot_segments(location)
+			} else if location.starts_with('?') {
+				format!("{}{}", base, location)
+			} else {
+				// relative to the directory of the current path
+				let directory = &base[..=base.rfind('/').unwrap_or_default()];
+				remove_dot_segments(&format!("{}{}", directory, location))
+			};
+			Self {
+				path,
+				..self.clone()
+			}
+		};
+		if self.https && !url.https {
+			return Err(anyhow!(
+				"Refusing redirect from HTTPS to HTTP: {}",
+				location
+			));
+		}
+
+		Ok(url)
+	}
+}
+
+impl std::fmt::Display for Url {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"{}://{}{}",
+			if self.https { "https" } else { "http" },
+			self.authority(),
+			self.path
+		)
+	}
+}
+
+/// Removes the segments `.` and `..` of an absolute path, which may end with a query
+fn remove_dot_segments(path: &str) -> String {
+	let (path, query) = match path.find('?') {
+		Some(i) => path.split_at(i),
+		None => (path, ""),
+	};
+	let mut segments: Vec<&str> = Vec::new();
+	let parts: Vec<&str> = path.split('/').skip(1).collect();
+	for (i, segment) in parts.iter().enumerate() {
+		let last = i + 1 == parts.len();
+		match *segment {
+			"." | ".." => {
+				if *segment == ".." {
+					segments.pop();
+				}
+				// a trailing dot segment refers to the directory
+				if last {
+					segments.push("");
+				}
+			}
+			x => segments.push(x),
+		}
+	}
+
+	format!("/{}{}", segments.join("/"), query)
+}
+
+/// Returns the port used if a URL gives none
+fn default_port(https: bool) -> u16 {
+	if https { 443 } else { 80 }
+}
+
+/// Returns the first byte and, if known, the size of the backup of a `Content-Range` header
+///
+/// The header has the form `bytes START-END/SIZE`, with `*` as SIZE if it is unknown.
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+	let (range, size) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+	let (start, _) = range.split_once('-')?;
+	Some((start.trim().parse().ok()?, size.trim().parse().ok()))
+}
+
+/// Reads a line of the status or headers of a response, at most `MAX_HEADER_LINE` bytes long
+fn read_header_line(response: &mut impl BufRead) -> Result<String, anyhow::Error> {
+	let mut line = String::new();
+	response
+		.take(MAX_HEADER_LINE as u64 + 1)
+		.read_line(&mut line)?;
+	if line.len() > MAX_HEADER_LINE {
+		return Err(anyhow!(
+			"HTTP response has a line longer than {} bytes",
+			MAX_HEADER_LINE
+		));
+	}
+
+	Ok(line)
+}
+
+/// Connection to a server, with or without TLS
+trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+/// Backup read over HTTP(S) without downloading it first
+///
+/// Seeking and dropped connections are handled by a new request of the remaining data with a
+/// `Range` header. Servers ignoring the range send the whole backup, whose beginning is then
+/// skipped.
+pub struct HttpSource {
+	url: Url,
+	response: Option<std::io::BufReader<Box<dyn Stream>>>,
+	position: u64,
+	size: Option<u64>,
+}
+
+impl HttpSource {
+	/// Starts downloading the backup at `url`
+	pub fn open(url: &str) -> Result<Self, anyhow::Error> {
+		let mut source = Self {
+			url: Url::parse(url)?,
+			response: None,
+			position: 0,
+			size: None,
+		};
+		source
+			.request(0)
+			.with_context(|| format!("Could not download backup: {}", url))?;
+
+		Ok(source)
+	}
+
+	/// Opens a connection to the host of `url`
+	///
+	/// Every address of the host is tried in turn. Connections time out after
+	/// `TIMEOUT_CONNECT`, reads and writes after `TIMEOUT_READ_WRITE`.
+	fn connect(url: &Url) -> Result<Box<dyn Stream>, anyhow::Error> {
+		let addresses = std::net::ToSocketAddrs::to_socket_addrs(&(url.host.as_str(), url.port))
+			.with_context(|| format!("Could not resolve {}", url.authority()))?;
+		let mut result = Err(anyhow!("No address found for {}", url.authority()));
+		for address in addresses {
+			result = std::net::TcpStream::connect_timeout(&address, TIMEOUT_CONNECT)
+				.with_context(|| format!("Could not connect to {}", url.authority()));
+			if result.is_ok() {
+				break;
+			}
+		}
+		let stream = result?;
+		stream.set_read_timeout(Some(TIMEOUT_READ_WRITE))?;
+		stream.set_write_timeout(Some(TIMEOUT_READ_WRITE))?;
+		if !url.https {
+			return Ok(Box::new(stream));
+		}
+
+		let connector =
+			openssl::ssl::SslConnector::builder(openssl::ssl::SslMethod::tls())?.build();
+		Ok(Box::new(connector.connect(&url.host, stream).map_err(
+			|e| anyhow!("TLS handshake with {} failed: {}", url.host, e),
+		)?))
+	}
+
+	/// Requests the backup from byte `offset` on, following redirects
+	fn request(&mut self, offset: u64) -> Result<(), anyhow::Error> {
+		self.response = None;
+
+		for _ in 0..=MAX_REDIRECTS {
+			let mut stream = Self::connect(&self.url)?;
+
+			// HTTP/1.0 responses are never chunked and end with the connection
+			let mut request = format!(
+				"GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: {}/{}\r\nAccept-Encoding: identity\r\n",
+				self.url.path,
+				self.url.authority(),
+				clap::crate_name!(),
+				clap::crate_version!()
+			);
+			if offset > 0 {
+				request.push_str(&format!("Range: bytes={}-\r\n", offset));
+			}
+			request.push_str("\r\n");
+			stream.write_all(request.as_bytes())?;
+
+			let mut response = std::io::BufReader::new(stream);
+			let status_line = read_header_line(&mut response)?;
+			let status: u16 = status_line
+				.split_whitespace()
+				.nth(1)
+				.and_then(|x| x.parse().ok())
+				.with_context(|| format!("Invalid HTTP response: {}", status_line.trim()))?;
+
+			let mut headers = std::collections::HashMap::new();
+			for i in 0.. {
+				let line = read_header_line(&mut response)?;
+				if line.trim().is_empty() {
+					break;
+				}
+				if i == MAX_HEADERS {
+					return Err(anyhow!(
+						"HTTP response has more than {} header lines",
+						MAX_HEADERS
+					));
+				}
+				if let Some((name, value)) = line.split_once(':') {
+					headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+				}
+			}
+			let content_length = headers
+				.get("content-length")
+				.and_then(|x| x.parse::<u64>().ok());
+
+			match status {
+				301 | 302 | 303 | 307 | 308 => {
+					let location = headers
+						.get("location")
+						.context("Redirect without location")?;
+					self.url = self.url.join(location)?;
+					info!("Redirected to {}", self.url);
+				}
+				206 => {
+					let (start, size) = headers
+						.get("content-range")
+						.and_then(|x| parse_content_range(x))
+						.context("Partial response without valid Content-Range")?;
+					if start != offset {
+						return Err(anyhow!(
+							"Server sent data from byte {} instead of byte {}",
+							start,
+							offset
+						));
+					}
+					self.size = size.or(self.size);
+					self.response = Some(response);
+					self.position = offset;
+					return Ok(());
+				}
+				200 => {
+					self.size = content_length.or(self.size);
+					if offset > 0 {
+						warn!(
+							"Server does not support ranged requests, skipping {} bytes",
+							offset
+						);
+						let skipped =
+							std::io::copy(&mut (&mut response).take(offset), &mut std::io::sink())?;
+						if skipped < offset {
+							return Err(anyhow!("Download ended after {} bytes", skipped));
+						}
+					}
+					self.response = Some(response);
+					self.position = offset;
+					return Ok(());
+				}
+				_ => {
+					return Err(anyhow!(
+						"Server returned HTTP status {} for {}",
+						status,
+						self.url
+					));
+				}
+			}
+		}
+
+		Err(anyhow!("Too many redirects for {}", self.url))
+	}
+
+	/// Returns whether data is missing after a download ended at the current position
+	fn is_incomplete(&self) -> bool {
+		self.size.is_some_and(|x| self.position < x)
+	}
+}
+
+impl Read for HttpSource {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let mut retries = 0;
+		loop {
+			let result = match &mut self.response {
+				Some(response) => response.read(buf),
+				None => Ok(0),
+			};
+
+			match result {
+				Ok(0) if buf.is_empty() || !self.is_incomplete() => return Ok(0),
+				Ok(count) if count > 0 => {
+					self.position += count as u64;
+					return Ok(count);
+				}
+				Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+				// the connection has been dropped
+				result if retries < MAX_RETRIES => {
+					retries += 1;
+					warn!(
+						"Download interrupted at byte {} ({}), resuming",
+						self.position,
+						match result {
+							Err(e) => e.to_string(),
+							Ok(_) => String::from("connection closed"),
+						}
+					);
+					self.request(self.position).map_err(std::io::Error::other)?;
+				}
+				Ok(_) => return Ok(0),
+				Err(e) => return Err(e),
+			}
+		}
+	}
+}
+
+impl std::io::Seek for HttpSource {
+	fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+		let target = match pos {
+			std::io::SeekFrom::Start(x) => Some(x),
+			std::io::SeekFrom::Current(x) => self.position.checked_add_signed(x),
+			std::io::SeekFrom::End(x) => self.size.and_then(|size| size.checked_add_signed(x)),
+		}
+		.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid seek"))?;
+
+		if target != self.position {
+			self.request(target).map_err(std::io::Error::other)?;
+		}
+
+		Ok(self.position)
+	}
+}
+
+impl crate::input::Source for HttpSource {
+	fn size(&self) -> Result<Option<u64>, std::io::Error> {
+		Ok(self.size)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn url_parse() {
+		let url = Url::parse("https://example.org/backups/signal.backup?token=1").unwrap();
+		assert!(url.https);
+		assert_eq!(url.host, "example.org");
+		assert_eq!(url.port, 443);
+		assert_eq!(url.path, "/backups/signal.backup?token=1");
+		assert_eq!(url.authority(), "example.org");
+
+		let url = Url::parse("http://example.org:8080").unwrap();
+		assert!(!url.https);
+		assert_eq!(url.port, 8080);
+		assert_eq!(url.path, "/");
+		// the Host header needs a port other than the default one
+		assert_eq!(url.authority(), "example.org:8080");
+		assert_eq!(url.to_string(), "http://example.org:8080/");
+
+		let url = Url::parse("http://[::1]:8080/signal.backup").unwrap();
+		assert_eq!(url.host, "::1");
+		assert_eq!(url.port, 8080);
+		assert_eq!(url.authority(), "[::1]:8080");
+
+		let url = Url::parse("https://[2001:db8::1]/signal.backup").unwrap();
+		assert_eq!(url.host, "2001:db8::1");
+		assert_eq!(url.port, 443);
+		assert_eq!(url.to_string(), "https://[2001:db8::1]/signal.backup");
+
+		assert!(Url::parse("ftp://example.org/").is_err());
+		assert!(Url::parse("http:///signal.backup").is_err());
+		assert!(Url::parse("http://example.org:port/").is_err());
+		assert!(Url::parse("http://[::1/").is_err());
+		assert!(Url::parse("http://[::1]8080/").is_err());
+		assert!(Url::parse("http://::1/").is_err());
+	}
+
+	#[test]
+	fn url_join() {
+		let url = Url::parse("https://example.org:8443/a/signal.backup").unwrap();
+		let joined = url.join("/b/signal.backup").unwrap();
+		assert_eq!(
+			joined.to_string(),
+			"https://example.org:8443/b/signal.backup"
+		);
+		let joined = url.join("https://cdn.example.org/signal.backup").unwrap();
+		assert_eq!(joined.to_string(), "https://cdn.example.org/signal.backup");
+
+		// relative locations as in RFC 3986
+		let url = Url::parse("https://example.org/a/b/c?token=1").unwrap();
+		for (location, expected) in [
+			("signal.backup", "https://example.org/a/b/signal.backup"),
+			("./signal.backup", "https://example.org/a/b/signal.backup"),
+			(
+				"../signal.backup?x=1",
+				"https://example.org/a/signal.backup?x=1",
+			),
+			(
+				"../../../signal.backup",
+				"https://example.org/signal.backup",
+			),
+			("..", "https://example.org/a/"),
+			("?token=2", "https://example.org/a/b/c?token=2"),
+			("?time=12:00", "https://example.org/a/b/c?time=12:00"),
+			("", "https://example.org/a/b/c?token=1"),
+			(
+				"/x/./y/../signal.backup#part",
+				"https://example.org/x/signal.backup",
+			),
+			(
+				"//cdn.example.org/signal.backup",
+				"https://cdn.example.org/signal.backup",
+			),
+		] {
+			assert_eq!(
+				url.join(location).unwrap().to_string(),
+				expected,
+				"{}",
+				location
+			);
+		}
+		assert!(url.join("ftp://example.org/signal.backup").is_err());
+
+		// no downgrade to plain HTTP
+		assert!(url.join("http://example.org/signal.backup").is_err());
+
+		let url = Url::parse("http://example.org/signal.backup").unwrap();
+		assert!(url.join("https://example.org/signal.backup").unwrap().https);
+	}
+
+	#[test]
+	fn header_line() {
+		let mut response = std::io::Cursor::new(b"HTTP/1.0 200 OK\r\n\r\n".to_vec());
+		assert_eq!(
+			read_header_line(&mut response).unwrap(),
+			"HTTP/1.0 200 OK\r\n"
+		);
+		assert_eq!(read_header_line(&mut response).unwrap(), "\r\n");
+		assert_eq!(read_header_line(&mut response).unwrap(), "");
+
+		let mut response = std::io::Cursor::new(vec![b'a'; 2 * MAX_HEADER_LINE]);
+		assert!(read_header_line(&mut response).is_err());
+	}
+
+	#[test]
+	fn content_range() {
+		assert_eq!(
+			parse_content_range("bytes 100-199/1000"),
+			Some((100, Some(1000)))
+		);
+		assert_eq!(parse_content_range("bytes 100-199/*"), Some((100, None)));
+		assert_eq!(parse_content_range("bytes */1000"), None);
+		assert_eq!(parse_content_range("100-199/1000"), None);
+	}
+}
@@ -0,0 +1,148 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use log::{info, warn};
+use std::io::Read;
+use std::io::Seek;
+
+/// Returns the number of a part named like `backup.part2`, if named so
+fn part_number(path: &std::path::Path) -> Option<usize> {
+	let name = path.file_name()?.to_str()?;
+	name[name.rfind(".part")? + ".part".len()..].parse().ok()
+}
+
+/// Checks that the parts of a split backup are complete and given in order
+///
+/// Parts named like `backup.part1` have to be numbered from 1 without gaps. As backups are
+/// usually split into parts of the same size, parts other than the last whose size differs from
+/// the first part are warned about.
+fn check_parts(paths: &[std::path::PathBuf], sizes: &[u64]) -> Result<(), anyhow::Error> {
+	let numbers: Option<Vec<usize>> = paths.iter().map(|x| part_number(x)).collect();
+	if let Some(numbers) = numbers {
+		for (i, (number, path)) in numbers.iter().zip(paths).enumerate() {
+			if *number != i + 1 {
+				return Err(anyhow!(
+					"Parts of backup are not in order or a part is missing: expected part {} instead of {}",
+					i + 1,
+					path.to_string_lossy()
+				));
+			}
+		}
+	}
+
+	for (path, size) in paths.iter().zip(sizes) {
+		if *size == 0 {
+			return Err(anyhow!(
+				"Part of backup is empty: {}",
+				path.to_string_lossy()
+			));
+		}
+	}
+	for (path, size) in paths.iter().zip(sizes).take(paths.len() - 1).skip(1) {
+		if *size != sizes[0] {
+			warn!(
+				"Part {} has {} bytes unlike the first part with {} bytes, check that no part is missing or truncated",
+				path.to_string_lossy(),
+				size,
+				sizes[0]
+			);
+		}
+	}
+
+	Ok(())
+}
+
+/// Backup split into several files, which are read one after another like a single file
+pub struct PartsSource {
+	parts: Vec<std::fs::File>,
+	/// Size of each part in bytes
+	sizes: Vec<u64>,
+	/// Index of the part currently read
+	current: usize,
+	/// Position in the whole backup
+	position: u64,
+}
+
+impl PartsSource {
+	/// Opens the first part at `path` and the further `parts` in the given order
+	pub fn open(
+		path: &std::path::Path,
+		parts: &[std::path::PathBuf],
+	) -> Result<Self, anyhow::Error> {
+		let paths: Vec<std::path::PathBuf> = std::iter::once(path.to_path_buf())
+			.chain(parts.iter().cloned())
+			.collect();
+		let mut files = Vec::new();
+		let mut sizes = Vec::new();
+		for path in &paths {
+			if crate::input_http::is_url(path) {
+				return Err(anyhow!(
+					"Parts of a backup cannot be downloaded: {}",
+					path.to_string_lossy()
+				));
+			}
+			let file = std::fs::File::open(path).with_context(|| {
+				format!("Could not open part of backup: {}", path.to_string_lossy())
+			})?;
+			sizes.push(file.metadata()?.len());
+			files.push(file);
+		}
+		check_parts(&paths, &sizes)?;
+		info!("Backup is read from {} parts", files.len());
+
+		Ok(Self {
+			parts: files,
+			sizes,
+			current: 0,
+			position: 0,
+		})
+	}
+}
+
+impl Read for PartsSource {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		while let Some(part) = self.parts.get_mut(self.current) {
+			let len = part.read(buf)?;
+			if len > 0 || buf.is_empty() {
+				self.position += len as u64;
+				return Ok(len);
+			}
+
+			// continue at the start of the next part
+			self.current += 1;
+			if let Some(part) = self.parts.get_mut(self.current) {
+				part.rewind()?;
+			}
+		}
+		Ok(0)
+	}
+}
+
+impl Seek for PartsSource {
+	fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+		let size: u64 = self.sizes.iter().sum();
+		let target = match pos {
+			std::io::SeekFrom::Start(x) => Some(x),
+			std::io::SeekFrom::Current(x) => self.position.checked_add_signed(x),
+			std::io::SeekFrom::End(x) => size.checked_add_signed(x),
+		}
+		.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid seek"))?;
+
+		// find the part containing the target, positions after the end are in the last part
+		let mut offset = target;
+		self.current = 0;
+		while self.current + 1 < self.parts.len() && offset >= self.sizes[self.current] {
+			offset -= self.sizes[self.current];
+			self.current += 1;
+		}
+		self.parts[self.current].seek(std::io::SeekFrom::Start(offset))?;
+		self.position = target;
+
+		Ok(self.position)
+	}
+}
+
+impl crate::input::Source for PartsSource {
+	fn size(&self) -> Result<Option<u64>, std::io::Error> {
+		Ok(Some(self.sizes.iter().sum()))
+	}
+}
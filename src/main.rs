@@ -1,38 +1,541 @@
-use log::error;
+use anyhow::anyhow;
+use anyhow::Context;
+use log::{debug, error, info, warn};
 use std::convert::TryInto;
+use std::io::Write;
 
 mod Backups;
 mod args;
+mod batch;
+mod checkpoint;
+mod database;
 mod decrypter;
 mod display;
+mod duplicates;
+mod encode;
+mod encrypter;
+mod export_attachments_index;
+mod export_calls;
+mod export_contact_sheet;
+mod export_sticker_manifest;
+mod export_threads;
+mod export_vcard;
 mod frame;
+mod frame_index;
+mod frame_stats;
+mod frames_file;
+mod info;
 mod input;
+mod input_http;
+mod input_parts;
 mod message;
+mod mime_map;
 mod output;
 mod output_csv;
+#[cfg(feature = "duckdb")]
+mod output_duckdb;
+mod output_dump;
+mod output_eml;
+mod output_json;
 mod output_none;
+#[cfg(feature = "parquet")]
+mod output_parquet;
+mod output_protobuf_json;
 mod output_raw;
+mod output_sink;
+mod output_sql;
+mod output_threads;
+mod warning_tally;
+
+/// Set by Ctrl-C, stops every reader of the backup before its next frame
+static INTERRUPTED: std::sync::LazyLock<std::sync::Arc<std::sync::atomic::AtomicBool>> =
+	std::sync::LazyLock::new(|| std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)));
+
+/// Opens the backup for reading with all options of `config` regarding input
+///
+/// Options of the output, like dumping frames, are left to the caller.
+pub fn open_reader(
+	config: &args::Config,
+	password: Option<&[u8]>,
+) -> Result<input::InputFile, anyhow::Error> {
+	let mut reader = input::InputFile::new(
+		&config.path_input,
+		&config.path_input_parts,
+		password,
+		config.cipher,
+		config.kdf,
+		config.verify_mac,
+		config.strict,
+	)?;
+	reader.set_follow(config.follow);
+	reader.set_interrupt(INTERRUPTED.clone());
+	reader.set_async_verify(config.async_verify);
+	reader.set_crypto_backend(config.crypto_backend);
+	reader.set_max_frames_per_second(config.max_frames_per_second);
+	reader.set_debug_bytes(config.debug_bytes);
+	reader.set_ignore_version_frame(config.ignore_version_frame);
+	reader.set_strict_end(config.strict_end);
+
+	Ok(reader)
+}
+
+/// Returns the first candidate password which decrypts the first frame of the backup
+///
+/// A single password is returned without reading the backup. A plaintext frames file is read
+/// without password.
+fn select_password(config: &args::Config) -> Result<Option<&[u8]>, anyhow::Error> {
+	if config.plaintext_frames {
+		return Ok(None);
+	}
+
+	// letters or symbols hint at a pasted PIN or wrong clipboard content instead of the passphrase
+	for (i, (unexpected, length)) in config.passwords_stripped.iter().enumerate() {
+		if *unexpected > 0 {
+			warn!(
+				"Removed {} characters other than digits from password{} ({:.0}% of its {} characters), check that it is the backup passphrase",
+				unexpected,
+				if config.passwords.len() > 1 {
+					format!(" candidate {}", i + 1)
+				} else {
+					String::new()
+				},
+				100.0 * *unexpected as f64 / *length as f64,
+				length
+			);
+		}
+	}
+
+	if let [password] = config.passwords.as_slice() {
+		return Ok(Some(password));
+	}
+
+	for (i, password) in config.passwords.iter().enumerate() {
+		let result =
+			open_reader(config, Some(password)).and_then(|mut reader| reader.check_password());
+		match result {
+			Ok(()) => {
+				// the password itself is never logged
+				info!(
+					"Password candidate {} of {} is correct",
+					i + 1,
+					config.passwords.len()
+				);
+				return Ok(Some(password));
+			}
+			Err(e) if get_exit_code(&e) == exit_code::WRONG_PASSWORD => {
+				debug!("Password candidate {} is wrong: {:#}", i + 1, e);
+			}
+			Err(e) => return Err(e),
+		}
+	}
+
+	Err(anyhow!("None of the {} password candidates is correct", config.passwords.len())
+		.context(input::DecodeError::WrongPassword))
+}
+
+/// Reads the database version from the version frame at the start of the backup
+fn read_version(config: &args::Config, password: Option<&[u8]>) -> Result<u32, anyhow::Error> {
+	let mut reader = open_reader(config, password)?;
+	match reader.next_frame()? {
+		Some(frame::Frame::Version { version }) => Ok(version),
+		Some(frame) => Err(anyhow!(
+			"Backup starts with {} instead of a version frame, placeholder {{version}} of output path cannot be replaced",
+			frame
+		)),
+		None => Err(anyhow!(
+			"Backup has no version frame, placeholder {{version}} of output path cannot be replaced"
+		)),
+	}
+}
+
+/// Creates the sink files are written to, an archive or the output directory
+fn create_sink(
+	config: &args::Config,
+) -> Result<Box<dyn crate::output_sink::SignalSink>, anyhow::Error> {
+	let sink: Box<dyn crate::output_sink::SignalSink> = match &config.path_output_archive {
+		Some(path) => match config.output_archive_format {
+			crate::output_sink::ArchiveFormat::Zip => Box::new(
+				crate::output_sink::SignalSinkZip::new(path, config.force_overwrite)?,
+			),
+			format => Box::new(crate::output_sink::SignalSinkTar::new(
+				path,
+				config.force_overwrite,
+				format,
+			)?),
+		},
+		None => Box::new(crate::output_sink::SignalSinkDirectory::new(
+			&config.path_output,
+			config.get_output_directories(),
+			config.write_chunk_size,
+			config.verify_writes,
+		)?),
+	};
+
+	if !config.manifest {
+		return Ok(sink);
+	}
+
+	let key = config
+		.path_sign_key
+		.as_deref()
+		.map(|path| {
+			std::fs::read(path)
+				.with_context(|| format!("Could not read signing key: {}", path.to_string_lossy()))
+		})
+		.transpose()?;
+	if key.as_ref().is_some_and(|x| x.is_empty()) {
+		return Err(anyhow!("Signing key is empty"));
+	}
+
+	Ok(Box::new(crate::output_sink::SignalSinkManifest::new(
+		sink,
+		key,
+		config.force_overwrite,
+	)?))
+}
+
+/// Counts the frames of the backup by reading it completely, including the header
+///
+/// Frames are decrypted and their HMACs verified, but not written. The frames of attachments
+/// have to be parsed to find the length of the attachment following them.
+fn count_frames(config: &args::Config, password: Option<&[u8]>) -> Result<usize, anyhow::Error> {
+	info!("Counting frames");
+	let start = std::time::Instant::now();
+
+	let mut reader = open_reader(config, password)?;
+	while reader.next_frame()?.is_some() {}
+
+	// the end frame is never written
+	let count = reader.get_count_frame() - 1;
+	info!(
+		"Counted {} frames in {:.1} seconds",
+		count,
+		start.elapsed().as_secs_f64()
+	);
+
+	Ok(count)
+}
+
+/// Prints the number, type and size in bytes of every frame to stdout, one line per frame
+///
+/// The size is the number of bytes the frame takes in the backup, with its length, its HMAC and
+/// the data of attachments, avatars and stickers following it. Data is still decrypted and
+/// verified, but no output is written.
+fn list_frames(config: &args::Config, password: Option<&[u8]>) -> Result<(), anyhow::Error> {
+	let mut reader = open_reader(config, password)?;
+
+	let mut stdout = std::io::stdout().lock();
+	let mut offset = reader.get_position()?.offset;
+	writeln!(stdout, "{} Header {}", reader.get_count_frame(), offset)?;
+	loop {
+		let frame = reader.next_frame()?;
+		let offset_next = reader.get_position()?.offset;
+		writeln!(
+			stdout,
+			"{} {} {}",
+			reader.get_count_frame(),
+			frame.as_ref().map_or("End", |x| x.type_name()),
+			offset_next - offset
+		)?;
+		offset = offset_next;
+		if frame.is_none() {
+			break;
+		}
+	}
+	stdout.flush()?;
+
+	info!("Listed {} frames", reader.get_count_frame());
+	Ok(())
+}
+
+/// Installs the handler of Ctrl-C
+///
+/// Ctrl-C stops reading, but the frames read so far are still written and the output is
+/// finished like after the end of the backup.
+fn handle_interrupt() -> Result<(), anyhow::Error> {
+	ctrlc::set_handler(|| {
+		// a second Ctrl-C aborts immediately, e.g. if finishing a large output takes too long
+		if INTERRUPTED.swap(true, std::sync::atomic::Ordering::SeqCst) {
+			crate::output_sink::remove_staging_directories();
+			std::process::exit(exit_code::INTERRUPTED);
+		}
+	})
+	.context("Failed to install handler for Ctrl-C")
+}
+
+fn run(config: &mut args::Config) -> Result<(), anyhow::Error> {
+	if config.ignore_version_frame {
+		warn!(
+			"Versions of the backup are ignored: keys are derived with default parameters and the database version is unknown"
+		);
+	}
+
+	if let Some(format) = config.info {
+		let backup = input::InputFile::read_info(&config.path_input, &config.path_input_parts)?;
+		let password = if backup.plaintext || config.passwords.is_empty() {
+			None
+		} else {
+			select_password(config)?
+		};
+		return crate::info::print_info(config, backup, password, format);
+	}
+
+	let password = select_password(config)?.map(<[u8]>::to_vec);
+	let password = password.as_deref();
+
+	if config.encode {
+		return crate::encode::encode(
+			&config.path_input,
+			&config.path_output,
+			password.context("No password provided")?,
+			config.force_overwrite,
+		);
+	}
+
+	if config.check_password {
+		let mut reader = open_reader(config, password)?;
+		reader.check_password()?;
+		info!("Password is correct");
+		return Ok(());
+	}
+
+	if config.list_frames {
+		return list_frames(config, password);
+	}
+
+	// the output path is only known after reading the version frame
+	if config.path_output_version {
+		let version = read_version(config, password)?;
+		config.set_output_version(version)?;
+	}
+	let config: &args::Config = config;
+
+	let mime_map = config
+		.path_mime_map
+		.as_deref()
+		.map(crate::mime_map::MimeMap::read)
+		.transpose()?;
 
-fn run(config: &args::Config) -> Result<(), anyhow::Error> {
 	// output
 	let mut output: Box<dyn crate::output::SignalOutput> = match config.output_type {
 		crate::output::SignalOutputType::None => {
-			Box::new(crate::output_none::SignalOutputNone::new())
+			let sink = if config.only.is_empty() {
+				None
+			} else {
+				Some(create_sink(config)?)
+			};
+			Box::new(crate::output_none::SignalOutputNone::new(
+				config.verify_deep,
+				sink,
+				config.only.clone(),
+				config.force_overwrite,
+				mime_map,
+				config.only_content_type.clone(),
+				config.attachment_size_threshold,
+			))
 		}
-		crate::output::SignalOutputType::Raw => Box::new(crate::output_raw::SignalOutputRaw::new(
+		crate::output::SignalOutputType::Raw => {
+			let sink: Box<dyn crate::output_sink::SignalSink> = if config.output_to_stdout() {
+				if config.manifest {
+					warn!("No manifest is written with output to stdout");
+				}
+				if config.verify_writes {
+					warn!("Written files are not verified with output to stdout");
+				}
+				Box::new(crate::output_sink::SignalSinkStdout::new()?)
+			} else {
+				create_sink(config)?
+			};
+			if config.output_raw_db_in_memory {
+				info!("Database is kept in memory: {}", config.output_raw_db_in_memory_reason);
+			} else {
+				info!("Database is created on disk: {}", config.output_raw_db_in_memory_reason);
+			}
+			let mut output = crate::output_raw::SignalOutputRaw::new(
+				sink,
+				crate::output_raw::SignalOutputRawOptions {
+					force_write: config.force_overwrite,
+					open_db_in_memory: config.output_raw_db_in_memory,
+					memory_limit: config.output_raw_memory_limit,
+					sql_batch_size: config.output_raw_sql_batch_size,
+					commit_interval: config.output_raw_commit_interval,
+					fast_db: config.output_raw_fast_db,
+					export_vcard: config.output_raw_export_vcard,
+					export_calls: config.output_raw_export_calls,
+					export_attachments_index: config.output_raw_export_attachments_index,
+					export_sticker_manifest: config.output_raw_export_sticker_manifest,
+					preserve_timestamps: config.output_raw_preserve_timestamps,
+					avatar_contact_sheet: config.output_raw_avatar_contact_sheet,
+					split_db_by_thread: config.output_raw_split_db_by_thread,
+					sequential_attachments: config.output_raw_sequential_attachments,
+					build_fts: config.output_raw_build_fts,
+					diff_against: config.output_raw_diff_against.clone(),
+					normalize_db: config.output_raw_normalize_db,
+					attachments_in_db: config.output_raw_attachments_in_db,
+					checkpoints: config.checkpoint_interval.is_some(),
+					resume: config.resume,
+					mime_map,
+					only_content_type: config.only_content_type.clone(),
+					date_range: config.date_range,
+					attachment_size_threshold: config.attachment_size_threshold,
+					exclude_tables: config.output_raw_exclude_tables.clone(),
+					verify_attachments: config.output_raw_verify_attachments,
+					strict: config.strict,
+					decode_metadata: if config.output_raw_decode_metadata {
+						Some(crate::output_raw::DecodeMetadata {
+							input: config
+								.path_input
+								.file_name()
+								.unwrap_or(config.path_input.as_os_str())
+								.to_string_lossy()
+								.to_string(),
+							input_size: std::iter::once(&config.path_input)
+								.chain(&config.path_input_parts)
+								.map(|x| std::fs::metadata(x).ok().map(|x| x.len()))
+								.sum(),
+							verify_mac: config.verify_mac,
+						})
+					} else {
+						None
+					},
+				},
+			)?;
+			// a single broken statement should not make the remaining backup unusable
+			output.set_on_statement_error(Box::new(|statement, e| {
+				error!("Failed to execute database statement: {}: {}", statement, e);
+				crate::output_raw::StatementErrorAction::Continue
+			}));
+			Box::new(output)
+		}
+		crate::output::SignalOutputType::Csv => Box::new(crate::output_csv::SignalOutputCsv::new(
 			&config.path_output,
 			config.force_overwrite,
-			config.output_raw_db_in_memory,
+			config.sort_by.clone(),
+			config.date_range,
 		)?),
-		crate::output::SignalOutputType::Csv => Box::new(crate::output_csv::SignalOutputCsv::new(
+		crate::output::SignalOutputType::Json => {
+			Box::new(crate::output_json::SignalOutputJson::new(
+				&config.path_output,
+				config.force_overwrite,
+				config.sort_by.clone(),
+				config.date_range,
+				config.output_json_statements,
+				config.output_json_receipts,
+			)?)
+		}
+		crate::output::SignalOutputType::Eml => Box::new(crate::output_eml::SignalOutputEml::new(
 			&config.path_output,
 			config.force_overwrite,
+			config.date_range,
 		)?),
+		crate::output::SignalOutputType::Sql => Box::new(crate::output_sql::SignalOutputSql::new(
+			&config.path_output,
+			config.force_overwrite,
+		)?),
+		crate::output::SignalOutputType::Dump => {
+			Box::new(crate::output_dump::SignalOutputDump::new(
+				&config.path_output,
+				config.force_overwrite,
+				&config.output_raw_exclude_tables,
+			)?)
+		}
+		#[cfg(feature = "parquet")]
+		crate::output::SignalOutputType::Parquet => {
+			Box::new(crate::output_parquet::SignalOutputParquet::new(
+				&config.path_output,
+				config.force_overwrite,
+				&config.output_raw_exclude_tables,
+			)?)
+		}
+		#[cfg(not(feature = "parquet"))]
+		crate::output::SignalOutputType::Parquet => {
+			return Err(anyhow!("built without output type PARQUET"));
+		}
+		#[cfg(feature = "duckdb")]
+		crate::output::SignalOutputType::Duckdb => {
+			Box::new(crate::output_duckdb::SignalOutputDuckdb::new(
+				&config.path_output,
+				config.force_overwrite,
+				&config.output_raw_exclude_tables,
+			)?)
+		}
+		#[cfg(not(feature = "duckdb"))]
+		crate::output::SignalOutputType::Duckdb => {
+			return Err(anyhow!("built without output type DUCKDB"));
+		}
+		// frames are written by the reader before they are converted, see below
+		crate::output::SignalOutputType::ProtobufJson => {
+			Box::new(crate::output_protobuf_json::SignalOutputProtobufJson::new())
+		}
+		crate::output::SignalOutputType::Threads => Box::new(
+			crate::output_threads::SignalOutputThreads::new(config.sort_by.clone(), config.date_range)?,
+		),
 	};
 
 	// input
-	let mut reader =
-		input::InputFile::new(&config.path_input, &config.password, config.verify_mac)?;
+	let mut reader = open_reader(config, password)?;
+	if let crate::output::SignalOutputType::ProtobufJson = config.output_type {
+		reader.set_protobuf_json(crate::output_protobuf_json::ProtobufJsonWriter::new(
+			&config.path_output,
+			config.force_overwrite,
+		)?)?;
+	}
+	if let Some(path) = &config.path_dump_frames {
+		reader.set_frames_dump(crate::frames_file::FramesWriter::new(
+			path,
+			config.force_overwrite,
+		)?)?;
+	}
+
+	let interrupted = INTERRUPTED.clone();
+	let interrupted_read = interrupted.clone();
+
+	// checkpoints
+	let path_checkpoint = config
+		.path_output
+		.join(crate::checkpoint::FILENAME_CHECKPOINT);
+	let (header_salt, header_iv) = reader.get_header();
+	let (header_salt, header_iv) = (header_salt.to_vec(), header_iv.to_vec());
+	let mut frames_total = None;
+	if config.resume {
+		let checkpoint = crate::checkpoint::Checkpoint::read(&path_checkpoint)?;
+		checkpoint.check_header(&header_salt, &header_iv)?;
+		reader.resume(&checkpoint.position)?;
+		output.resume(checkpoint.output)?;
+		frames_total = checkpoint.frames_total;
+		// without further checkpoints, the database is committed in batches beyond this one
+		if config.checkpoint_interval.is_none() {
+			std::fs::remove_file(&path_checkpoint).with_context(|| {
+				format!(
+					"Could not delete checkpoint: {}",
+					path_checkpoint.to_string_lossy()
+				)
+			})?;
+			info!(
+				"Resumed from checkpoint, no further checkpoints are written without --checkpoint-interval"
+			);
+		}
+	}
+	if config.precount && frames_total.is_none() {
+		frames_total = Some(count_frames(config, password)?);
+	}
+	if let Some((path, number)) = &config.start_frame {
+		let index = crate::frame_index::FrameIndex::read(path)?;
+		index.check_header(&header_salt, &header_iv)?;
+		reader.resume(index.get(*number)?)?;
+		warn!(
+			"Frames before frame {} are skipped, the output lacks e.g. the tables created by them",
+			number
+		);
+	}
+	let mut frame_index = config.path_write_index.clone().map(|path| {
+		(
+			path,
+			crate::frame_index::FrameIndex::new(&header_salt, &header_iv),
+		)
+	});
+	let checkpoint_interval = config.checkpoint_interval;
+	let checkpoints = checkpoint_interval.is_some();
 
 	// progress bar
 	let progress = display::Progress::new(
@@ -41,7 +544,12 @@ fn run(config: &args::Config) -> Result<(), anyhow::Error> {
 		// don't print progress bars as they are overwritten by debug messages
 		// this implies that only messages of level debug are allowed as long as bars are
 		// active
-		config.log_level == log::Level::Debug,
+		config.log_level == log::Level::Debug || config.quiet || config.debug_bytes
+			// the bars of backups decoded at the same time would overwrite each other
+			|| config.jobs > 1,
+		// the status line only makes sense on a terminal, as it is rewritten in place
+		config.progress && std::io::IsTerminal::is_terminal(&std::io::stderr()),
+		frames_total.map(|x| x.try_into().unwrap()),
 	);
 	let progress_read = progress.clone();
 	let progress_write = progress.clone();
@@ -49,18 +557,63 @@ fn run(config: &args::Config) -> Result<(), anyhow::Error> {
 	// channel to parallelize input reading / processing and output writing
 	// and to display correct status
 	let (frame_tx, frame_rx) = std::sync::mpsc::sync_channel(10);
+	let input_complete = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+	let input_complete_read = input_complete.clone();
+
+	let mut duplicates = config
+		.detect_duplicates
+		.then(crate::duplicates::DuplicateDetector::new);
+	let mut frame_stats = config
+		.color_frames_in_summary
+		.then(crate::frame_stats::FrameStats::new);
+
+	let thread_input = std::thread::spawn(move || -> Result<_, anyhow::Error> {
+		// the reader is accessed in the loop, thus it cannot be used as iterator
+		loop {
+			let count_byte = reader.get_count_byte();
+			if interrupted_read.load(std::sync::atomic::Ordering::SeqCst) {
+				break;
+			}
+
+			if let Some((_, index)) = &mut frame_index {
+				match reader.get_position() {
+					Ok(x) => index.frames.push(x),
+					Err(e) => {
+						progress_read.finish_bytes();
+						return Err(e);
+					}
+				}
+			}
+
+			match reader.next_frame() {
+				Ok(Some(x)) => {
+					if let Some(duplicates) = &mut duplicates {
+						duplicates.check(&x, reader.get_count_frame());
+					}
+					if let Some(frame_stats) = &mut frame_stats {
+						frame_stats.add(&x, reader.get_count_byte() - count_byte);
+					}
+
+					// the position after the frame is sent with it, thus the output thread
+					// writes a checkpoint only after the frame has been written
+					// a checkpoint must not contain frames whose HMAC is still being verified
+					let position = match checkpoint_interval {
+						Some(n) if reader.get_count_frame() % n == 0 => {
+							match reader.finish_verify().and_then(|_| reader.get_position()) {
+								Ok(x) => Some(x),
+								Err(e) => {
+									progress_read.finish_bytes();
+									return Err(e);
+								}
+							}
+						}
+						_ => None,
+					};
 
-	let thread_input = std::thread::spawn(move || -> Result<(), anyhow::Error> {
-		// we have to use a while let loop here because we want to access the reader object
-		// in the loop. This does not work with a simple for loop.
-		#[allow(clippy::while_let_on_iterator)]
-		while let Some(frame) = reader.next() {
-			match frame {
-				Ok(x) => {
 					// if we cannot send a frame, probably an error has occured in the
 					// output thread. Thus, just shut down the input thread. We will print
 					// the error in the output thread.
-					if frame_tx.send(x).is_err() {
+					if frame_tx.send((x, position)).is_err() {
 						break;
 					}
 
@@ -68,20 +621,65 @@ fn run(config: &args::Config) -> Result<(), anyhow::Error> {
 					progress_read.set_read_frames(reader.get_count_frame().try_into().unwrap());
 					progress_read.set_read_bytes(reader.get_count_byte().try_into().unwrap());
 				}
+				Ok(None) => {
+					// the end frame is not worth starting at
+					if let Some((_, index)) = &mut frame_index {
+						index.frames.pop();
+					}
+					input_complete_read.store(true, std::sync::atomic::Ordering::SeqCst);
+					break;
+				}
+				// waiting for more data with --follow has been interrupted
+				Err(e) if matches!(e.downcast_ref(), Some(input::DecodeError::Interrupted)) => {
+					break;
+				}
 				Err(e) => {
 					progress_read.finish_bytes();
+					// the index up to the failed frame helps to examine the backup
+					if let Some((path, index)) = &frame_index
+						&& let Err(e) = index.write(path)
+					{
+						error!("{:#}", e);
+					}
 					return Err(e);
 				}
 			}
 		}
 
+		// a failed HMAC of the frames read so far is still reported after an interruption
+		if let Err(e) = reader.finish_verify() {
+			progress_read.finish_bytes();
+			return Err(e);
+		}
 		progress_read.finish_bytes();
-		Ok(())
+		if let Some((path, index)) = &frame_index {
+			index.write(path)?;
+			info!(
+				"Wrote positions of {} frames to {}",
+				index.frames.len(),
+				path.to_string_lossy()
+			);
+		}
+		if let Some(duplicates) = &duplicates {
+			duplicates.finish();
+		}
+		Ok(frame_stats)
 	});
 
-	let thread_output = std::thread::spawn(move || -> Result<(), anyhow::Error> {
-		for received in frame_rx {
-			match output.write_frame(received) {
+	let thread_output = std::thread::spawn(move || -> Result<usize, anyhow::Error> {
+		for (received, position) in frame_rx {
+			let result = output.write_frame(received).and_then(|_| match position {
+				Some(position) => crate::checkpoint::Checkpoint {
+					salt: header_salt.clone(),
+					iv: header_iv.clone(),
+					position,
+					output: output.checkpoint()?,
+					frames_total,
+				}
+				.write(&path_checkpoint),
+				None => Ok(()),
+			});
+			match result {
 				Ok(_) => progress_write
 					.set_written_frames(output.get_written_frames().try_into().unwrap()),
 				Err(e) => {
@@ -92,38 +690,160 @@ fn run(config: &args::Config) -> Result<(), anyhow::Error> {
 		}
 
 		progress_write.finish_frames();
+
+		// with checkpoints, the output must match the last checkpoint if reading fails, thus
+		// the frames written afterwards are not finished
+		let written_frames = output.get_written_frames();
+		if checkpoints && !input_complete.load(std::sync::atomic::Ordering::SeqCst) {
+			return Ok(written_frames);
+		}
 		output.finish()?;
-		Ok(())
+
+		// the checkpoint is obsolete after a complete decode
+		if checkpoints && path_checkpoint.exists() {
+			std::fs::remove_file(&path_checkpoint).with_context(|| {
+				format!(
+					"Could not delete checkpoint: {}",
+					path_checkpoint.to_string_lossy()
+				)
+			})?;
+		}
+		Ok(written_frames)
 	});
 
 	progress.finish_multi();
-	if let Err(e) = thread_input.join().unwrap() {
-		error!("{:#}.", e);
+	let result_input = thread_input.join().unwrap();
+	let result_output = thread_output.join().unwrap();
+	if let Ok(Some(frame_stats)) = &result_input {
+		frame_stats.finish(config.quiet);
 	}
-	if let Err(e) = thread_output.join().unwrap() {
-		error!("{:#}.", e);
+
+	// an error while reading also stops writing, thus it is reported first
+	match (result_input, result_output) {
+		(Err(e_input), Err(e_output)) => {
+			error!("{:#}.", e_output);
+			Err(e_input)
+		}
+		(Err(e), Ok(_)) | (Ok(_), Err(e)) => Err(e),
+		(Ok(_), Ok(written_frames)) if interrupted.load(std::sync::atomic::Ordering::SeqCst) => {
+			if checkpoints {
+				info!(
+					"Stopped after {} frames, continue with --resume from the last checkpoint",
+					written_frames
+				);
+			} else {
+				info!(
+					"Stopped after {} frames, the output of these frames has been finished",
+					written_frames
+				);
+			}
+			Err(input::DecodeError::Interrupted.into())
+		}
+		(Ok(_), Ok(_)) => Ok(()),
 	}
+}
 
-	Ok(())
+/// Exit codes of this tool, see README for their documentation
+mod exit_code {
+	pub const ERROR: i32 = 1;
+	pub const WRONG_PASSWORD: i32 = 2;
+	pub const TRUNCATED: i32 = 3;
+	pub const MAC_VERIFICATION_FAILED: i32 = 4;
+	pub const IO: i32 = 5;
+	pub const INTERRUPTED: i32 = 6;
+}
+
+/// Determines the exit code of an error
+fn get_exit_code(e: &anyhow::Error) -> i32 {
+	match e.downcast_ref::<input::DecodeError>() {
+		Some(input::DecodeError::WrongPassword) => return exit_code::WRONG_PASSWORD,
+		Some(input::DecodeError::Interrupted) => return exit_code::INTERRUPTED,
+		None => (),
+	}
+
+	for cause in e.chain() {
+		if let Some(decrypter::DecryptError::MacVerificationFailed { .. }) = cause.downcast_ref() {
+			return exit_code::MAC_VERIFICATION_FAILED;
+		}
+		if let Some(x) = cause.downcast_ref::<std::io::Error>() {
+			return match x.kind() {
+				std::io::ErrorKind::UnexpectedEof => exit_code::TRUNCATED,
+				_ => exit_code::IO,
+			};
+		}
+	}
+
+	exit_code::ERROR
+}
+
+/// Sets up logging to the terminal and / or the log file
+fn init_logger(config: &args::Config) -> Result<(), anyhow::Error> {
+	let mut loggers: Vec<Box<dyn simplelog::SharedLogger>> = Vec::new();
+
+	if !config.log_file_only {
+		loggers.push(simplelog::TermLogger::new(
+			config.log_level,
+			simplelog::Config::default(),
+			// stdout must only contain the output if it is written there
+			if config.output_to_stdout()
+				|| config.info.is_some()
+				|| config.list_frames
+				|| matches!(config.output_type, crate::output::SignalOutputType::Threads)
+			{
+				simplelog::TerminalMode::Stderr
+			} else {
+				simplelog::TerminalMode::Mixed
+			},
+			simplelog::ColorChoice::Auto,
+		));
+	}
+
+	if let Some(path) = &config.log_file {
+		if config.log_file_rotate && path.exists() {
+			let mut path_rotated = path.clone().into_os_string();
+			path_rotated.push(".1");
+			std::fs::rename(path, &path_rotated).with_context(|| {
+				format!("Could not rotate log file: {}", path.to_string_lossy())
+			})?;
+		}
+
+		let file = std::fs::File::create(path)
+			.with_context(|| format!("Could not open log file: {}", path.to_string_lossy()))?;
+		loggers.push(simplelog::WriteLogger::new(
+			config.log_level,
+			simplelog::Config::default(),
+			file,
+		));
+	}
+
+	// warnings are counted for a summary at the end, also if they are not printed
+	loggers.push(crate::warning_tally::WarningTally::new());
+
+	simplelog::CombinedLogger::init(loggers).context("Could not initialize logger")
 }
 
 fn main() {
 	// build config structure
-	let config = args::Config::new().unwrap_or_else(|e| {
+	let mut config = args::Config::new().unwrap_or_else(|e| {
 		eprintln!("Problem parsing arguments: {}.", e);
-		std::process::exit(1);
+		std::process::exit(exit_code::ERROR);
 	});
 
-	simplelog::TermLogger::init(
-		config.log_level,
-		simplelog::Config::default(),
-		simplelog::TerminalMode::Mixed,
-		simplelog::ColorChoice::Auto,
-	)
-	.unwrap();
+	init_logger(&config).unwrap_or_else(|e| {
+		eprintln!("Problem setting up logging: {:#}.", e);
+		std::process::exit(exit_code::ERROR);
+	});
 
-	if let Err(e) = run(&config) {
+	let result = handle_interrupt().and_then(|_| {
+		if config.batch {
+			crate::batch::run(&config)
+		} else {
+			run(&mut config)
+		}
+	});
+	crate::warning_tally::log_summary();
+	if let Err(e) = result {
 		error!("{:#}.", e);
-		std::process::exit(1);
+		std::process::exit(get_exit_code(&e));
 	}
 }
@@ -14,6 +14,24 @@ pub struct Message {
 }
 
 impl Message {
+	/// Columns messages can be sorted by
+	pub const COLUMNS: [&'static str; 4] = ["address", "body", "date_sent", "date_received"];
+
+	/// Compares two messages by `column`, one of `COLUMNS`
+	pub fn compare(&self, other: &Self, column: &str) -> std::cmp::Ordering {
+		match column {
+			"address" => self.address.cmp(&other.address),
+			"body" => self.body.cmp(&other.body),
+			"date_sent" => self.date_sent.cmp(&other.date_sent),
+			"date_received" => self.date_received.cmp(&other.date_received),
+			_ => std::cmp::Ordering::Equal,
+		}
+	}
+
+	pub fn get_date_sent(&self) -> chrono::NaiveDateTime {
+		self.date_sent
+	}
+
 	pub fn new(sql_parameter: &[rusqlite::types::Value]) -> Self {
 		Self {
 			address: if let rusqlite::types::Value::Text(x) = sql_parameter[2].to_owned() {
@@ -28,15 +46,19 @@ impl Message {
 			},
 			date_sent: if let rusqlite::types::Value::Integer(x) = sql_parameter[5] {
 				// omit nanoseconds here ...
-				chrono::NaiveDateTime::from_timestamp(x / 1000, 0)
+				chrono::DateTime::from_timestamp(x / 1000, 0)
+					.unwrap_or_default()
+					.naive_utc()
 			} else {
-				chrono::NaiveDateTime::from_timestamp(0, 0)
+				chrono::NaiveDateTime::default()
 			},
 			date_received: if let rusqlite::types::Value::Integer(x) = sql_parameter[6] {
 				// omit nanoseconds here ...
-				chrono::NaiveDateTime::from_timestamp(x / 1000, 0)
+				chrono::DateTime::from_timestamp(x / 1000, 0)
+					.unwrap_or_default()
+					.naive_utc()
 			} else {
-				chrono::NaiveDateTime::from_timestamp(0, 0)
+				chrono::NaiveDateTime::default()
 			},
 		}
 	}
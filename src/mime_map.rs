@@ -0,0 +1,158 @@
+use anyhow::anyhow;
+use anyhow::Context;
+
+/// Extension of files whose type is unknown
+const EXTENSION_UNKNOWN: &str = "bin";
+
+/// Extensions of common content types of Signal attachments, supplemented or overridden by the
+/// map file
+const DEFAULT_EXTENSIONS: &[(&str, &str)] = &[
+	("application/pdf", "pdf"),
+	("application/zip", "zip"),
+	("audio/aac", "aac"),
+	("audio/mp4", "m4a"),
+	("audio/mpeg", "mp3"),
+	("audio/ogg", "ogg"),
+	("image/gif", "gif"),
+	("image/heic", "heic"),
+	("image/jpeg", "jpg"),
+	("image/png", "png"),
+	("image/webp", "webp"),
+	("text/plain", "txt"),
+	("text/vcard", "vcf"),
+	("text/x-signal-plain", "txt"),
+	("text/x-vcard", "vcf"),
+	("video/3gpp", "3gp"),
+	("video/mp4", "mp4"),
+	("video/quicktime", "mov"),
+];
+
+/// Extensions of files by content type
+///
+/// The extension of a file is taken from its content type in the database if it is in the map,
+/// otherwise it is determined from the first bytes of the file. Files of unknown type get the
+/// extension `bin`.
+pub struct MimeMap {
+	extensions: std::collections::HashMap<String, String>,
+}
+
+impl MimeMap {
+	/// Reads the map from `path`, which contains lines of the form `type=ext`
+	///
+	/// Empty lines and lines starting with `#` are ignored.
+	pub fn read(path: &std::path::Path) -> Result<Self, anyhow::Error> {
+		let content = std::fs::read_to_string(path)
+			.with_context(|| format!("Could not read MIME map: {}", path.to_string_lossy()))?;
+
+		let mut extensions: std::collections::HashMap<String, String> = DEFAULT_EXTENSIONS
+			.iter()
+			.map(|(content_type, extension)| (content_type.to_string(), extension.to_string()))
+			.collect();
+
+		for (i, line) in content.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let invalid = |reason: &str| {
+				anyhow!(
+					"Invalid entry in line {} of MIME map {}: {} ({})",
+					i + 1,
+					path.to_string_lossy(),
+					line,
+					reason
+				)
+			};
+			let (content_type, extension) = line
+				.split_once('=')
+				.ok_or_else(|| invalid("expected type=ext"))?;
+			let content_type = normalize_content_type(content_type);
+			let extension = extension.trim().trim_start_matches('.');
+
+			match content_type.split_once('/') {
+				Some((x, y)) if !x.is_empty() && !y.is_empty() => (),
+				_ => return Err(invalid("type must be of the form type/subtype")),
+			}
+			if extension.is_empty() || !extension.chars().all(|c| c.is_ascii_alphanumeric()) {
+				return Err(invalid("extension must only contain letters and digits"));
+			}
+
+			extensions.insert(content_type, extension.to_string());
+		}
+
+		Ok(Self { extensions })
+	}
+
+	/// Returns the extension of a file with `data`, whose content type is `content_type` if known
+	pub fn get_extension(&self, content_type: Option<&str>, data: &[u8]) -> String {
+		content_type
+			.and_then(|x| self.extensions.get(&normalize_content_type(x)))
+			.cloned()
+			.or_else(|| infer::get(data).map(|x| x.extension().to_string()))
+			.unwrap_or_else(|| String::from(EXTENSION_UNKNOWN))
+	}
+}
+
+/// Filter of attachments by content type
+///
+/// Patterns are globs like `image/*` or `video/mp4`, where `*` matches any characters. Content
+/// types and patterns are compared in lower case and without parameters.
+#[derive(Clone)]
+pub struct ContentTypeFilter {
+	patterns: Vec<String>,
+}
+
+impl ContentTypeFilter {
+	/// Parses comma separated patterns, e.g. `image/*,video/mp4`
+	pub fn parse(patterns: &str) -> Result<Self, anyhow::Error> {
+		let patterns = patterns
+			.split(',')
+			.map(|x| match normalize_content_type(x) {
+				x if x.is_empty() => Err(anyhow!("Empty content type pattern given")),
+				x => Ok(x),
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+
+		Ok(Self { patterns })
+	}
+
+	/// Returns whether a file with `data`, whose content type is `content_type` if known,
+	/// matches any pattern
+	///
+	/// Without content type, the type detected from the first bytes of `data` is matched.
+	/// Files of unknown type never match.
+	pub fn matches(&self, content_type: Option<&str>, data: &[u8]) -> bool {
+		let Some(content_type) = content_type
+			.map(normalize_content_type)
+			.or_else(|| infer::get(data).map(|x| x.mime_type().to_string()))
+		else {
+			return false;
+		};
+
+		self.patterns
+			.iter()
+			.any(|x| matches_glob(x.as_bytes(), content_type.as_bytes()))
+	}
+}
+
+/// Returns whether `text` matches `pattern`, in which `*` matches any characters
+fn matches_glob(pattern: &[u8], text: &[u8]) -> bool {
+	match pattern.split_first() {
+		None => text.is_empty(),
+		Some((b'*', rest)) => (0..=text.len()).any(|i| matches_glob(rest, &text[i..])),
+		Some((c, rest)) => text
+			.split_first()
+			.is_some_and(|(x, text)| x == c && matches_glob(rest, text)),
+	}
+}
+
+/// Removes parameters like the charset from a content type and converts it to lower case
+fn normalize_content_type(content_type: &str) -> String {
+	content_type
+		.split(';')
+		.next()
+		.unwrap_or_default()
+		.trim()
+		.to_lowercase()
+}
@@ -28,8 +28,21 @@ pub trait SignalOutput: Send {
 
 	fn write_key_value(&mut self, key_value: &crate::Backups::KeyValue) ->  Result<(), anyhow::Error>;
 
+	fn write_unknown(&mut self, fields: &[u32]) -> Result<(), anyhow::Error>;
+
 	fn get_written_frames(&self) -> usize;
 
+	/// Makes all frames written so far durable and returns the state needed to continue writing
+	/// with `resume` after an interruption
+	fn checkpoint(&mut self) -> Result<serde_json::Value, anyhow::Error> {
+		Err(anyhow!("Output type does not support checkpoints"))
+	}
+
+	/// Continues writing after the frames of a checkpoint with its `state`
+	fn resume(&mut self, _state: serde_json::Value) -> Result<(), anyhow::Error> {
+		Err(anyhow!("Output type does not support checkpoints"))
+	}
+
 	fn write_frame(&mut self, frame: crate::frame::Frame) -> Result<(), anyhow::Error> {
 		match frame {
 			crate::frame::Frame::Statement {
@@ -48,6 +61,7 @@ pub trait SignalOutput: Send {
 			}
 			crate::frame::Frame::Version { version } => self.write_version(version),
 			crate::frame::Frame::KeyValue { key_value } => self.write_key_value(&key_value),
+			crate::frame::Frame::Unknown { fields } => self.write_unknown(&fields),
 			_ => Err(anyhow!("unexpected frame found")),
 		}
 	}
@@ -56,8 +70,35 @@ pub trait SignalOutput: Send {
 }
 
 /// Defined output types
+#[derive(Clone, Copy)]
 pub enum SignalOutputType {
 	None,
 	Raw,
 	Csv,
+	Json,
+	Eml,
+	Sql,
+	Dump,
+	/// Every table as Parquet file, only available with the feature `parquet`
+	Parquet,
+	/// Every table in a DuckDB database, only available with the feature `duckdb`
+	Duckdb,
+	ProtobufJson,
+	Threads,
+}
+
+impl SignalOutputType {
+	/// Returns whether this tool has been built with the output type
+	pub fn is_available(&self) -> bool {
+		(!matches!(self, Self::Parquet) || cfg!(feature = "parquet"))
+			&& (!matches!(self, Self::Duckdb) || cfg!(feature = "duckdb"))
+	}
+}
+
+/// Kinds of files which can be written with output type NONE
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SignalOutputMedia {
+	Attachments,
+	Avatars,
+	Stickers,
 }
@@ -3,18 +3,40 @@ use anyhow::Context;
 use log::info;
 
 /// Write csv output of backup
+///
+/// If messages are sorted, all of them are kept in memory until the end. Otherwise, they are
+/// written immediately.
 pub struct SignalOutputCsv {
 	writer: csv::Writer<std::fs::File>,
 	written_frames: usize,
+	sort_by: Option<String>,
+	date_range: crate::database::DateRange,
+	messages: Vec<crate::message::Message>,
 }
 
 impl SignalOutputCsv {
 	/// Creates new output object
 	///
-	/// `force_write` determines whether existing files will be overwritten.
-	pub fn new(path: &std::path::Path, force_overwrite: bool) -> Result<Self, anyhow::Error> {
+	/// `force_write` determines whether existing files will be overwritten. Messages are sorted
+	/// by column `sort_by` (one of `Message::COLUMNS`), if given. Only messages sent in
+	/// `date_range` are written.
+	pub fn new(
+		path: &std::path::Path,
+		force_overwrite: bool,
+		sort_by: Option<String>,
+		date_range: crate::database::DateRange,
+	) -> Result<Self, anyhow::Error> {
 		info!("Output path: {}", &path.to_string_lossy());
 
+		let columns = crate::message::Message::COLUMNS;
+		if let Some(x) = sort_by.as_deref().filter(|x| !columns.contains(x)) {
+			return Err(anyhow!(
+				"Unknown column to sort by: {} (expected one of {})",
+				x,
+				columns.join(", ")
+			));
+		}
+
 		// check output path
 		if path.exists() && !path.is_dir() {
 			return Err(anyhow!(
@@ -22,7 +44,7 @@ impl SignalOutputCsv {
 				path.to_string_lossy()
 			));
 		} else {
-			std::fs::create_dir_all(&path).with_context(|| {
+			std::fs::create_dir_all(path).with_context(|| {
 				format!("Path could not be created: {}", path.to_string_lossy())
 			})?;
 		}
@@ -46,6 +68,9 @@ impl SignalOutputCsv {
 			writer: csv::Writer::from_path(path_csv)?,
 			// we set read frames to 1 due to the header frame we will never write
 			written_frames: 1,
+			sort_by,
+			date_range,
+			messages: Vec::new(),
 		})
 	}
 }
@@ -58,7 +83,14 @@ impl crate::output::SignalOutput for SignalOutputCsv {
 	) -> Result<(), anyhow::Error> {
 		if statement.starts_with("INSERT INTO sms") {
 			let mess = crate::message::Message::new(parameters);
-			self.writer.serialize(mess)?;
+			let date_sent = mess.get_date_sent().and_utc().timestamp_millis();
+			if self.date_range.contains(date_sent) {
+				if self.sort_by.is_some() {
+					self.messages.push(mess);
+				} else {
+					self.writer.serialize(mess)?;
+				}
+			}
 		}
 
 		self.written_frames += 1;
@@ -98,7 +130,7 @@ impl crate::output::SignalOutput for SignalOutputCsv {
 		Ok(())
 	}
 
-	fn write_key_value(&mut self, key_value: &crate::Backups::KeyValue) ->  Result<(), anyhow::Error>{
+	fn write_key_value(&mut self, _key_value: &crate::Backups::KeyValue) ->  Result<(), anyhow::Error>{
 		self.written_frames += 1;
 		Ok(())
 	}
@@ -107,7 +139,21 @@ impl crate::output::SignalOutput for SignalOutputCsv {
 		self.written_frames
 	}
 
+	fn write_unknown(&mut self, _fields: &[u32]) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
 	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		if let Some(column) = &self.sort_by {
+			// stable sort, thus messages with equal values stay in backup order
+			self.messages.sort_by(|a, b| a.compare(b, column));
+			for mess in self.messages.drain(..) {
+				self.writer.serialize(mess)?;
+			}
+		}
+
+		self.writer.flush()?;
 		Ok(())
 	}
 }
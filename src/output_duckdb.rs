@@ -0,0 +1,255 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use log::{info, warn};
+
+/// Name of the DuckDB database in the output directory
+const FILENAME_DATABASE: &str = "signal_backup.duckdb";
+
+/// Write every table of a backup to a DuckDB database
+///
+/// Like output type PARQUET, statements are replayed into an in memory database first, as the
+/// schema of Signal relies on SQLite, e.g. its triggers, partial indexes, virtual tables and
+/// values of any type in a column. When all frames are written, every table is created in
+/// DuckDB with columns of the type derived from their declared SQLite type, without
+/// constraints, and its rows are appended. Statements skipped by output type RAW and excluded
+/// tables are not part of it. Attachments, avatars, stickers and preferences are not written.
+pub struct SignalOutputDuckdb {
+	path_database: std::path::PathBuf,
+	sqlite_connection: rusqlite::Connection,
+	/// Tables whose statements are skipped, in lower case
+	exclude_tables: std::collections::HashSet<String>,
+	written_frames: usize,
+}
+
+impl SignalOutputDuckdb {
+	/// Creates new output object
+	///
+	/// `force_overwrite` determines whether an existing database will be overwritten.
+	/// Statements of `exclude_tables` are skipped.
+	pub fn new(
+		path: &std::path::Path,
+		force_overwrite: bool,
+		exclude_tables: &[String],
+	) -> Result<Self, anyhow::Error> {
+		info!("Output path: {}", &path.to_string_lossy());
+
+		// check output path
+		if path.exists() && !path.is_dir() {
+			return Err(anyhow!(
+				"{} exists and is not a directory",
+				path.to_string_lossy()
+			));
+		} else {
+			std::fs::create_dir_all(path).with_context(|| {
+				format!("Path could not be created: {}", path.to_string_lossy())
+			})?;
+		}
+
+		let path_database = path.join(FILENAME_DATABASE);
+		if path_database.exists() {
+			if !force_overwrite {
+				return Err(anyhow!(
+					"Database does already exist: {}. Try -f",
+					path_database.to_string_lossy()
+				));
+			}
+			// DuckDB would add the tables to the existing database
+			std::fs::remove_file(&path_database).with_context(|| {
+				format!(
+					"Could not delete database: {}",
+					path_database.to_string_lossy()
+				)
+			})?;
+		}
+
+		Ok(Self {
+			path_database,
+			sqlite_connection: rusqlite::Connection::open_in_memory()
+				.context("could not open connection to in memory database")?,
+			exclude_tables: exclude_tables.iter().map(|x| x.to_lowercase()).collect(),
+			// we set read frames to 1 due to the header frame we will never write
+			written_frames: 1,
+		})
+	}
+
+	/// Creates `table` in `connection` and appends all its rows
+	///
+	/// Values which do not fit the type of their column are written as null.
+	fn write_table(
+		&self,
+		connection: &duckdb::Connection,
+		table: &str,
+	) -> Result<usize, anyhow::Error> {
+		let columns = crate::database::get_typed_columns(&self.sqlite_connection, table)?;
+		let definitions: Vec<String> = columns
+			.iter()
+			.map(|(name, column_type)| {
+				format!(
+					"{} {}",
+					crate::database::quote_identifier(name),
+					match column_type {
+						crate::database::ColumnType::Integer => "BIGINT",
+						crate::database::ColumnType::Real => "DOUBLE",
+						crate::database::ColumnType::Text => "VARCHAR",
+						crate::database::ColumnType::Blob => "BLOB",
+					}
+				)
+			})
+			.collect();
+		connection
+			.execute_batch(&format!(
+				"CREATE TABLE {} ({});",
+				crate::database::quote_identifier(table),
+				definitions.join(", ")
+			))
+			.with_context(|| format!("failed to create table {} in DuckDB", table))?;
+
+		let mut statement = self
+			.sqlite_connection
+			.prepare(&format!(
+				"SELECT * FROM {}",
+				crate::database::quote_identifier(table)
+			))
+			.with_context(|| format!("failed to read table {}", table))?;
+		let mut rows = statement.query([])?;
+		let mut appender = connection
+			.appender(table)
+			.with_context(|| format!("failed to append to table {} in DuckDB", table))?;
+		let mut count_rows = 0;
+		let mut count_mismatch = 0;
+		while let Some(row) = rows.next()? {
+			let mut values = Vec::with_capacity(columns.len());
+			for (i, (_, column_type)) in columns.iter().enumerate() {
+				use duckdb::types::Value;
+
+				values.push(
+					match crate::database::convert_value(row.get_ref(i)?, *column_type) {
+						Some(rusqlite::types::Value::Integer(x)) => Value::BigInt(x),
+						Some(rusqlite::types::Value::Real(x)) => Value::Double(x),
+						Some(rusqlite::types::Value::Text(x)) => Value::Text(x),
+						Some(rusqlite::types::Value::Blob(x)) => Value::Blob(x),
+						Some(rusqlite::types::Value::Null) => Value::Null,
+						None => {
+							count_mismatch += 1;
+							Value::Null
+						}
+					},
+				);
+			}
+			appender
+				.append_row(duckdb::appender_params_from_iter(values))
+				.with_context(|| format!("failed to append to table {} in DuckDB", table))?;
+			count_rows += 1;
+		}
+		appender
+			.flush()
+			.with_context(|| format!("failed to append to table {} in DuckDB", table))?;
+
+		if count_mismatch > 0 {
+			warn!(
+				"{} values of table {} do not match the type of their column and are written as null",
+				count_mismatch, table
+			);
+		}
+
+		Ok(count_rows)
+	}
+}
+
+impl crate::output::SignalOutput for SignalOutputDuckdb {
+	fn write_statement(
+		&mut self,
+		statement: &str,
+		parameters: &[rusqlite::types::Value],
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		if crate::database::is_statement_skipped(statement)
+			|| crate::database::get_statement_table(statement)
+				.is_some_and(|x| self.exclude_tables.contains(&x.to_lowercase()))
+		{
+			return Ok(());
+		}
+
+		crate::database::execute_statement(&self.sqlite_connection, statement, parameters)
+			.with_context(|| format!("failed to execute database statement: {}", statement))?;
+
+		Ok(())
+	}
+
+	fn write_attachment(
+		&mut self,
+		_data: &[u8],
+		_attachmend_id: u64,
+		_row_id: u64,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_sticker(&mut self, _data: &[u8], _row_id: u64) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_avatar(&mut self, _data: &[u8], _name: &str) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_preference(
+		&mut self,
+		_pref: &crate::Backups::SharedPreference,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_version(&mut self, _version: u32) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_key_value(
+		&mut self,
+		_key_value: &crate::Backups::KeyValue,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_unknown(&mut self, _fields: &[u32]) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn get_written_frames(&self) -> usize {
+		self.written_frames
+	}
+
+	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		let connection = duckdb::Connection::open(&self.path_database).with_context(|| {
+			format!(
+				"Failed to open DuckDB database: {}",
+				self.path_database.to_string_lossy()
+			)
+		})?;
+
+		let tables = crate::database::get_data_tables(&self.sqlite_connection)?;
+		let mut count_rows = 0;
+		for table in &tables {
+			count_rows += self.write_table(&connection, table)?;
+		}
+		connection
+			.close()
+			.map_err(|(_, e)| e)
+			.context("failed to close DuckDB database")?;
+		info!(
+			"Wrote {} tables with {} rows to {}",
+			tables.len(),
+			count_rows,
+			FILENAME_DATABASE
+		);
+
+		Ok(())
+	}
+}
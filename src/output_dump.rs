@@ -0,0 +1,226 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use log::info;
+use std::io::Write;
+
+/// Write the database of a backup as SQL script
+///
+/// Unlike output type SQL, statements are replayed into an in memory database first. When all
+/// frames are written, the final state of the database is written to `signal_backup.sql` like
+/// `.dump` of the sqlite shell: the schema and all rows of every table, followed by indexes,
+/// triggers and views. Statements skipped by output type RAW and excluded tables are not part
+/// of it. Attachments, avatars, stickers and preferences are not written.
+pub struct SignalOutputDump {
+	writer: std::io::BufWriter<std::fs::File>,
+	sqlite_connection: rusqlite::Connection,
+	/// Tables whose statements are skipped, in lower case
+	exclude_tables: std::collections::HashSet<String>,
+	written_frames: usize,
+}
+
+impl SignalOutputDump {
+	/// Creates new output object
+	///
+	/// `force_overwrite` determines whether existing files will be overwritten. Statements of
+	/// `exclude_tables` are skipped.
+	pub fn new(
+		path: &std::path::Path,
+		force_overwrite: bool,
+		exclude_tables: &[String],
+	) -> Result<Self, anyhow::Error> {
+		info!("Output path: {}", &path.to_string_lossy());
+
+		// check output path
+		if path.exists() && !path.is_dir() {
+			return Err(anyhow!(
+				"{} exists and is not a directory",
+				path.to_string_lossy()
+			));
+		} else {
+			std::fs::create_dir_all(path).with_context(|| {
+				format!("Path could not be created: {}", path.to_string_lossy())
+			})?;
+		}
+
+		let path_sql = path.join("signal_backup.sql");
+
+		if path_sql.exists() && !force_overwrite {
+			return Err(anyhow!(
+				"Backup file already exists and may not be overwritten. Try -f"
+			));
+		}
+
+		let file = std::fs::File::create(&path_sql)
+			.with_context(|| format!("Could not create file: {}", path_sql.to_string_lossy()))?;
+
+		Ok(Self {
+			writer: std::io::BufWriter::new(file),
+			sqlite_connection: rusqlite::Connection::open_in_memory()
+				.context("could not open connection to in memory database")?,
+			exclude_tables: exclude_tables.iter().map(|x| x.to_lowercase()).collect(),
+			// we set read frames to 1 due to the header frame we will never write
+			written_frames: 1,
+		})
+	}
+
+	/// Writes all rows of `table` as `INSERT` statements
+	///
+	/// Rows are written one by one while they are read, thus large tables are never held in
+	/// memory.
+	fn write_rows(&mut self, table: &str) -> Result<usize, anyhow::Error> {
+		let name = crate::database::quote_identifier(table);
+		let mut statement = self
+			.sqlite_connection
+			.prepare(&format!("SELECT * FROM {}", name))
+			.with_context(|| format!("failed to read table {}", table))?;
+		let column_count = statement.column_count();
+		let mut rows = statement.query([])?;
+
+		let mut count = 0;
+		while let Some(row) = rows.next()? {
+			let mut values = Vec::with_capacity(column_count);
+			for i in 0..column_count {
+				values.push(crate::output_sql::to_literal(&row.get_ref(i)?.into()));
+			}
+			writeln!(
+				self.writer,
+				"INSERT INTO {} VALUES({});",
+				name,
+				values.join(",")
+			)
+			.context("Failed to write SQL dump")?;
+			count += 1;
+		}
+
+		Ok(count)
+	}
+
+	/// Writes the schema and rows of the database in the order of the sqlite shell
+	fn write_dump(&mut self) -> Result<(), anyhow::Error> {
+		let version: i64 = self
+			.sqlite_connection
+			.pragma_query_value(None, "user_version", |row| row.get(0))
+			.context("failed to read database version")?;
+		writeln!(
+			self.writer,
+			"PRAGMA foreign_keys=OFF;\nBEGIN TRANSACTION;\nPRAGMA user_version={};",
+			version
+		)
+		.context("Failed to write SQL dump")?;
+
+		let tables = crate::database::query_rows(
+			&self.sqlite_connection,
+			"SELECT name, sql FROM sqlite_master WHERE type = 'table' AND sql NOT NULL ORDER BY rowid",
+			[],
+		)?;
+		let mut count_rows = 0;
+		for table in &tables {
+			let (Some(name), Some(sql)) = (table.get_string("name"), table.get_string("sql"))
+			else {
+				continue;
+			};
+			// created by sqlite itself, only its rows are restored
+			if name == "sqlite_sequence" {
+				writeln!(self.writer, "DELETE FROM sqlite_sequence;")
+					.context("Failed to write SQL dump")?;
+			} else {
+				writeln!(self.writer, "{};", sql).context("Failed to write SQL dump")?;
+			}
+			count_rows += self.write_rows(&name)?;
+		}
+
+		for entry in crate::database::query_rows(
+			&self.sqlite_connection,
+			"SELECT sql FROM sqlite_master WHERE type IN ('index', 'trigger', 'view') AND sql NOT NULL ORDER BY rowid",
+			[],
+		)? {
+			if let Some(sql) = entry.get_string("sql") {
+				writeln!(self.writer, "{};", sql).context("Failed to write SQL dump")?;
+			}
+		}
+
+		writeln!(self.writer, "COMMIT;").context("Failed to write SQL dump")?;
+		self.writer.flush().context("Failed to write SQL dump")?;
+		info!("Dumped {} tables with {} rows", tables.len(), count_rows);
+
+		Ok(())
+	}
+}
+
+impl crate::output::SignalOutput for SignalOutputDump {
+	fn write_statement(
+		&mut self,
+		statement: &str,
+		parameters: &[rusqlite::types::Value],
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		if crate::database::is_statement_skipped(statement)
+			|| crate::database::get_statement_table(statement)
+				.is_some_and(|x| self.exclude_tables.contains(&x.to_lowercase()))
+		{
+			return Ok(());
+		}
+
+		crate::database::execute_statement(&self.sqlite_connection, statement, parameters)
+			.with_context(|| format!("failed to execute database statement: {}", statement))?;
+
+		Ok(())
+	}
+
+	fn write_attachment(
+		&mut self,
+		_data: &[u8],
+		_attachmend_id: u64,
+		_row_id: u64,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_sticker(&mut self, _data: &[u8], _row_id: u64) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_avatar(&mut self, _data: &[u8], _name: &str) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_preference(
+		&mut self,
+		_pref: &crate::Backups::SharedPreference,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_version(&mut self, version: u32) -> Result<(), anyhow::Error> {
+		self.sqlite_connection
+			.pragma_update(None, "user_version", version)
+			.context("failed to set database version")?;
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_key_value(
+		&mut self,
+		_key_value: &crate::Backups::KeyValue,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_unknown(&mut self, _fields: &[u32]) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn get_written_frames(&self) -> usize {
+		self.written_frames
+	}
+
+	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		self.write_dump()
+	}
+}
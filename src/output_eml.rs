@@ -0,0 +1,521 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use base64::Engine;
+use log::{info, warn};
+
+/// Domain of the email addresses of recipients, which is reserved to never exist
+const DOMAIN: &str = "signal.invalid";
+
+/// Write messages as emails (RFC 5322)
+///
+/// Statements are collected in an in memory database, attachments are kept in a staging
+/// directory, which is deleted when the output is dropped, also after an error. When all frames
+/// are written, every message is written as `.eml` file to a directory of its thread, with its
+/// attachments as MIME parts.
+pub struct SignalOutputEml {
+	path_output: std::path::PathBuf,
+	force_overwrite: bool,
+	date_range: crate::database::DateRange,
+	staging: crate::output_sink::StagingDirectory,
+	sqlite_connection: rusqlite::Connection,
+	written_frames: usize,
+	/// Temporary files of attachments by row id
+	attachments: std::collections::HashMap<u64, std::path::PathBuf>,
+}
+
+impl SignalOutputEml {
+	/// Creates new output object
+	///
+	/// `force_overwrite` determines whether existing files will be overwritten. Only messages
+	/// sent in `date_range` are written, together with their attachments.
+	pub fn new(
+		path: &std::path::Path,
+		force_overwrite: bool,
+		date_range: crate::database::DateRange,
+	) -> Result<Self, anyhow::Error> {
+		info!("Output path: {}", &path.to_string_lossy());
+
+		// check output path
+		if path.exists() && !path.is_dir() {
+			return Err(anyhow!(
+				"{} exists and is not a directory",
+				path.to_string_lossy()
+			));
+		} else {
+			std::fs::create_dir_all(path).with_context(|| {
+				format!("Path could not be created: {}", path.to_string_lossy())
+			})?;
+		}
+
+		Ok(Self {
+			path_output: path.to_path_buf(),
+			force_overwrite,
+			date_range,
+			staging: crate::output_sink::StagingDirectory::new()?,
+			sqlite_connection: rusqlite::Connection::open_in_memory()
+				.context("could not open connection to in memory database")?,
+			// we set read frames to 1 due to the header frame we will never write
+			written_frames: 1,
+			attachments: std::collections::HashMap::new(),
+		})
+	}
+
+	/// Writes all messages as emails
+	fn write_messages(&self) -> Result<usize, anyhow::Error> {
+		let connection = &self.sqlite_connection;
+		if !crate::database::has_table(connection, "message")? {
+			warn!("Backup does not contain a message table, no messages are exported");
+			return Ok(0);
+		}
+
+		let recipients = crate::database::get_recipients(connection)?;
+		let address = |id: Option<i64>| match id {
+			Some(id) => format_address(recipients.get(&id), id),
+			None => format_address(None, 0),
+		};
+
+		// attachments by message id, newer backups store them in table attachment, older ones
+		// in table part
+		let mut attachments: std::collections::HashMap<i64, Vec<Attachment>> =
+			std::collections::HashMap::new();
+		for (table, column_message, column_type) in [
+			("attachment", "message_id", "content_type"),
+			("part", "mid", "ct"),
+		] {
+			if !crate::database::has_table(connection, table)? {
+				continue;
+			}
+			for row in crate::database::query_rows(
+				connection,
+				&format!("SELECT * FROM {} ORDER BY _id", table),
+				[],
+			)? {
+				attachments
+					.entry(row.get_i64(column_message).unwrap_or_default())
+					.or_default()
+					.push(Attachment {
+						row_id: row.get_i64("_id").unwrap_or_default(),
+						content_type: row.get_string(column_type),
+						file_name: row.get_string("file_name"),
+					});
+			}
+		}
+
+		let quotes = crate::database::get_quotes(connection)?;
+		let revisions = crate::database::get_revisions(connection)?;
+		// Message-IDs by message id, to reference quoted messages, which precede their replies
+		let mut message_ids = std::collections::HashMap::new();
+
+		let mut count = 0;
+		for row in
+			crate::database::query_rows(connection, "SELECT * FROM message ORDER BY _id", [])?
+		{
+			let id = row.get_i64("_id").unwrap_or_default();
+			let thread_id = row.get_i64("thread_id").unwrap_or_default();
+			let date_sent = row.get_i64("date_sent").unwrap_or_default();
+			let message_id = format!("<{}.{}.{}@{}>", thread_id, id, date_sent, DOMAIN);
+			message_ids.insert(id, message_id.clone());
+			// only the latest version of an edited message is written
+			if !self.date_range.contains(date_sent) || crate::database::is_previous_revision(&row) {
+				continue;
+			}
+			let mut body = row.get_string("body").unwrap_or_default();
+			let quote = quotes.get(&id);
+
+			let subject = match body.lines().next() {
+				Some(x) if x.chars().count() > 60 => {
+					format!("{}...", x.chars().take(60).collect::<String>())
+				}
+				Some(x) => x.to_string(),
+				None => String::from("Signal message"),
+			};
+
+			let mut eml = String::new();
+			eml.push_str(&format!("From: {}\r\n", address(row.get_i64("from_recipient_id"))));
+			eml.push_str(&format!("To: {}\r\n", address(row.get_i64("to_recipient_id"))));
+			eml.push_str(&format!(
+				"Date: {}\r\n",
+				chrono::DateTime::from_timestamp_millis(date_sent)
+					.unwrap_or_default()
+					.to_rfc2822()
+			));
+			eml.push_str(&format!("Subject: {}\r\n", encode_header(&subject)));
+			eml.push_str(&format!("Message-ID: {}\r\n", message_id));
+			if let Some(x) = quote.and_then(|x| x.id).and_then(|x| message_ids.get(&x)) {
+				eml.push_str(&format!("In-Reply-To: {}\r\n", x));
+			}
+			eml.push_str("MIME-Version: 1.0\r\n");
+
+			if revisions.contains_key(&id) {
+				body.push_str("\n\n(edited)");
+			}
+
+			// the quoted text precedes the reply like in a reply to an email
+			if let Some(quote) = quote {
+				let mut quoted: String = quote
+					.body
+					.as_deref()
+					.unwrap_or_default()
+					.lines()
+					.map(|x| format!("> {}\n", x))
+					.collect();
+				if quoted.is_empty() {
+					quoted.push_str(">\n");
+				}
+				body = format!("{}\n{}", quoted, body);
+			}
+
+			let text = format!(
+				"Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}",
+				encode_base64(body.as_bytes())
+			);
+
+			let parts = attachments.remove(&id).unwrap_or_default();
+			if parts.is_empty() {
+				eml.push_str(&text);
+			} else {
+				// base64 never contains "-", thus the boundary cannot appear in any part
+				let boundary = format!("----signal-backup-decode-{}", id);
+				eml.push_str(&format!(
+					"Content-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n",
+					boundary
+				));
+				eml.push_str(&format!("--{}\r\n{}", boundary, text));
+
+				for part in parts {
+					let Some(path) = self.attachments.get(&(part.row_id as u64)) else {
+						warn!(
+							"Data of attachment {} of message {} is missing in the backup",
+							part.row_id, id
+						);
+						continue;
+					};
+					let data = std::fs::read(path).with_context(|| {
+						format!("Failed to read file: {}", path.to_string_lossy())
+					})?;
+
+					let content_type = part
+						.content_type
+						.or_else(|| infer::get(&data).map(|x| x.mime_type().to_string()))
+						.unwrap_or_else(|| String::from("application/octet-stream"));
+					let file_name = part
+						.file_name
+						.unwrap_or_else(|| format!("attachment-{}", part.row_id));
+
+					eml.push_str(&format!("--{}\r\n", boundary));
+					eml.push_str(&format!(
+						"Content-Type: {};\r\n {}\r\n",
+						content_type,
+						format_parameter("name", &file_name)
+					));
+					eml.push_str(&format!(
+						"Content-Disposition: attachment;\r\n {}\r\n",
+						format_parameter("filename", &file_name)
+					));
+					eml.push_str("Content-Transfer-Encoding: base64\r\n\r\n");
+					eml.push_str(&encode_base64(&data));
+				}
+
+				eml.push_str(&format!("--{}--\r\n", boundary));
+			}
+
+			let path = self
+				.path_output
+				.join(thread_id.to_string())
+				.join(format!("{}.eml", id));
+			if path.exists() && !self.force_overwrite {
+				return Err(anyhow!(
+					"File does already exist: {}. Try -f",
+					path.to_string_lossy()
+				));
+			}
+			if let Some(parent) = path.parent() {
+				std::fs::create_dir_all(parent).with_context(|| {
+					format!("Failed to create path: {}", parent.to_string_lossy())
+				})?;
+			}
+			std::fs::write(&path, eml)
+				.with_context(|| format!("Failed to write to file: {}", path.to_string_lossy()))?;
+			count += 1;
+		}
+
+		Ok(count)
+	}
+}
+
+/// Attachment of a message as stored in the database
+struct Attachment {
+	row_id: i64,
+	content_type: Option<String>,
+	file_name: Option<String>,
+}
+
+/// Encodes data as base64 with lines of 76 characters as required by MIME
+fn encode_base64(data: &[u8]) -> String {
+	let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+	let mut lines = String::new();
+	for line in encoded.as_bytes().chunks(76) {
+		lines.push_str(&String::from_utf8_lossy(line));
+		lines.push_str("\r\n");
+	}
+
+	lines
+}
+
+/// Maximum length of the text of an encoded word in base64, see RFC 2047
+///
+/// An encoded word `=?utf-8?B?...?=` must not be longer than 75 characters.
+const ENCODED_WORD_LENGTH: usize = 75 - "=?utf-8?B??=".len();
+
+/// Maximum length of a section of a parameter value encoded as described in RFC 2231
+const PARAMETER_SECTION_LENGTH: usize = 60;
+
+/// Returns whether `value` can be written to a header as it is
+fn is_plain_header(value: &str) -> bool {
+	value
+		.chars()
+		.all(|c| c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\')
+}
+
+/// Encodes text for a header, non ASCII text is encoded as described in RFC 2047
+///
+/// Encoded words are limited in length, thus longer text is split at character boundaries into
+/// several words on folded lines.
+fn encode_header(value: &str) -> String {
+	if is_plain_header(value) {
+		return value.to_string();
+	}
+
+	// every 3 bytes are encoded as 4 characters
+	let max_bytes = ENCODED_WORD_LENGTH / 4 * 3;
+	let mut words = Vec::new();
+	let mut start = 0;
+	for (i, c) in value.char_indices() {
+		if i + c.len_utf8() - start > max_bytes {
+			words.push(&value[start..i]);
+			start = i;
+		}
+	}
+	words.push(&value[start..]);
+
+	words
+		.iter()
+		.map(|x| {
+			format!(
+				"=?utf-8?B?{}?=",
+				base64::engine::general_purpose::STANDARD.encode(x)
+			)
+		})
+		.collect::<Vec<_>>()
+		.join("\r\n ")
+}
+
+/// Formats the parameter `name` of a header, e.g. the file name of an attachment
+///
+/// Values which cannot be quoted as they are, e.g. non ASCII file names, are percent encoded as
+/// described in RFC 2231 and split into sections on folded lines.
+fn format_parameter(name: &str, value: &str) -> String {
+	if is_plain_header(value) && value.len() <= PARAMETER_SECTION_LENGTH {
+		return format!("{}=\"{}\"", name, value);
+	}
+
+	let encoded: String = value
+		.bytes()
+		.map(|b| {
+			if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+				char::from(b).to_string()
+			} else {
+				format!("%{:02X}", b)
+			}
+		})
+		.collect();
+
+	// sections must not split an encoded byte
+	let mut sections = Vec::new();
+	let mut rest = encoded.as_str();
+	while !rest.is_empty() {
+		let mut end = rest.len().min(PARAMETER_SECTION_LENGTH);
+		if let Some(x) = rest[..end].rfind('%').filter(|x| x + 3 > end) {
+			end = x;
+		}
+		sections.push(&rest[..end]);
+		rest = &rest[end..];
+	}
+
+	if sections.len() == 1 {
+		return format!("{}*=utf-8''{}", name, sections[0]);
+	}
+	sections
+		.iter()
+		.enumerate()
+		.map(|(i, x)| {
+			format!(
+				"{}*{}*={}{}",
+				name,
+				i,
+				if i == 0 { "utf-8''" } else { "" },
+				x
+			)
+		})
+		.collect::<Vec<_>>()
+		.join(";\r\n ")
+}
+
+/// Formats a recipient as email address
+///
+/// The phone number (or recipient id) is used as local part of an address at `DOMAIN`.
+fn format_address(recipient: Option<&crate::database::Recipient>, id: i64) -> String {
+	let local = recipient
+		.and_then(|x| x.phone.as_ref())
+		.map(|x| x.trim_start_matches('+').to_string())
+		.unwrap_or_else(|| format!("recipient-{}", id));
+
+	match recipient {
+		// encoded words must not be quoted
+		Some(x) if is_plain_header(&x.display_name()) => {
+			format!("\"{}\" <{}@{}>", x.display_name(), local, DOMAIN)
+		}
+		Some(x) => format!(
+			"{} <{}@{}>",
+			encode_header(&x.display_name()),
+			local,
+			DOMAIN
+		),
+		None => format!("<{}@{}>", local, DOMAIN),
+	}
+}
+
+impl crate::output::SignalOutput for SignalOutputEml {
+	fn write_statement(
+		&mut self,
+		statement: &str,
+		parameters: &[rusqlite::types::Value],
+	) -> Result<(), anyhow::Error> {
+		if !crate::database::is_statement_skipped(statement) {
+			crate::database::execute_statement(&self.sqlite_connection, statement, parameters)
+				.with_context(|| format!("failed to execute database statement: {}", statement))?;
+		}
+
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_attachment(
+		&mut self,
+		data: &[u8],
+		_attachmend_id: u64,
+		row_id: u64,
+	) -> Result<(), anyhow::Error> {
+		let path = self.staging.path().join(row_id.to_string());
+		std::fs::write(&path, data)
+			.with_context(|| format!("Failed to write to file: {}", path.to_string_lossy()))?;
+		self.attachments.insert(row_id, path);
+
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_sticker(&mut self, _data: &[u8], _row_id: u64) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_avatar(&mut self, _data: &[u8], _name: &str) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_preference(
+		&mut self,
+		_pref: &crate::Backups::SharedPreference,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_version(&mut self, _version: u32) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_key_value(
+		&mut self,
+		_key_value: &crate::Backups::KeyValue,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_unknown(&mut self, _fields: &[u32]) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn get_written_frames(&self) -> usize {
+		self.written_frames
+	}
+
+	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		let count = self.write_messages()?;
+		info!("Wrote {} messages as emails", count);
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn header_encoding() {
+		assert_eq!(encode_header("Hello"), "Hello");
+		assert_eq!(encode_header("Grüße"), "=?utf-8?B?R3LDvMOfZQ==?=");
+
+		// long text is split into several encoded words without splitting characters
+		let subject = "ä".repeat(40);
+		let encoded = encode_header(&subject);
+		let words = encoded.split("\r\n ").collect::<Vec<_>>();
+		assert_eq!(words.len(), 2);
+		let mut decoded = Vec::new();
+		for word in words {
+			assert!(word.len() <= 75, "{}", word);
+			let text = word
+				.strip_prefix("=?utf-8?B?")
+				.unwrap()
+				.strip_suffix("?=")
+				.unwrap();
+			let bytes = base64::engine::general_purpose::STANDARD
+				.decode(text)
+				.unwrap();
+			assert!(std::str::from_utf8(&bytes).is_ok());
+			decoded.extend(bytes);
+		}
+		assert_eq!(String::from_utf8(decoded).unwrap(), subject);
+	}
+
+	#[test]
+	fn parameter_encoding() {
+		assert_eq!(
+			format_parameter("filename", "photo.jpg"),
+			"filename=\"photo.jpg\""
+		);
+		assert_eq!(
+			format_parameter("filename", "Grüße \"1\".txt"),
+			"filename*=utf-8''Gr%C3%BC%C3%9Fe%20%221%22.txt"
+		);
+
+		// long values are split into sections without splitting encoded bytes
+		let encoded = format_parameter("filename", &format!("{}.txt", "ü".repeat(20)));
+		let sections = encoded.split(";\r\n ").collect::<Vec<_>>();
+		assert_eq!(sections.len(), 3);
+		assert!(sections[0].starts_with("filename*0*=utf-8''%C3%BC"));
+		assert!(sections[1].starts_with("filename*1*=%"));
+		assert!(sections[2].ends_with(".txt"));
+		assert!(
+			sections
+				.iter()
+				.all(|x| x.split_once('=').unwrap().1.len() <= 60 + "utf-8''".len())
+		);
+	}
+}
@@ -0,0 +1,650 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use base64::Engine;
+use log::{info, warn};
+use serde::Serialize;
+
+/// Write json output of backup
+///
+/// Statements are collected in an in memory database. When all frames are written, messages are
+/// queried from this database and written together with their reactions, mentions, the message
+/// they reply to, their previous versions, if edited, and optionally their receipts. Groups are
+/// written with their members and conversations with their timer of disappearing messages.
+/// Optionally, all statements are written with their typed parameters.
+pub struct SignalOutputJson {
+	path_json: std::path::PathBuf,
+	sort_by: Option<String>,
+	date_range: crate::database::DateRange,
+	sqlite_connection: rusqlite::Connection,
+	written_frames: usize,
+	avatars: std::collections::HashSet<String>,
+	statements: Option<Vec<Statement>>,
+	receipts: bool,
+}
+
+impl SignalOutputJson {
+	/// Creates new output object
+	///
+	/// `force_overwrite` determines whether existing files will be overwritten. Messages, groups
+	/// and their details are sorted by the database column `sort_by` if their table has it and by
+	/// their order in the database otherwise. Only messages sent in `date_range` are written.
+	/// With `write_statements`, all statements of the backup are written as well. With
+	/// `write_receipts`, outgoing messages are written with their delivery and read receipts.
+	pub fn new(
+		path: &std::path::Path,
+		force_overwrite: bool,
+		sort_by: Option<String>,
+		date_range: crate::database::DateRange,
+		write_statements: bool,
+		write_receipts: bool,
+	) -> Result<Self, anyhow::Error> {
+		info!("Output path: {}", &path.to_string_lossy());
+
+		// check output path
+		if path.exists() && !path.is_dir() {
+			return Err(anyhow!(
+				"{} exists and is not a directory",
+				path.to_string_lossy()
+			));
+		} else {
+			std::fs::create_dir_all(path).with_context(|| {
+				format!("Path could not be created: {}", path.to_string_lossy())
+			})?;
+		}
+
+		let path_json = path.join("signal_backup.json");
+
+		if path_json.exists() && !force_overwrite {
+			return Err(anyhow!(
+				"Backup file already exists and may not be overwritten. Try -f"
+			));
+		}
+
+		Ok(Self {
+			path_json,
+			sort_by,
+			date_range,
+			sqlite_connection: rusqlite::Connection::open_in_memory()
+				.context("could not open connection to in memory database")?,
+			// we set read frames to 1 due to the header frame we will never write
+			written_frames: 1,
+			avatars: std::collections::HashSet::new(),
+			statements: if write_statements { Some(Vec::new()) } else { None },
+			receipts: write_receipts,
+		})
+	}
+}
+
+/// Content of json file
+#[derive(Serialize)]
+struct Export {
+	messages: Vec<Message>,
+	groups: Vec<Group>,
+	threads: Vec<Thread>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	statements: Option<Vec<Statement>>,
+}
+
+/// A statement of the backup with its parameters
+#[derive(Serialize)]
+struct Statement {
+	statement: String,
+	parameters: Vec<Parameter>,
+}
+
+/// A statement parameter with the type it has in the backup
+///
+/// The type is kept explicitly as JSON cannot distinguish e.g. integers and doubles. Blobs are
+/// encoded as base64.
+#[derive(Serialize)]
+struct Parameter {
+	value: serde_json::Value,
+	/// Either `string`, `integer`, `double`, `blob` or `null`
+	#[serde(rename = "type")]
+	kind: &'static str,
+}
+
+impl From<&rusqlite::types::Value> for Parameter {
+	fn from(value: &rusqlite::types::Value) -> Self {
+		let (value, kind) = match value {
+			rusqlite::types::Value::Null => (serde_json::Value::Null, "null"),
+			rusqlite::types::Value::Integer(x) => ((*x).into(), "integer"),
+			rusqlite::types::Value::Real(x) => ((*x).into(), "double"),
+			rusqlite::types::Value::Text(x) => (x.as_str().into(), "string"),
+			rusqlite::types::Value::Blob(x) => (
+				base64::engine::general_purpose::STANDARD.encode(x).into(),
+				"blob",
+			),
+		};
+		Self { value, kind }
+	}
+}
+
+/// A Signal message with its reactions and mentions
+#[derive(Serialize)]
+struct Message {
+	id: i64,
+	thread_id: i64,
+	/// Name of sender
+	from: String,
+	/// Name of receiver
+	to: String,
+	body: Option<String>,
+	date_sent: chrono::NaiveDateTime,
+	date_received: chrono::NaiveDateTime,
+	reactions: Vec<Reaction>,
+	mentions: Vec<Mention>,
+	/// Message this message replies to
+	#[serde(skip_serializing_if = "Option::is_none")]
+	quote: Option<Quote>,
+	/// Previous versions of an edited message, starting with the original
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	edits: Vec<Edit>,
+	/// Receipts of an outgoing message by recipient, if written
+	#[serde(skip_serializing_if = "Option::is_none")]
+	receipts: Option<Vec<Receipt>>,
+}
+
+/// Previous version of an edited message
+#[derive(Serialize)]
+struct Edit {
+	body: Option<String>,
+	date_sent: chrono::NaiveDateTime,
+}
+
+/// Message quoted by a reply
+#[derive(Serialize)]
+struct Quote {
+	/// Id of the quoted message, missing if it is not part of the backup anymore
+	id: Option<i64>,
+	/// Name of the author of the quoted message
+	author: String,
+	body: Option<String>,
+}
+
+/// Receipt of an outgoing message from one of its recipients
+#[derive(Serialize)]
+struct Receipt {
+	/// Name of the recipient
+	recipient: String,
+	/// Latest status, either `undelivered`, `delivered`, `read`, `viewed`, `skipped` or `unknown`
+	status: &'static str,
+	/// Time the latest status has been received, missing if unknown
+	date: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+struct Reaction {
+	/// Name of reacting recipient
+	author: String,
+	emoji: String,
+	date_sent: chrono::NaiveDateTime,
+}
+
+#[derive(Serialize)]
+struct Mention {
+	/// Name of mentioned recipient
+	recipient: String,
+	/// Position of the mention in the message body
+	range_start: i64,
+	range_length: i64,
+}
+
+/// A Signal group with its members
+#[derive(Serialize)]
+struct Group {
+	group_id: String,
+	title: Option<String>,
+	/// Group version, either `v1` (legacy) or `v2`
+	version: String,
+	active: bool,
+	/// Names of members
+	members: Vec<String>,
+	/// Name of the group's avatar in the backup, if it contains one
+	avatar: Option<String>,
+}
+
+/// A conversation with a contact or group
+#[derive(Serialize)]
+struct Thread {
+	id: i64,
+	/// Name of the contact or group
+	recipient: String,
+	/// Current timer of disappearing messages, e.g. `1 week`, or `off`
+	disappearing_messages: String,
+}
+
+impl Export {
+	/// Queries messages, groups and threads
+	///
+	/// `avatars` are the names of all avatars in the backup. Rows are sorted by column `sort_by`
+	/// where possible. Messages are limited to those sent in `date_range`, outgoing ones are
+	/// written with their receipts if `receipts`.
+	fn new(
+		connection: &rusqlite::Connection,
+		avatars: &std::collections::HashSet<String>,
+		sort_by: Option<&str>,
+		date_range: crate::database::DateRange,
+		receipts: bool,
+	) -> Result<Self, anyhow::Error> {
+		let recipients = crate::database::get_recipients(connection)?;
+		let name = |id: Option<i64>| match id {
+			Some(id) => recipients
+				.get(&id)
+				.map(|x| x.display_name())
+				.unwrap_or_else(|| format!("recipient {}", id)),
+			None => String::from(""),
+		};
+
+		// rows are always read ordered, thus two exports of the same backup are identical
+		let query = |table: &str| -> Result<Vec<crate::database::Row>, anyhow::Error> {
+			crate::database::query_rows(
+				connection,
+				&format!(
+					"SELECT * FROM {} {}",
+					table,
+					crate::database::get_order_by(connection, table, sort_by)?
+				),
+				[],
+			)
+		};
+
+		Ok(Self {
+			messages: Self::get_messages(connection, &query, &name, date_range, receipts)?,
+			groups: Self::get_groups(connection, &query, &name, avatars)?,
+			threads: Self::get_threads(connection, &query, &name, &recipients)?,
+			statements: None,
+		})
+	}
+
+	fn get_messages(
+		connection: &rusqlite::Connection,
+		query: &dyn Fn(&str) -> Result<Vec<crate::database::Row>, anyhow::Error>,
+		name: &dyn Fn(Option<i64>) -> String,
+		date_range: crate::database::DateRange,
+		receipts: bool,
+	) -> Result<Vec<Message>, anyhow::Error> {
+		if !crate::database::has_table(connection, "message")? {
+			warn!("Backup does not contain a message table, no messages are exported");
+			return Ok(Vec::new());
+		}
+
+		// reactions and mentions by message id
+		let mut reactions: std::collections::HashMap<i64, Vec<Reaction>> =
+			std::collections::HashMap::new();
+		if crate::database::has_table(connection, "reaction")? {
+			for row in query("reaction")? {
+				reactions
+					.entry(row.get_i64("message_id").unwrap_or_default())
+					.or_default()
+					.push(Reaction {
+						author: name(row.get_i64("author_id")),
+						emoji: row.get_string("emoji").unwrap_or_default(),
+						date_sent: crate::database::timestamp_to_date(
+							row.get_i64("date_sent").unwrap_or_default(),
+						),
+					});
+			}
+		}
+
+		let mut mentions: std::collections::HashMap<i64, Vec<Mention>> =
+			std::collections::HashMap::new();
+		if crate::database::has_table(connection, "mention")? {
+			for row in query("mention")? {
+				mentions
+					.entry(row.get_i64("message_id").unwrap_or_default())
+					.or_default()
+					.push(Mention {
+						recipient: name(row.get_i64("recipient_id")),
+						range_start: row.get_i64("range_start").unwrap_or_default(),
+						range_length: row.get_i64("range_length").unwrap_or_default(),
+					});
+			}
+		}
+
+		let mut quotes = crate::database::get_quotes(connection)?;
+		let mut revisions = crate::database::get_revisions(connection)?;
+		let mut group_receipts = if receipts {
+			Self::get_group_receipts(connection, query, name)?
+		} else {
+			std::collections::HashMap::new()
+		};
+
+		let mut messages = Vec::new();
+		for row in query("message")? {
+			let id = row.get_i64("_id").unwrap_or_default();
+			let date_sent = row.get_i64("date_sent").unwrap_or_default();
+			// previous versions of edited messages are written with their latest version
+			if !date_range.contains(date_sent) || crate::database::is_previous_revision(&row) {
+				continue;
+			}
+
+			messages.push(Message {
+				id,
+				thread_id: row.get_i64("thread_id").unwrap_or_default(),
+				from: name(row.get_i64("from_recipient_id")),
+				to: name(row.get_i64("to_recipient_id")),
+				body: row.get_string("body"),
+				date_sent: crate::database::timestamp_to_date(date_sent),
+				date_received: crate::database::timestamp_to_date(
+					row.get_i64("date_received").unwrap_or_default(),
+				),
+				reactions: reactions.remove(&id).unwrap_or_default(),
+				mentions: mentions.remove(&id).unwrap_or_default(),
+				quote: quotes.remove(&id).map(|x| Quote {
+					id: x.id,
+					author: name(x.author_id),
+					body: x.body,
+				}),
+				edits: revisions
+					.remove(&id)
+					.unwrap_or_default()
+					.into_iter()
+					.map(|x| Edit {
+						body: x.body,
+						date_sent: crate::database::timestamp_to_date(x.date_sent),
+					})
+					.collect(),
+				receipts: if receipts && is_outgoing(&row) {
+					// messages to a group have a receipt of every member, others of their
+					// recipient only
+					Some(
+						group_receipts
+							.remove(&id)
+							.unwrap_or_else(|| get_receipt(&row, name).into_iter().collect()),
+					)
+				} else {
+					None
+				},
+			});
+		}
+
+		Ok(messages)
+	}
+
+	/// Queries the receipts of messages sent to groups by message id
+	fn get_group_receipts(
+		connection: &rusqlite::Connection,
+		query: &dyn Fn(&str) -> Result<Vec<crate::database::Row>, anyhow::Error>,
+		name: &dyn Fn(Option<i64>) -> String,
+	) -> Result<std::collections::HashMap<i64, Vec<Receipt>>, anyhow::Error> {
+		let mut receipts: std::collections::HashMap<i64, Vec<Receipt>> =
+			std::collections::HashMap::new();
+		if !crate::database::has_table(connection, "group_receipts")? {
+			return Ok(receipts);
+		}
+
+		for row in query("group_receipts")? {
+			// see GroupReceiptTable of the Signal app
+			let status = match row.get_i64("status") {
+				Some(0) => "undelivered",
+				Some(1) => "delivered",
+				Some(2) => "read",
+				Some(3) => "viewed",
+				Some(4) => "skipped",
+				_ => "unknown",
+			};
+			receipts
+				.entry(row.get_i64("mms_id").unwrap_or_default())
+				.or_default()
+				.push(Receipt {
+					recipient: name(row.get_i64("address")),
+					status,
+					date: row
+						.get_i64("timestamp")
+						.filter(|x| *x > 0)
+						.map(crate::database::timestamp_to_date),
+				});
+		}
+
+		Ok(receipts)
+	}
+
+	/// Queries groups
+	///
+	/// Members of v2 groups are stored in the table `group_membership`. Legacy groups and older
+	/// backups list them comma separated in the column `members`, either as recipient ids or, in
+	/// very old backups, as phone numbers.
+	fn get_groups(
+		connection: &rusqlite::Connection,
+		query: &dyn Fn(&str) -> Result<Vec<crate::database::Row>, anyhow::Error>,
+		name: &dyn Fn(Option<i64>) -> String,
+		avatars: &std::collections::HashSet<String>,
+	) -> Result<Vec<Group>, anyhow::Error> {
+		if !crate::database::has_table(connection, "groups")? {
+			return Ok(Vec::new());
+		}
+
+		let mut memberships: std::collections::HashMap<String, Vec<String>> =
+			std::collections::HashMap::new();
+		if crate::database::has_table(connection, "group_membership")? {
+			for row in query("group_membership")? {
+				memberships
+					.entry(row.get_string("group_id").unwrap_or_default())
+					.or_default()
+					.push(name(row.get_i64("recipient_id")));
+			}
+		}
+
+		let mut groups = Vec::new();
+		for row in query("groups")? {
+			let group_id = row.get_string("group_id").unwrap_or_default();
+
+			let members = memberships.remove(&group_id).unwrap_or_else(|| {
+				row.get_string("members")
+					.unwrap_or_default()
+					.split(',')
+					.filter(|x| !x.is_empty())
+					.map(|x| match x.parse() {
+						Ok(id) if !x.starts_with('+') => name(Some(id)),
+						_ => x.to_string(),
+					})
+					.collect()
+			});
+
+			let version = if group_id.starts_with("__signal_group__v2__")
+				|| row.has_value("master_key")
+			{
+				"v2"
+			} else {
+				"v1"
+			};
+
+			// avatars of groups are named by recipient id or, in older backups, group id
+			let avatar = [
+				row.get_i64("recipient_id").map(|x| x.to_string()),
+				Some(group_id.clone()),
+			]
+			.into_iter()
+			.flatten()
+			.find(|x| avatars.contains(x));
+
+			groups.push(Group {
+				group_id,
+				title: row.get_string("title"),
+				version: String::from(version),
+				active: row.get_i64("active").unwrap_or(1) != 0,
+				members,
+				avatar,
+			});
+		}
+
+		Ok(groups)
+	}
+
+	/// Queries threads
+	///
+	/// The timer of disappearing messages is stored with the recipient of a thread, which older
+	/// backups store in the column `thread_recipient_id`.
+	fn get_threads(
+		connection: &rusqlite::Connection,
+		query: &dyn Fn(&str) -> Result<Vec<crate::database::Row>, anyhow::Error>,
+		name: &dyn Fn(Option<i64>) -> String,
+		recipients: &std::collections::HashMap<i64, crate::database::Recipient>,
+	) -> Result<Vec<Thread>, anyhow::Error> {
+		if !crate::database::has_table(connection, "thread")? {
+			return Ok(Vec::new());
+		}
+
+		let mut threads = Vec::new();
+		for row in query("thread")? {
+			let recipient_id = row
+				.get_i64("recipient_id")
+				.or_else(|| row.get_i64("thread_recipient_id"));
+			let expiration = recipient_id
+				.and_then(|x| recipients.get(&x))
+				.map(|x| x.message_expiration_time)
+				.unwrap_or(0);
+
+			threads.push(Thread {
+				id: row.get_i64("_id").unwrap_or_default(),
+				recipient: name(recipient_id),
+				disappearing_messages: crate::database::format_expiration(expiration),
+			});
+		}
+
+		Ok(threads)
+	}
+}
+
+/// Returns whether a message has been sent by the owner of the backup
+///
+/// The lowest five bits of column type are the base type, see MessageTypes of the Signal app.
+/// Outgoing calls and messages being sent, sent or failed are outgoing.
+fn is_outgoing(row: &crate::database::Row) -> bool {
+	matches!(
+		row.get_i64("type").unwrap_or_default() & 0x1f,
+		2 | 11 | 21..=26
+	)
+}
+
+/// Returns the receipt of an outgoing message sent to a single recipient, if it has any
+///
+/// Newer backups flag receipts in columns has_delivery_receipt, has_read_receipt and viewed,
+/// older ones count them.
+fn get_receipt(
+	row: &crate::database::Row,
+	name: &dyn Fn(Option<i64>) -> String,
+) -> Option<Receipt> {
+	let has = |columns: &[&str]| {
+		columns
+			.iter()
+			.find_map(|x| row.get_i64(x))
+			.is_some_and(|x| x > 0)
+	};
+	let status = if has(&["viewed", "viewed_receipt_count"]) {
+		"viewed"
+	} else if has(&["has_read_receipt", "read_receipt_count"]) {
+		"read"
+	} else if has(&["has_delivery_receipt", "delivery_receipt_count"]) {
+		"delivered"
+	} else {
+		return None;
+	};
+
+	Some(Receipt {
+		recipient: name(row.get_i64("to_recipient_id")),
+		status,
+		date: row
+			.get_i64("receipt_timestamp")
+			.filter(|x| *x > 0)
+			.map(crate::database::timestamp_to_date),
+	})
+}
+
+impl crate::output::SignalOutput for SignalOutputJson {
+	fn write_statement(
+		&mut self,
+		statement: &str,
+		parameters: &[rusqlite::types::Value],
+	) -> Result<(), anyhow::Error> {
+		if !crate::database::is_statement_skipped(statement) {
+			crate::database::execute_statement(&self.sqlite_connection, statement, parameters)
+				.with_context(|| format!("failed to execute database statement: {}", statement))?;
+		}
+
+		if let Some(statements) = &mut self.statements {
+			statements.push(Statement {
+				statement: statement.to_string(),
+				parameters: parameters.iter().map(Parameter::from).collect(),
+			});
+		}
+
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_attachment(
+		&mut self,
+		_data: &[u8],
+		_attachmend_id: u64,
+		_row_id: u64,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_sticker(&mut self, _data: &[u8], _row_id: u64) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_avatar(&mut self, _data: &[u8], name: &str) -> Result<(), anyhow::Error> {
+		self.avatars.insert(name.to_string());
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_preference(
+		&mut self,
+		_pref: &crate::Backups::SharedPreference,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_version(&mut self, _version: u32) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_key_value(
+		&mut self,
+		_key_value: &crate::Backups::KeyValue,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_unknown(&mut self, _fields: &[u32]) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn get_written_frames(&self) -> usize {
+		self.written_frames
+	}
+
+	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		let mut export = Export::new(
+			&self.sqlite_connection,
+			&self.avatars,
+			self.sort_by.as_deref(),
+			self.date_range,
+			self.receipts,
+		)?;
+		export.statements = self.statements.take();
+
+		let file = std::fs::File::create(&self.path_json).with_context(|| {
+			format!("Failed to open file: {}", self.path_json.to_string_lossy())
+		})?;
+		serde_json::to_writer_pretty(std::io::BufWriter::new(file), &export).with_context(|| {
+			format!(
+				"Failed to write to file: {}",
+				self.path_json.to_string_lossy()
+			)
+		})?;
+
+		Ok(())
+	}
+}
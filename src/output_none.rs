@@ -1,54 +1,184 @@
-use log::info;
+use anyhow::anyhow;
+use log::{info, warn};
 
 /// Write no output of backup
 ///
 /// This output module does not write any backup files. This module can be used to check HMAC of
-/// the backup file but not writing any output.
+/// the backup file but not writing any output. Only attachments, avatars or stickers can be
+/// written with `--only`, named like by output type RAW.
+///
+/// With deep verification, the frames are counted by type and statements are checked to have as
+/// many parameters as placeholders. Frames unknown to this tool fail deep verification.
 pub struct SignalOutputNone {
 	written_frames: usize,
+	deep: bool,
+	/// Number of frames by type, only counted with deep verification
+	counts: std::collections::BTreeMap<&'static str, usize>,
+	/// Number of invalid frames by problem, only counted with deep verification
+	failures: std::collections::BTreeMap<&'static str, usize>,
+	/// Sink media files are written to, if any
+	sink: Option<Box<dyn crate::output_sink::SignalSink>>,
+	media: Vec<crate::output::SignalOutputMedia>,
+	force_write: bool,
+	mime_map: Option<crate::mime_map::MimeMap>,
+	/// Content types of attachments which are written, all if not given
+	only_content_type: Option<crate::mime_map::ContentTypeFilter>,
+	/// Size in bytes from which attachments are written to a directory of large attachments
+	attachment_size_threshold: Option<u64>,
+	count_attachment_skipped: usize,
+	count_avatar: usize,
+	count_sticker: usize,
 }
 
 impl SignalOutputNone {
 	/// Creates new output object
 	///
-	/// `force_write` determines whether existing files will be overwritten.
-	pub fn new() -> Self {
-		info!("No output will be written");
+	/// `deep` enables deep verification of the frames. Files of the kinds `media` are written to
+	/// `sink` while all other frames are still discarded. `force_write` determines whether
+	/// existing files will be overwritten. Files are named with extensions of `mime_map`, if
+	/// given. Attachments are only written if their type matches `only_content_type`, if given,
+	/// and separated by `attachment_size_threshold`, see `output_sink::attachment_directory`.
+	pub fn new(
+		deep: bool,
+		sink: Option<Box<dyn crate::output_sink::SignalSink>>,
+		media: Vec<crate::output::SignalOutputMedia>,
+		force_write: bool,
+		mime_map: Option<crate::mime_map::MimeMap>,
+		only_content_type: Option<crate::mime_map::ContentTypeFilter>,
+		attachment_size_threshold: Option<u64>,
+	) -> Self {
+		if sink.is_some() && !media.is_empty() {
+			info!("Only the selected attachments, avatars or stickers will be written");
+		} else {
+			info!("No output will be written");
+		}
 
 		Self {
 			// we set read frames to 1 due to the header frame we will never write
 			written_frames: 1,
+			deep,
+			counts: std::collections::BTreeMap::new(),
+			failures: std::collections::BTreeMap::new(),
+			sink,
+			media,
+			force_write,
+			mime_map,
+			only_content_type,
+			attachment_size_threshold,
+			count_attachment_skipped: 0,
+			count_avatar: 0,
+			count_sticker: 0,
+		}
+	}
+
+	/// Writes `data` to `directory/filename` if files of kind `media` are written
+	fn write_media(
+		&mut self,
+		media: crate::output::SignalOutputMedia,
+		directory: &str,
+		filename: &str,
+		data: &[u8],
+	) -> Result<(), anyhow::Error> {
+		match &mut self.sink {
+			Some(sink) if self.media.contains(&media) => {
+				crate::output_sink::write_media_file(
+					sink.as_mut(),
+					directory,
+					filename,
+					data,
+					self.mime_map.as_ref(),
+					// statements are discarded, thus content types are unknown
+					None,
+					self.force_write,
+				)?;
+			}
+			_ => (),
+		}
+
+		Ok(())
+	}
+
+	/// Counts a written frame of type `kind`
+	fn count(&mut self, kind: &'static str) {
+		self.written_frames += 1;
+		if self.deep {
+			*self.counts.entry(kind).or_default() += 1;
 		}
 	}
+
+	/// Counts an invalid frame with `problem`
+	fn fail(&mut self, problem: &'static str) {
+		*self.failures.entry(problem).or_default() += 1;
+	}
 }
 
 impl crate::output::SignalOutput for SignalOutputNone {
 	fn write_statement(
 		&mut self,
-		_statement: &str,
-		_parameters: &[rusqlite::types::Value],
+		statement: &str,
+		parameters: &[rusqlite::types::Value],
 	) -> Result<(), anyhow::Error> {
-		self.written_frames += 1;
+		self.count("statements");
+		// statements with numbered placeholders cannot be checked by their number
+		if self.deep
+			&& crate::database::split_placeholders(statement)
+				.is_ok_and(|x| x.len() != parameters.len() + 1)
+		{
+			warn!("Statement has wrong number of parameters: {}", statement);
+			self.fail("Statements with wrong number of parameters");
+		}
 		Ok(())
 	}
 
 	fn write_attachment(
 		&mut self,
-		_data: &[u8],
-		_attachmend_id: u64,
-		_row_id: u64,
+		data: &[u8],
+		attachmend_id: u64,
+		row_id: u64,
 	) -> Result<(), anyhow::Error> {
-		self.written_frames += 1;
+		// statements are discarded, thus only the detected type of the data is known
+		if self
+			.only_content_type
+			.as_ref()
+			.is_some_and(|x| !x.matches(None, data))
+		{
+			self.count_attachment_skipped += 1;
+		} else {
+			self.write_media(
+				crate::output::SignalOutputMedia::Attachments,
+				crate::output_sink::attachment_directory(
+					data.len(),
+					self.attachment_size_threshold,
+				),
+				&format!("{}_{}", attachmend_id, row_id),
+				data,
+			)?;
+		}
+		self.count("attachments");
 		Ok(())
 	}
 
-	fn write_sticker(&mut self, _data: &[u8], _row_id: u64) -> Result<(), anyhow::Error> {
-		self.written_frames += 1;
+	fn write_sticker(&mut self, data: &[u8], row_id: u64) -> Result<(), anyhow::Error> {
+		self.write_media(
+			crate::output::SignalOutputMedia::Stickers,
+			"sticker",
+			&format!("{}_{}", row_id, self.count_sticker),
+			data,
+		)?;
+		self.count_sticker += 1;
+		self.count("stickers");
 		Ok(())
 	}
 
-	fn write_avatar(&mut self, _data: &[u8], _name: &str) -> Result<(), anyhow::Error> {
-		self.written_frames += 1;
+	fn write_avatar(&mut self, data: &[u8], name: &str) -> Result<(), anyhow::Error> {
+		self.write_media(
+			crate::output::SignalOutputMedia::Avatars,
+			"avatar",
+			&format!("{}_{}", name, self.count_avatar),
+			data,
+		)?;
+		self.count_avatar += 1;
+		self.count("avatars");
 		Ok(())
 	}
 
@@ -56,12 +186,12 @@ impl crate::output::SignalOutput for SignalOutputNone {
 		&mut self,
 		_pref: &crate::Backups::SharedPreference,
 	) -> Result<(), anyhow::Error> {
-		self.written_frames += 1;
+		self.count("preferences");
 		Ok(())
 	}
 
 	fn write_version(&mut self, _version: u32) -> Result<(), anyhow::Error> {
-		self.written_frames += 1;
+		self.count("versions");
 		Ok(())
 	}
 
@@ -69,12 +199,58 @@ impl crate::output::SignalOutput for SignalOutputNone {
 		self.written_frames
 	}
 
-	fn write_key_value(&mut self, key_value: &crate::Backups::KeyValue) ->  Result<(), anyhow::Error>{
-		self.written_frames += 1;
+	fn write_key_value(&mut self, _key_value: &crate::Backups::KeyValue) ->  Result<(), anyhow::Error>{
+		self.count("key values");
 		Ok(())
 	}
 
-	fn finish(&mut self) -> Result<(), anyhow::Error> {
+	fn write_unknown(&mut self, fields: &[u32]) -> Result<(), anyhow::Error> {
+		self.count("unknown frames");
+		if self.deep {
+			warn!("Frame of unknown type with fields {:?}", fields);
+			self.fail("Frames of unknown type");
+		}
 		Ok(())
 	}
+
+	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		if let Some(sink) = &mut self.sink {
+			sink.finish()?;
+		}
+		if self.only_content_type.is_some() {
+			info!(
+				"Skipped {} attachments of other content types",
+				self.count_attachment_skipped
+			);
+		}
+
+		if !self.deep {
+			return Ok(());
+		}
+
+		for (kind, count) in &self.counts {
+			info!("Verified {}: {}", kind, count);
+		}
+		// older backups store settings as shared preferences, newer ones mostly as key values
+		let stores: Vec<_> = [("preferences", "shared preferences"), ("key values", "key values")]
+			.into_iter()
+			.filter(|(kind, _)| self.counts.contains_key(kind))
+			.map(|(_, store)| store)
+			.collect();
+		if !stores.is_empty() {
+			info!("Settings are stored as {}", stores.join(" and "));
+		}
+		if self.failures.is_empty() {
+			info!("Deep verification passed");
+			return Ok(());
+		}
+
+		for (problem, count) in &self.failures {
+			warn!("{}: {}", problem, count);
+		}
+		Err(anyhow!(
+			"Deep verification failed for {} frames",
+			self.failures.values().sum::<usize>()
+		))
+	}
 }
@@ -0,0 +1,319 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use arrow_array::builder::{
+	ArrayBuilder, BinaryBuilder, Float64Builder, Int64Builder, StringBuilder,
+};
+use log::{info, warn};
+
+/// Number of rows written to a Parquet file at once
+const BATCH_ROWS: usize = 8192;
+
+/// Write every table of a backup as Parquet file
+///
+/// Like output type DUMP, statements are replayed into an in memory database first. When all
+/// frames are written, every table is written to `TABLE.parquet` with a column of the type
+/// derived from the declared type of its SQLite column. Statements skipped by output type RAW
+/// and excluded tables are not part of it. Attachments, avatars, stickers and preferences are
+/// not written.
+pub struct SignalOutputParquet {
+	path: std::path::PathBuf,
+	sqlite_connection: rusqlite::Connection,
+	/// Tables whose statements are skipped, in lower case
+	exclude_tables: std::collections::HashSet<String>,
+	written_frames: usize,
+}
+
+impl SignalOutputParquet {
+	/// Creates new output object
+	///
+	/// `force_overwrite` determines whether existing Parquet files will be overwritten.
+	/// Statements of `exclude_tables` are skipped.
+	pub fn new(
+		path: &std::path::Path,
+		force_overwrite: bool,
+		exclude_tables: &[String],
+	) -> Result<Self, anyhow::Error> {
+		info!("Output path: {}", &path.to_string_lossy());
+
+		// check output path
+		if path.exists() && !path.is_dir() {
+			return Err(anyhow!(
+				"{} exists and is not a directory",
+				path.to_string_lossy()
+			));
+		} else {
+			std::fs::create_dir_all(path).with_context(|| {
+				format!("Path could not be created: {}", path.to_string_lossy())
+			})?;
+		}
+
+		// tables are only known at the end, thus any Parquet file may be overwritten
+		if !force_overwrite
+			&& std::fs::read_dir(path)?
+				.filter_map(|x| x.ok())
+				.any(|x| x.path().extension().is_some_and(|x| x == "parquet"))
+		{
+			return Err(anyhow!(
+				"Parquet files already exist and may not be overwritten. Try -f"
+			));
+		}
+
+		Ok(Self {
+			path: path.to_path_buf(),
+			sqlite_connection: rusqlite::Connection::open_in_memory()
+				.context("could not open connection to in memory database")?,
+			exclude_tables: exclude_tables.iter().map(|x| x.to_lowercase()).collect(),
+			// we set read frames to 1 due to the header frame we will never write
+			written_frames: 1,
+		})
+	}
+
+	/// Writes all rows of `table` to `table.parquet`
+	///
+	/// Rows are read and written in batches, thus large tables are never held in memory. Values
+	/// which do not fit the type of their column are written as null.
+	fn write_table(&self, table: &str) -> Result<usize, anyhow::Error> {
+		let columns = crate::database::get_typed_columns(&self.sqlite_connection, table)?;
+		let fields: Vec<arrow_schema::Field> = columns
+			.iter()
+			.map(|(name, column_type)| {
+				let data_type = match column_type {
+					crate::database::ColumnType::Integer => arrow_schema::DataType::Int64,
+					crate::database::ColumnType::Real => arrow_schema::DataType::Float64,
+					crate::database::ColumnType::Text => arrow_schema::DataType::Utf8,
+					crate::database::ColumnType::Blob => arrow_schema::DataType::Binary,
+				};
+				arrow_schema::Field::new(name, data_type, true)
+			})
+			.collect();
+		let schema = std::sync::Arc::new(arrow_schema::Schema::new(fields));
+
+		let path = self.path.join(format!("{}.parquet", table));
+		let file = std::fs::File::create(&path)
+			.with_context(|| format!("Could not create file: {}", path.to_string_lossy()))?;
+		let properties = parquet::file::properties::WriterProperties::builder()
+			.set_compression(parquet::basic::Compression::SNAPPY)
+			.build();
+		let mut writer =
+			parquet::arrow::ArrowWriter::try_new(file, schema.clone(), Some(properties))
+				.context("Failed to write Parquet file")?;
+
+		let mut statement = self
+			.sqlite_connection
+			.prepare(&format!(
+				"SELECT * FROM {}",
+				crate::database::quote_identifier(table)
+			))
+			.with_context(|| format!("failed to read table {}", table))?;
+		let mut rows = statement.query([])?;
+		let mut builders: Vec<ColumnBuilder> = schema
+			.fields()
+			.iter()
+			.map(|x| ColumnBuilder::new(x.data_type()))
+			.collect();
+		let mut count_rows = 0;
+		let mut count_mismatch = 0;
+		while let Some(row) = rows.next()? {
+			for (i, (builder, (_, column_type))) in builders.iter_mut().zip(&columns).enumerate() {
+				let value = crate::database::convert_value(row.get_ref(i)?, *column_type);
+				if !builder.append(value) {
+					count_mismatch += 1;
+				}
+			}
+			count_rows += 1;
+
+			if count_rows % BATCH_ROWS == 0 {
+				write_batch(&mut writer, &schema, &mut builders)?;
+			}
+		}
+		write_batch(&mut writer, &schema, &mut builders)?;
+		writer.close().context("Failed to write Parquet file")?;
+
+		if count_mismatch > 0 {
+			warn!(
+				"{} values of table {} do not match the type of their column and are written as null",
+				count_mismatch, table
+			);
+		}
+
+		Ok(count_rows)
+	}
+}
+
+/// Returns whether `table` can be used as file name in the output directory
+///
+/// Names are taken from the backup, thus they must not lead out of the output directory. As
+/// `.parquet` is appended, names like `..` are harmless.
+fn is_file_name(table: &str) -> bool {
+	!table.contains(['/', '\\', '\0'])
+}
+
+/// Writes the rows appended to `builders` as one batch
+fn write_batch(
+	writer: &mut parquet::arrow::ArrowWriter<std::fs::File>,
+	schema: &arrow_schema::SchemaRef,
+	builders: &mut [ColumnBuilder],
+) -> Result<(), anyhow::Error> {
+	if builders.first().is_none_or(|x| x.len() == 0) {
+		return Ok(());
+	}
+
+	let batch = arrow_array::RecordBatch::try_new(
+		schema.clone(),
+		builders.iter_mut().map(|x| x.finish()).collect(),
+	)?;
+	writer.write(&batch).context("Failed to write Parquet file")
+}
+
+/// Builder of a column of one of the Arrow types SQLite values are mapped to
+enum ColumnBuilder {
+	Integer(Int64Builder),
+	Real(Float64Builder),
+	Text(StringBuilder),
+	Blob(BinaryBuilder),
+}
+
+impl ColumnBuilder {
+	fn new(data_type: &arrow_schema::DataType) -> Self {
+		match data_type {
+			arrow_schema::DataType::Int64 => Self::Integer(Int64Builder::new()),
+			arrow_schema::DataType::Float64 => Self::Real(Float64Builder::new()),
+			arrow_schema::DataType::Utf8 => Self::Text(StringBuilder::new()),
+			_ => Self::Blob(BinaryBuilder::new()),
+		}
+	}
+
+	/// Appends `value`, as converted by `database::convert_value`
+	///
+	/// Returns `false` if the value could not be converted, it is appended as null then.
+	fn append(&mut self, value: Option<rusqlite::types::Value>) -> bool {
+		use rusqlite::types::Value;
+
+		match (&mut *self, &value) {
+			(Self::Integer(builder), Some(Value::Integer(x))) => builder.append_value(*x),
+			(Self::Real(builder), Some(Value::Real(x))) => builder.append_value(*x),
+			(Self::Text(builder), Some(Value::Text(x))) => builder.append_value(x),
+			(Self::Blob(builder), Some(Value::Blob(x))) => builder.append_value(x),
+			(Self::Integer(builder), _) => builder.append_null(),
+			(Self::Real(builder), _) => builder.append_null(),
+			(Self::Text(builder), _) => builder.append_null(),
+			(Self::Blob(builder), _) => builder.append_null(),
+		}
+
+		value.is_some()
+	}
+
+	fn len(&self) -> usize {
+		match self {
+			Self::Integer(x) => x.len(),
+			Self::Real(x) => x.len(),
+			Self::Text(x) => x.len(),
+			Self::Blob(x) => x.len(),
+		}
+	}
+
+	/// Returns the column of the appended values and resets the builder
+	fn finish(&mut self) -> arrow_array::ArrayRef {
+		match self {
+			Self::Integer(x) => std::sync::Arc::new(x.finish()),
+			Self::Real(x) => std::sync::Arc::new(x.finish()),
+			Self::Text(x) => std::sync::Arc::new(x.finish()),
+			Self::Blob(x) => std::sync::Arc::new(x.finish()),
+		}
+	}
+}
+
+impl crate::output::SignalOutput for SignalOutputParquet {
+	fn write_statement(
+		&mut self,
+		statement: &str,
+		parameters: &[rusqlite::types::Value],
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		if crate::database::is_statement_skipped(statement)
+			|| crate::database::get_statement_table(statement)
+				.is_some_and(|x| self.exclude_tables.contains(&x.to_lowercase()))
+		{
+			return Ok(());
+		}
+
+		crate::database::execute_statement(&self.sqlite_connection, statement, parameters)
+			.with_context(|| format!("failed to execute database statement: {}", statement))?;
+
+		Ok(())
+	}
+
+	fn write_attachment(
+		&mut self,
+		_data: &[u8],
+		_attachmend_id: u64,
+		_row_id: u64,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_sticker(&mut self, _data: &[u8], _row_id: u64) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_avatar(&mut self, _data: &[u8], _name: &str) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_preference(
+		&mut self,
+		_pref: &crate::Backups::SharedPreference,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_version(&mut self, _version: u32) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_key_value(
+		&mut self,
+		_key_value: &crate::Backups::KeyValue,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_unknown(&mut self, _fields: &[u32]) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn get_written_frames(&self) -> usize {
+		self.written_frames
+	}
+
+	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		let tables = crate::database::get_data_tables(&self.sqlite_connection)?;
+
+		let mut count_tables = 0;
+		let mut count_rows = 0;
+		for table in &tables {
+			if !is_file_name(table) {
+				warn!(
+					"Table {:?} is not written, its name cannot be used as file name",
+					table
+				);
+				continue;
+			}
+			count_rows += self.write_table(table)?;
+			count_tables += 1;
+		}
+		info!(
+			"Wrote {} tables with {} rows as Parquet files",
+			count_tables, count_rows
+		);
+
+		Ok(())
+	}
+}
@@ -0,0 +1,247 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use base64::Engine;
+use log::info;
+use std::io::Write;
+
+/// Write every frame of a backup as its complete protobuf in JSON
+///
+/// Unlike the other outputs, frames are written before they are converted to `Frame`, which
+/// only keeps the fields this tool uses. Thus, every field is written, also unknown ones. Each
+/// frame is written as a JSON object in a line of its own, the header first and the end frame
+/// last. The data of attachments, avatars and stickers is not part of the protobuf and not
+/// written.
+pub struct ProtobufJsonWriter {
+	writer: Box<dyn Write + Send>,
+	written_frames: usize,
+}
+
+impl ProtobufJsonWriter {
+	/// Creates new writer to `signal_backup.protobuf.jsonl` in directory `path`, or to stdout if
+	/// `path` is `-`
+	///
+	/// `force_overwrite` determines whether an existing file will be overwritten.
+	pub fn new(path: &std::path::Path, force_overwrite: bool) -> Result<Self, anyhow::Error> {
+		if path == std::path::Path::new("-") {
+			info!("Output: stdout");
+			return Ok(Self {
+				writer: Box::new(std::io::BufWriter::new(std::io::stdout())),
+				written_frames: 0,
+			});
+		}
+
+		info!("Output path: {}", &path.to_string_lossy());
+
+		// check output path
+		if path.exists() && !path.is_dir() {
+			return Err(anyhow!(
+				"{} exists and is not a directory",
+				path.to_string_lossy()
+			));
+		} else {
+			std::fs::create_dir_all(path).with_context(|| {
+				format!("Path could not be created: {}", path.to_string_lossy())
+			})?;
+		}
+
+		let path_json = path.join("signal_backup.protobuf.jsonl");
+		if path_json.exists() && !force_overwrite {
+			return Err(anyhow!(
+				"Backup file already exists and may not be overwritten. Try -f"
+			));
+		}
+
+		let file = std::fs::File::create(&path_json)
+			.with_context(|| format!("Failed to open file: {}", path_json.to_string_lossy()))?;
+
+		Ok(Self {
+			writer: Box::new(std::io::BufWriter::new(file)),
+			written_frames: 0,
+		})
+	}
+
+	/// Writes `frame` as a line of JSON
+	pub fn write_frame(&mut self, frame: &dyn protobuf::MessageDyn) -> Result<(), anyhow::Error> {
+		serde_json::to_writer(&mut self.writer, &message_to_json(frame))
+			.and_then(|_| self.writer.write_all(b"\n").map_err(serde_json::Error::io))
+			.context("Failed to write frame as JSON")?;
+		self.written_frames += 1;
+
+		Ok(())
+	}
+
+	/// Writes all buffered frames
+	pub fn finish(&mut self) -> Result<(), anyhow::Error> {
+		self.writer
+			.flush()
+			.context("Failed to write frames as JSON")?;
+		info!("Wrote {} frames as JSON", self.written_frames);
+
+		Ok(())
+	}
+}
+
+/// Output of type PROTOBUF-JSON
+///
+/// Frames are already written by the `ProtobufJsonWriter` of the reader, thus they are only
+/// counted here.
+pub struct SignalOutputProtobufJson {
+	written_frames: usize,
+}
+
+impl SignalOutputProtobufJson {
+	pub fn new() -> Self {
+		Self {
+			// we set read frames to 1 due to the header frame we will never write
+			written_frames: 1,
+		}
+	}
+}
+
+impl crate::output::SignalOutput for SignalOutputProtobufJson {
+	fn write_statement(
+		&mut self,
+		_statement: &str,
+		_parameters: &[rusqlite::types::Value],
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_attachment(
+		&mut self,
+		_data: &[u8],
+		_attachmend_id: u64,
+		_row_id: u64,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_sticker(&mut self, _data: &[u8], _row_id: u64) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_avatar(&mut self, _data: &[u8], _name: &str) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_preference(
+		&mut self,
+		_pref: &crate::Backups::SharedPreference,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_version(&mut self, _version: u32) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_key_value(
+		&mut self,
+		_key_value: &crate::Backups::KeyValue,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_unknown(&mut self, _fields: &[u32]) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn get_written_frames(&self) -> usize {
+		self.written_frames
+	}
+
+	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		Ok(())
+	}
+}
+
+/// Converts a protobuf message to JSON by reflection
+///
+/// Fields are named as in the `.proto` file, fields which are not set are left out. Bytes are
+/// encoded as base64 and enums by the name of their value. Fields unknown to the `.proto` file
+/// are written to `unknown_fields` by their number.
+fn message_to_json(message: &dyn protobuf::MessageDyn) -> serde_json::Value {
+	let mut object = serde_json::Map::new();
+
+	for field in message.descriptor_dyn().fields() {
+		let value = match field.get_reflect(message) {
+			protobuf::reflect::ReflectFieldRef::Optional(x) => match x.value() {
+				Some(x) => value_to_json(x),
+				None => continue,
+			},
+			protobuf::reflect::ReflectFieldRef::Repeated(x) if x.is_empty() => continue,
+			protobuf::reflect::ReflectFieldRef::Repeated(x) => {
+				serde_json::Value::Array(x.into_iter().map(value_to_json).collect())
+			}
+			protobuf::reflect::ReflectFieldRef::Map(x) if x.is_empty() => continue,
+			protobuf::reflect::ReflectFieldRef::Map(x) => serde_json::Value::Object(
+				(&x).into_iter()
+					.map(|(key, value)| (key.to_string(), value_to_json(value)))
+					.collect(),
+			),
+		};
+		object.insert(field.name().to_string(), value);
+	}
+
+	let mut unknown = serde_json::Map::new();
+	for (number, value) in message.special_fields_dyn().unknown_fields().iter() {
+		let value = match value {
+			protobuf::UnknownValueRef::Fixed32(x) => serde_json::Value::from(x),
+			protobuf::UnknownValueRef::Fixed64(x) => serde_json::Value::from(x),
+			protobuf::UnknownValueRef::Varint(x) => serde_json::Value::from(x),
+			protobuf::UnknownValueRef::LengthDelimited(x) => {
+				serde_json::Value::from(base64::engine::general_purpose::STANDARD.encode(x))
+			}
+		};
+		// fields may be repeated
+		match unknown.entry(number.to_string()) {
+			serde_json::map::Entry::Vacant(x) => {
+				x.insert(value);
+			}
+			serde_json::map::Entry::Occupied(mut x) => match x.get_mut() {
+				serde_json::Value::Array(values) => values.push(value),
+				first => *first = serde_json::Value::Array(vec![first.take(), value]),
+			},
+		}
+	}
+	if !unknown.is_empty() {
+		object.insert(
+			String::from("unknown_fields"),
+			serde_json::Value::Object(unknown),
+		);
+	}
+
+	serde_json::Value::Object(object)
+}
+
+/// Converts a single value of a protobuf field to JSON
+fn value_to_json(value: protobuf::reflect::ReflectValueRef) -> serde_json::Value {
+	match value {
+		protobuf::reflect::ReflectValueRef::U32(x) => serde_json::Value::from(x),
+		protobuf::reflect::ReflectValueRef::U64(x) => serde_json::Value::from(x),
+		protobuf::reflect::ReflectValueRef::I32(x) => serde_json::Value::from(x),
+		protobuf::reflect::ReflectValueRef::I64(x) => serde_json::Value::from(x),
+		protobuf::reflect::ReflectValueRef::F32(x) => serde_json::Value::from(x),
+		protobuf::reflect::ReflectValueRef::F64(x) => serde_json::Value::from(x),
+		protobuf::reflect::ReflectValueRef::Bool(x) => serde_json::Value::from(x),
+		protobuf::reflect::ReflectValueRef::String(x) => serde_json::Value::from(x),
+		protobuf::reflect::ReflectValueRef::Bytes(x) => {
+			serde_json::Value::from(base64::engine::general_purpose::STANDARD.encode(x))
+		}
+		protobuf::reflect::ReflectValueRef::Enum(descriptor, x) => {
+			match descriptor.value_by_number(x) {
+				Some(x) => serde_json::Value::from(x.name()),
+				None => serde_json::Value::from(x),
+			}
+		}
+		protobuf::reflect::ReflectValueRef::Message(x) => message_to_json(&*x),
+	}
+}
@@ -1,51 +1,258 @@
 use anyhow::anyhow;
 use anyhow::Context;
-use log::{debug, info};
-use std::io::Write;
+use base64::Engine;
+use log::{debug, info, warn};
+use sha2::Digest;
+
+/// File name of the sqlite database
+const FILENAME_DATABASE: &str = "signal_backup.db";
+
+/// File name of the database attachments are stored in with `attachments_in_db`
+pub(crate) const FILENAME_DATABASE_ATTACHMENTS: &str = "attachments.sqlite";
+
+/// File name of the exported contacts
+const FILENAME_VCARD: &str = "contacts.vcf";
+
+/// File name of the contact sheet
+const FILENAME_CONTACT_SHEET: &str = "contact_sheet.png";
+
+/// File name of the exported call history
+const FILENAME_CALLS: &str = "calls.csv";
+
+/// File name of the index of written attachments
+const FILENAME_ATTACHMENTS_INDEX: &str = "attachments_index.csv";
+
+/// File name of the manifest of written sticker packs
+const FILENAME_STICKER_MANIFEST: &str = "sticker/manifest.json";
+
+/// File name of the settings of newer backups, which are stored as key values instead of
+/// shared preferences
+const FILENAME_KEY_VALUES: &str = "key_values.ini";
+
+/// Directory of the databases of single threads
+const DIRECTORY_THREADS: &str = "threads";
+
+/// Decision of a handler for failed statements
+pub enum StatementErrorAction {
+	/// Skip the failed statement and continue with the next frame
+	Continue,
+	/// Abort writing the backup
+	Abort,
+}
+
+/// Handler called with statement and error whenever a statement could not be executed
+pub type StatementErrorHandler =
+	Box<dyn FnMut(&str, &rusqlite::Error) -> StatementErrorAction + Send>;
+
+/// Options of the raw output
+#[derive(Default)]
+pub struct SignalOutputRawOptions {
+	/// Overwrite existing files
+	pub force_write: bool,
+	/// Load the database in memory and write it to disk at the end
+	pub open_db_in_memory: bool,
+	/// Size in bytes of the in memory database at which it is moved to disk
+	pub memory_limit: Option<u64>,
+	/// Number of statements executed in one transaction
+	pub sql_batch_size: usize,
+	/// Number of frames after which the open transaction is committed
+	pub commit_interval: Option<usize>,
+	/// Disable journal and synchronous writes of the database while the backup is loaded
+	pub fast_db: bool,
+	/// Write all contacts to a vCard file
+	pub export_vcard: bool,
+	/// Write the call history to a CSV file
+	pub export_calls: bool,
+	/// Write every attachment file with its message and thread to a CSV file
+	pub export_attachments_index: bool,
+	/// Write every sticker pack with its stickers to a JSON file
+	pub export_sticker_manifest: bool,
+	/// Set the modification time of attachment files to the date of their message
+	pub preserve_timestamps: bool,
+	/// Draw all contacts with their avatars into an image
+	pub avatar_contact_sheet: bool,
+	/// Write the conversation of every thread to a separate database
+	pub split_db_by_thread: bool,
+	/// Name attachments by their position in the backup instead of their database ids
+	pub sequential_attachments: bool,
+	/// Create a full text search table of all messages
+	pub build_fts: bool,
+	/// Database of a previous backup, rows existing in it are not written
+	pub diff_against: Option<std::path::PathBuf>,
+	/// Rebuild indexes, update statistics and compact the database at the end
+	pub normalize_db: bool,
+	/// Store attachments in a separate database instead of files
+	pub attachments_in_db: bool,
+	/// Extensions of files by content type, instead of determining them from the data only
+	pub mime_map: Option<crate::mime_map::MimeMap>,
+	/// Content types of attachments which are written, all if not given
+	pub only_content_type: Option<crate::mime_map::ContentTypeFilter>,
+	/// Only attachments of messages sent in this range are written
+	pub date_range: crate::database::DateRange,
+	/// Size in bytes from which attachments are written to a directory of large attachments
+	pub attachment_size_threshold: Option<u64>,
+	/// Tables whose statements are skipped
+	pub exclude_tables: Vec<String>,
+	/// Compare the hash of every attachment to the hash stored in the database
+	pub verify_attachments: bool,
+	/// Fail instead of warning if an attachment does not match its stored hash
+	pub strict: bool,
+	/// Commit the database only with checkpoints, so that it always matches the last one
+	pub checkpoints: bool,
+	/// Continue writing an existing database, see `resume`
+	pub resume: bool,
+	/// Record how the database has been decoded in a table of its own
+	pub decode_metadata: Option<DecodeMetadata>,
+}
+
+/// Provenance of a decoded database, written to `database::TABLE_DECODE_METADATA`
+pub struct DecodeMetadata {
+	/// File name of the backup, or its URL
+	pub input: String,
+	/// Size of the backup in bytes, unknown for downloads
+	pub input_size: Option<u64>,
+	/// Whether the HMAC of every frame has been verified
+	pub verify_mac: bool,
+}
+
+/// State of the raw output stored in checkpoints
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CheckpointState {
+	written_frames: usize,
+	count_attachment: usize,
+	#[serde(default)]
+	count_attachment_skipped: usize,
+	#[serde(default)]
+	count_attachment_out_of_range: usize,
+	count_sticker: usize,
+	count_avatar: usize,
+	count_statement_existing: usize,
+	preferences: std::collections::BTreeMap<std::path::PathBuf, Vec<(String, String)>>,
+	avatars: std::collections::HashMap<String, std::path::PathBuf>,
+	#[serde(default)]
+	written_attachments: Option<Vec<crate::export_attachments_index::WrittenAttachment>>,
+	#[serde(default)]
+	written_stickers: Option<Vec<crate::export_sticker_manifest::WrittenSticker>>,
+	#[serde(default)]
+	count_digest_verified: usize,
+	#[serde(default)]
+	count_digest_mismatch: usize,
+	#[serde(default)]
+	count_digest_missing: usize,
+	#[serde(default)]
+	tables_seen: std::collections::BTreeSet<String>,
+}
 
 /// Write raw backup
 ///
 /// This output module writes the backup in a sqlite database and media files in different
 /// directories.
 pub struct SignalOutputRaw {
-	path_output: std::path::PathBuf,
+	sink: Box<dyn crate::output_sink::SignalSink>,
 	force_write: bool,
 	sqlite_connection: rusqlite::Connection,
 	sqlite_in_memory: bool,
+	memory_limit: Option<u64>,
 	count_attachment: usize,
+	/// Number of attachments not written as their content type does not match
+	count_attachment_skipped: usize,
+	/// Number of attachments not written as their message is not in the date range
+	count_attachment_out_of_range: usize,
 	count_sticker: usize,
 	count_avatar: usize,
 	written_frames: usize,
-	created_files: std::boxed::Box<std::collections::HashSet<std::path::PathBuf>>
+	preferences: std::collections::BTreeMap<std::path::PathBuf, ini::Ini>,
+	on_statement_error: StatementErrorHandler,
+	sql_batch_size: usize,
+	count_statement_transaction: usize,
+	commit_interval: Option<usize>,
+	/// Number of written frames at the last commit
+	frames_committed: usize,
+	fast_db: bool,
+	export_vcard: bool,
+	export_calls: bool,
+	export_attachments_index: bool,
+	preserve_timestamps: bool,
+	/// Attachments written to files, if they are indexed or their timestamps are preserved
+	written_attachments: Option<Vec<crate::export_attachments_index::WrittenAttachment>>,
+	/// Stickers written to files, if they are listed in a manifest
+	written_stickers: Option<Vec<crate::export_sticker_manifest::WrittenSticker>>,
+	/// Thumbnails of avatars by name for the contact sheet, if it is created
+	contact_sheet_thumbnails: Option<std::collections::HashMap<String, image::RgbaImage>>,
+	split_db_by_thread: bool,
+	sequential_attachments: bool,
+	build_fts: bool,
+	normalize_db: bool,
+	reference_database: Option<crate::database::ReferenceDatabase>,
+	attachments_connection: Option<rusqlite::Connection>,
+	count_statement_existing: usize,
+	avatars: std::collections::HashMap<String, std::path::PathBuf>,
+	checkpoints: bool,
+	mime_map: Option<crate::mime_map::MimeMap>,
+	only_content_type: Option<crate::mime_map::ContentTypeFilter>,
+	date_range: crate::database::DateRange,
+	attachment_size_threshold: Option<u64>,
+	/// Tables whose statements are skipped, in lower case
+	exclude_tables: std::collections::HashSet<String>,
+	verify_attachments: bool,
+	strict: bool,
+	/// Number of attachments matching, not matching and without their stored hash
+	count_digest_verified: usize,
+	count_digest_mismatch: usize,
+	count_digest_missing: usize,
+	/// Tables rows have been inserted into, to log the first row of each table
+	tables_seen: std::collections::HashSet<String>,
+	decode_metadata: Option<DecodeMetadata>,
 }
 
 impl SignalOutputRaw {
-	/// Creates new output object
-	///
-	/// `force_write` determines whether existing files will be overwritten.
+	/// Creates new output object writing to `sink`
 	pub fn new(
-		path: &std::path::Path,
-		force_write: bool,
-		open_db_in_memory: bool,
+		sink: Box<dyn crate::output_sink::SignalSink>,
+		options: SignalOutputRawOptions,
 	) -> Result<Self, anyhow::Error> {
-		info!("Output path: {}", &path.to_string_lossy());
-
-		// check output path
-		if path.exists() && !path.is_dir() {
-			return Err(anyhow!(
-				"{} exists and is not a directory",
-				path.to_string_lossy()
-			));
-		} else {
-			std::fs::create_dir_all(&path).with_context(|| {
-				format!("Path could not be created: {}", path.to_string_lossy())
-			})?;
-		}
+		let SignalOutputRawOptions {
+			force_write,
+			open_db_in_memory,
+			memory_limit,
+			sql_batch_size,
+			commit_interval,
+			fast_db,
+			export_vcard,
+			export_calls,
+			export_attachments_index,
+			export_sticker_manifest,
+			preserve_timestamps,
+			avatar_contact_sheet,
+			split_db_by_thread,
+			sequential_attachments,
+			build_fts,
+			diff_against,
+			normalize_db,
+			attachments_in_db,
+			checkpoints,
+			resume,
+			mime_map,
+			only_content_type,
+			date_range,
+			attachment_size_threshold,
+			exclude_tables,
+			verify_attachments,
+			strict,
+			decode_metadata,
+		} = options;
 
 		// open database connection
-		let path_sqlite = path.join("signal_backup.db");
+		let path_sqlite = sink.staging_path(std::path::Path::new(FILENAME_DATABASE));
 
-		if path_sqlite.exists() {
+		if resume {
+			if open_db_in_memory || !path_sqlite.exists() {
+				return Err(anyhow!(
+					"Backup database to resume does not exist on disk: {}",
+					path_sqlite.to_string_lossy()
+				));
+			}
+		} else if path_sqlite.exists() {
 			if force_write {
 				std::fs::remove_file(&path_sqlite).with_context(|| {
 					format!(
@@ -73,53 +280,397 @@ impl SignalOutputRaw {
 			})?
 		};
 
+		if export_vcard && sink.exists(std::path::Path::new(FILENAME_VCARD)) && !force_write {
+			return Err(anyhow!("Contacts file does already exist: {}. Try -f", FILENAME_VCARD));
+		}
+
+		if export_calls && sink.exists(std::path::Path::new(FILENAME_CALLS)) && !force_write {
+			return Err(anyhow!("Calls file does already exist: {}. Try -f", FILENAME_CALLS));
+		}
+
+		if export_attachments_index
+			&& sink.exists(std::path::Path::new(FILENAME_ATTACHMENTS_INDEX))
+			&& !force_write
+		{
+			return Err(anyhow!(
+				"Attachments index does already exist: {}. Try -f",
+				FILENAME_ATTACHMENTS_INDEX
+			));
+		}
+
+		if export_sticker_manifest
+			&& sink.exists(std::path::Path::new(FILENAME_STICKER_MANIFEST))
+			&& !force_write
+		{
+			return Err(anyhow!(
+				"Sticker manifest does already exist: {}. Try -f",
+				FILENAME_STICKER_MANIFEST
+			));
+		}
+
+		if avatar_contact_sheet
+			&& sink.exists(std::path::Path::new(FILENAME_CONTACT_SHEET))
+			&& !force_write
+		{
+			return Err(anyhow!(
+				"Contact sheet does already exist: {}. Try -f",
+				FILENAME_CONTACT_SHEET
+			));
+		}
+
+		if split_db_by_thread
+			&& sink.exists(std::path::Path::new(DIRECTORY_THREADS))
+			&& !force_write
+		{
+			return Err(anyhow!(
+				"Thread databases do already exist: {}. Try -f",
+				DIRECTORY_THREADS
+			));
+		}
+
+		let attachments_connection = if attachments_in_db {
+			Some(Self::open_attachments_database(sink.as_ref(), force_write)?)
+		} else {
+			None
+		};
+
+		let reference_database = match diff_against {
+			Some(x) => {
+				info!("Reference database: {}", x.to_string_lossy());
+				Some(crate::database::ReferenceDatabase::open(&x)?)
+			}
+			None => None,
+		};
+
+		if fast_db {
+			Self::tune_database(&sqlite_connection)?;
+		}
+
 		Ok(Self {
-			path_output: path.to_path_buf(),
+			sink,
 			force_write,
 			sqlite_connection,
 			sqlite_in_memory: open_db_in_memory,
+			memory_limit,
 			count_attachment: 0,
+			count_attachment_skipped: 0,
+			count_attachment_out_of_range: 0,
 			count_sticker: 0,
 			count_avatar: 0,
 			// we set read frames to 1 due to the header frame we will never write
 			written_frames: 1,
-			created_files: std::boxed::Box::new(std::collections::HashSet::new())
+			preferences: std::collections::BTreeMap::new(),
+			on_statement_error: Box::new(|_, _| StatementErrorAction::Abort),
+			sql_batch_size,
+			count_statement_transaction: 0,
+			commit_interval,
+			frames_committed: 0,
+			fast_db,
+			export_vcard,
+			export_calls,
+			export_attachments_index,
+			preserve_timestamps,
+			written_attachments: if export_attachments_index || preserve_timestamps {
+				Some(Vec::new())
+			} else {
+				None
+			},
+			written_stickers: if export_sticker_manifest {
+				Some(Vec::new())
+			} else {
+				None
+			},
+			contact_sheet_thumbnails: if avatar_contact_sheet {
+				Some(std::collections::HashMap::new())
+			} else {
+				None
+			},
+			split_db_by_thread,
+			sequential_attachments,
+			build_fts,
+			normalize_db,
+			reference_database,
+			attachments_connection,
+			count_statement_existing: 0,
+			avatars: std::collections::HashMap::new(),
+			checkpoints,
+			mime_map,
+			only_content_type,
+			date_range,
+			attachment_size_threshold,
+			exclude_tables: exclude_tables.iter().map(|x| x.to_lowercase()).collect(),
+			verify_attachments,
+			strict,
+			count_digest_verified: 0,
+			count_digest_mismatch: 0,
+			count_digest_missing: 0,
+			tables_seen: std::collections::HashSet::new(),
+			decode_metadata,
 		})
 	}
 
+	/// Writes a single row describing this decode to `database::TABLE_DECODE_METADATA`
+	///
+	/// The number of frames includes the header, like the number of written frames logged.
+	fn write_decode_metadata(&self, metadata: &DecodeMetadata) -> Result<(), anyhow::Error> {
+		self.sqlite_connection
+			.execute_batch(&format!(
+				"DROP TABLE IF EXISTS {0};
+				CREATE TABLE {0} (tool_version TEXT NOT NULL, decoded_at TEXT NOT NULL, input_file TEXT NOT NULL, input_size INTEGER, frames INTEGER NOT NULL, mac_verified INTEGER NOT NULL);",
+				crate::database::TABLE_DECODE_METADATA
+			))
+			.context("failed to create decode metadata table")?;
+		self.sqlite_connection
+			.execute(
+				&format!(
+					"INSERT INTO {} VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+					crate::database::TABLE_DECODE_METADATA
+				),
+				rusqlite::params![
+					env!("CARGO_PKG_VERSION"),
+					chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+					metadata.input,
+					metadata.input_size.and_then(|x| i64::try_from(x).ok()),
+					i64::try_from(self.written_frames)?,
+					metadata.verify_mac,
+				],
+			)
+			.context("failed to write decode metadata")?;
+
+		Ok(())
+	}
+
+	/// Opens the database attachments are stored in and begins a transaction for all of them
+	fn open_attachments_database(
+		sink: &dyn crate::output_sink::SignalSink,
+		force_write: bool,
+	) -> Result<rusqlite::Connection, anyhow::Error> {
+		let path = sink.staging_path(std::path::Path::new(FILENAME_DATABASE_ATTACHMENTS));
+		if path.exists() || sink.exists(std::path::Path::new(FILENAME_DATABASE_ATTACHMENTS)) {
+			if !force_write {
+				return Err(anyhow!(
+					"Attachment database already exists: {}. Try -f",
+					path.to_string_lossy()
+				));
+			}
+			if path.exists() {
+				std::fs::remove_file(&path).with_context(|| {
+					format!(
+						"Could not delete old attachment database: {}",
+						path.to_string_lossy()
+					)
+				})?;
+			}
+		}
+
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent).with_context(|| {
+				format!("Path could not be created: {}", parent.to_string_lossy())
+			})?;
+		}
+		let connection = rusqlite::Connection::open(&path).with_context(|| {
+			format!(
+				"could not open connection to database file: {}",
+				path.to_string_lossy()
+			)
+		})?;
+		connection
+			.execute_batch(
+				"CREATE TABLE attachment (attachment_id INTEGER NOT NULL, row_id INTEGER NOT NULL, content_type TEXT, data BLOB NOT NULL, PRIMARY KEY (attachment_id, row_id)); BEGIN;",
+			)
+			.context("failed to create attachment database")?;
+
+		Ok(connection)
+	}
+
+	/// Disables journal and synchronous writes of the database
+	fn tune_database(connection: &rusqlite::Connection) -> Result<(), anyhow::Error> {
+		// the backup file is the source of truth, thus crash safety is not needed while loading
+		connection
+			.execute_batch(
+				"PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF; PRAGMA temp_store = MEMORY;",
+			)
+			.context("failed to tune database settings")
+	}
+
+	/// Moves the in memory database to disk if it exceeds the memory limit
+	///
+	/// Must only be called between transactions. Attachments are never kept in memory, they are
+	/// written as soon as they are read.
+	fn check_memory_limit(&mut self) -> Result<(), anyhow::Error> {
+		let Some(limit) = self.memory_limit.filter(|_| self.sqlite_in_memory) else {
+			return Ok(());
+		};
+
+		let size: i64 = self
+			.sqlite_connection
+			.query_row(
+				"SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+				[],
+				|row| row.get(0),
+			)
+			.context("failed to read size of in memory database")?;
+		if (size as u64) < limit {
+			return Ok(());
+		}
+
+		let path_sqlite = self
+			.sink
+			.staging_path(std::path::Path::new(FILENAME_DATABASE));
+		info!(
+			"In memory database exceeds memory limit ({} bytes), moving it to disk: {}",
+			size,
+			path_sqlite.to_string_lossy()
+		);
+
+		self.sqlite_connection
+			.execute("VACUUM INTO ?1", [path_sqlite.to_string_lossy()])
+			.with_context(|| {
+				format!(
+					"Failed to copy in memory database to file: {}",
+					path_sqlite.to_string_lossy()
+				)
+			})?;
+		self.sqlite_connection = rusqlite::Connection::open(&path_sqlite).with_context(|| {
+			format!(
+				"could not open connection to database file: {}",
+				path_sqlite.to_string_lossy()
+			)
+		})?;
+		if self.fast_db {
+			Self::tune_database(&self.sqlite_connection)?;
+		}
+		self.sqlite_in_memory = false;
+
+		Ok(())
+	}
+
+	/// Sets the handler deciding whether to continue if a statement fails
+	///
+	/// By default, writing is aborted on the first failed statement.
+	pub fn set_on_statement_error(&mut self, handler: StatementErrorHandler) {
+		self.on_statement_error = handler;
+	}
+
 	fn write_to_file(
-		&self,
+		&mut self,
 		path_specific: &str,
 		filename: &str,
 		data: &[u8],
+		content_type: Option<&str>,
+	) -> Result<std::path::PathBuf, anyhow::Error> {
+		crate::output_sink::write_media_file(
+			self.sink.as_mut(),
+			path_specific,
+			filename,
+			data,
+			self.mime_map.as_ref(),
+			content_type,
+			self.force_write,
+		)
+	}
+
+	/// Returns whether a statement writes to an excluded table
+	///
+	/// Indexes and triggers of excluded tables are excluded as well. Retained tables referencing
+	/// an excluded table by a foreign key are created with a warning, as their rows may refer to
+	/// rows missing in the database.
+	fn is_statement_excluded(&self, statement: &str) -> bool {
+		if self.exclude_tables.is_empty() {
+			return false;
+		}
+
+		match crate::database::get_statement_table(statement) {
+			Some(table) if self.exclude_tables.contains(&table.to_lowercase()) => {
+				if statement.starts_with("CREATE TABLE") {
+					info!("Skip excluded table: {}", table);
+				}
+				true
+			}
+			Some(table) => {
+				if statement.starts_with("CREATE TABLE") {
+					for referenced in crate::database::get_referenced_tables(statement) {
+						if self.exclude_tables.contains(&referenced.to_lowercase()) {
+							warn!(
+								"Table {} references excluded table {} by a foreign key",
+								table, referenced
+							);
+						}
+					}
+				}
+				false
+			}
+			None => false,
+		}
+	}
+
+	/// Compares the SHA-256 hash of an attachment to the hash stored in its `row`
+	///
+	/// Signal stores the base64 encoded hash in column `data_hash_end` or, in older backups,
+	/// `data_hash`. A mismatch means that the attachment was damaged before the backup was
+	/// created, which the HMAC of the frame cannot detect.
+	fn verify_attachment_digest(
+		&mut self,
+		data: &[u8],
+		attachment_id: u64,
+		row_id: u64,
+		row: Option<&crate::database::Row>,
 	) -> Result<(), anyhow::Error> {
-		// create path to attachment file
-		let path = self.path_output.join(path_specific);
-		std::fs::create_dir_all(&path)
-			.with_context(|| format!("Failed to create path: {}", path.to_string_lossy()))?;
+		let Some(expected) = row.and_then(|x| x.get_first_string(&["data_hash_end", "data_hash"]))
+		else {
+			debug!("Attachment {}_{} has no stored hash", attachment_id, row_id);
+			self.count_digest_missing += 1;
+			return Ok(());
+		};
 
-		// add filename and extension to path
-		let mut path = path.join(filename);
-		let infer = infer::Infer::new();
-		if let Some(x) = infer.get(&data) {
-			path.set_extension(x.extension());
+		let actual = base64::engine::general_purpose::STANDARD.encode(sha2::Sha256::digest(data));
+		if actual == expected {
+			self.count_digest_verified += 1;
+			return Ok(());
 		}
 
-		if path.exists() && !self.force_write {
-			return Err(anyhow!(
-				"File does already exist: {}. Try -f",
-				path.to_string_lossy()
-			));
+		self.count_digest_mismatch += 1;
+		let message = format!(
+			"Attachment {}_{} does not match its stored hash (stored: {}, calculated: {})",
+			attachment_id, row_id, expected, actual
+		);
+		if self.strict {
+			Err(anyhow!(message))
+		} else {
+			warn!("{}", message);
+			Ok(())
+		}
+	}
+
+	/// Commits the currently open transaction, if any
+	fn commit(&mut self) -> Result<(), anyhow::Error> {
+		if self.count_statement_transaction > 0 {
+			self.sqlite_connection
+				.execute_batch("COMMIT")
+				.context("failed to commit database transaction")?;
+			self.count_statement_transaction = 0;
 		}
 
-		// open connection to file
-		let mut buffer = std::fs::File::create(&path)
-			.with_context(|| format!("Failed to open file: {}", path.to_string_lossy()))?;
+		Ok(())
+	}
+
+	/// Commits the open transaction if the commit interval has passed since the last commit
+	///
+	/// Attachments usually follow all statements, thus this is also checked for frames which
+	/// are no statements.
+	fn commit_if_due(&mut self) -> Result<(), anyhow::Error> {
+		let Some(interval) = self.commit_interval else {
+			return Ok(());
+		};
+		if self.written_frames - self.frames_committed < interval {
+			return Ok(());
+		}
 
-		// write to file
-		buffer
-			.write_all(data)
-			.with_context(|| format!("Failed to write to file: {}", path.to_string_lossy()))?;
+		self.frames_committed = self.written_frames;
+		if self.count_statement_transaction > 0 {
+			debug!("Commit database at frame {}", self.written_frames);
+			self.commit()?;
+		}
 
 		Ok(())
 	}
@@ -131,24 +682,66 @@ impl crate::output::SignalOutput for SignalOutputRaw {
 		statement: &str,
 		parameters: &[rusqlite::types::Value],
 	) -> Result<(), anyhow::Error> {
-		// In database version 9 signal added full text search and uses TRIGGERs to create the virtual tables. however this breaks when importing the data.
-		if statement.starts_with("CREATE TRIGGER")
-			|| statement.contains("_fts")
-			|| statement.starts_with("CREATE TABLE sqlite_")
+		if crate::database::is_statement_skipped(statement) {
+			self.written_frames += 1;
+			return Ok(());
+		}
+
+		if self.is_statement_excluded(statement) {
+			self.written_frames += 1;
+			return Ok(());
+		}
+
+		// tables are filled one after another, thus this shows the progress in terms of tables
+		if let Some(table) = crate::database::get_insert_table(statement)
+			.filter(|x| !self.tables_seen.contains(*x))
 		{
+			info!("First row for table: {}", table);
+			self.tables_seen.insert(table.to_string());
+		}
+
+		let existing = match &mut self.reference_database {
+			Some(x) => x.contains(&self.sqlite_connection, statement, parameters)?,
+			None => false,
+		};
+		if existing {
+			self.count_statement_existing += 1;
 			self.written_frames += 1;
 			return Ok(());
 		}
 
+		// replaying statements one by one outside of a transaction is slow, thus they are
+		// executed in batches
+		if self.count_statement_transaction == 0 {
+			self.sqlite_connection
+				.execute_batch("BEGIN")
+				.context("failed to begin database transaction")?;
+		}
+
 		debug!("Write statement: {}", &statement);
-		let mut stmt = self
-			.sqlite_connection
-			.prepare_cached(statement)
-			.with_context(|| format!("failed to prepare database statement: {}", statement))?;
-		stmt.execute(rusqlite::params_from_iter(parameters.iter()))
-			.with_context(|| format!("failed to execute database statement: {}", statement))?;
+		if let Err(e) =
+			crate::database::execute_statement(&self.sqlite_connection, statement, parameters)
+		{
+			match (self.on_statement_error)(statement, &e) {
+				StatementErrorAction::Continue => (),
+				StatementErrorAction::Abort => {
+					self.sqlite_connection.execute_batch("ROLLBACK").ok();
+					self.count_statement_transaction = 0;
+					return Err(e).with_context(|| {
+						format!("failed to execute database statement: {}", statement)
+					});
+				}
+			}
+		}
+
+		self.count_statement_transaction += 1;
+		if !self.checkpoints && self.count_statement_transaction >= self.sql_batch_size {
+			self.commit()?;
+			self.check_memory_limit()?;
+		}
 
 		self.written_frames += 1;
+		self.commit_if_due()?;
 
 		Ok(())
 	}
@@ -159,14 +752,92 @@ impl crate::output::SignalOutput for SignalOutputRaw {
 		attachmend_id: u64,
 		row_id: u64,
 	) -> Result<(), anyhow::Error> {
-		self.write_to_file(
-			"attachment",
-			&format!("{}_{}", attachmend_id, row_id),
-			&data,
-		)?;
+		// the row of the attachment is inserted before its data
+		let row = if self.mime_map.is_some()
+			|| self.only_content_type.is_some()
+			|| self.date_range.is_limited()
+			|| self.verify_attachments
+		{
+			crate::database::get_attachment(&self.sqlite_connection, row_id as i64)?
+		} else {
+			None
+		};
+		if self.verify_attachments {
+			self.verify_attachment_digest(data, attachmend_id, row_id, row.as_ref())?;
+		}
+		// attachments whose message is unknown cannot be excluded by its date
+		let message_id = row
+			.as_ref()
+			.and_then(crate::database::get_attachment_message_id);
+		if self.date_range.is_limited()
+			&& let Some(message_id) = message_id
+			&& let Some(date) =
+				crate::database::get_message_date(&self.sqlite_connection, message_id)?
+			&& !self.date_range.contains(date)
+		{
+			self.count_attachment_out_of_range += 1;
+			self.written_frames += 1;
+			return Ok(());
+		}
+		let content_type = row.and_then(|x| x.get_first_string(&["content_type", "ct"]));
+		if self
+			.only_content_type
+			.as_ref()
+			.is_some_and(|x| !x.matches(content_type.as_deref(), data))
+		{
+			self.count_attachment_skipped += 1;
+			self.written_frames += 1;
+			return Ok(());
+		}
+
+		if let Some(connection) = &self.attachments_connection {
+			connection
+				.execute(
+					"INSERT OR REPLACE INTO attachment VALUES (?1, ?2, ?3, ?4)",
+					rusqlite::params![
+						attachmend_id as i64,
+						row_id as i64,
+						infer::get(data).map(|x| x.mime_type()),
+						data
+					],
+				)
+				.map_err(|e| match e.sqlite_error_code() {
+					// sqlite limits the size of blobs, 1 GB by default
+					Some(rusqlite::ErrorCode::TooBig) => anyhow!(
+						"Attachment {}_{} of {} bytes is too large to be stored in the database",
+						attachmend_id,
+						row_id,
+						data.len()
+					),
+					_ => anyhow::Error::new(e).context("failed to store attachment in database"),
+				})?;
+
+			self.count_attachment += 1;
+			self.written_frames += 1;
+			return Ok(());
+		}
+
+		// sequential names (0001, 0002, ...) are expected by tools written for other exporters
+		let filename = if self.sequential_attachments {
+			format!("{:04}", self.count_attachment + 1)
+		} else {
+			format!("{}_{}", attachmend_id, row_id)
+		};
+		let directory =
+			crate::output_sink::attachment_directory(data.len(), self.attachment_size_threshold);
+		let path = self.write_to_file(directory, &filename, data, content_type.as_deref())?;
+		if let Some(written_attachments) = &mut self.written_attachments {
+			written_attachments.push(crate::export_attachments_index::WrittenAttachment {
+				path,
+				attachment_id: attachmend_id,
+				row_id,
+				size: data.len(),
+			});
+		}
 
 		self.count_attachment += 1;
 		self.written_frames += 1;
+		self.commit_if_due()?;
 
 		Ok(())
 	}
@@ -177,14 +848,19 @@ impl crate::output::SignalOutput for SignalOutputRaw {
 		//    path = self.path_sticker.join(format!("{}_{}", row_id, 2));
 		//}
 
-		self.write_to_file(
+		let path = self.write_to_file(
 			"sticker",
 			&format!("{}_{}", row_id, self.count_sticker),
-			&data,
+			data,
+			None,
 		)?;
+		if let Some(written_stickers) = &mut self.written_stickers {
+			written_stickers.push(crate::export_sticker_manifest::WrittenSticker { path, row_id });
+		}
 
 		self.count_sticker += 1;
 		self.written_frames += 1;
+		self.commit_if_due()?;
 
 		Ok(())
 	}
@@ -195,10 +871,23 @@ impl crate::output::SignalOutput for SignalOutputRaw {
 		//    path = self.path_sticker.join(format!("{}_{}", row_id, 2));
 		//}
 
-		self.write_to_file("avatar", &format!("{}_{}", name, self.count_avatar), &data)?;
+		let path =
+			self.write_to_file("avatar", &format!("{}_{}", name, self.count_avatar), data, None)?;
+		self.avatars.insert(name.to_string(), path);
+
+		// avatars are small, thus their thumbnails are kept until the end
+		if let Some(thumbnails) = &mut self.contact_sheet_thumbnails {
+			match crate::export_contact_sheet::create_thumbnail(data) {
+				Some(x) => {
+					thumbnails.insert(name.to_string(), x);
+				}
+				None => debug!("Avatar {} is no supported image, using placeholder", name),
+			}
+		}
 
 		self.count_avatar += 1;
 		self.written_frames += 1;
+		self.commit_if_due()?;
 
 		Ok(())
 	}
@@ -207,32 +896,24 @@ impl crate::output::SignalOutput for SignalOutputRaw {
 		&mut self,
 		pref: &crate::Backups::SharedPreference,
 	) -> Result<(), anyhow::Error> {
-		// create path to attachment file
-		let path = self.path_output.join("preference");
-		std::fs::create_dir_all(&path)
-			.with_context(|| format!("Failed to create path: {}", path.to_string_lossy()))?;
-
-		// open connection to file
-		let path = path.join(pref.file());
-		if path.exists() && !self.force_write && !self.created_files.contains(&path) {
-			return Err(anyhow!(
-				"Config file does already exist: {}. Try -f",
-				path.to_string_lossy()
-			));
+		let path = std::path::Path::new("preference").join(pref.file());
+
+		// check for existing files only on first occurence, the file is written in `finish`
+		if !self.preferences.contains_key(&path) {
+			if self.sink.exists(&path) && !self.force_write {
+				return Err(anyhow!(
+					"Config file does already exist: {}. Try -f",
+					path.to_string_lossy()
+				));
+			}
+			self.preferences.insert(path.clone(), ini::Ini::new());
 		}
 
-		// write to file
-		let mut conf = ini::Ini::load_from_file(&path).unwrap_or_default();
-		conf.with_section(None::<String>)
+		self.preferences
+			.get_mut(&path)
+			.unwrap()
+			.with_section(None::<String>)
 			.set(pref.key(), pref.value());
-		conf.write_to_file(&path).with_context(|| {
-			format!(
-				"Could not write to preference file: {}",
-				path.to_string_lossy()
-			)
-		})?;
-
-		self.created_files.insert(path);
 		self.written_frames += 1;
 
 		Ok(())
@@ -240,11 +921,47 @@ impl crate::output::SignalOutput for SignalOutputRaw {
 
 	fn write_version(&mut self, version: u32) -> Result<(), anyhow::Error> {
 		info!("Database Version: {:?}", version);
+		// Signal stores the version of its database the same way
+		self.sqlite_connection
+			.pragma_update(None, "user_version", version)
+			.context("failed to set database version")?;
 		self.written_frames += 1;
 		Ok(())
 	}
 
 	fn write_key_value(&mut self, key_value: &crate::Backups::KeyValue) ->  Result<(), anyhow::Error>{
+		let path = std::path::PathBuf::from(FILENAME_KEY_VALUES);
+
+		// check for existing files only on first occurence, the file is written in `finish`
+		if !self.preferences.contains_key(&path) {
+			info!(
+				"Backup stores settings as key values, they are written to {}",
+				FILENAME_KEY_VALUES
+			);
+			if self.sink.exists(&path) && !self.force_write {
+				return Err(anyhow!(
+					"Config file does already exist: {}. Try -f",
+					path.to_string_lossy()
+				));
+			}
+			self.preferences.insert(path.clone(), ini::Ini::new());
+		}
+
+		self.preferences
+			.get_mut(&path)
+			.unwrap()
+			.with_section(None::<String>)
+			.set(key_value.key(), crate::frame::format_key_value(key_value));
+		self.written_frames += 1;
+
+		Ok(())
+	}
+
+	fn write_unknown(&mut self, fields: &[u32]) -> Result<(), anyhow::Error> {
+		warn!(
+			"Skipping frame with unknown fields {:?}, it was probably written by a newer version of Signal",
+			fields
+		);
 		self.written_frames += 1;
 		Ok(())
 	}
@@ -253,25 +970,405 @@ impl crate::output::SignalOutput for SignalOutputRaw {
 		self.written_frames
 	}
 
+	fn checkpoint(&mut self) -> Result<serde_json::Value, anyhow::Error> {
+		if self.sqlite_in_memory || self.attachments_connection.is_some() {
+			return Err(anyhow!(
+				"Checkpoints need a database on disk and attachments stored as files"
+			));
+		}
+		self.commit()?;
+
+		let state = CheckpointState {
+			written_frames: self.written_frames,
+			count_attachment: self.count_attachment,
+			count_attachment_skipped: self.count_attachment_skipped,
+			count_attachment_out_of_range: self.count_attachment_out_of_range,
+			count_sticker: self.count_sticker,
+			count_avatar: self.count_avatar,
+			count_statement_existing: self.count_statement_existing,
+			preferences: self
+				.preferences
+				.iter()
+				.map(|(path, conf)| {
+					let values = conf
+						.general_section()
+						.iter()
+						.map(|(k, v)| (k.to_string(), v.to_string()))
+						.collect();
+					(path.clone(), values)
+				})
+				.collect(),
+			avatars: self.avatars.clone(),
+			written_attachments: self.written_attachments.clone(),
+			written_stickers: self.written_stickers.clone(),
+			count_digest_verified: self.count_digest_verified,
+			count_digest_mismatch: self.count_digest_mismatch,
+			count_digest_missing: self.count_digest_missing,
+			tables_seen: self.tables_seen.iter().cloned().collect(),
+		};
+
+		Ok(serde_json::to_value(state)?)
+	}
+
+	/// Continues writing after a checkpoint
+	///
+	/// Files of frames written after the checkpoint are overwritten.
+	fn resume(&mut self, state: serde_json::Value) -> Result<(), anyhow::Error> {
+		let state: CheckpointState =
+			serde_json::from_value(state).context("Checkpoint of output is damaged")?;
+
+		self.written_frames = state.written_frames;
+		self.count_attachment = state.count_attachment;
+		self.count_attachment_skipped = state.count_attachment_skipped;
+		self.count_attachment_out_of_range = state.count_attachment_out_of_range;
+		self.count_sticker = state.count_sticker;
+		self.count_avatar = state.count_avatar;
+		self.count_statement_existing = state.count_statement_existing;
+		for (path, values) in state.preferences {
+			let mut conf = ini::Ini::new();
+			for (k, v) in values {
+				conf.with_section(None::<String>).set(k, v);
+			}
+			self.preferences.insert(path, conf);
+		}
+		self.avatars = state.avatars;
+		self.count_digest_verified = state.count_digest_verified;
+		self.count_digest_mismatch = state.count_digest_mismatch;
+		self.count_digest_missing = state.count_digest_missing;
+		self.tables_seen = state.tables_seen.into_iter().collect();
+		if self.written_attachments.is_some() {
+			self.written_attachments = Some(state.written_attachments.unwrap_or_default());
+		}
+		if self.written_stickers.is_some() {
+			self.written_stickers = Some(state.written_stickers.unwrap_or_default());
+		}
+		self.force_write = true;
+
+		Ok(())
+	}
+
 	fn finish(&mut self) -> Result<(), anyhow::Error> {
-		if !self.sqlite_in_memory {
-			return Ok(());
+		self.commit()?;
+
+		if self.reference_database.is_some() {
+			info!(
+				"Skipped {} rows already in reference database",
+				self.count_statement_existing
+			);
 		}
 
-		let path_sqlite = self.path_output.join("signal_backup.db");
+		if self.fast_db {
+			self.sqlite_connection
+				.execute_batch(
+					"PRAGMA journal_mode = DELETE; PRAGMA synchronous = FULL; PRAGMA temp_store = DEFAULT;",
+				)
+				.context("failed to restore database settings")?;
+		}
 
-		self.sqlite_connection
-			.execute(
-				&format!("VACUUM INTO \"{}\";", path_sqlite.to_string_lossy()),
-				[],
-			)
-			.with_context(|| {
+		if self.only_content_type.is_some() {
+			info!(
+				"Skipped {} attachments of other content types",
+				self.count_attachment_skipped
+			);
+		}
+		if self.date_range.is_limited() {
+			info!(
+				"Skipped {} attachments of messages out of the date range",
+				self.count_attachment_out_of_range
+			);
+		}
+
+		if self.verify_attachments {
+			info!(
+				"Verified hashes of attachments: {} match, {} do not match, {} have no stored hash",
+				self.count_digest_verified, self.count_digest_mismatch, self.count_digest_missing
+			);
+		}
+
+		if self.build_fts {
+			info!("Build full text search table: {}", crate::database::TABLE_FTS);
+			crate::database::build_fts(&self.sqlite_connection)?;
+		}
+
+		if self.normalize_db {
+			info!("Normalize database");
+			self.sqlite_connection
+				.execute_batch("REINDEX; ANALYZE;")
+				.context("failed to reindex and analyze database")?;
+
+			// copying the in memory database with VACUUM INTO compacts it anyway
+			if !self.sqlite_in_memory {
+				self.sqlite_connection
+					.execute_batch("VACUUM;")
+					.context("failed to vacuum database")?;
+			}
+		}
+
+		// write preference files
+		for (path, conf) in std::mem::take(&mut self.preferences) {
+			let mut data = Vec::new();
+			conf.write_to(&mut data).with_context(|| {
 				format!(
-					"Failed to copy in memory database to file: {}",
-					path_sqlite.to_string_lossy()
+					"Could not write to preference file: {}",
+					path.to_string_lossy()
 				)
 			})?;
+			self.sink.write_file(&path, &data)?;
+		}
 
-		Ok(())
+		if self.export_vcard {
+			let vcards =
+				crate::export_vcard::create_vcards(&self.sqlite_connection, &self.avatars)?;
+			self.sink
+				.write_file(std::path::Path::new(FILENAME_VCARD), vcards.as_bytes())?;
+		}
+
+		if let Some(thumbnails) = &self.contact_sheet_thumbnails {
+			let sheet = crate::export_contact_sheet::create_contact_sheet(
+				&self.sqlite_connection,
+				thumbnails,
+			)?;
+			self.sink
+				.write_file(std::path::Path::new(FILENAME_CONTACT_SHEET), &sheet)?;
+		}
+
+		if self.export_calls {
+			let calls = crate::export_calls::create_calls(&self.sqlite_connection)?;
+			self.sink
+				.write_file(std::path::Path::new(FILENAME_CALLS), &calls)?;
+		}
+
+		if let Some(written_attachments) = &self.written_attachments
+			&& self.export_attachments_index
+		{
+			let index = crate::export_attachments_index::create_attachments_index(
+				&self.sqlite_connection,
+				written_attachments,
+			)?;
+			self.sink
+				.write_file(std::path::Path::new(FILENAME_ATTACHMENTS_INDEX), &index)?;
+		}
+
+		if let Some(written_attachments) = &self.written_attachments
+			&& self.preserve_timestamps
+		{
+			let dates = crate::export_attachments_index::get_attachment_dates(
+				&self.sqlite_connection,
+				written_attachments,
+			)?;
+			let mut count_preserved = 0;
+			for (attachment, date) in written_attachments.iter().zip(dates) {
+				// attachments without a message keep the time they have been written at
+				if let Some(date) = date {
+					self.sink.set_modified(&attachment.path, date)?;
+					count_preserved += 1;
+				}
+			}
+			info!(
+				"Set modification time of {} of {} attachments to the date of their message",
+				count_preserved,
+				written_attachments.len()
+			);
+		}
+
+		if let Some(written_stickers) = &self.written_stickers {
+			let manifest = crate::export_sticker_manifest::create_sticker_manifest(
+				&self.sqlite_connection,
+				written_stickers,
+			)?;
+			self.sink
+				.write_file(std::path::Path::new(FILENAME_STICKER_MANIFEST), &manifest)?;
+		}
+
+		let path_attachments = std::path::Path::new(FILENAME_DATABASE_ATTACHMENTS);
+		let has_attachments_connection = self.attachments_connection.is_some();
+		if let Some(connection) = self.attachments_connection.take() {
+			connection
+				.execute_batch("COMMIT")
+				.context("failed to commit attachment database")?;
+			connection
+				.close()
+				.map_err(|(_, e)| e)
+				.context("failed to close attachment database")?;
+		}
+
+		// the attachment database is only committed afterwards, as it is read from here
+		if self.split_db_by_thread {
+			let path_attachments_staged = if has_attachments_connection {
+				Some(self.sink.staging_path(path_attachments))
+			} else {
+				None
+			};
+			let thread_ids = crate::export_threads::get_thread_ids(&self.sqlite_connection)?;
+			info!("Write databases of {} threads", thread_ids.len());
+			for thread_id in thread_ids {
+				let path = std::path::Path::new(DIRECTORY_THREADS)
+					.join(format!("{}.sqlite", thread_id));
+				crate::export_threads::create_thread_database(
+					&self.sqlite_connection,
+					thread_id,
+					&self.sink.staging_path(&path),
+					path_attachments_staged.as_deref(),
+				)?;
+				self.sink.commit_staged(&path)?;
+			}
+		}
+
+		if has_attachments_connection {
+			self.sink.commit_staged(path_attachments)?;
+		}
+
+		// only written now, thus the databases of threads do not contain the table
+		if let Some(metadata) = &self.decode_metadata {
+			self.write_decode_metadata(metadata)?;
+		}
+
+		// write database
+		let path_database = std::path::Path::new(FILENAME_DATABASE);
+		if self.sqlite_in_memory {
+			let path_sqlite = self.sink.staging_path(path_database);
+
+			self.sqlite_connection
+				.execute("VACUUM INTO ?1", [path_sqlite.to_string_lossy()])
+				.with_context(|| {
+					format!(
+						"Failed to copy in memory database to file: {}",
+						path_sqlite.to_string_lossy()
+					)
+				})?;
+		}
+		self.sink.commit_staged(path_database)?;
+
+		self.sink.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::output::SignalOutput;
+
+	fn open_output(path: &std::path::Path, resume: bool) -> SignalOutputRaw {
+		let sink = crate::output_sink::SignalSinkDirectory::new(
+			path,
+			std::collections::HashMap::new(),
+			1024 * 1024,
+			false,
+		)
+		.unwrap();
+		SignalOutputRaw::new(
+			Box::new(sink),
+			SignalOutputRawOptions {
+				checkpoints: true,
+				resume,
+				verify_attachments: true,
+				..Default::default()
+			},
+		)
+		.unwrap()
+	}
+
+	fn open_input() -> crate::input::InputFile {
+		crate::input::InputFile::new(
+			&std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tiny.backup"),
+			&[],
+			Some(b"123456789012345678901234567890"),
+			crate::decrypter::BackupCipher::default(),
+			None,
+			true,
+			true,
+		)
+		.unwrap()
+	}
+
+	/// Returns the schema and all rows of the database in `path`
+	fn dump_database(path: &std::path::Path) -> Vec<String> {
+		let connection = rusqlite::Connection::open(path.join(FILENAME_DATABASE)).unwrap();
+		let mut dump = Vec::new();
+		let tables: Vec<(String, String)> = connection
+			.prepare("SELECT name, sql FROM sqlite_master ORDER BY name")
+			.unwrap()
+			.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+			.unwrap()
+			.collect::<Result<_, _>>()
+			.unwrap();
+		for (name, sql) in tables {
+			dump.push(sql.clone());
+			if !sql.starts_with("CREATE TABLE") {
+				continue;
+			}
+			let mut statement = connection
+				.prepare(&format!("SELECT * FROM {}", name))
+				.unwrap();
+			let columns = statement.column_count();
+			let mut rows = statement.query([]).unwrap();
+			while let Some(row) = rows.next().unwrap() {
+				let values: Vec<rusqlite::types::Value> =
+					(0..columns).map(|i| row.get(i).unwrap()).collect();
+				dump.push(format!("{}: {:?}", name, values));
+			}
+		}
+		dump
+	}
+
+	/// Interrupts decoding `tests/fixtures/tiny.backup` after a checkpoint and resumes it, which
+	/// results in the same database as decoding it at once
+	#[test]
+	fn checkpoint_resume() {
+		let path = std::env::temp_dir().join(format!(
+			"signal-backup-decode-{}-checkpoint",
+			std::process::id()
+		));
+		let path_complete = path.join("complete");
+		let path_resumed = path.join("resumed");
+
+		let mut output = open_output(&path_complete, false);
+		for frame in open_input() {
+			output.write_frame(frame.unwrap()).unwrap();
+		}
+		output.finish().unwrap();
+
+		// checkpoint after the attachment, frames written afterwards are not committed
+		let mut output = open_output(&path_resumed, false);
+		let mut input = open_input();
+		let mut position = None;
+		let mut state = None;
+		while let Some(frame) = input.next_frame().unwrap() {
+			let is_attachment = matches!(frame, crate::frame::Frame::Attachment { .. });
+			output.write_frame(frame).unwrap();
+			if is_attachment {
+				position = Some(input.get_position().unwrap());
+				state = Some(output.checkpoint().unwrap());
+			} else if state.is_some() {
+				break;
+			}
+		}
+		drop(output);
+		let state = state.unwrap();
+		assert_eq!(state["count_digest_missing"], 1);
+		assert!(!state["tables_seen"].as_array().unwrap().is_empty());
+
+		let mut output = open_output(&path_resumed, true);
+		output.resume(state.clone()).unwrap();
+		assert_eq!(output.checkpoint().unwrap(), state);
+		let mut input = open_input();
+		input.resume(&position.unwrap()).unwrap();
+		for frame in input {
+			output.write_frame(frame.unwrap()).unwrap();
+		}
+		output.finish().unwrap();
+
+		assert_eq!(dump_database(&path_resumed), dump_database(&path_complete));
+		assert_eq!(
+			output.get_written_frames(),
+			std::fs::read_to_string(
+				std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+					.join("tests/fixtures/tiny.expected")
+			)
+			.unwrap()
+			.lines()
+			.count() + 1
+		);
+		std::fs::remove_dir_all(&path).unwrap();
 	}
 }
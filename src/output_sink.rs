@@ -0,0 +1,846 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use hmac::Mac;
+use log::{debug, info, warn};
+use sha2::Digest;
+use std::io::Write;
+
+/// Destination of files written by an output module
+///
+/// All paths are relative to the root of the output, e.g. `attachment/1_2.jpg`.
+pub trait SignalSink: Send {
+	/// Returns whether a file already exists at `path`
+	fn exists(&self, path: &std::path::Path) -> bool;
+
+	/// Writes `data` to `path`
+	fn write_file(&mut self, path: &std::path::Path, data: &[u8]) -> Result<(), anyhow::Error>;
+
+	/// Returns a path on the local file system where a file can be prepared before it is
+	/// committed to the sink at `path` with `commit_staged`
+	fn staging_path(&self, path: &std::path::Path) -> std::path::PathBuf;
+
+	/// Commits a file prepared at `staging_path(path)` to the sink
+	fn commit_staged(&mut self, path: &std::path::Path) -> Result<(), anyhow::Error>;
+
+	/// Sets the modification time of the file written to `path`, if the sink keeps it
+	fn set_modified(
+		&mut self,
+		_path: &std::path::Path,
+		_time: std::time::SystemTime,
+	) -> Result<(), anyhow::Error> {
+		Ok(())
+	}
+
+	/// Finishes the sink. No files may be written afterwards.
+	fn finish(&mut self) -> Result<(), anyhow::Error>;
+}
+
+/// Returns the directory an attachment of `size` bytes is written to
+///
+/// With `threshold`, attachments smaller than it are separated from the larger ones, e.g. to
+/// find photos and videos apart from thumbnails and tiny images.
+pub fn attachment_directory(size: usize, threshold: Option<u64>) -> &'static str {
+	match threshold {
+		Some(x) if (size as u64) < x => "attachment/small",
+		Some(_) => "attachment/large",
+		None => "attachment",
+	}
+}
+
+/// Writes `data` to `directory/filename` in `sink`, with the extension of its file type
+///
+/// With `mime_map`, the extension is determined by the map from `content_type`, if known.
+/// Otherwise, it is determined from `data`. Returns the path written to. Existing files are only
+/// overwritten with `force_write`.
+pub fn write_media_file(
+	sink: &mut dyn SignalSink,
+	directory: &str,
+	filename: &str,
+	data: &[u8],
+	mime_map: Option<&crate::mime_map::MimeMap>,
+	content_type: Option<&str>,
+	force_write: bool,
+) -> Result<std::path::PathBuf, anyhow::Error> {
+	// add filename and extension to path
+	let mut path = std::path::Path::new(directory).join(filename);
+	match mime_map {
+		Some(x) => {
+			path.set_extension(x.get_extension(content_type, data));
+		}
+		None => {
+			let infer = infer::Infer::new();
+			if let Some(x) = infer.get(data) {
+				path.set_extension(x.extension());
+			}
+		}
+	}
+
+	if sink.exists(&path) && !force_write {
+		return Err(anyhow!(
+			"File does already exist: {}. Try -f",
+			path.to_string_lossy()
+		));
+	}
+
+	sink.write_file(&path, data)?;
+
+	Ok(path)
+}
+
+/// Staging directories which still exist, see `remove_staging_directories`
+static STAGING_DIRECTORIES: std::sync::Mutex<Vec<std::path::PathBuf>> =
+	std::sync::Mutex::new(Vec::new());
+
+/// Temporary directory unique for this process, deleted with its content when dropped
+///
+/// Sinks which do not write to the local file system prepare files which are only complete at the
+/// end (e.g. the database) in this directory. Outputs keep temporary files here, too. As these
+/// files are decrypted, the directory is created with a random name and is only accessible by the
+/// current user.
+pub struct StagingDirectory {
+	directory: tempfile::TempDir,
+}
+
+impl StagingDirectory {
+	/// Creates a new staging directory in the temporary directory of the system
+	pub fn new() -> Result<Self, anyhow::Error> {
+		let mut builder = tempfile::Builder::new();
+		builder.prefix("signal-backup-decode-");
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::PermissionsExt;
+			builder.permissions(std::fs::Permissions::from_mode(0o700));
+		}
+		let directory = builder.tempdir().with_context(|| {
+			format!(
+				"Temporary directory could not be created in {}",
+				std::env::temp_dir().to_string_lossy()
+			)
+		})?;
+
+		STAGING_DIRECTORIES
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.push(directory.path().to_path_buf());
+
+		Ok(Self { directory })
+	}
+
+	/// Returns the path of the directory
+	pub fn path(&self) -> &std::path::Path {
+		self.directory.path()
+	}
+}
+
+impl Drop for StagingDirectory {
+	fn drop(&mut self) {
+		// the directory itself is deleted by `TempDir`
+		STAGING_DIRECTORIES
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.retain(|x| x != self.directory.path());
+	}
+}
+
+/// Deletes all staging directories which still exist
+///
+/// Called before the process exits without dropping the outputs, e.g. on a second Ctrl-C.
+pub fn remove_staging_directories() {
+	if let Ok(directories) = STAGING_DIRECTORIES.lock() {
+		for path in directories.iter() {
+			std::fs::remove_dir_all(path).ok();
+		}
+	}
+}
+
+/// Write files to a directory tree
+pub struct SignalSinkDirectory {
+	path_output: std::path::PathBuf,
+	directories: std::collections::HashMap<std::path::PathBuf, std::path::PathBuf>,
+	chunk_size: usize,
+	verify_writes: bool,
+	/// Number of files read back after writing, and of those not matching the written data
+	count_verified: usize,
+	count_mismatch: usize,
+}
+
+impl SignalSinkDirectory {
+	/// Creates new sink writing to directory `path`
+	///
+	/// `directories` maps top level directories of the output (e.g. `attachment`) to other
+	/// directories they are written to instead. Files larger than `chunk_size` bytes are written
+	/// in parts of this size, smaller ones at once. With `verify_writes`, every file is read back
+	/// after writing and compared to the data written.
+	pub fn new(
+		path: &std::path::Path,
+		directories: std::collections::HashMap<std::path::PathBuf, std::path::PathBuf>,
+		chunk_size: usize,
+		verify_writes: bool,
+	) -> Result<Self, anyhow::Error> {
+		info!("Output path: {}", &path.to_string_lossy());
+
+		// check output path
+		if path.exists() && !path.is_dir() {
+			return Err(anyhow!(
+				"{} exists and is not a directory",
+				path.to_string_lossy()
+			));
+		} else {
+			std::fs::create_dir_all(path).with_context(|| {
+				format!("Path could not be created: {}", path.to_string_lossy())
+			})?;
+		}
+
+		// check other directories early instead of failing with the first file written there
+		for (name, directory) in &directories {
+			info!(
+				"Output path of {}: {}",
+				name.to_string_lossy(),
+				directory.to_string_lossy()
+			);
+			std::fs::create_dir_all(directory).with_context(|| {
+				format!("Path could not be created: {}", directory.to_string_lossy())
+			})?;
+			let path_probe = directory.join(".signal-backup-decode");
+			std::fs::File::create(&path_probe)
+				.and_then(|_| std::fs::remove_file(&path_probe))
+				.with_context(|| {
+					format!("Path is not writable: {}", directory.to_string_lossy())
+				})?;
+		}
+
+		Ok(Self {
+			path_output: path.to_path_buf(),
+			directories,
+			chunk_size,
+			verify_writes,
+			count_verified: 0,
+			count_mismatch: 0,
+		})
+	}
+
+	/// Reads the file at `path` back and compares its SHA-256 hash to the hash of `data`
+	///
+	/// The file is synced to disk before, so that errors of writing it are not hidden by the
+	/// page cache. A mismatch, e.g. caused by a failing disk, is reported as warning and fails
+	/// the sink when it is finished.
+	fn verify_file(&mut self, path: &std::path::Path, data: &[u8]) -> Result<(), anyhow::Error> {
+		let mut file = std::fs::File::open(path)
+			.with_context(|| format!("Failed to open file: {}", path.to_string_lossy()))?;
+		let mut hasher = sha2::Sha256::new();
+		std::io::copy(&mut file, &mut hasher)
+			.with_context(|| format!("Failed to read file: {}", path.to_string_lossy()))?;
+
+		self.count_verified += 1;
+		if hasher.finalize() != sha2::Sha256::digest(data) {
+			self.count_mismatch += 1;
+			warn!(
+				"Written file does not match the decrypted data: {}",
+				path.to_string_lossy()
+			);
+		}
+
+		Ok(())
+	}
+
+	/// Returns the path on the file system for a path relative to the output
+	fn resolve(&self, path: &std::path::Path) -> std::path::PathBuf {
+		let mut components = path.components();
+		let directory = components
+			.next()
+			.and_then(|x| self.directories.get(std::path::Path::new(&x)));
+
+		match directory {
+			Some(x) => x.join(components.as_path()),
+			None => self.path_output.join(path),
+		}
+	}
+}
+
+impl SignalSink for SignalSinkDirectory {
+	fn exists(&self, path: &std::path::Path) -> bool {
+		self.resolve(path).exists()
+	}
+
+	fn write_file(&mut self, path: &std::path::Path, data: &[u8]) -> Result<(), anyhow::Error> {
+		let path = self.resolve(path);
+
+		// create parent directory of file
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent)
+				.with_context(|| format!("Failed to create path: {}", parent.to_string_lossy()))?;
+		}
+
+		// open connection to file
+		let mut buffer = std::fs::File::create(&path)
+			.with_context(|| format!("Failed to open file: {}", path.to_string_lossy()))?;
+
+		// write to file
+		let mut written = 0;
+		for chunk in data.chunks(self.chunk_size) {
+			buffer
+				.write_all(chunk)
+				.with_context(|| format!("Failed to write to file: {}", path.to_string_lossy()))?;
+			written += chunk.len();
+			if data.len() > self.chunk_size {
+				debug!(
+					"Wrote {} of {} bytes to {}",
+					written,
+					data.len(),
+					path.to_string_lossy()
+				);
+			}
+		}
+
+		if self.verify_writes {
+			buffer
+				.sync_all()
+				.with_context(|| format!("Failed to write to file: {}", path.to_string_lossy()))?;
+			drop(buffer);
+			self.verify_file(&path, data)?;
+		}
+
+		Ok(())
+	}
+
+	fn staging_path(&self, path: &std::path::Path) -> std::path::PathBuf {
+		// files are prepared in place
+		self.resolve(path)
+	}
+
+	fn commit_staged(&mut self, _path: &std::path::Path) -> Result<(), anyhow::Error> {
+		Ok(())
+	}
+
+	fn set_modified(
+		&mut self,
+		path: &std::path::Path,
+		time: std::time::SystemTime,
+	) -> Result<(), anyhow::Error> {
+		let path = self.resolve(path);
+		filetime::set_file_mtime(&path, filetime::FileTime::from_system_time(time)).with_context(
+			|| {
+				format!(
+					"Failed to set modification time of file: {}",
+					path.to_string_lossy()
+				)
+			},
+		)
+	}
+
+	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		if !self.verify_writes {
+			return Ok(());
+		}
+
+		info!("Read back {} written files", self.count_verified);
+		if self.count_mismatch > 0 {
+			return Err(anyhow!(
+				"{} of {} written files do not match the decrypted data",
+				self.count_mismatch,
+				self.count_verified
+			));
+		}
+
+		Ok(())
+	}
+}
+
+/// Format of an archive the output is written to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArchiveFormat {
+	Tar,
+	TarGz,
+	TarZst,
+	Zip,
+}
+
+impl ArchiveFormat {
+	/// Determines the format by the file name of the archive, tar if it is unknown
+	pub fn from_path(path: &std::path::Path) -> Self {
+		let filename = path.to_string_lossy().to_lowercase();
+		if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
+			Self::TarGz
+		} else if filename.ends_with(".tar.zst") || filename.ends_with(".tzst") {
+			Self::TarZst
+		} else if filename.ends_with(".zip") {
+			Self::Zip
+		} else {
+			Self::Tar
+		}
+	}
+}
+
+/// Modification time of all files in archives
+///
+/// A fixed time makes archives of the same backup identical.
+const ARCHIVE_MTIME: u64 = 0;
+
+/// Writer of the underlying archive file, optionally compressed
+enum ArchiveWriter {
+	Plain(std::io::BufWriter<std::fs::File>),
+	Gzip(flate2::write::GzEncoder<std::io::BufWriter<std::fs::File>>),
+	Zstd(zstd::stream::write::Encoder<'static, std::io::BufWriter<std::fs::File>>),
+}
+
+impl ArchiveWriter {
+	/// Writes any trailing data (e.g. compression footers) and flushes the file
+	fn finish(self) -> std::io::Result<()> {
+		match self {
+			Self::Plain(mut x) => x.flush(),
+			Self::Gzip(x) => x.finish()?.flush(),
+			Self::Zstd(x) => x.finish()?.flush(),
+		}
+	}
+}
+
+impl Write for ArchiveWriter {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		match self {
+			Self::Plain(x) => x.write(buf),
+			Self::Gzip(x) => x.write(buf),
+			Self::Zstd(x) => x.write(buf),
+		}
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		match self {
+			Self::Plain(x) => x.flush(),
+			Self::Gzip(x) => x.flush(),
+			Self::Zstd(x) => x.flush(),
+		}
+	}
+}
+
+/// Creates the file of an archive
+fn create_archive(
+	path: &std::path::Path,
+	force_write: bool,
+) -> Result<std::io::BufWriter<std::fs::File>, anyhow::Error> {
+	info!("Output archive: {}", &path.to_string_lossy());
+
+	if path.exists() && !force_write {
+		return Err(anyhow!(
+			"Archive does already exist: {}. Try -f",
+			path.to_string_lossy()
+		));
+	}
+
+	Ok(std::io::BufWriter::new(
+		std::fs::File::create(path)
+			.with_context(|| format!("Failed to open archive: {}", path.to_string_lossy()))?,
+	))
+}
+
+/// Returns a tar header of a file with fixed owner, mode and modification time
+fn create_tar_header(size: u64) -> tar::Header {
+	let mut header = tar::Header::new_gnu();
+	header.set_size(size);
+	header.set_mode(0o644);
+	header.set_mtime(ARCHIVE_MTIME);
+
+	header
+}
+
+/// Write files into a tar archive, optionally gzip or zstd compressed
+///
+/// Files are added in the order they are written with fixed metadata, thus the archive of a
+/// backup is reproducible.
+pub struct SignalSinkTar {
+	path_archive: std::path::PathBuf,
+	builder: Option<tar::Builder<ArchiveWriter>>,
+	staging: StagingDirectory,
+	entries: std::collections::HashSet<std::path::PathBuf>,
+}
+
+impl SignalSinkTar {
+	/// Creates new sink writing to tar archive `path`
+	///
+	/// `force_write` determines whether an existing archive will be overwritten. `format` must
+	/// be one of the tar formats.
+	pub fn new(
+		path: &std::path::Path,
+		force_write: bool,
+		format: ArchiveFormat,
+	) -> Result<Self, anyhow::Error> {
+		let file = create_archive(path, force_write)?;
+		let writer = match format {
+			ArchiveFormat::TarGz => ArchiveWriter::Gzip(flate2::write::GzEncoder::new(
+				file,
+				flate2::Compression::default(),
+			)),
+			ArchiveFormat::TarZst => ArchiveWriter::Zstd(
+				zstd::stream::write::Encoder::new(file, 0)
+					.context("Failed to create zstd compressor")?,
+			),
+			_ => ArchiveWriter::Plain(file),
+		};
+
+		Ok(Self {
+			path_archive: path.to_path_buf(),
+			builder: Some(tar::Builder::new(writer)),
+			staging: StagingDirectory::new()?,
+			entries: std::collections::HashSet::new(),
+		})
+	}
+
+	fn builder(&mut self) -> Result<&mut tar::Builder<ArchiveWriter>, anyhow::Error> {
+		self.builder
+			.as_mut()
+			.ok_or_else(|| anyhow!("Archive has already been finished"))
+	}
+}
+
+impl SignalSink for SignalSinkTar {
+	fn exists(&self, path: &std::path::Path) -> bool {
+		self.entries.contains(path)
+	}
+
+	fn write_file(&mut self, path: &std::path::Path, data: &[u8]) -> Result<(), anyhow::Error> {
+		let mut header = create_tar_header(data.len() as u64);
+		self.builder()?
+			.append_data(&mut header, path, data)
+			.with_context(|| format!("Failed to add to archive: {}", path.to_string_lossy()))?;
+		self.entries.insert(path.to_path_buf());
+
+		Ok(())
+	}
+
+	fn staging_path(&self, path: &std::path::Path) -> std::path::PathBuf {
+		self.staging.path().join(path)
+	}
+
+	fn commit_staged(&mut self, path: &std::path::Path) -> Result<(), anyhow::Error> {
+		let path_local = self.staging_path(path);
+
+		// metadata of the staged file would make the archive differ between runs
+		let file = std::fs::File::open(&path_local)
+			.with_context(|| format!("Failed to open file: {}", path_local.to_string_lossy()))?;
+		let mut header = create_tar_header(file.metadata()?.len());
+		self.builder()?
+			.append_data(&mut header, path, file)
+			.with_context(|| format!("Failed to add to archive: {}", path.to_string_lossy()))?;
+		self.entries.insert(path.to_path_buf());
+
+		std::fs::remove_file(&path_local).with_context(|| {
+			format!(
+				"Could not delete temporary file: {}",
+				path_local.to_string_lossy()
+			)
+		})?;
+
+		Ok(())
+	}
+
+	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		if let Some(builder) = self.builder.take() {
+			builder
+				.into_inner()
+				.and_then(|x| x.finish())
+				.with_context(|| {
+					format!(
+						"Failed to finish archive: {}",
+						self.path_archive.to_string_lossy()
+					)
+				})?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Write files into a zip archive
+///
+/// Like `SignalSinkTar`, files are added in the order they are written with fixed metadata.
+pub struct SignalSinkZip {
+	path_archive: std::path::PathBuf,
+	writer: Option<zip::ZipWriter<std::io::BufWriter<std::fs::File>>>,
+	staging: StagingDirectory,
+	entries: std::collections::HashSet<std::path::PathBuf>,
+}
+
+impl SignalSinkZip {
+	/// Creates new sink writing to zip archive `path`
+	///
+	/// `force_write` determines whether an existing archive will be overwritten.
+	pub fn new(path: &std::path::Path, force_write: bool) -> Result<Self, anyhow::Error> {
+		Ok(Self {
+			path_archive: path.to_path_buf(),
+			writer: Some(zip::ZipWriter::new(create_archive(path, force_write)?)),
+			staging: StagingDirectory::new()?,
+			entries: std::collections::HashSet::new(),
+		})
+	}
+
+	/// Starts a new file in the archive, which is written afterwards
+	fn start_file(
+		&mut self,
+		path: &std::path::Path,
+		size: u64,
+	) -> Result<&mut zip::ZipWriter<std::io::BufWriter<std::fs::File>>, anyhow::Error> {
+		let options = zip::write::SimpleFileOptions::default()
+			.compression_method(zip::CompressionMethod::Deflated)
+			.last_modified_time(zip::DateTime::default())
+			.unix_permissions(0o644)
+			.large_file(size > u64::from(u32::MAX));
+
+		// zip archives always use / as separator
+		let name = path
+			.components()
+			.map(|x| x.as_os_str().to_string_lossy())
+			.collect::<Vec<_>>()
+			.join("/");
+
+		let writer = self
+			.writer
+			.as_mut()
+			.ok_or_else(|| anyhow!("Archive has already been finished"))?;
+		writer
+			.start_file(name, options)
+			.with_context(|| format!("Failed to add to archive: {}", path.to_string_lossy()))?;
+		self.entries.insert(path.to_path_buf());
+
+		Ok(writer)
+	}
+}
+
+impl SignalSink for SignalSinkZip {
+	fn exists(&self, path: &std::path::Path) -> bool {
+		self.entries.contains(path)
+	}
+
+	fn write_file(&mut self, path: &std::path::Path, data: &[u8]) -> Result<(), anyhow::Error> {
+		self.start_file(path, data.len() as u64)?
+			.write_all(data)
+			.with_context(|| format!("Failed to add to archive: {}", path.to_string_lossy()))
+	}
+
+	fn staging_path(&self, path: &std::path::Path) -> std::path::PathBuf {
+		self.staging.path().join(path)
+	}
+
+	fn commit_staged(&mut self, path: &std::path::Path) -> Result<(), anyhow::Error> {
+		let path_local = self.staging_path(path);
+
+		let mut file = std::fs::File::open(&path_local)
+			.with_context(|| format!("Failed to open file: {}", path_local.to_string_lossy()))?;
+		let size = file.metadata()?.len();
+		std::io::copy(&mut file, self.start_file(path, size)?)
+			.with_context(|| format!("Failed to add to archive: {}", path.to_string_lossy()))?;
+
+		std::fs::remove_file(&path_local).with_context(|| {
+			format!(
+				"Could not delete temporary file: {}",
+				path_local.to_string_lossy()
+			)
+		})?;
+
+		Ok(())
+	}
+
+	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		if let Some(writer) = self.writer.take() {
+			writer
+				.finish()
+				.map_err(anyhow::Error::from)
+				.and_then(|mut x| Ok(x.flush()?))
+				.with_context(|| {
+					format!(
+						"Failed to finish archive: {}",
+						self.path_archive.to_string_lossy()
+					)
+				})?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Write the database to stdout
+///
+/// Only files committed with `commit_staged` (i.e. the database) are written, all other files
+/// are skipped.
+pub struct SignalSinkStdout {
+	staging: StagingDirectory,
+	warned_skipped: bool,
+}
+
+impl SignalSinkStdout {
+	/// Creates new sink writing to stdout
+	pub fn new() -> Result<Self, anyhow::Error> {
+		info!("Output: stdout");
+
+		Ok(Self {
+			staging: StagingDirectory::new()?,
+			warned_skipped: false,
+		})
+	}
+}
+
+impl SignalSink for SignalSinkStdout {
+	fn exists(&self, _path: &std::path::Path) -> bool {
+		false
+	}
+
+	fn write_file(&mut self, path: &std::path::Path, _data: &[u8]) -> Result<(), anyhow::Error> {
+		if !self.warned_skipped {
+			warn!(
+				"Only the database is written to stdout, skipping other files (e.g. {})",
+				path.to_string_lossy()
+			);
+			self.warned_skipped = true;
+		}
+
+		Ok(())
+	}
+
+	fn staging_path(&self, path: &std::path::Path) -> std::path::PathBuf {
+		self.staging.path().join(path)
+	}
+
+	fn commit_staged(&mut self, path: &std::path::Path) -> Result<(), anyhow::Error> {
+		let path_local = self.staging_path(path);
+
+		let mut file = std::fs::File::open(&path_local)
+			.with_context(|| format!("Failed to open file: {}", path_local.to_string_lossy()))?;
+		let mut stdout = std::io::stdout().lock();
+		std::io::copy(&mut file, &mut stdout)
+			.and_then(|_| stdout.flush())
+			.context("Failed to write to stdout")?;
+
+		std::fs::remove_file(&path_local).with_context(|| {
+			format!(
+				"Could not delete temporary file: {}",
+				path_local.to_string_lossy()
+			)
+		})?;
+
+		Ok(())
+	}
+
+	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		Ok(())
+	}
+}
+
+/// Name of the manifest listing the SHA-256 hash of every file written
+pub const MANIFEST_FILENAME: &str = "manifest.sha256";
+
+/// Name of the HMAC-SHA256 signature of the manifest
+pub const MANIFEST_SIGNATURE_FILENAME: &str = "manifest.sha256.hmac";
+
+/// Wraps a sink and writes a manifest of all files written to it when finished
+///
+/// The manifest contains a line `HASH  PATH` for every file, sorted by path, like the output of
+/// `sha256sum`. With a key, the manifest is signed by its HMAC-SHA256, written hex encoded to
+/// a separate file.
+pub struct SignalSinkManifest {
+	sink: Box<dyn SignalSink>,
+	key: Option<Vec<u8>>,
+	hashes: std::collections::BTreeMap<std::path::PathBuf, String>,
+}
+
+impl SignalSinkManifest {
+	/// Creates new sink writing a manifest signed with `key`, if given, to `sink`
+	pub fn new(
+		sink: Box<dyn SignalSink>,
+		key: Option<Vec<u8>>,
+		force_write: bool,
+	) -> Result<Self, anyhow::Error> {
+		for filename in [MANIFEST_FILENAME, MANIFEST_SIGNATURE_FILENAME] {
+			if !force_write && sink.exists(std::path::Path::new(filename)) {
+				return Err(anyhow!("Manifest does already exist: {}. Try -f", filename));
+			}
+		}
+
+		Ok(Self {
+			sink,
+			key,
+			hashes: std::collections::BTreeMap::new(),
+		})
+	}
+
+	/// Returns the manifest of all files written so far
+	fn manifest(&self) -> String {
+		self.hashes
+			.iter()
+			.map(|(path, hash)| format!("{}  {}\n", hash, path.to_string_lossy()))
+			.collect()
+	}
+}
+
+/// Returns the hex encoded SHA-256 hash of `data`
+pub fn hash_hex(data: impl AsRef<[u8]>) -> String {
+	encode_hex(&sha2::Sha256::digest(data))
+}
+
+/// Returns `data` hex encoded in lower case
+fn encode_hex(data: &[u8]) -> String {
+	data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl SignalSink for SignalSinkManifest {
+	fn exists(&self, path: &std::path::Path) -> bool {
+		self.sink.exists(path)
+	}
+
+	fn write_file(&mut self, path: &std::path::Path, data: &[u8]) -> Result<(), anyhow::Error> {
+		self.sink.write_file(path, data)?;
+		self.hashes.insert(path.to_path_buf(), hash_hex(data));
+
+		Ok(())
+	}
+
+	fn staging_path(&self, path: &std::path::Path) -> std::path::PathBuf {
+		self.sink.staging_path(path)
+	}
+
+	fn commit_staged(&mut self, path: &std::path::Path) -> Result<(), anyhow::Error> {
+		// hash before committing, which may remove the staged file
+		let path_local = self.sink.staging_path(path);
+		let mut file = std::fs::File::open(&path_local)
+			.with_context(|| format!("Failed to open file: {}", path_local.to_string_lossy()))?;
+		let mut hasher = sha2::Sha256::new();
+		std::io::copy(&mut file, &mut hasher)
+			.with_context(|| format!("Failed to read file: {}", path_local.to_string_lossy()))?;
+		let hash = encode_hex(&hasher.finalize());
+
+		self.sink.commit_staged(path)?;
+		self.hashes.insert(path.to_path_buf(), hash);
+
+		Ok(())
+	}
+
+	fn set_modified(
+		&mut self,
+		path: &std::path::Path,
+		time: std::time::SystemTime,
+	) -> Result<(), anyhow::Error> {
+		self.sink.set_modified(path, time)
+	}
+
+	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		let manifest = self.manifest();
+		self.sink
+			.write_file(std::path::Path::new(MANIFEST_FILENAME), manifest.as_bytes())?;
+		info!(
+			"Wrote hashes of {} files to {}",
+			self.hashes.len(),
+			MANIFEST_FILENAME
+		);
+
+		if let Some(key) = &self.key {
+			let mut mac =
+				hmac::Hmac::<sha2::Sha256>::new_from_slice(key).context("Invalid signing key")?;
+			mac.update(manifest.as_bytes());
+			let signature = encode_hex(&mac.finalize().into_bytes());
+			self.sink.write_file(
+				std::path::Path::new(MANIFEST_SIGNATURE_FILENAME),
+				format!("{}\n", signature).as_bytes(),
+			)?;
+			info!("Signed manifest in {}", MANIFEST_SIGNATURE_FILENAME);
+		}
+
+		self.sink.finish()
+	}
+}
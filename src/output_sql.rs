@@ -0,0 +1,244 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use log::info;
+use std::io::Write;
+
+/// Write sql output of backup
+///
+/// Statements are written to `dump.sql` with their parameters inlined, without replaying them
+/// into a database. Statements skipped by output type RAW, like triggers and full text search
+/// tables, are skipped here as well. Attachments, avatars, stickers and preferences are not
+/// written.
+pub struct SignalOutputSql {
+	writer: std::io::BufWriter<std::fs::File>,
+	written_frames: usize,
+}
+
+impl SignalOutputSql {
+	/// Creates new output object
+	///
+	/// `force_overwrite` determines whether existing files will be overwritten.
+	pub fn new(path: &std::path::Path, force_overwrite: bool) -> Result<Self, anyhow::Error> {
+		info!("Output path: {}", &path.to_string_lossy());
+
+		// check output path
+		if path.exists() && !path.is_dir() {
+			return Err(anyhow!(
+				"{} exists and is not a directory",
+				path.to_string_lossy()
+			));
+		} else {
+			std::fs::create_dir_all(path).with_context(|| {
+				format!("Path could not be created: {}", path.to_string_lossy())
+			})?;
+		}
+
+		let path_sql = path.join("dump.sql");
+
+		if path_sql.exists() && !force_overwrite {
+			return Err(anyhow!(
+				"Backup file already exists and may not be overwritten. Try -f"
+			));
+		}
+
+		let file = std::fs::File::create(&path_sql).with_context(|| {
+			format!("Could not create file: {}", path_sql.to_string_lossy())
+		})?;
+
+		Ok(Self {
+			writer: std::io::BufWriter::new(file),
+			// we set read frames to 1 due to the header frame we will never write
+			written_frames: 1,
+		})
+	}
+}
+
+/// Renders `value` as SQL literal
+///
+/// Strings are quoted with their single quotes doubled and blobs are hex encoded. Reals are
+/// always written with a decimal point or exponent, thus they stay reals when read back.
+pub fn to_literal(value: &rusqlite::types::Value) -> String {
+	match value {
+		rusqlite::types::Value::Null => String::from("NULL"),
+		rusqlite::types::Value::Integer(x) => x.to_string(),
+		// sqlite stores NaN as NULL and has no literal for infinity besides overflowing reals
+		rusqlite::types::Value::Real(x) if x.is_nan() => String::from("NULL"),
+		rusqlite::types::Value::Real(x) if x.is_infinite() => {
+			String::from(if *x > 0.0 { "1e999" } else { "-1e999" })
+		}
+		rusqlite::types::Value::Real(x) => format!("{:?}", x),
+		rusqlite::types::Value::Text(x) => format!("'{}'", x.replace('\'', "''")),
+		rusqlite::types::Value::Blob(x) => {
+			let hex: String = x.iter().map(|b| format!("{:02X}", b)).collect();
+			format!("X'{}'", hex)
+		}
+	}
+}
+
+/// Replaces the placeholders `?` of `statement` by `parameters`
+fn inline_parameters(
+	statement: &str,
+	parameters: &[rusqlite::types::Value],
+) -> Result<String, anyhow::Error> {
+	let parts = crate::database::split_placeholders(statement)?;
+	if parts.len() != parameters.len() + 1 {
+		return Err(anyhow!(
+			"Statement has {} placeholders but {} parameters",
+			parts.len() - 1,
+			parameters.len()
+		));
+	}
+
+	let mut result = String::with_capacity(statement.len());
+	for (part, parameter) in parts.iter().zip(parameters) {
+		result.push_str(part);
+		result.push_str(&to_literal(parameter));
+	}
+	result.push_str(parts[parts.len() - 1]);
+
+	Ok(result)
+}
+
+impl crate::output::SignalOutput for SignalOutputSql {
+	fn write_statement(
+		&mut self,
+		statement: &str,
+		parameters: &[rusqlite::types::Value],
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		if crate::database::is_statement_skipped(statement) {
+			return Ok(());
+		}
+
+		let statement = inline_parameters(statement, parameters)
+			.with_context(|| format!("Failed to inline parameters of statement: {}", statement))?;
+		writeln!(self.writer, "{};", statement).context("Failed to write SQL statement")?;
+
+		Ok(())
+	}
+
+	fn write_attachment(
+		&mut self,
+		_data: &[u8],
+		_attachmend_id: u64,
+		_row_id: u64,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_sticker(&mut self, _data: &[u8], _row_id: u64) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_avatar(&mut self, _data: &[u8], _name: &str) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_preference(
+		&mut self,
+		_pref: &crate::Backups::SharedPreference,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_version(&mut self, version: u32) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		// as comment, thus the dump stays loadable into databases other than sqlite
+		writeln!(self.writer, "-- Database version: {}", version)
+			.context("Failed to write SQL statement")?;
+		Ok(())
+	}
+
+	fn write_key_value(&mut self, _key_value: &crate::Backups::KeyValue) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_unknown(&mut self, _fields: &[u32]) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn get_written_frames(&self) -> usize {
+		self.written_frames
+	}
+
+	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		self.writer.flush().context("Failed to write SQL dump")?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rusqlite::types::Value;
+
+	#[test]
+	fn literals() {
+		assert_eq!(to_literal(&Value::Null), "NULL");
+		assert_eq!(to_literal(&Value::Integer(-42)), "-42");
+		assert_eq!(to_literal(&Value::Real(1.0)), "1.0");
+		assert_eq!(to_literal(&Value::Real(f64::NAN)), "NULL");
+		assert_eq!(to_literal(&Value::Real(f64::INFINITY)), "1e999");
+		assert_eq!(to_literal(&Value::Real(f64::NEG_INFINITY)), "-1e999");
+		assert_eq!(
+			to_literal(&Value::Text(String::from("it's a 'quote'"))),
+			"'it''s a ''quote'''"
+		);
+		assert_eq!(to_literal(&Value::Blob(vec![0, 0xab, 0xff])), "X'00ABFF'");
+		assert_eq!(to_literal(&Value::Blob(Vec::new())), "X''");
+
+		// every literal is read back by sqlite as the value it has been rendered from
+		let connection = rusqlite::Connection::open_in_memory().unwrap();
+		for value in [
+			Value::Null,
+			Value::Integer(i64::MIN),
+			Value::Real(0.1),
+			Value::Real(-1e300),
+			Value::Real(f64::INFINITY),
+			Value::Real(f64::NEG_INFINITY),
+			Value::Text(String::from("'; DROP TABLE message; --")),
+			Value::Text(String::from("line\nbreak and \"double\" quotes")),
+			Value::Blob(vec![0, 39, 255]),
+		] {
+			let read: Value = connection
+				.query_row(&format!("SELECT {}", to_literal(&value)), [], |row| {
+					row.get(0)
+				})
+				.unwrap();
+			assert_eq!(read, value);
+		}
+	}
+
+	#[test]
+	fn inline() {
+		let parameters = [Value::Integer(1), Value::Text(String::from("?"))];
+		assert_eq!(
+			inline_parameters("INSERT INTO message VALUES (?,?)", &parameters).unwrap(),
+			"INSERT INTO message VALUES (1,'?')"
+		);
+
+		// question marks in literals, identifiers and comments are no placeholders
+		assert_eq!(
+			inline_parameters(
+				"INSERT INTO \"a?\" (`b?`, [c?]) VALUES ('?''?', ?) -- ?\n/* ? */ ?",
+				&parameters
+			)
+			.unwrap(),
+			"INSERT INTO \"a?\" (`b?`, [c?]) VALUES ('?''?', 1) -- ?\n/* ? */ '?'"
+		);
+		assert_eq!(
+			inline_parameters("SELECT '?' /*/ ? */", &[]).unwrap(),
+			"SELECT '?' /*/ ? */"
+		);
+
+		// wrong number and numbered placeholders are rejected
+		assert!(inline_parameters("INSERT INTO message VALUES (?)", &parameters).is_err());
+		assert!(inline_parameters("INSERT INTO message VALUES (?2,?1)", &parameters).is_err());
+	}
+}
@@ -0,0 +1,235 @@
+use anyhow::anyhow;
+use anyhow::Context;
+use log::{info, warn};
+use std::io::Write;
+
+/// List conversations of a backup
+///
+/// Statements are collected in an in memory database. When all frames are written, every thread
+/// is printed to stdout with its recipient, number of messages and the dates of its first and
+/// last message. No files are written.
+pub struct SignalOutputThreads {
+	sort_by: Option<String>,
+	date_range: crate::database::DateRange,
+	sqlite_connection: rusqlite::Connection,
+	written_frames: usize,
+}
+
+/// A conversation with its messages counted
+struct Thread {
+	id: i64,
+	/// Name of the contact or group
+	name: String,
+	messages: usize,
+	/// Dates the first and last message were sent, as timestamp
+	first_message: Option<i64>,
+	last_message: Option<i64>,
+}
+
+impl SignalOutputThreads {
+	/// Columns threads can be sorted by
+	pub const COLUMNS: [&'static str; 4] = ["id", "name", "messages", "last_message"];
+
+	/// Creates new output object
+	///
+	/// Threads are sorted by column `sort_by` (one of `COLUMNS`), by default by their last
+	/// message with the most recent first. Only messages sent in `date_range` are counted.
+	pub fn new(
+		sort_by: Option<String>,
+		date_range: crate::database::DateRange,
+	) -> Result<Self, anyhow::Error> {
+		if let Some(x) = sort_by.as_deref().filter(|x| !Self::COLUMNS.contains(x)) {
+			return Err(anyhow!(
+				"Unknown column to sort threads by: {} (expected one of {})",
+				x,
+				Self::COLUMNS.join(", ")
+			));
+		}
+		info!("Output: list of threads");
+
+		Ok(Self {
+			sort_by,
+			date_range,
+			sqlite_connection: rusqlite::Connection::open_in_memory()
+				.context("could not open connection to in memory database")?,
+			// we set read frames to 1 due to the header frame we will never write
+			written_frames: 1,
+		})
+	}
+
+	/// Queries all threads with their messages counted
+	///
+	/// With a date range, threads without messages in it are left out.
+	fn get_threads(&self) -> Result<Vec<Thread>, anyhow::Error> {
+		let connection = &self.sqlite_connection;
+		if !crate::database::has_table(connection, "thread")? {
+			warn!("Backup does not contain a thread table, no threads are listed");
+			return Ok(Vec::new());
+		}
+
+		let recipients = crate::database::get_recipients(connection)?;
+		let mut threads: std::collections::BTreeMap<i64, Thread> =
+			std::collections::BTreeMap::new();
+		for row in crate::database::query_rows(connection, "SELECT * FROM thread", [])? {
+			// older backups store the recipient in column thread_recipient_id
+			let recipient_id = row
+				.get_i64("recipient_id")
+				.or_else(|| row.get_i64("thread_recipient_id"));
+			let id = row.get_i64("_id").unwrap_or_default();
+			threads.insert(
+				id,
+				Thread {
+					id,
+					name: match recipient_id {
+						Some(x) => recipients
+							.get(&x)
+							.map(|x| x.display_name())
+							.unwrap_or_else(|| format!("recipient {}", x)),
+						None => String::from(""),
+					},
+					messages: 0,
+					first_message: None,
+					last_message: None,
+				},
+			);
+		}
+
+		if crate::database::has_table(connection, "message")? {
+			for row in crate::database::query_rows(connection, "SELECT * FROM message", [])? {
+				let date_sent = row.get_i64("date_sent").unwrap_or_default();
+				if !self.date_range.contains(date_sent)
+					|| crate::database::is_previous_revision(&row)
+				{
+					continue;
+				}
+				let Some(thread) = row.get_i64("thread_id").and_then(|x| threads.get_mut(&x))
+				else {
+					continue;
+				};
+				thread.messages += 1;
+				thread.first_message =
+					Some(thread.first_message.map_or(date_sent, |x| x.min(date_sent)));
+				thread.last_message =
+					Some(thread.last_message.map_or(date_sent, |x| x.max(date_sent)));
+			}
+		} else {
+			warn!("Backup does not contain a message table, messages are not counted");
+		}
+
+		let has_date_range = self.date_range.is_limited();
+		let mut threads: Vec<Thread> = threads
+			.into_values()
+			.filter(|x| !has_date_range || x.messages > 0)
+			.collect();
+		match self.sort_by.as_deref() {
+			Some("id") => (),
+			Some("name") => threads.sort_by(|a, b| a.name.cmp(&b.name)),
+			Some("messages") => threads.sort_by_key(|x| std::cmp::Reverse(x.messages)),
+			_ => threads.sort_by_key(|x| std::cmp::Reverse(x.last_message)),
+		}
+
+		Ok(threads)
+	}
+}
+
+/// Formats the date of a message for the list, `-` if there is none
+fn format_date(timestamp: Option<i64>) -> String {
+	match timestamp {
+		Some(x) => crate::database::timestamp_to_date(x)
+			.format("%Y-%m-%d %H:%M")
+			.to_string(),
+		None => String::from("-"),
+	}
+}
+
+impl crate::output::SignalOutput for SignalOutputThreads {
+	fn write_statement(
+		&mut self,
+		statement: &str,
+		parameters: &[rusqlite::types::Value],
+	) -> Result<(), anyhow::Error> {
+		if !crate::database::is_statement_skipped(statement) {
+			crate::database::execute_statement(&self.sqlite_connection, statement, parameters)
+				.with_context(|| format!("failed to execute database statement: {}", statement))?;
+		}
+
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_attachment(
+		&mut self,
+		_data: &[u8],
+		_attachmend_id: u64,
+		_row_id: u64,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_sticker(&mut self, _data: &[u8], _row_id: u64) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_avatar(&mut self, _data: &[u8], _name: &str) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_preference(
+		&mut self,
+		_pref: &crate::Backups::SharedPreference,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_version(&mut self, _version: u32) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_key_value(
+		&mut self,
+		_key_value: &crate::Backups::KeyValue,
+	) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn write_unknown(&mut self, _fields: &[u32]) -> Result<(), anyhow::Error> {
+		self.written_frames += 1;
+		Ok(())
+	}
+
+	fn get_written_frames(&self) -> usize {
+		self.written_frames
+	}
+
+	fn finish(&mut self) -> Result<(), anyhow::Error> {
+		let threads = self.get_threads()?;
+
+		let mut stdout = std::io::stdout().lock();
+		writeln!(
+			stdout,
+			"{:>6}  {:>8}  {:<16}  {:<16}  NAME",
+			"ID", "MESSAGES", "FIRST", "LAST"
+		)?;
+		for thread in &threads {
+			writeln!(
+				stdout,
+				"{:>6}  {:>8}  {:<16}  {:<16}  {}",
+				thread.id,
+				thread.messages,
+				format_date(thread.first_message),
+				format_date(thread.last_message),
+				thread.name
+			)?;
+		}
+		stdout.flush()?;
+		info!("Listed {} threads", threads.len());
+
+		Ok(())
+	}
+}
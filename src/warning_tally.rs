@@ -0,0 +1,73 @@
+use log::warn;
+
+/// Number of warnings by module they were logged in
+static COUNTS: std::sync::Mutex<std::collections::BTreeMap<String, usize>> =
+	std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+/// Logger which counts warnings instead of printing them
+///
+/// Warnings are printed by the other loggers as soon as they occur, together with the frame
+/// they concern. This logger only keeps their number for the summary at the end of a run.
+pub struct WarningTally;
+
+impl WarningTally {
+	pub fn new() -> Box<Self> {
+		Box::new(Self)
+	}
+}
+
+impl log::Log for WarningTally {
+	fn enabled(&self, metadata: &log::Metadata) -> bool {
+		metadata.level() == log::Level::Warn
+	}
+
+	fn log(&self, record: &log::Record) {
+		if !self.enabled(record.metadata()) {
+			return;
+		}
+
+		// the target is the module path, e.g. signal_backup_decode::input
+		let module = match record.target().strip_prefix(env!("CARGO_CRATE_NAME")) {
+			Some("") => String::from("main"),
+			Some(x) => x.trim_start_matches("::").to_string(),
+			None => record.target().to_string(),
+		};
+		*COUNTS.lock().unwrap().entry(module).or_insert(0) += 1;
+	}
+
+	fn flush(&self) {}
+}
+
+impl simplelog::SharedLogger for WarningTally {
+	fn level(&self) -> log::LevelFilter {
+		log::LevelFilter::Warn
+	}
+
+	fn config(&self) -> Option<&simplelog::Config> {
+		None
+	}
+
+	fn as_log(self: Box<Self>) -> Box<dyn log::Log> {
+		self
+	}
+}
+
+/// Logs the number of warnings of this run by module, if there were any
+pub fn log_summary() {
+	let counts = std::mem::take(&mut *COUNTS.lock().unwrap());
+	let total: usize = counts.values().sum();
+	if total == 0 {
+		return;
+	}
+
+	warn!(
+		"Finished with {} warning{} ({})",
+		total,
+		if total == 1 { "" } else { "s" },
+		counts
+			.iter()
+			.map(|(module, count)| format!("{}: {}", module, count))
+			.collect::<Vec<_>>()
+			.join(", ")
+	);
+}
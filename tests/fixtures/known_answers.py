@@ -0,0 +1,73 @@
+#!/usr/bin/env python3
+"""Prints the known answers of `tiny.backup` checked by `input::tests::test_vector_known_answers`
+
+The backup format is implemented here from Signal's description of it, independently of this
+tool: the keys are derived by iterating SHA-512 over password and salt and expanding the result
+with HKDF-SHA256, frames are encrypted with AES-256 in CTR mode, whose counter is the big endian
+number in the first 4 bytes of the IV, and their HMAC-SHA256 over the encrypted length and data
+is truncated to 10 bytes. Needs the package `cryptography`.
+"""
+
+import hashlib
+import hmac
+import pathlib
+import struct
+
+from cryptography.hazmat.primitives import hashes
+from cryptography.hazmat.primitives.ciphers import Cipher, algorithms, modes
+from cryptography.hazmat.primitives.kdf.hkdf import HKDFExpand
+
+PASSWORD = b"123456789012345678901234567890"
+
+
+def read_header(data):
+    """Returns IV and salt of the unencrypted header frame and its length"""
+    (length,) = struct.unpack(">I", data[:4])
+    frame = data[4 : 4 + length]
+    # BackupFrame { header = 1 { iv = 1, salt = 2 } }
+    assert frame[0] == 0x0A and frame[1] == length - 2
+    header = frame[2:]
+    fields = {}
+    while header:
+        tag, size = header[0], header[1]
+        fields[tag >> 3] = header[2 : 2 + size]
+        header = header[2 + size :]
+    return fields[1], fields[2], 4 + length
+
+
+def derive_keys(password, salt):
+    digest = hashlib.sha512()
+    digest.update(salt)
+    hash = password
+    for _ in range(250_000):
+        digest.update(hash)
+        digest.update(password)
+        hash = digest.digest()
+        digest = hashlib.sha512()
+    # HKDF without salt, i.e. a salt of 32 zero bytes, extracting from the first 32 bytes
+    prk = hmac.new(b"\0" * 32, hash[:32], hashlib.sha256).digest()
+    return HKDFExpand(hashes.SHA256(), 64, b"Backup Export").derive(prk)
+
+
+def main():
+    data = (pathlib.Path(__file__).parent / "tiny.backup").read_bytes()
+    iv, salt, offset = read_header(data)
+    keys = derive_keys(PASSWORD, salt)
+    cipher_key, mac_key = keys[:32], keys[32:]
+
+    keystream = Cipher(algorithms.AES(cipher_key), modes.CTR(iv)).encryptor().update(b"\0" * 32)
+    length_encrypted = data[offset : offset + 4]
+    (length,) = struct.unpack(">I", bytes(a ^ b for a, b in zip(length_encrypted, keystream)))
+    frame = data[offset + 4 : offset + 4 + length]
+    mac = hmac.new(mac_key, length_encrypted + frame[:-10], hashlib.sha256).digest()[:10]
+    assert mac == frame[-10:], "HMAC of the first frame does not match"
+
+    print("keys:", keys.hex())
+    print("keystream of the first frame:", keystream.hex())
+    print("length of the first frame:", length)
+    print("HMAC of the first frame:", mac.hex())
+    print("first frame:", bytes(a ^ b for a, b in zip(frame[:-10], keystream[4:])).hex())
+
+
+if __name__ == "__main__":
+    main()